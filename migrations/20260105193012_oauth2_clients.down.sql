@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+drop table oauth2_access_tokens;
+drop table oauth2_clients;
+drop type oauth2_client_auth_method;
\ No newline at end of file
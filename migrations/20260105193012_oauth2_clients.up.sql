@@ -0,0 +1,27 @@
+-- Add up migration script here
+
+create type oauth2_client_auth_method as enum ('client_secret_basic', 'client_secret_post', 'private_key_jwt', 'none');
+
+create table oauth2_clients
+(
+    id                         uuid primary key,
+    name                       text                      not null,
+    secret_hash                bytea,
+    token_endpoint_auth_method oauth2_client_auth_method not null,
+    grant_types                text[]                    not null default '{}',
+    scopes                     text[]                    not null default '{}',
+    audiences                  text[]                    not null default '{}',
+    jwks                       jsonb,
+    created_at                 timestamptz               not null default now()
+);
+
+create table oauth2_access_tokens
+(
+    id         uuid primary key,
+    token_hash bytea       not null unique,
+    client_id  uuid        not null references oauth2_clients (id) on delete cascade,
+    scopes     text[]      not null,
+    audiences  text[]      not null,
+    expires_at timestamptz not null,
+    created_at timestamptz not null default now()
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+drop table sessions;
+drop table users;
\ No newline at end of file
@@ -0,0 +1,19 @@
+-- Add up migration script here
+
+create table users
+(
+    id            uuid primary key,
+    username      text        not null unique,
+    email         text unique,
+    password_hash text,
+    created_at    timestamptz not null default now()
+);
+
+create table sessions
+(
+    id         uuid primary key,
+    token_hash bytea       not null unique,
+    user_id    uuid        not null references users (id) on delete cascade,
+    expires_at timestamptz not null,
+    created_at timestamptz not null default now()
+);
@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+drop table oauth2_device_codes;
+drop type oauth2_device_code_status;
+drop table oauth2_refresh_tokens;
+alter table oauth2_access_tokens drop column user_id;
\ No newline at end of file
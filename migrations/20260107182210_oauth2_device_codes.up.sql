@@ -0,0 +1,33 @@
+-- Add up migration script here
+
+alter table oauth2_access_tokens
+    add column user_id uuid references users (id) on delete cascade;
+
+create table oauth2_refresh_tokens
+(
+    id         uuid primary key,
+    token_hash bytea       not null unique,
+    client_id  uuid        not null references oauth2_clients (id) on delete cascade,
+    user_id    uuid        not null references users (id) on delete cascade,
+    scopes     text[]      not null,
+    audiences  text[]      not null,
+    expires_at timestamptz not null,
+    created_at timestamptz not null default now()
+);
+
+create type oauth2_device_code_status as enum ('pending', 'approved', 'denied');
+
+create table oauth2_device_codes
+(
+    id               uuid primary key,
+    device_code_hash bytea                     not null unique,
+    user_code        text                      not null unique,
+    client_id        uuid                      not null references oauth2_clients (id) on delete cascade,
+    scopes           text[]                    not null,
+    status           oauth2_device_code_status not null default 'pending',
+    user_id          uuid references users (id) on delete cascade,
+    poll_interval    integer                   not null,
+    last_polled_at   timestamptz,
+    expires_at       timestamptz               not null,
+    created_at       timestamptz               not null default now()
+);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+alter table oauth2_clients drop column introspect_audiences;
\ No newline at end of file
@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+alter table oauth2_clients
+    add column introspect_audiences text[] not null default '{}';
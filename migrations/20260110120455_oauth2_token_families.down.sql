@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+alter table oauth2_access_tokens drop column family_id;
+alter table oauth2_refresh_tokens drop column family_id;
\ No newline at end of file
@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+alter table oauth2_refresh_tokens
+    add column family_id uuid;
+update oauth2_refresh_tokens
+set family_id = id;
+alter table oauth2_refresh_tokens
+    alter column family_id set not null;
+
+alter table oauth2_access_tokens
+    add column family_id uuid;
+
+create index oauth2_refresh_tokens_family_id_idx on oauth2_refresh_tokens (family_id);
+create index oauth2_access_tokens_family_id_idx on oauth2_access_tokens (family_id);
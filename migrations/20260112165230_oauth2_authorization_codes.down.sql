@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+drop table grants;
+drop table oauth2_authorization_codes;
+alter table oauth2_clients drop column redirect_uris, drop column first_party;
\ No newline at end of file
@@ -0,0 +1,30 @@
+-- Add up migration script here
+
+alter table oauth2_clients
+    add column redirect_uris text[]  not null default '{}',
+    add column first_party   boolean not null default false;
+
+create table oauth2_authorization_codes
+(
+    id                    uuid primary key,
+    code_hash             bytea       not null unique,
+    client_id             uuid        not null references oauth2_clients (id) on delete cascade,
+    user_id               uuid        not null references users (id) on delete cascade,
+    redirect_uri          text        not null,
+    scopes                text[]      not null,
+    code_challenge        text,
+    code_challenge_method text,
+    expires_at            timestamptz not null,
+    created_at            timestamptz not null default now()
+);
+
+create table grants
+(
+    id         uuid primary key,
+    user_id    uuid        not null references users (id) on delete cascade,
+    client_id  uuid        not null references oauth2_clients (id) on delete cascade,
+    scopes     text[]      not null,
+    created_at timestamptz not null default now(),
+    updated_at timestamptz not null default now(),
+    unique (user_id, client_id)
+);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+alter table users drop column attributes;
+drop table oauth2_scopes;
\ No newline at end of file
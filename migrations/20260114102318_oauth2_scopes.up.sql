@@ -0,0 +1,12 @@
+-- Add up migration script here
+
+create table oauth2_scopes
+(
+    name        text primary key,
+    description text        not null default '',
+    claims      text[]      not null default '{}',
+    created_at  timestamptz not null default now()
+);
+
+alter table users
+    add column attributes jsonb not null default '{}';
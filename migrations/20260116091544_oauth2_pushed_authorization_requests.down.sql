@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+drop table oauth2_pushed_authorization_requests;
+alter table oauth2_clients drop column require_pushed_authorization_requests;
\ No newline at end of file
@@ -0,0 +1,14 @@
+-- Add up migration script here
+
+alter table oauth2_clients
+    add column require_pushed_authorization_requests boolean not null default false;
+
+create table oauth2_pushed_authorization_requests
+(
+    id             uuid primary key,
+    reference_hash bytea       not null unique,
+    client_id      uuid        not null references oauth2_clients (id) on delete cascade,
+    parameters     jsonb       not null,
+    expires_at     timestamptz not null,
+    created_at     timestamptz not null default now()
+);
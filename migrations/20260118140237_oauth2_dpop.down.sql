@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+drop table oauth2_dpop_proofs;
+drop table oauth2_dpop_nonces;
+alter table oauth2_refresh_tokens drop column dpop_jkt;
+alter table oauth2_access_tokens drop column dpop_jkt;
+alter table oauth2_clients drop column dpop_bound_access_tokens;
\ No newline at end of file
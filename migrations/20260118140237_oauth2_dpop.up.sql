@@ -0,0 +1,26 @@
+-- Add up migration script here
+
+alter table oauth2_clients
+    add column dpop_bound_access_tokens boolean not null default false;
+
+alter table oauth2_access_tokens
+    add column dpop_jkt text;
+
+alter table oauth2_refresh_tokens
+    add column dpop_jkt text;
+
+create table oauth2_dpop_nonces
+(
+    id         uuid primary key,
+    nonce_hash bytea       not null unique,
+    expires_at timestamptz not null,
+    created_at timestamptz not null default now()
+);
+
+create table oauth2_dpop_proofs
+(
+    jti_hash   bytea primary key,
+    expires_at timestamptz not null
+);
+
+create index oauth2_dpop_proofs_expires_at_idx on oauth2_dpop_proofs (expires_at);
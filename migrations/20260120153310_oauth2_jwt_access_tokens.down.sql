@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+drop table signing_keys;
+drop table oauth2_resources;
+alter table oauth2_clients drop column access_token_format;
+drop type oauth2_access_token_format;
\ No newline at end of file
@@ -0,0 +1,23 @@
+-- Add up migration script here
+
+create type oauth2_access_token_format as enum ('opaque', 'jwt');
+
+alter table oauth2_clients
+    add column access_token_format oauth2_access_token_format not null default 'opaque';
+
+create table oauth2_resources
+(
+    id                  uuid primary key,
+    identifier          text        not null unique,
+    access_token_format oauth2_access_token_format,
+    created_at          timestamptz not null default now()
+);
+
+create table signing_keys
+(
+    id          uuid primary key,
+    algorithm   text        not null,
+    private_key bytea       not null,
+    public_jwk  jsonb       not null,
+    created_at  timestamptz not null default now()
+);
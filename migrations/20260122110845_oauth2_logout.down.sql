@@ -0,0 +1,8 @@
+-- Add down migration script here
+
+drop table backchannel_logouts;
+drop table session_clients;
+alter table oauth2_clients
+    drop column post_logout_redirect_uris,
+    drop column frontchannel_logout_uri,
+    drop column backchannel_logout_uri;
\ No newline at end of file
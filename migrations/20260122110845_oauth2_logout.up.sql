@@ -0,0 +1,28 @@
+-- Add up migration script here
+
+alter table oauth2_clients
+    add column post_logout_redirect_uris text[] not null default '{}',
+    add column frontchannel_logout_uri   text,
+    add column backchannel_logout_uri    text;
+
+create table session_clients
+(
+    session_id uuid        not null references sessions (id) on delete cascade,
+    client_id  uuid        not null references oauth2_clients (id) on delete cascade,
+    created_at timestamptz not null default now(),
+    primary key (session_id, client_id)
+);
+
+create table backchannel_logouts
+(
+    id              uuid primary key,
+    client_id       uuid        not null references oauth2_clients (id) on delete cascade,
+    user_id         uuid        not null,
+    session_id      uuid        not null,
+    attempts        integer     not null default 0,
+    next_attempt_at timestamptz not null,
+    last_error      text,
+    created_at      timestamptz not null default now()
+);
+
+create index backchannel_logouts_next_attempt_at_idx on backchannel_logouts (next_attempt_at);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+alter table oauth2_access_tokens
+    drop column act;
\ No newline at end of file
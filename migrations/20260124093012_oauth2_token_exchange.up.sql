@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+alter table oauth2_access_tokens
+    add column act jsonb;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+drop table oauth2_client_assertions;
+alter table oauth2_clients
+    drop column jwks_uri,
+    drop column request_uris;
\ No newline at end of file
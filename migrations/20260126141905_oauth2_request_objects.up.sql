@@ -0,0 +1,11 @@
+-- Add up migration script here
+
+alter table oauth2_clients
+    add column jwks_uri     text,
+    add column request_uris text[] not null default '{}';
+
+create table oauth2_client_assertions
+(
+    jti_hash   bytea primary key,
+    expires_at timestamptz not null
+);
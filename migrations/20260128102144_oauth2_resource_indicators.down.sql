@@ -0,0 +1,11 @@
+-- Add down migration script here
+
+alter table oauth2_device_codes
+    drop column resources;
+
+alter table oauth2_authorization_codes
+    drop column resources;
+
+alter table oauth2_resources
+    drop column scopes,
+    drop column access_token_lifetime;
\ No newline at end of file
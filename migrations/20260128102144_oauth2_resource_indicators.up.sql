@@ -0,0 +1,11 @@
+-- Add up migration script here
+
+alter table oauth2_resources
+    add column scopes                text[] not null default '{}',
+    add column access_token_lifetime bigint;
+
+alter table oauth2_authorization_codes
+    add column resources text[] not null default '{}';
+
+alter table oauth2_device_codes
+    add column resources text[] not null default '{}';
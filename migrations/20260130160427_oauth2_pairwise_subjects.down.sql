@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+alter table oauth2_clients
+    drop column subject_type,
+    drop column sector_identifier_uri;
+
+drop type oauth2_subject_type;
\ No newline at end of file
@@ -0,0 +1,7 @@
+-- Add up migration script here
+
+create type oauth2_subject_type as enum ('public', 'pairwise');
+
+alter table oauth2_clients
+    add column subject_type          oauth2_subject_type not null default 'public',
+    add column sector_identifier_uri text;
@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+alter table oauth2_clients
+    drop column application_type;
+
+drop type oauth2_application_type;
\ No newline at end of file
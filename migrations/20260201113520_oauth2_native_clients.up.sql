@@ -0,0 +1,6 @@
+-- Add up migration script here
+
+create type oauth2_application_type as enum ('web', 'native');
+
+alter table oauth2_clients
+    add column application_type oauth2_application_type not null default 'web';
@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+drop table identities;
+
+drop table upstream_logins;
\ No newline at end of file
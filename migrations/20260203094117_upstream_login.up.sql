@@ -0,0 +1,25 @@
+-- Add up migration script here
+
+create table upstream_logins
+(
+    id            uuid primary key,
+    state_hash    bytea       not null unique,
+    provider      text        not null,
+    nonce         text        not null,
+    code_verifier text        not null,
+    return_to     text        not null,
+    expires_at    timestamptz not null,
+    created_at    timestamptz not null default now()
+);
+
+create table identities
+(
+    id         uuid primary key,
+    user_id    uuid        not null references users (id) on delete cascade,
+    provider   text        not null,
+    subject    text        not null,
+    created_at timestamptz not null default now(),
+    unique (provider, subject)
+);
+
+create index identities_user_id_idx on identities (user_id);
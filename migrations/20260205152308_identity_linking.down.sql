@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+alter table upstream_logins
+    drop column user_id;
\ No newline at end of file
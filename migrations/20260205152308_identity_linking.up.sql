@@ -0,0 +1,4 @@
+-- Add up migration script here
+
+alter table upstream_logins
+    add column user_id uuid references users (id) on delete cascade;
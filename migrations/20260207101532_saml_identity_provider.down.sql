@@ -0,0 +1,7 @@
+-- Add down migration script here
+
+drop table saml_sessions;
+
+drop table saml_service_providers;
+
+drop type saml_binding;
\ No newline at end of file
@@ -0,0 +1,28 @@
+-- Add up migration script here
+
+create type saml_binding as enum ('redirect', 'post');
+
+create table saml_service_providers
+(
+    id                    uuid primary key,
+    entity_id             text         not null unique,
+    name                  text         not null,
+    acs_url               text         not null,
+    slo_url               text,
+    slo_binding           saml_binding not null default 'redirect',
+    certificate           bytea,
+    name_id_format        text         not null,
+    attributes            jsonb        not null default '{}',
+    authn_requests_signed boolean      not null default false,
+    created_at            timestamptz  not null default now()
+);
+
+create table saml_sessions
+(
+    session_id          uuid        not null references sessions (id) on delete cascade,
+    service_provider_id uuid        not null references saml_service_providers (id) on delete cascade,
+    name_id             text        not null,
+    session_index       text        not null,
+    created_at          timestamptz not null default now(),
+    primary key (session_id, service_provider_id)
+);
@@ -0,0 +1,13 @@
+-- Add up migration script here
+
+create table saml_logins
+(
+    id         uuid primary key,
+    state_hash bytea       not null unique,
+    provider   text        not null,
+    request_id text        not null,
+    return_to  text        not null,
+    user_id    uuid references users (id) on delete cascade,
+    expires_at timestamptz not null,
+    created_at timestamptz not null default now()
+);
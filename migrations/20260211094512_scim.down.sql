@@ -0,0 +1,7 @@
+drop table group_members;
+drop table groups;
+drop table scim_users;
+drop table scim_tokens;
+
+alter table users
+    drop column active;
\ No newline at end of file
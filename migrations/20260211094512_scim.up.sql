@@ -0,0 +1,45 @@
+-- Add up migration script here
+
+alter table users
+    add column active boolean not null default true;
+
+create table scim_tokens
+(
+    id         uuid primary key,
+    token_hash bytea       not null unique,
+    tenant     text        not null,
+    name       text        not null,
+    created_at timestamptz not null default now()
+);
+
+create table scim_users
+(
+    user_id     uuid primary key references users (id) on delete cascade,
+    tenant      text        not null,
+    external_id text,
+    created_at  timestamptz not null default now(),
+    updated_at  timestamptz not null default now()
+);
+
+create index scim_users_tenant_idx on scim_users (tenant);
+
+create table groups
+(
+    id           uuid primary key,
+    tenant       text        not null,
+    display_name text        not null,
+    external_id  text,
+    created_at   timestamptz not null default now(),
+    updated_at   timestamptz not null default now(),
+    unique (tenant, display_name)
+);
+
+create table group_members
+(
+    group_id   uuid        not null references groups (id) on delete cascade,
+    user_id    uuid        not null references users (id) on delete cascade,
+    created_at timestamptz not null default now(),
+    primary key (group_id, user_id)
+);
+
+create index group_members_user_id_idx on group_members (user_id);
@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+drop table role_assignments;
+drop table roles;
\ No newline at end of file
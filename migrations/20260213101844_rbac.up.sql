@@ -0,0 +1,22 @@
+-- Add up migration script here
+
+create table roles
+(
+    id          uuid primary key,
+    name        text        not null unique,
+    description text        not null default '',
+    permissions text[]      not null default '{}',
+    created_at  timestamptz not null default now()
+);
+
+create table role_assignments
+(
+    id              uuid primary key,
+    user_id         uuid        not null references users (id) on delete cascade,
+    role_id         uuid        not null references roles (id) on delete cascade,
+    organization_id uuid,
+    created_at      timestamptz not null default now(),
+    unique nulls not distinct (user_id, role_id, organization_id)
+);
+
+create index role_assignments_role_id_idx on role_assignments (role_id);
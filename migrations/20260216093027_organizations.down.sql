@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+drop table organization_members;
+drop table organizations;
+drop type membership_role;
\ No newline at end of file
@@ -0,0 +1,25 @@
+-- Add up migration script here
+
+create type membership_role as enum ('owner', 'admin', 'member');
+
+create table organizations
+(
+    id         uuid primary key,
+    slug       text        not null unique,
+    name       text        not null,
+    domains    text[]      not null default '{}',
+    created_at timestamptz not null default now()
+);
+
+create index organizations_domains_idx on organizations using gin (domains);
+
+create table organization_members
+(
+    tenant_id  uuid            not null references organizations (id) on delete cascade,
+    user_id    uuid            not null references users (id) on delete cascade,
+    role       membership_role not null default 'member',
+    created_at timestamptz     not null default now(),
+    primary key (tenant_id, user_id)
+);
+
+create index organization_members_user_id_idx on organization_members (user_id);
@@ -0,0 +1,3 @@
+-- Add down migration script here
+
+drop table organization_invitations;
\ No newline at end of file
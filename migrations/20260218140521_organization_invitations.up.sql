@@ -0,0 +1,15 @@
+-- Add up migration script here
+
+create table organization_invitations
+(
+    id         uuid primary key,
+    tenant_id  uuid            not null references organizations (id) on delete cascade,
+    email      text            not null,
+    role       membership_role not null default 'member',
+    token_hash bytea           not null unique,
+    invited_by uuid            references users (id) on delete set null,
+    expires_at timestamptz     not null,
+    created_at timestamptz     not null default now()
+);
+
+create unique index organization_invitations_tenant_id_email_idx on organization_invitations (tenant_id, lower(email));
@@ -0,0 +1,6 @@
+-- Add down migration script here
+
+delete from oauth2_authorization_codes where redirect_uri is null;
+
+alter table oauth2_authorization_codes
+    alter column redirect_uri set not null;
\ No newline at end of file
@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+-- null when the authorization request left the redirect uri out
+alter table oauth2_authorization_codes
+    alter column redirect_uri drop not null;
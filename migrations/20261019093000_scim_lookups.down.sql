@@ -0,0 +1,9 @@
+-- Add down migration script here
+
+drop index groups_tenant_external_id_idx;
+drop index groups_tenant_display_name_idx;
+drop index groups_tenant_created_at_idx;
+
+drop index users_lower_username_idx;
+drop index scim_users_tenant_external_id_idx;
+drop index scim_users_tenant_created_at_idx;
\ No newline at end of file
@@ -0,0 +1,10 @@
+-- Add up migration script here
+
+-- lists page through resources in the order they were created, and look them up by these without regard to case
+create index scim_users_tenant_created_at_idx on scim_users (tenant, created_at, user_id);
+create index scim_users_tenant_external_id_idx on scim_users (tenant, lower(external_id));
+create index users_lower_username_idx on users (lower(username));
+
+create index groups_tenant_created_at_idx on groups (tenant, created_at, id);
+create index groups_tenant_display_name_idx on groups (tenant, lower(display_name));
+create index groups_tenant_external_id_idx on groups (tenant, lower(external_id));
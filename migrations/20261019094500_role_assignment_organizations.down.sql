@@ -0,0 +1,4 @@
+-- Add down migration script here
+
+alter table role_assignments
+    drop constraint role_assignments_organization_id_fkey;
\ No newline at end of file
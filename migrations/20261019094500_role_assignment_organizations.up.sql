@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+alter table role_assignments
+    add constraint role_assignments_organization_id_fkey
+        foreign key (organization_id) references organizations (id) on delete cascade;
@@ -0,0 +1,5 @@
+-- Add down migration script here
+
+alter table oauth2_authorization_codes
+    drop column session_id,
+    drop column nonce;
\ No newline at end of file
@@ -0,0 +1,9 @@
+-- Add up migration script here
+
+-- outstanding codes have no session to put in their id tokens, they expire within minutes anyway
+delete from oauth2_authorization_codes;
+
+-- id tokens name the login session the code was issued in, ending it ends the code too
+alter table oauth2_authorization_codes
+    add column session_id uuid not null references sessions (id) on delete cascade,
+    add column nonce      text;
@@ -0,0 +1,5 @@
+-- Add up migration script here
+
+-- the organization the token endpoint was called for, its roles are the token's too
+alter table oauth2_access_tokens
+    add column organization_id uuid references organizations (id) on delete cascade;
@@ -0,0 +1,45 @@
+-- Add down migration script here
+
+alter table role_assignments
+    rename constraint role_assignments_tenant_id_fkey to role_assignments_organization_id_fkey;
+alter table role_assignments
+    rename column tenant_id to organization_id;
+
+drop index groups_tenant_external_id_idx;
+drop index groups_tenant_display_name_idx;
+drop index groups_tenant_created_at_idx;
+drop index scim_users_tenant_external_id_idx;
+drop index scim_users_tenant_created_at_idx;
+drop index scim_tokens_tenant_id_idx;
+
+alter table scim_tokens
+    add column tenant text;
+alter table scim_users
+    add column tenant text;
+alter table groups
+    add column tenant text;
+
+update scim_tokens
+set tenant = (select coalesce(domains[1], slug) from organizations where id = tenant_id);
+update scim_users
+set tenant = (select coalesce(domains[1], slug) from organizations where id = tenant_id);
+update groups
+set tenant = (select coalesce(domains[1], slug) from organizations where id = tenant_id);
+
+alter table scim_tokens
+    alter column tenant set not null,
+    drop column tenant_id;
+alter table scim_users
+    alter column tenant set not null,
+    drop column tenant_id;
+alter table groups
+    alter column tenant set not null,
+    drop column tenant_id,
+    add unique (tenant, display_name);
+
+create index scim_users_tenant_idx on scim_users (tenant);
+create index scim_users_tenant_created_at_idx on scim_users (tenant, created_at, user_id);
+create index scim_users_tenant_external_id_idx on scim_users (tenant, lower(external_id));
+create index groups_tenant_created_at_idx on groups (tenant, created_at, id);
+create index groups_tenant_display_name_idx on groups (tenant, lower(display_name));
+create index groups_tenant_external_id_idx on groups (tenant, lower(external_id));
\ No newline at end of file
@@ -0,0 +1,47 @@
+-- Add up migration script here
+
+-- SCIM tenants were the host names their users sign in at. Each becomes the organization with that domain, host
+-- names no organization has yet get one of their own
+insert into organizations (id, slug, name, domains)
+select gen_random_uuid(), tenant, tenant, array [lower(tenant)]
+from (select tenant from scim_tokens union select tenant from scim_users union select tenant from groups) tenants
+where not exists (select 1 from organizations where domains @> array [lower(tenant)])
+on conflict (slug) do nothing;
+
+alter table scim_tokens
+    add column tenant_id uuid references organizations (id) on delete cascade;
+alter table scim_users
+    add column tenant_id uuid references organizations (id) on delete cascade;
+alter table groups
+    add column tenant_id uuid references organizations (id) on delete cascade;
+
+update scim_tokens
+set tenant_id = (select id from organizations where domains @> array [lower(tenant)] order by created_at limit 1);
+update scim_users
+set tenant_id = (select id from organizations where domains @> array [lower(tenant)] order by created_at limit 1);
+update groups
+set tenant_id = (select id from organizations where domains @> array [lower(tenant)] order by created_at limit 1);
+
+alter table scim_tokens
+    alter column tenant_id set not null,
+    drop column tenant;
+alter table scim_users
+    alter column tenant_id set not null,
+    drop column tenant;
+alter table groups
+    alter column tenant_id set not null,
+    drop column tenant,
+    add unique (tenant_id, display_name);
+
+create index scim_tokens_tenant_id_idx on scim_tokens (tenant_id);
+create index scim_users_tenant_created_at_idx on scim_users (tenant_id, created_at, user_id);
+create index scim_users_tenant_external_id_idx on scim_users (tenant_id, lower(external_id));
+create index groups_tenant_created_at_idx on groups (tenant_id, created_at, id);
+create index groups_tenant_display_name_idx on groups (tenant_id, lower(display_name));
+create index groups_tenant_external_id_idx on groups (tenant_id, lower(external_id));
+
+-- role assignments limited to an organization are scoped to it like everything else of it
+alter table role_assignments
+    rename column organization_id to tenant_id;
+alter table role_assignments
+    rename constraint role_assignments_organization_id_fkey to role_assignments_tenant_id_fkey;
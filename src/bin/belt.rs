@@ -3,6 +3,7 @@ use meow_auth::cli::Run;
 
 #[tokio::main]
 async fn main() {
+    #[cfg(windows)]
     let _ = nu_ansi_term::enable_ansi_support();
     // https://github.com/crate-ci/typos/blob/master/crates/typos-cli/src/bin/typos-cli/main.rs#L14C33-L15C5
     human_panic::setup_panic!();
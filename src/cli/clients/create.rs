@@ -0,0 +1,250 @@
+use crate::cli::Run;
+use crate::database::models::client::{
+    self, AccessTokenFormat, ApplicationType, ClientAuthMethod, DBClient, SubjectType,
+};
+use crate::settings::Settings;
+use clap::Parser;
+use jsonwebtoken::jwk::JwkSet;
+use sqlx::types::Json;
+use sqlx::{Connection, PgConnection};
+use std::path::PathBuf;
+
+/// Register a new OAuth2 client
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct CreateClient {
+    /// Human readable name of the client
+    #[clap(short, long)]
+    name: String,
+
+    /// How the client authenticates at the token endpoint
+    #[clap(short, long, value_enum, default_value = "client-secret-basic")]
+    auth_method: ClientAuthMethod,
+
+    /// Grant type the client is allowed to use. Can be repeated
+    #[clap(short, long = "grant-type")]
+    grant_types: Vec<String>,
+
+    /// Scope the client is allowed to request. Can be repeated
+    #[clap(short, long = "scope")]
+    scopes: Vec<String>,
+
+    /// Audience the client's tokens are restricted to. Can be repeated
+    #[clap(long = "audience")]
+    audiences: Vec<String>,
+
+    /// Audience whose tokens the client may introspect, for resource servers. Can be repeated
+    #[clap(long = "introspect-audience")]
+    introspect_audiences: Vec<String>,
+
+    /// Redirect uri the client may use at the authorization endpoint. Can be repeated
+    #[clap(short, long = "redirect-uri")]
+    redirect_uris: Vec<String>,
+
+    /// Where the client may send users after they logged out. Can be repeated
+    #[clap(long = "post-logout-redirect-uri")]
+    post_logout_redirect_uris: Vec<String>,
+
+    /// Loaded in a hidden iframe when the user logs out
+    #[clap(long)]
+    frontchannel_logout_uri: Option<String>,
+
+    /// Receives a logout token when the user logs out
+    #[clap(long)]
+    backchannel_logout_uri: Option<String>,
+
+    /// Native apps are public clients redirecting to loopback, private-use scheme or claimed https uris
+    #[clap(long, value_enum, default_value = "web")]
+    application_type: ApplicationType,
+
+    /// Which sub the client gets to see for users
+    #[clap(long, value_enum, default_value = "public")]
+    subject_type: SubjectType,
+
+    /// Json array of the redirect uris of every client in the sector, they all see the same pairwise subjects
+    #[clap(long)]
+    sector_identifier_uri: Option<String>,
+
+    /// Trust the client, users won't be asked for consent
+    #[clap(long)]
+    first_party: bool,
+
+    /// Only accept authorization requests pushed to the PAR endpoint first
+    #[clap(long)]
+    require_par: bool,
+
+    /// Only issue access tokens bound to a DPoP key
+    #[clap(long)]
+    dpop: bool,
+
+    /// What issued access tokens look like. Resources the tokens are for can override this
+    #[clap(long, value_enum, default_value = "opaque")]
+    access_token_format: AccessTokenFormat,
+
+    /// Path to a JWK Set file with the client's public keys. Required for private_key_jwt, unless --jwks-uri is given
+    #[clap(long, conflicts_with = "jwks_uri")]
+    jwks: Option<PathBuf>,
+
+    /// Where the client publishes its public keys, for clients rotating them on their own
+    #[clap(long)]
+    jwks_uri: Option<String>,
+
+    /// Uri request objects may be fetched from. Can be repeated
+    #[clap(long = "request-uri")]
+    request_uris: Vec<String>,
+}
+
+impl Run for CreateClient {
+    async fn run(&self) -> anyhow::Result<()> {
+        let jwks = match &self.jwks {
+            Some(path) => Some(serde_json::from_str::<JwkSet>(
+                &tokio::fs::read_to_string(path).await?,
+            )?),
+            None => None,
+        };
+
+        if self.auth_method == ClientAuthMethod::PrivateKeyJwt
+            && jwks.is_none()
+            && self.jwks_uri.is_none()
+        {
+            anyhow::bail!(
+                "Clients using private_key_jwt need their keys. Pass them with --jwks or --jwks-uri"
+            );
+        }
+
+        if self.application_type == ApplicationType::Native {
+            self.check_native()?;
+        }
+
+        if self.subject_type == SubjectType::Pairwise {
+            self.check_sector().await?;
+        }
+
+        let secret = matches!(
+            self.auth_method,
+            ClientAuthMethod::ClientSecretBasic | ClientAuthMethod::ClientSecretPost
+        )
+        .then(crate::crypto::generate_token);
+
+        let client = DBClient::builder()
+            .name(self.name.clone())
+            .secret_hash(secret.as_deref().map(crate::crypto::hash_token))
+            .token_endpoint_auth_method(self.auth_method)
+            .grant_types(self.grant_types.clone())
+            .scopes(self.scopes.clone())
+            .audiences(self.audiences.clone())
+            .jwks(jwks.map(Json))
+            .jwks_uri(self.jwks_uri.clone())
+            .request_uris(self.request_uris.clone())
+            .introspect_audiences(self.introspect_audiences.clone())
+            .redirect_uris(self.redirect_uris.clone())
+            .post_logout_redirect_uris(self.post_logout_redirect_uris.clone())
+            .frontchannel_logout_uri(self.frontchannel_logout_uri.clone())
+            .backchannel_logout_uri(self.backchannel_logout_uri.clone())
+            .application_type(self.application_type)
+            .subject_type(self.subject_type)
+            .sector_identifier_uri(self.sector_identifier_uri.clone())
+            .first_party(self.first_party)
+            .require_pushed_authorization_requests(self.require_par)
+            .dpop_bound_access_tokens(self.dpop)
+            .access_token_format(self.access_token_format)
+            .build();
+
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        client.insert(&mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        println!("Created client '{}'", client.name);
+        println!("client_id: {}", client.id);
+        if let Some(secret) = secret {
+            println!("client_secret: {secret}");
+            println!("Store the secret somewhere safe, it cannot be shown again.");
+        }
+
+        Ok(())
+    }
+}
+
+impl CreateClient {
+    /// Native apps can't keep secrets, and only receive redirects on the device. (RFC 8252 sections 7 and 8.4)
+    fn check_native(&self) -> anyhow::Result<()> {
+        if self.auth_method.is_confidential() {
+            anyhow::bail!("Native clients are public, pass --auth-method none");
+        }
+
+        for uri in &self.redirect_uris {
+            let url = url::Url::parse(uri)?;
+            if !(url.scheme() == "https"
+                || client::is_loopback_redirect(&url)
+                || client::is_private_use_redirect(&url))
+            {
+                anyhow::bail!(
+                    "Native clients redirect to http://127.0.0.1 or http://[::1], a reverse domain name scheme or https, not {uri}"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Pairwise subjects are the same for every client sharing a sector. (OpenID Connect Core 1.0 section 8.1)
+    ///
+    /// Without a sector identifier uri the redirect uris are the sector, so they have to share a host. Loopback and
+    /// private-use scheme uris don't name a sector, every native app can use them. With one, the document it points
+    /// to has to list every redirect uri.
+    async fn check_sector(&self) -> anyhow::Result<()> {
+        let Some(sector_identifier_uri) = &self.sector_identifier_uri else {
+            let mut hosts = Vec::new();
+            for uri in &self.redirect_uris {
+                let url = url::Url::parse(uri)?;
+                let host = url
+                    .host_str()
+                    .filter(|_| !client::is_loopback_redirect(&url))
+                    .filter(|_| !client::is_private_use_redirect(&url))
+                    .filter(|host| *host != "localhost");
+                let Some(host) = host else {
+                    anyhow::bail!(
+                        "{uri} is no sector of its own, pairwise clients redirecting there need a --sector-identifier-uri"
+                    );
+                };
+                hosts.push(host.to_string());
+            }
+            hosts.sort();
+            hosts.dedup();
+
+            if hosts.len() > 1 {
+                anyhow::bail!(
+                    "Redirect uris on different hosts need a --sector-identifier-uri for pairwise subjects"
+                );
+            }
+
+            return Ok(());
+        };
+
+        let url = url::Url::parse(sector_identifier_uri)?;
+        if url.scheme() != "https" || url.host_str().is_none() {
+            anyhow::bail!("The sector identifier uri has to use https");
+        }
+
+        let listed: Vec<String> = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()?
+            .get(url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!("The sector identifier uri has to return a json array of uris")
+            })?;
+        if let Some(missing) = self.redirect_uris.iter().find(|uri| !listed.contains(uri)) {
+            anyhow::bail!("The sector identifier uri does not list the redirect uri {missing}");
+        }
+
+        Ok(())
+    }
+}
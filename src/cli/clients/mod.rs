@@ -0,0 +1,31 @@
+use crate::cli::HelpTemplate;
+use crate::cli::Run;
+use clap::{Parser, Subcommand};
+mod create;
+
+/// OAuth2 client related commands
+#[derive(Parser, Default)]
+#[clap(author, help_template = HelpTemplate, arg_required_else_help(true))]
+pub struct Clients {
+    #[clap(subcommand)]
+    pub command: Option<ClientsCommand>,
+}
+
+impl Run for Clients {
+    async fn run(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.command {
+            match cmd {
+                ClientsCommand::Create(create) => create.run().await,
+            }
+        } else {
+            println!("No clients command provided. Use --help for more information.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ClientsCommand {
+    // boxed, the client options would blow up the size of every other command
+    Create(Box<create::CreateClient>),
+}
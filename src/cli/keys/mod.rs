@@ -0,0 +1,30 @@
+use crate::cli::HelpTemplate;
+use crate::cli::Run;
+use clap::{Parser, Subcommand};
+mod rotate;
+
+/// Signing key related commands
+#[derive(Parser, Default)]
+#[clap(author, help_template = HelpTemplate, arg_required_else_help(true))]
+pub struct Keys {
+    #[clap(subcommand)]
+    pub command: Option<KeysCommand>,
+}
+
+impl Run for Keys {
+    async fn run(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.command {
+            match cmd {
+                KeysCommand::Rotate(rotate) => rotate.run().await,
+            }
+        } else {
+            println!("No keys command provided. Use --help for more information.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum KeysCommand {
+    Rotate(rotate::RotateKey),
+}
@@ -0,0 +1,25 @@
+use crate::cli::Run;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::{Connection, PgConnection};
+
+/// Generate a new signing key. Servers sign with it after a restart, older keys stay published
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct RotateKey;
+
+impl Run for RotateKey {
+    async fn run(&self) -> anyhow::Result<()> {
+        let key = crate::keys::generate()?;
+
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        key.insert(&mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        println!("Created signing key {} ({})", key.id, key.algorithm);
+        Ok(())
+    }
+}
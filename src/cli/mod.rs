@@ -4,8 +4,17 @@ use console::Term;
 use dialoguer::Confirm;
 use tokio::task;
 
+mod clients;
 mod database;
+mod keys;
+mod organizations;
+mod resources;
+mod roles;
+mod saml;
+mod scim;
+mod scopes;
 mod settings;
+mod users;
 
 pub trait Run {
     #[allow(async_fn_in_trait)]
@@ -39,6 +48,18 @@ pub enum Commands {
     Settings(settings::Settings),
     #[clap(alias = "db")]
     Database(database::Database),
+    #[clap(alias = "c")]
+    Clients(clients::Clients),
+    #[clap(alias = "u")]
+    Users(users::Users),
+    Scopes(scopes::Scopes),
+    #[clap(alias = "orgs")]
+    Organizations(organizations::Organizations),
+    Resources(resources::Resources),
+    Roles(roles::Roles),
+    Keys(keys::Keys),
+    Saml(saml::Saml),
+    Scim(scim::Scim),
 }
 
 impl Run for Commands {
@@ -46,6 +67,15 @@ impl Run for Commands {
         match self {
             Self::Settings(settings) => settings.run().await,
             Self::Database(database) => database.run().await,
+            Self::Clients(clients) => clients.run().await,
+            Self::Users(users) => users.run().await,
+            Self::Scopes(scopes) => scopes.run().await,
+            Self::Organizations(organizations) => organizations.run().await,
+            Self::Resources(resources) => resources.run().await,
+            Self::Roles(roles) => roles.run().await,
+            Self::Keys(keys) => keys.run().await,
+            Self::Saml(saml) => saml.run().await,
+            Self::Scim(scim) => scim.run().await,
         }
     }
 }
@@ -0,0 +1,59 @@
+use crate::cli::Run;
+use crate::database::models::organization::DBOrganization;
+use crate::database::models::organization_member::{DBOrganizationMember, MembershipRole};
+use crate::database::models::user::DBUser;
+use crate::database::tenant::Tenant;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::PgPool;
+
+/// Make a user a member of an organization, or change their role if they already are
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct AddMember {
+    /// Slug of the organization
+    #[clap(short, long)]
+    organization: String,
+
+    /// Name the user signs in with
+    #[clap(short, long)]
+    username: String,
+
+    #[clap(short, long, value_enum, default_value = "member")]
+    role: MembershipRole,
+}
+
+impl Run for AddMember {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let pool = PgPool::connect(&settings.postgres_db.uri).await?;
+
+        let Some(organization) = DBOrganization::find_by_slug(&self.organization, &pool).await?
+        else {
+            anyhow::bail!("There is no organization '{}'", self.organization);
+        };
+        let Some(user) = DBUser::find_by_username(&self.username, &pool).await? else {
+            anyhow::bail!("There is no user named '{}'", self.username);
+        };
+
+        let tenant = Tenant::new(organization);
+        let member = DBOrganizationMember::builder()
+            .tenant_id(tenant.id())
+            .user_id(user.id)
+            .role(self.role)
+            .build();
+        let mut transaction = pool.begin().await?;
+        member.insert(&mut transaction).await?;
+        member.update_role(&mut transaction).await?;
+        transaction.commit().await?;
+        pool.close().await;
+
+        println!(
+            "'{}' is a member of '{}' with role {:?}",
+            user.username,
+            tenant.organization().slug,
+            self.role
+        );
+        Ok(())
+    }
+}
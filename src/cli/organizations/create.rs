@@ -0,0 +1,61 @@
+use crate::cli::Run;
+use crate::database::models::organization::DBOrganization;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::PgPool;
+
+/// Add an organization to the database
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct CreateOrganization {
+    /// Names the organization in the path prefix and the organization header, like `acme`
+    #[clap(short, long)]
+    slug: String,
+
+    /// Shown to users
+    #[clap(short, long)]
+    name: String,
+
+    /// Host name requests for the organization come in at, can be repeated
+    #[clap(short, long = "domain")]
+    domains: Vec<String>,
+}
+
+impl Run for CreateOrganization {
+    async fn run(&self) -> anyhow::Result<()> {
+        if self.slug.is_empty() || self.slug.contains('/') {
+            anyhow::bail!("A slug can't be empty or contain '/'");
+        }
+
+        let organization = DBOrganization::builder()
+            .slug(self.slug.clone())
+            .name(self.name.clone())
+            .domains(
+                self.domains
+                    .iter()
+                    .map(|domain| domain.to_lowercase())
+                    .collect(),
+            )
+            .build();
+
+        let settings = Settings::parse()?;
+        let pool = PgPool::connect(&settings.postgres_db.uri).await?;
+
+        for domain in &organization.domains {
+            if let Some(other) = DBOrganization::find_by_domain(domain, &pool).await? {
+                anyhow::bail!("{domain} already belongs to '{}'", other.slug);
+            }
+        }
+
+        let mut transaction = pool.begin().await?;
+        organization.insert(&mut transaction).await?;
+        transaction.commit().await?;
+        pool.close().await;
+
+        println!(
+            "Created organization '{}' ({})",
+            organization.slug, organization.id
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use crate::cli::Run;
+use crate::database::models::organization::DBOrganization;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::{Connection, PgConnection};
+
+/// Remove an organization from the database, with its members and everything else it has
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct DeleteOrganization {
+    /// Slug of the organization
+    #[clap(short, long)]
+    slug: String,
+}
+
+impl Run for DeleteOrganization {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        let deleted = DBOrganization::delete_by_slug(&self.slug, &mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        if !deleted {
+            anyhow::bail!("There is no organization '{}'", self.slug);
+        }
+
+        println!("Deleted organization '{}'", self.slug);
+        Ok(())
+    }
+}
@@ -0,0 +1,39 @@
+use crate::cli::HelpTemplate;
+use crate::cli::Run;
+use clap::{Parser, Subcommand};
+mod add_member;
+mod create;
+mod delete;
+mod remove_member;
+
+/// Organizations served by the deployment and their members
+#[derive(Parser, Default)]
+#[clap(author, help_template = HelpTemplate, arg_required_else_help(true))]
+pub struct Organizations {
+    #[clap(subcommand)]
+    pub command: Option<OrganizationsCommand>,
+}
+
+impl Run for Organizations {
+    async fn run(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.command {
+            match cmd {
+                OrganizationsCommand::Create(create) => create.run().await,
+                OrganizationsCommand::Delete(delete) => delete.run().await,
+                OrganizationsCommand::AddMember(add) => add.run().await,
+                OrganizationsCommand::RemoveMember(remove) => remove.run().await,
+            }
+        } else {
+            println!("No organizations command provided. Use --help for more information.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum OrganizationsCommand {
+    Create(create::CreateOrganization),
+    Delete(delete::DeleteOrganization),
+    AddMember(add_member::AddMember),
+    RemoveMember(remove_member::RemoveMember),
+}
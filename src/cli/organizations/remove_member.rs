@@ -0,0 +1,55 @@
+use crate::cli::Run;
+use crate::database::models::organization::DBOrganization;
+use crate::database::models::organization_member::DBOrganizationMember;
+use crate::database::models::role_assignment::DBRoleAssignment;
+use crate::database::models::user::DBUser;
+use crate::database::tenant::Tenant;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::PgPool;
+
+/// Take a user out of an organization, with the roles they have there
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct RemoveMember {
+    /// Slug of the organization
+    #[clap(short, long)]
+    organization: String,
+
+    /// Name the user signs in with
+    #[clap(short, long)]
+    username: String,
+}
+
+impl Run for RemoveMember {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let pool = PgPool::connect(&settings.postgres_db.uri).await?;
+
+        let Some(organization) = DBOrganization::find_by_slug(&self.organization, &pool).await?
+        else {
+            anyhow::bail!("There is no organization '{}'", self.organization);
+        };
+        let Some(user) = DBUser::find_by_username(&self.username, &pool).await? else {
+            anyhow::bail!("There is no user named '{}'", self.username);
+        };
+
+        let tenant = Tenant::new(organization);
+        let mut transaction = pool.begin().await?;
+        let deleted = DBOrganizationMember::delete(&tenant, user.id, &mut transaction).await?;
+        DBRoleAssignment::delete_by_user_in_tenant(&tenant, user.id, &mut transaction).await?;
+        transaction.commit().await?;
+        pool.close().await;
+
+        if !deleted {
+            anyhow::bail!(
+                "'{}' is not a member of '{}'",
+                user.username,
+                self.organization
+            );
+        }
+
+        println!("Removed '{}' from '{}'", user.username, self.organization);
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+use crate::cli::Run;
+use crate::database::models::client::AccessTokenFormat;
+use crate::database::models::resource::DBResource;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::{Connection, PgConnection};
+
+/// Register a protected resource, identified by the audience its tokens carry. Clients request tokens for it with the resource parameter
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct CreateResource {
+    /// Audience of the resource's tokens, usually its base url
+    #[clap(short, long)]
+    identifier: String,
+
+    /// Access token format the resource wants, overriding the one the client chose
+    #[clap(long, value_enum)]
+    access_token_format: Option<AccessTokenFormat>,
+
+    /// Scope tokens for the resource may carry, others are left out. Can be repeated, any scope when not given
+    #[clap(short, long = "scope")]
+    scopes: Vec<String>,
+
+    /// Lifetime of access tokens for the resource in seconds, when shorter than the default
+    #[clap(long)]
+    access_token_lifetime: Option<i64>,
+}
+
+impl Run for CreateResource {
+    async fn run(&self) -> anyhow::Result<()> {
+        let resource = DBResource::builder()
+            .identifier(self.identifier.clone())
+            .access_token_format(self.access_token_format)
+            .scopes(self.scopes.clone())
+            .access_token_lifetime(self.access_token_lifetime)
+            .build();
+
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        resource.insert(&mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        println!(
+            "Created resource '{}' ({})",
+            resource.identifier, resource.id
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,30 @@
+use crate::cli::HelpTemplate;
+use crate::cli::Run;
+use clap::{Parser, Subcommand};
+mod create;
+
+/// Protected resource related commands
+#[derive(Parser, Default)]
+#[clap(author, help_template = HelpTemplate, arg_required_else_help(true))]
+pub struct Resources {
+    #[clap(subcommand)]
+    pub command: Option<ResourcesCommand>,
+}
+
+impl Run for Resources {
+    async fn run(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.command {
+            match cmd {
+                ResourcesCommand::Create(create) => create.run().await,
+            }
+        } else {
+            println!("No resources command provided. Use --help for more information.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ResourcesCommand {
+    Create(create::CreateResource),
+}
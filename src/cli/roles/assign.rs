@@ -0,0 +1,67 @@
+use crate::cli::Run;
+use crate::database::models::organization::DBOrganization;
+use crate::database::models::role::DBRole;
+use crate::database::models::role_assignment::DBRoleAssignment;
+use crate::database::models::user::DBUser;
+use crate::database::tenant::Tenant;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::PgPool;
+
+/// Give a user a role, everywhere or within one organization
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct AssignRole {
+    /// Name the user signs in with
+    #[clap(short, long)]
+    username: String,
+
+    /// Name of the role
+    #[clap(short, long)]
+    role: String,
+
+    /// Slug of the organization the role is limited to. Leave out for everywhere
+    #[clap(short, long)]
+    organization: Option<String>,
+}
+
+impl Run for AssignRole {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let pool = PgPool::connect(&settings.postgres_db.uri).await?;
+
+        let Some(user) = DBUser::find_by_username(&self.username, &pool).await? else {
+            anyhow::bail!("There is no user named '{}'", self.username);
+        };
+        let Some(role) = DBRole::find_by_name(&self.role, &pool).await? else {
+            anyhow::bail!("There is no role named '{}'", self.role);
+        };
+        let tenant = match &self.organization {
+            Some(slug) => match DBOrganization::find_by_slug(slug, &pool).await? {
+                Some(organization) => Some(Tenant::new(organization)),
+                None => anyhow::bail!("There is no organization '{slug}'"),
+            },
+            None => None,
+        };
+
+        let mut transaction = pool.begin().await?;
+        DBRoleAssignment::builder()
+            .user_id(user.id)
+            .role_id(role.id)
+            .tenant_id(tenant.as_ref().map(Tenant::id))
+            .build()
+            .insert(&mut transaction)
+            .await?;
+        transaction.commit().await?;
+        pool.close().await;
+
+        match &self.organization {
+            Some(organization) => println!(
+                "'{}' has role '{}' in '{organization}'",
+                user.username, role.name
+            ),
+            None => println!("'{}' has role '{}'", user.username, role.name),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use crate::cli::Run;
+use crate::database::models::role::DBRole;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::{Connection, PgConnection};
+
+/// Add a role to the database
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct CreateRole {
+    /// Name the role is assigned by
+    #[clap(short, long)]
+    name: String,
+
+    /// What the role is for
+    #[clap(short, long, default_value = "")]
+    description: String,
+
+    /// Permission granted by the role, like `roles:read`, can be repeated
+    #[clap(short, long = "permission")]
+    permissions: Vec<String>,
+}
+
+impl Run for CreateRole {
+    async fn run(&self) -> anyhow::Result<()> {
+        let role = DBRole::builder()
+            .name(self.name.clone())
+            .description(self.description.clone())
+            .permissions(self.permissions.clone())
+            .build();
+
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        role.insert(&mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        println!("Created role '{}'", role.name);
+        Ok(())
+    }
+}
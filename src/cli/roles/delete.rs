@@ -0,0 +1,32 @@
+use crate::cli::Run;
+use crate::database::models::role::DBRole;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::{Connection, PgConnection};
+
+/// Remove a role from the database, and from everyone who has it
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct DeleteRole {
+    /// Name of the role
+    #[clap(short, long)]
+    name: String,
+}
+
+impl Run for DeleteRole {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        let deleted = DBRole::delete_by_name(&self.name, &mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        if !deleted {
+            anyhow::bail!("There is no role named '{}'", self.name);
+        }
+
+        println!("Deleted role '{}'", self.name);
+        Ok(())
+    }
+}
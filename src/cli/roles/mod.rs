@@ -0,0 +1,39 @@
+use crate::cli::HelpTemplate;
+use crate::cli::Run;
+use clap::{Parser, Subcommand};
+mod assign;
+mod create;
+mod delete;
+mod unassign;
+
+/// Roles, the permissions they grant and who has them
+#[derive(Parser, Default)]
+#[clap(author, help_template = HelpTemplate, arg_required_else_help(true))]
+pub struct Roles {
+    #[clap(subcommand)]
+    pub command: Option<RolesCommand>,
+}
+
+impl Run for Roles {
+    async fn run(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.command {
+            match cmd {
+                RolesCommand::Create(create) => create.run().await,
+                RolesCommand::Delete(delete) => delete.run().await,
+                RolesCommand::Assign(assign) => assign.run().await,
+                RolesCommand::Unassign(unassign) => unassign.run().await,
+            }
+        } else {
+            println!("No roles command provided. Use --help for more information.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum RolesCommand {
+    Create(create::CreateRole),
+    Delete(delete::DeleteRole),
+    Assign(assign::AssignRole),
+    Unassign(unassign::UnassignRole),
+}
@@ -0,0 +1,130 @@
+use crate::cli::Run;
+use crate::database::models::saml_service_provider::{DBSamlServiceProvider, SamlBinding};
+use crate::saml::metadata::ServiceProviderMetadata;
+use crate::saml::{HTTP_POST, NAME_ID_EMAIL, NAME_ID_PERSISTENT, NAME_ID_UNSPECIFIED};
+use crate::settings::Settings;
+use clap::{Parser, ValueEnum};
+use sqlx::types::Json;
+use sqlx::{Connection, PgConnection};
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum NameIdFormat {
+    /// A pairwise identifier only this service provider knows the user by
+    Persistent,
+    /// The user's email address
+    Email,
+    /// The username
+    Unspecified,
+}
+
+impl NameIdFormat {
+    fn urn(self) -> &'static str {
+        match self {
+            Self::Persistent => NAME_ID_PERSISTENT,
+            Self::Email => NAME_ID_EMAIL,
+            Self::Unspecified => NAME_ID_UNSPECIFIED,
+        }
+    }
+}
+
+/// Register a SAML service provider from its metadata, so users can sign into it
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct ImportServiceProvider {
+    /// Path or url of the service provider's metadata
+    #[clap(short, long)]
+    metadata: String,
+
+    /// Human readable name of the service provider, its entity id when not given
+    #[clap(short, long)]
+    name: Option<String>,
+
+    /// What users are known by, the first format the metadata lists or persistent when not given
+    #[clap(long, value_enum)]
+    name_id_format: Option<NameIdFormat>,
+
+    /// Attribute to release as NAME=CLAIM, filled from the user's claim. Can be repeated
+    #[clap(short, long = "attribute", value_parser = parse_attribute)]
+    attributes: Vec<(String, String)>,
+}
+
+fn parse_attribute(value: &str) -> Result<(String, String), String> {
+    value
+        .split_once('=')
+        .filter(|(name, claim)| !name.is_empty() && !claim.is_empty())
+        .map(|(name, claim)| (name.to_string(), claim.to_string()))
+        .ok_or_else(|| format!("expected NAME=CLAIM, got {value}"))
+}
+
+impl Run for ImportServiceProvider {
+    async fn run(&self) -> anyhow::Result<()> {
+        let xml = if self.metadata.starts_with("https://") || self.metadata.starts_with("http://") {
+            reqwest::get(&self.metadata)
+                .await?
+                .error_for_status()?
+                .text()
+                .await?
+        } else {
+            tokio::fs::read_to_string(&self.metadata).await?
+        };
+        let metadata = ServiceProviderMetadata::parse(&xml)?;
+
+        if metadata.authn_requests_signed && metadata.signing_certificate.is_none() {
+            anyhow::bail!(
+                "The service provider signs its requests, but its metadata has no signing certificate"
+            );
+        }
+
+        let name_id_format = match self.name_id_format {
+            Some(format) => format.urn().to_string(),
+            None => metadata
+                .name_id_format
+                .clone()
+                .unwrap_or_else(|| NAME_ID_PERSISTENT.to_string()),
+        };
+        let (slo_url, slo_binding) = match metadata.single_logout_service {
+            Some((location, binding)) if binding == HTTP_POST => {
+                (Some(location), SamlBinding::Post)
+            }
+            Some((location, _)) => (Some(location), SamlBinding::Redirect),
+            None => (None, SamlBinding::Redirect),
+        };
+
+        let service_provider = DBSamlServiceProvider::builder()
+            .name(
+                self.name
+                    .clone()
+                    .unwrap_or_else(|| metadata.entity_id.clone()),
+            )
+            .entity_id(metadata.entity_id)
+            .acs_url(metadata.assertion_consumer_service_url)
+            .slo_url(slo_url)
+            .slo_binding(slo_binding)
+            .certificate(metadata.signing_certificate)
+            .name_id_format(name_id_format)
+            .attributes(Json(
+                self.attributes.iter().cloned().collect::<BTreeMap<_, _>>(),
+            ))
+            .authn_requests_signed(metadata.authn_requests_signed)
+            .build();
+
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        service_provider.insert(&mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        println!(
+            "Imported service provider '{}' ({})",
+            service_provider.name, service_provider.entity_id
+        );
+        if service_provider.slo_url.is_none() {
+            println!(
+                "Its metadata has no single logout service, it won't be told when users sign out."
+            );
+        }
+        Ok(())
+    }
+}
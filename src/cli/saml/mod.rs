@@ -0,0 +1,30 @@
+use crate::cli::HelpTemplate;
+use crate::cli::Run;
+use clap::{Parser, Subcommand};
+mod import;
+
+/// SAML service provider related commands
+#[derive(Parser, Default)]
+#[clap(author, help_template = HelpTemplate, arg_required_else_help(true))]
+pub struct Saml {
+    #[clap(subcommand)]
+    pub command: Option<SamlCommand>,
+}
+
+impl Run for Saml {
+    async fn run(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.command {
+            match cmd {
+                SamlCommand::Import(import) => import.run().await,
+            }
+        } else {
+            println!("No saml command provided. Use --help for more information.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum SamlCommand {
+    Import(import::ImportServiceProvider),
+}
@@ -0,0 +1,55 @@
+use crate::cli::Run;
+use crate::database::models::organization::DBOrganization;
+use crate::database::models::scim_token::DBScimToken;
+use crate::database::tenant::Tenant;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::PgPool;
+
+/// Create a token for an organization's identity provider
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct CreateToken {
+    /// Slug of the organization. The token only sees users and groups of this organization
+    #[clap(short, long)]
+    organization: String,
+
+    /// What the token is for, like the name of the identity provider holding it
+    #[clap(short, long)]
+    name: String,
+}
+
+impl Run for CreateToken {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let pool = PgPool::connect(&settings.postgres_db.uri).await?;
+
+        let Some(organization) = DBOrganization::find_by_slug(&self.organization, &pool).await?
+        else {
+            anyhow::bail!("There is no organization '{}'", self.organization);
+        };
+        let tenant = Tenant::new(organization);
+
+        let secret = crate::crypto::generate_token();
+        let token = DBScimToken::builder()
+            .token_hash(crate::crypto::hash_token(&secret))
+            .tenant_id(tenant.id())
+            .name(self.name.clone())
+            .build();
+        let mut transaction = pool.begin().await?;
+        token.insert(&mut transaction).await?;
+        transaction.commit().await?;
+        pool.close().await;
+
+        println!(
+            "Created SCIM token '{}' for '{}'",
+            token.name,
+            tenant.organization().slug
+        );
+        println!("id: {}", token.id);
+        println!("base url: {}/scim/v2", settings.oauth2.issuer);
+        println!("token: {secret}");
+        println!("Store the token somewhere safe, it cannot be shown again.");
+        Ok(())
+    }
+}
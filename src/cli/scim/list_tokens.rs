@@ -0,0 +1,35 @@
+use crate::cli::Run;
+use crate::database::models::organization::DBOrganization;
+use crate::database::models::scim_token::DBScimToken;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::PgPool;
+
+/// List the tokens of every organization
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct ListTokens {}
+
+impl Run for ListTokens {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let pool = PgPool::connect(&settings.postgres_db.uri).await?;
+        let tokens = DBScimToken::find_all(&pool).await?;
+
+        if tokens.is_empty() {
+            println!("There are no SCIM tokens");
+        }
+        for token in tokens {
+            let organization = DBOrganization::find_by_id(token.tenant_id, &pool).await?;
+            println!(
+                "{}  {}  {}  created {}",
+                token.id,
+                organization.map_or_else(|| token.tenant_id.to_string(), |o| o.slug),
+                token.name,
+                token.created_at
+            );
+        }
+        pool.close().await;
+        Ok(())
+    }
+}
@@ -0,0 +1,36 @@
+use crate::cli::HelpTemplate;
+use crate::cli::Run;
+use clap::{Parser, Subcommand};
+mod create_token;
+mod list_tokens;
+mod revoke_token;
+
+/// Bearer tokens identity providers provision users with over SCIM
+#[derive(Parser, Default)]
+#[clap(author, help_template = HelpTemplate, arg_required_else_help(true))]
+pub struct Scim {
+    #[clap(subcommand)]
+    pub command: Option<ScimCommand>,
+}
+
+impl Run for Scim {
+    async fn run(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.command {
+            match cmd {
+                ScimCommand::CreateToken(create) => create.run().await,
+                ScimCommand::ListTokens(list) => list.run().await,
+                ScimCommand::RevokeToken(revoke) => revoke.run().await,
+            }
+        } else {
+            println!("No scim command provided. Use --help for more information.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ScimCommand {
+    CreateToken(create_token::CreateToken),
+    ListTokens(list_tokens::ListTokens),
+    RevokeToken(revoke_token::RevokeToken),
+}
@@ -0,0 +1,33 @@
+use crate::cli::Run;
+use crate::database::models::scim_token::DBScimToken;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::PgPool;
+
+/// Revoke a token, the identity provider holding it can't provision anything anymore
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct RevokeToken {
+    /// Id of the token, as shown by list-tokens
+    #[clap(short, long)]
+    id: String,
+}
+
+impl Run for RevokeToken {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let pool = PgPool::connect(&settings.postgres_db.uri).await?;
+
+        let Some(token) = DBScimToken::find_by_id(self.id.parse()?, &pool).await? else {
+            anyhow::bail!("There is no SCIM token with id '{}'", self.id);
+        };
+
+        let mut transaction = pool.begin().await?;
+        token.delete(&mut transaction).await?;
+        transaction.commit().await?;
+        pool.close().await;
+
+        println!("Revoked SCIM token '{}'", token.name);
+        Ok(())
+    }
+}
@@ -0,0 +1,42 @@
+use crate::cli::Run;
+use crate::database::models::scope::DBScope;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::{Connection, PgConnection};
+
+/// Add a scope to the database. Scopes with the same name as one in the settings release the claims of both
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct CreateScope {
+    /// Name clients ask for the scope with
+    #[clap(short, long)]
+    name: String,
+
+    /// Shown to users when a client asks for the scope
+    #[clap(short, long, default_value = "")]
+    description: String,
+
+    /// Claim released by the scope, can be repeated. Non standard claims are read from the user's attributes
+    #[clap(short, long = "claim")]
+    claims: Vec<String>,
+}
+
+impl Run for CreateScope {
+    async fn run(&self) -> anyhow::Result<()> {
+        let scope = DBScope::builder()
+            .name(self.name.clone())
+            .description(self.description.clone())
+            .claims(self.claims.clone())
+            .build();
+
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        scope.insert(&mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        println!("Created scope '{}'", scope.name);
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+use crate::cli::Run;
+use crate::database::models::scope::DBScope;
+use crate::settings::Settings;
+use clap::Parser;
+use sqlx::{Connection, PgConnection};
+
+/// Remove a scope from the database. Scopes in the settings stay around
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct DeleteScope {
+    /// Name of the scope
+    #[clap(short, long)]
+    name: String,
+}
+
+impl Run for DeleteScope {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        let deleted = DBScope::delete_by_name(&self.name, &mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        if !deleted {
+            anyhow::bail!("There is no scope named '{}' in the database", self.name);
+        }
+
+        println!("Deleted scope '{}'", self.name);
+        Ok(())
+    }
+}
@@ -0,0 +1,33 @@
+use crate::cli::HelpTemplate;
+use crate::cli::Run;
+use clap::{Parser, Subcommand};
+mod create;
+mod delete;
+
+/// OAuth2 scope related commands
+#[derive(Parser, Default)]
+#[clap(author, help_template = HelpTemplate, arg_required_else_help(true))]
+pub struct Scopes {
+    #[clap(subcommand)]
+    pub command: Option<ScopesCommand>,
+}
+
+impl Run for Scopes {
+    async fn run(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.command {
+            match cmd {
+                ScopesCommand::Create(create) => create.run().await,
+                ScopesCommand::Delete(delete) => delete.run().await,
+            }
+        } else {
+            println!("No scopes command provided. Use --help for more information.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum ScopesCommand {
+    Create(create::CreateScope),
+    Delete(delete::DeleteScope),
+}
@@ -0,0 +1,48 @@
+use crate::cli::Run;
+use crate::database::models::user::DBUser;
+use crate::settings::Settings;
+use clap::Parser;
+use dialoguer::Password;
+use sqlx::{Connection, PgConnection};
+use tokio::task;
+
+/// Create a new user that can sign in with a password
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct CreateUser {
+    /// Name the user signs in with
+    #[clap(short, long)]
+    username: String,
+
+    /// Email address of the user
+    #[clap(short, long)]
+    email: Option<String>,
+}
+
+impl Run for CreateUser {
+    async fn run(&self) -> anyhow::Result<()> {
+        let password = task::spawn_blocking(|| {
+            Password::new()
+                .with_prompt("Password")
+                .with_confirmation("Repeat password", "The passwords don't match.")
+                .interact()
+        })
+        .await??;
+
+        let user = DBUser::builder()
+            .username(self.username.clone())
+            .email(self.email.clone())
+            .password_hash(Some(crate::crypto::hash_password(&password)?))
+            .build();
+
+        let settings = Settings::parse()?;
+        let mut db_conn = PgConnection::connect(&settings.postgres_db.uri).await?;
+        let mut transaction = db_conn.begin().await?;
+        user.insert(&mut transaction).await?;
+        transaction.commit().await?;
+        let _ = db_conn.close().await;
+
+        println!("Created user '{}' ({})", user.username, user.id);
+        Ok(())
+    }
+}
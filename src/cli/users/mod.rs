@@ -0,0 +1,33 @@
+use crate::cli::HelpTemplate;
+use crate::cli::Run;
+use clap::{Parser, Subcommand};
+mod create;
+mod set_attribute;
+
+/// User related commands
+#[derive(Parser, Default)]
+#[clap(author, help_template = HelpTemplate, arg_required_else_help(true))]
+pub struct Users {
+    #[clap(subcommand)]
+    pub command: Option<UsersCommand>,
+}
+
+impl Run for Users {
+    async fn run(&self) -> anyhow::Result<()> {
+        if let Some(cmd) = &self.command {
+            match cmd {
+                UsersCommand::Create(create) => create.run().await,
+                UsersCommand::SetAttribute(set) => set.run().await,
+            }
+        } else {
+            println!("No users command provided. Use --help for more information.");
+            Ok(())
+        }
+    }
+}
+
+#[derive(Subcommand, Clone)]
+pub enum UsersCommand {
+    Create(create::CreateUser),
+    SetAttribute(set_attribute::SetAttribute),
+}
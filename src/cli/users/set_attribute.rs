@@ -0,0 +1,57 @@
+use crate::cli::Run;
+use crate::database::models::user::DBUser;
+use crate::settings::Settings;
+use clap::Parser;
+use serde_json::Value;
+use sqlx::PgPool;
+
+/// Set or remove a custom attribute of a user, released as a claim by scopes asking for it
+#[derive(Parser, Clone, Debug)]
+#[clap(author)]
+pub struct SetAttribute {
+    /// Name the user signs in with
+    #[clap(short, long)]
+    username: String,
+
+    /// Name of the attribute, same as the claim it is released as
+    key: String,
+
+    /// Parsed as JSON when possible, a plain string otherwise. Leave out to remove the attribute
+    value: Option<String>,
+}
+
+impl Run for SetAttribute {
+    async fn run(&self) -> anyhow::Result<()> {
+        let settings = Settings::parse()?;
+        let pool = PgPool::connect(&settings.postgres_db.uri).await?;
+
+        let Some(mut user) = DBUser::find_by_username(&self.username, &pool).await? else {
+            anyhow::bail!("There is no user named '{}'", self.username);
+        };
+
+        let Value::Object(attributes) = &mut user.attributes else {
+            anyhow::bail!("The attributes of '{}' are not an object", self.username);
+        };
+
+        match &self.value {
+            Some(value) => {
+                let value = serde_json::from_str(value).unwrap_or(Value::String(value.clone()));
+                attributes.insert(self.key.clone(), value);
+            }
+            None => {
+                attributes.remove(&self.key);
+            }
+        }
+
+        let mut transaction = pool.begin().await?;
+        user.update_attributes(&mut transaction).await?;
+        transaction.commit().await?;
+        pool.close().await;
+
+        println!(
+            "Updated the attributes of '{}': {}",
+            user.username, user.attributes
+        );
+        Ok(())
+    }
+}
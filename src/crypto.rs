@@ -0,0 +1,126 @@
+use argon2::password_hash::SaltString;
+use argon2::password_hash::rand_core::OsRng;
+use argon2::{Argon2, PasswordHash, PasswordHasher, PasswordVerifier};
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use jsonwebtoken::jwk::{AlgorithmParameters, Jwk};
+use rand::{Rng, RngCore};
+use sha2::{Digest, Sha256};
+use std::sync::LazyLock;
+use subtle::ConstantTimeEq;
+
+/// Generates a random url safe token with 256 bits of entropy.
+pub fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut bytes);
+    BASE64_URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Hashes a high entropy secret (tokens, client secrets) for storage. These are never stored in plain text.
+pub fn hash_token(token: &str) -> Vec<u8> {
+    Sha256::digest(token.as_bytes()).to_vec()
+}
+
+/// Checks a plain text secret against a stored hash without leaking timing information.
+pub fn verify_token(token: &str, hash: &[u8]) -> bool {
+    hash_token(token).ct_eq(hash).into()
+}
+
+/// Base64url encoded SHA-256 digest, the form PKCE challenges and DPoP `ath` claims use.
+pub fn sha256_base64url(value: &str) -> String {
+    BASE64_URL_SAFE_NO_PAD.encode(Sha256::digest(value.as_bytes()))
+}
+
+/// A user's `sub` as seen by clients of one sector, which can't be linked to what other sectors see without the salt.
+/// (OpenID Connect Core 1.0 section 8.1)
+pub fn pairwise_subject(sector_identifier: &str, user_id: &str, salt: &str) -> String {
+    let mut hasher = Sha256::new();
+    for part in [sector_identifier, user_id, salt] {
+        hasher.update(part.as_bytes());
+        // separated, so different splits of the same bytes can't collide
+        hasher.update([0]);
+    }
+
+    BASE64_URL_SAFE_NO_PAD.encode(hasher.finalize())
+}
+
+/// Checks a PKCE code verifier against the S256 code challenge sent with the authorization request. (RFC 7636 section 4.6)
+pub fn verify_pkce_s256(code_verifier: &str, code_challenge: &str) -> bool {
+    sha256_base64url(code_verifier)
+        .as_bytes()
+        .ct_eq(code_challenge.as_bytes())
+        .into()
+}
+
+/// SHA-256 thumbprint of a public key. (RFC 7638)
+///
+/// Symmetric keys have no thumbprint, they are never accepted as proof of possession.
+pub fn jwk_thumbprint(jwk: &Jwk) -> Option<String> {
+    let json = |value: &str| serde_json::Value::from(value).to_string();
+    // members in lexicographic order without whitespace, as section 3.2 requires
+    let canonical = match &jwk.algorithm {
+        AlgorithmParameters::RSA(rsa) => format!(
+            r#"{{"e":{},"kty":"RSA","n":{}}}"#,
+            json(&rsa.e),
+            json(&rsa.n)
+        ),
+        AlgorithmParameters::EllipticCurve(ec) => format!(
+            r#"{{"crv":{},"kty":"EC","x":{},"y":{}}}"#,
+            serde_json::to_string(&ec.curve).ok()?,
+            json(&ec.x),
+            json(&ec.y)
+        ),
+        AlgorithmParameters::OctetKeyPair(okp) => format!(
+            r#"{{"crv":{},"kty":"OKP","x":{}}}"#,
+            serde_json::to_string(&okp.curve).ok()?,
+            json(&okp.x)
+        ),
+        AlgorithmParameters::OctetKey(_) => return None,
+    };
+
+    Some(sha256_base64url(&canonical))
+}
+
+/// Hashes a user chosen password with argon2, returning the PHC string.
+pub fn hash_password(password: &str) -> anyhow::Result<String> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .map_err(|e| anyhow::anyhow!("Failed hashing the password: {e}"))?;
+
+    Ok(hash.to_string())
+}
+
+/// Hash of a password nobody has, made once with the same parameters as real ones.
+static UNUSABLE_PASSWORD_HASH: LazyLock<String> =
+    LazyLock::new(|| hash_password(&generate_token()).expect("Failed hashing a password"));
+
+pub fn verify_password(password: &str, hash: &str) -> bool {
+    PasswordHash::new(hash).is_ok_and(|hash| {
+        Argon2::default()
+            .verify_password(password.as_bytes(), &hash)
+            .is_ok()
+    })
+}
+
+/// Takes as long as checking a password does, for when there is no hash to check it against. Otherwise how long
+/// the login page takes tells whether a username exists.
+pub fn verify_unusable_password(password: &str) {
+    verify_password(password, &UNUSABLE_PASSWORD_HASH);
+}
+
+/// Compares two secrets without leaking timing information.
+pub fn secrets_match(a: &str, b: &str) -> bool {
+    a.as_bytes().ct_eq(b.as_bytes()).into()
+}
+
+/// Characters used in user codes. No vowels so we don't accidentally spell words, as RFC 8628 section 6.1 suggests.
+const USER_CODE_CHARSET: &[u8] = b"BCDFGHJKLMNPQRSTVWXZ";
+
+/// Generates an 8 character user code, stored without the dash the user sees (`WDJB-MJHT`).
+pub fn generate_user_code() -> String {
+    let mut rng = rand::rng();
+    (0..8)
+        .map(|_| USER_CODE_CHARSET[rng.random_range(0..USER_CODE_CHARSET.len())] as char)
+        .collect()
+}
@@ -1,5 +1,6 @@
-mod ids;
-mod models;
+pub mod ids;
+pub mod models;
+pub mod tenant;
 
 use crate::settings::PostgresDB;
 use sqlx::PgPool;
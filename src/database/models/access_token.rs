@@ -0,0 +1,156 @@
+use crate::database::ids::UlidId;
+use crate::database::models::client::DBClientId;
+use crate::database::models::organization::DBOrganizationId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+use utoipa::ToSchema;
+
+pub type DBAccessTokenId = UlidId;
+
+/// An issued access token, opaque or a JWT. Only the hash of the token itself is ever stored.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBAccessToken {
+    #[builder(default = DBAccessTokenId::new())]
+    pub id: DBAccessTokenId,
+    pub token_hash: Vec<u8>,
+    pub client_id: DBClientId,
+    /// The user the token was issued for. Empty for tokens a client got on its own behalf
+    #[builder(default)]
+    pub user_id: Option<DBUserId>,
+    /// Tokens issued from the same authorization share a family, see [`DBRefreshToken`](super::refresh_token::DBRefreshToken)
+    #[builder(default)]
+    pub family_id: Option<UlidId>,
+    #[builder(default)]
+    pub scopes: Vec<String>,
+    #[builder(default)]
+    pub audiences: Vec<String>,
+    /// JWK thumbprint of the DPoP key the token is bound to (RFC 9449)
+    #[builder(default)]
+    pub dpop_jkt: Option<String>,
+    /// Who is acting on behalf of the user, set on tokens issued through token exchange
+    #[builder(default)]
+    pub act: Option<Json<Actor>>,
+    /// The organization the token was requested for, the user's roles there go with it
+    #[builder(default)]
+    pub organization_id: Option<DBOrganizationId>,
+    pub expires_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+/// An actor in a delegation chain. Earlier actors are nested inside. (RFC 8693 section 4.1)
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize, ToSchema)]
+pub struct Actor {
+    /// The user or client that is acting
+    pub sub: String,
+    /// Who this actor was acting for in turn
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[schema(no_recursion)]
+    pub act: Option<Box<Actor>>,
+}
+
+impl DBAccessToken {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into oauth2_access_tokens (id, token_hash, client_id, user_id, family_id, scopes, audiences, dpop_jkt, act, organization_id, expires_at, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+            self.id as DBAccessTokenId,
+            self.token_hash,
+            self.client_id as DBClientId,
+            self.user_id as Option<DBUserId>,
+            self.family_id as Option<UlidId>,
+            &self.scopes,
+            &self.audiences,
+            self.dpop_jkt,
+            self.act as _,
+            self.organization_id as Option<DBOrganizationId>,
+            self.expires_at,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_hash(
+        token_hash: &[u8],
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, token_hash, client_id, user_id as "user_id: DBUserId", family_id as "family_id: UlidId", scopes, audiences, dpop_jkt,
+            act as "act: Json<Actor>", organization_id as "organization_id: DBOrganizationId", expires_at, created_at
+            from oauth2_access_tokens where token_hash = $1"#,
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_access_tokens where id = $1",
+            self.id as DBAccessTokenId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_by_family(
+        family_id: UlidId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_access_tokens where family_id = $1",
+            family_id as UlidId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Revokes everything every client holds for the user, for when the user is deactivated.
+    pub async fn delete_by_user(
+        user_id: DBUserId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_access_tokens where user_id = $1",
+            user_id as DBUserId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Revokes everything the client holds for the user, for when the user takes back their consent.
+    pub async fn delete_by_user_and_client(
+        user_id: DBUserId,
+        client_id: DBClientId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_access_tokens where user_id = $1 and client_id = $2",
+            user_id as DBUserId,
+            client_id as DBClientId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
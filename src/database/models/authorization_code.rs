@@ -0,0 +1,110 @@
+use crate::database::ids::UlidId;
+use crate::database::models::client::DBClientId;
+use crate::database::models::session::DBSessionId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::PgTransaction;
+use typed_builder::TypedBuilder;
+
+pub type DBAuthorizationCodeId = UlidId;
+
+/// A short lived, single use code handed out by the authorization endpoint. (RFC 6749 section 4.1)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBAuthorizationCode {
+    #[builder(default = DBAuthorizationCodeId::new())]
+    pub id: DBAuthorizationCodeId,
+    pub code_hash: Vec<u8>,
+    pub client_id: DBClientId,
+    pub user_id: DBUserId,
+    /// The login session the user approved in, its id is the `sid` of the id token
+    pub session_id: DBSessionId,
+    /// None when the authorization request left it out and the only registered one was used
+    pub redirect_uri: Option<String>,
+    #[builder(default)]
+    pub scopes: Vec<String>,
+    /// Resources the tokens may be issued for (RFC 8707)
+    #[builder(default)]
+    pub resources: Vec<String>,
+    /// PKCE (RFC 7636)
+    #[builder(default)]
+    pub code_challenge: Option<String>,
+    #[builder(default)]
+    pub code_challenge_method: Option<String>,
+    /// Passed on to the id token unchanged (OpenID Connect Core 1.0 section 3.1.2.1)
+    #[builder(default)]
+    pub nonce: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBAuthorizationCode {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into oauth2_authorization_codes (id, code_hash, client_id, user_id, session_id, redirect_uri, scopes, code_challenge, code_challenge_method, resources, nonce, expires_at, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13)",
+            self.id as DBAuthorizationCodeId,
+            self.code_hash,
+            self.client_id as DBClientId,
+            self.user_id as DBUserId,
+            self.session_id as DBSessionId,
+            self.redirect_uri,
+            &self.scopes,
+            self.code_challenge,
+            self.code_challenge_method,
+            &self.resources,
+            self.nonce,
+            self.expires_at,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_hash_for_update(
+        code_hash: &[u8],
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from oauth2_authorization_codes where code_hash = $1 for update",
+            code_hash
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_authorization_codes where id = $1",
+            self.id as DBAuthorizationCodeId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Drops the codes the user approved and clients haven't redeemed yet, for when the user is deactivated.
+    pub async fn delete_by_user(
+        user_id: DBUserId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_authorization_codes where user_id = $1",
+            user_id as DBUserId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
@@ -0,0 +1,98 @@
+use crate::database::ids::UlidId;
+use crate::database::models::client::DBClientId;
+use crate::database::models::session::DBSessionId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::PgTransaction;
+use typed_builder::TypedBuilder;
+
+pub type DBBackchannelLogoutId = UlidId;
+
+/// A logout token still to be delivered to a client. Failed deliveries are retried until they run out of attempts.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBBackchannelLogout {
+    #[builder(default = DBBackchannelLogoutId::new())]
+    pub id: DBBackchannelLogoutId,
+    pub client_id: DBClientId,
+    pub user_id: DBUserId,
+    /// The session that ended. It is gone by now, this is only its id
+    pub session_id: DBSessionId,
+    #[builder(default)]
+    pub attempts: i32,
+    #[builder(default = Utc::now())]
+    pub next_attempt_at: DateTime<Utc>,
+    #[builder(default)]
+    pub last_error: Option<String>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBBackchannelLogout {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into backchannel_logouts (id, client_id, user_id, session_id, attempts, next_attempt_at, last_error, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8)",
+            self.id as DBBackchannelLogoutId,
+            self.client_id as DBClientId,
+            self.user_id as DBUserId,
+            self.session_id as DBSessionId,
+            self.attempts,
+            self.next_attempt_at,
+            self.last_error,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "update backchannel_logouts set attempts = $2, next_attempt_at = $3, last_error = $4 where id = $1",
+            self.id as DBBackchannelLogoutId,
+            self.attempts,
+            self.next_attempt_at,
+            self.last_error
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from backchannel_logouts where id = $1",
+            self.id as DBBackchannelLogoutId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Takes deliveries that are due by moving their next attempt to `lease_until`, so no other server picks them up
+    /// while they are being delivered. Ones another server is claiming at the same time are skipped.
+    pub async fn claim_due(
+        limit: i64,
+        lease_until: DateTime<Utc>,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "update backchannel_logouts set next_attempt_at = $2
+            where id in (
+                select id from backchannel_logouts where next_attempt_at <= now()
+                order by next_attempt_at limit $1 for update skip locked
+            )
+            returning *",
+            limit,
+            lease_until
+        )
+        .fetch_all(&mut **transaction)
+        .await?;
+
+        Ok(data)
+    }
+}
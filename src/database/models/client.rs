@@ -0,0 +1,335 @@
+use crate::database::ids::UlidId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::JwkSet;
+use sqlx::types::Json;
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+use url::Url;
+
+pub type DBClientId = UlidId;
+
+/// How a client proves its identity at the token endpoint (RFC 7591 names).
+#[derive(
+    Debug,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    sqlx::Type,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "oauth2_client_auth_method", rename_all = "snake_case")]
+pub enum ClientAuthMethod {
+    ClientSecretBasic,
+    ClientSecretPost,
+    PrivateKeyJwt,
+    None,
+}
+
+impl ClientAuthMethod {
+    pub fn is_confidential(self) -> bool {
+        self != Self::None
+    }
+}
+
+/// What kind of application a client is. (OpenID Connect Dynamic Client Registration 1.0 section 2)
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    sqlx::Type,
+    clap::ValueEnum,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "oauth2_application_type", rename_all = "snake_case")]
+pub enum ApplicationType {
+    #[default]
+    Web,
+    /// Desktop and mobile apps, which can't keep a secret and receive redirects locally (RFC 8252)
+    Native,
+}
+
+/// Which `sub` a client gets to see for a user. (OpenID Connect Core 1.0 section 8)
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    sqlx::Type,
+    clap::ValueEnum,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "oauth2_subject_type", rename_all = "snake_case")]
+pub enum SubjectType {
+    /// The user's id, the same for every client
+    #[default]
+    Public,
+    /// Different for every sector, so clients can't correlate users
+    Pairwise,
+}
+
+/// What issued access tokens look like. Resource servers validate JWTs on their own, opaque tokens through introspection.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    sqlx::Type,
+    clap::ValueEnum,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "oauth2_access_token_format", rename_all = "snake_case")]
+pub enum AccessTokenFormat {
+    #[default]
+    Opaque,
+    /// RFC 9068
+    Jwt,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBClient {
+    #[builder(default = DBClientId::new())]
+    pub id: DBClientId,
+    pub name: String,
+    #[builder(default)]
+    pub secret_hash: Option<Vec<u8>>,
+    pub token_endpoint_auth_method: ClientAuthMethod,
+    #[builder(default)]
+    pub grant_types: Vec<String>,
+    #[builder(default)]
+    pub scopes: Vec<String>,
+    #[builder(default)]
+    pub audiences: Vec<String>,
+    #[builder(default)]
+    pub jwks: Option<Json<JwkSet>>,
+    /// Where to fetch the client's keys from, for clients rotating them on their own
+    #[builder(default)]
+    pub jwks_uri: Option<String>,
+    /// Request objects may be fetched from these uris (RFC 9101 section 5.2)
+    #[builder(default)]
+    pub request_uris: Vec<String>,
+    /// Audiences this client may introspect tokens for. Lets resource servers validate tokens meant for them
+    #[builder(default)]
+    pub introspect_audiences: Vec<String>,
+    /// Where the authorization endpoint may send users back to
+    #[builder(default)]
+    pub redirect_uris: Vec<String>,
+    /// First party clients are trusted and never have to ask users for consent
+    #[builder(default)]
+    pub first_party: bool,
+    /// Authorization requests have to be pushed to the PAR endpoint first (RFC 9126)
+    #[builder(default)]
+    pub require_pushed_authorization_requests: bool,
+    /// Access tokens have to be bound to a DPoP key (RFC 9449)
+    #[builder(default)]
+    pub dpop_bound_access_tokens: bool,
+    /// Resources the tokens are meant for can override this
+    #[builder(default)]
+    pub access_token_format: AccessTokenFormat,
+    /// Where the end session endpoint may send users after logging out
+    #[builder(default)]
+    pub post_logout_redirect_uris: Vec<String>,
+    /// Loaded in an iframe when the user logs out (OpenID Connect Front-Channel Logout 1.0)
+    #[builder(default)]
+    pub frontchannel_logout_uri: Option<String>,
+    /// Receives logout tokens when the user logs out (OpenID Connect Back-Channel Logout 1.0)
+    #[builder(default)]
+    pub backchannel_logout_uri: Option<String>,
+    #[builder(default)]
+    pub application_type: ApplicationType,
+    #[builder(default)]
+    pub subject_type: SubjectType,
+    /// Groups clients of the same party under its host, they see the same pairwise subjects
+    #[builder(default)]
+    pub sector_identifier_uri: Option<String>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBClient {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into oauth2_clients (id, name, secret_hash, token_endpoint_auth_method, grant_types, scopes, audiences, jwks, introspect_audiences, redirect_uris, first_party, require_pushed_authorization_requests, dpop_bound_access_tokens, access_token_format, post_logout_redirect_uris, frontchannel_logout_uri, backchannel_logout_uri, jwks_uri, request_uris, subject_type, sector_identifier_uri, application_type, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14, $15, $16, $17, $18, $19, $20, $21, $22, $23)",
+            self.id as DBClientId,
+            self.name,
+            self.secret_hash,
+            self.token_endpoint_auth_method as ClientAuthMethod,
+            &self.grant_types,
+            &self.scopes,
+            &self.audiences,
+            self.jwks as _,
+            &self.introspect_audiences,
+            &self.redirect_uris,
+            self.first_party,
+            self.require_pushed_authorization_requests,
+            self.dpop_bound_access_tokens,
+            self.access_token_format as AccessTokenFormat,
+            &self.post_logout_redirect_uris,
+            self.frontchannel_logout_uri,
+            self.backchannel_logout_uri,
+            self.jwks_uri,
+            &self.request_uris,
+            self.subject_type as SubjectType,
+            self.sector_identifier_uri,
+            self.application_type as ApplicationType,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(id: DBClientId, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, name, secret_hash, token_endpoint_auth_method as "token_endpoint_auth_method: ClientAuthMethod",
+            grant_types, scopes, audiences, jwks as "jwks: Json<JwkSet>", introspect_audiences, redirect_uris, first_party,
+            require_pushed_authorization_requests, dpop_bound_access_tokens,
+            access_token_format as "access_token_format: AccessTokenFormat", post_logout_redirect_uris,
+            frontchannel_logout_uri, backchannel_logout_uri, jwks_uri, request_uris,
+            subject_type as "subject_type: SubjectType", sector_identifier_uri,
+            application_type as "application_type: ApplicationType", created_at
+            from oauth2_clients where id = $1"#,
+            id as DBClientId
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_id(
+        ids: &[DBClientId],
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, name, secret_hash, token_endpoint_auth_method as "token_endpoint_auth_method: ClientAuthMethod",
+            grant_types, scopes, audiences, jwks as "jwks: Json<JwkSet>", introspect_audiences, redirect_uris, first_party,
+            require_pushed_authorization_requests, dpop_bound_access_tokens,
+            access_token_format as "access_token_format: AccessTokenFormat", post_logout_redirect_uris,
+            frontchannel_logout_uri, backchannel_logout_uri, jwks_uri, request_uris,
+            subject_type as "subject_type: SubjectType", sector_identifier_uri,
+            application_type as "application_type: ApplicationType", created_at
+            from oauth2_clients where id = ANY($1)"#,
+            ids as &[DBClientId]
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub fn allows_grant_type(&self, grant_type: &str) -> bool {
+        self.grant_types.iter().any(|g| g == grant_type)
+    }
+
+    /// Registered redirect uris have to match exactly, except for the port of loopback redirects of native clients.
+    /// Those listen on whatever port the OS hands them. (RFC 8252 section 7.3)
+    pub fn allows_redirect_uri(&self, redirect_uri: &str) -> bool {
+        self.redirect_uris.iter().any(|uri| {
+            uri == redirect_uri
+                || (self.application_type == ApplicationType::Native
+                    && same_loopback_redirect(uri, redirect_uri))
+        })
+    }
+
+    /// Registered post logout redirect uris have to match exactly too.
+    pub fn allows_post_logout_redirect_uri(&self, redirect_uri: &str) -> bool {
+        self.post_logout_redirect_uris
+            .iter()
+            .any(|uri| uri == redirect_uri)
+    }
+
+    /// Request uris are compared without their fragment, which clients may use to tell versions apart.
+    pub fn allows_request_uri(&self, request_uri: &str) -> bool {
+        let request_uri = request_uri.split('#').next().unwrap_or_default();
+        self.request_uris.iter().any(|uri| uri == request_uri)
+    }
+
+    /// The host pairwise subjects are calculated for, from the sector identifier uri or else the redirect uris.
+    ///
+    /// Registering a pairwise client checks the sector identifier uri lists every redirect uri, and that redirect uris
+    /// without one share a host that is not loopback or a private-use scheme.
+    pub fn sector_identifier(&self) -> Option<String> {
+        self.sector_identifier_uri
+            .as_deref()
+            .or(self.redirect_uris.first().map(String::as_str))
+            .and_then(|uri| Url::parse(uri).ok())
+            .and_then(|url| url.host_str().map(String::from))
+    }
+
+    /// The `sub` this client gets to see for the user.
+    pub fn subject(&self, user_id: DBUserId, salt: &str) -> String {
+        match self.subject_type {
+            SubjectType::Public => user_id.to_string(),
+            SubjectType::Pairwise => {
+                // clients without redirect uris are a sector of their own
+                let sector = self
+                    .sector_identifier()
+                    .unwrap_or_else(|| self.id.to_string());
+                crate::crypto::pairwise_subject(&sector, &user_id.to_string(), salt)
+            }
+        }
+    }
+
+    /// Whether this client may look at a token issued to `client_id` for the given audiences.
+    pub fn can_introspect(&self, client_id: DBClientId, audiences: &[String]) -> bool {
+        client_id == self.id
+            || audiences
+                .iter()
+                .any(|aud| self.introspect_audiences.contains(aud))
+    }
+}
+
+/// Loopback redirects are plain http to an IP literal, `localhost` can be made to resolve elsewhere. (RFC 8252 section 8.3)
+pub fn is_loopback_redirect(url: &Url) -> bool {
+    url.scheme() == "http"
+        && match url.host() {
+            Some(url::Host::Ipv4(ip)) => ip.is_loopback(),
+            Some(url::Host::Ipv6(ip)) => ip.is_loopback(),
+            _ => false,
+        }
+}
+
+/// Private-use schemes have to be a reverse domain name the app owns, like `com.example.app`. (RFC 8252 section 7.1)
+pub fn is_private_use_redirect(url: &Url) -> bool {
+    url.scheme().contains('.')
+}
+
+fn same_loopback_redirect(registered: &str, requested: &str) -> bool {
+    let (Ok(registered), Ok(mut requested)) = (Url::parse(registered), Url::parse(requested))
+    else {
+        return false;
+    };
+    if !is_loopback_redirect(&registered) || !is_loopback_redirect(&requested) {
+        return false;
+    }
+
+    if requested.set_port(registered.port()).is_err() {
+        return false;
+    }
+    requested == registered
+}
@@ -0,0 +1,37 @@
+use chrono::{DateTime, Utc};
+use sqlx::PgTransaction;
+use typed_builder::TypedBuilder;
+
+/// A `private_key_jwt` client assertion that was used already, remembered until it expires so it can't be replayed.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBClientAssertion {
+    /// Hash of the client id and the assertion's jti
+    pub jti_hash: Vec<u8>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl DBClientAssertion {
+    /// Returns false when the assertion was seen before.
+    pub async fn insert_unique(
+        &self,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "insert into oauth2_client_assertions (jti_hash, expires_at) values ($1, $2) on conflict do nothing",
+            self.jti_hash,
+            self.expires_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete_expired(transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!("delete from oauth2_client_assertions where expires_at <= now()")
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(())
+    }
+}
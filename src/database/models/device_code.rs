@@ -0,0 +1,148 @@
+use crate::database::ids::UlidId;
+use crate::database::models::client::DBClientId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBDeviceCodeId = UlidId;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, serde::Serialize, serde::Deserialize, sqlx::Type)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "oauth2_device_code_status", rename_all = "snake_case")]
+pub enum DeviceCodeStatus {
+    Pending,
+    Approved,
+    Denied,
+}
+
+/// A pending device authorization (RFC 8628). The device polls with the device code while the
+/// user approves it somewhere else by typing in the user code.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBDeviceCode {
+    #[builder(default = DBDeviceCodeId::new())]
+    pub id: DBDeviceCodeId,
+    pub device_code_hash: Vec<u8>,
+    pub user_code: String,
+    pub client_id: DBClientId,
+    #[builder(default)]
+    pub scopes: Vec<String>,
+    /// Resources the tokens may be issued for (RFC 8707)
+    #[builder(default)]
+    pub resources: Vec<String>,
+    #[builder(default = DeviceCodeStatus::Pending)]
+    pub status: DeviceCodeStatus,
+    #[builder(default)]
+    pub user_id: Option<DBUserId>,
+    pub poll_interval: i32,
+    #[builder(default)]
+    pub last_polled_at: Option<DateTime<Utc>>,
+    pub expires_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBDeviceCode {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into oauth2_device_codes (id, device_code_hash, user_code, client_id, scopes, resources, status, user_id, poll_interval, last_polled_at, expires_at, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12)",
+            self.id as DBDeviceCodeId,
+            self.device_code_hash,
+            self.user_code,
+            self.client_id as DBClientId,
+            &self.scopes,
+            &self.resources,
+            self.status as DeviceCodeStatus,
+            self.user_id as Option<DBUserId>,
+            self.poll_interval,
+            self.last_polled_at,
+            self.expires_at,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Saves the status, the approving user and the polling state.
+    pub async fn update(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "update oauth2_device_codes set status = $2, user_id = $3, poll_interval = $4, last_polled_at = $5 where id = $1",
+            self.id as DBDeviceCodeId,
+            self.status as DeviceCodeStatus,
+            self.user_id as Option<DBUserId>,
+            self.poll_interval,
+            self.last_polled_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_device_codes where id = $1",
+            self.id as DBDeviceCodeId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Drops the codes the user approved and clients haven't redeemed yet, for when the user is deactivated.
+    pub async fn delete_by_user(
+        user_id: DBUserId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_device_codes where user_id = $1",
+            user_id as DBUserId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_hash_for_update(
+        device_code_hash: &[u8],
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, device_code_hash, user_code, client_id, scopes, resources, status as "status: DeviceCodeStatus",
+            user_id as "user_id: DBUserId", poll_interval, last_polled_at, expires_at, created_at
+            from oauth2_device_codes where device_code_hash = $1 for update"#,
+            device_code_hash
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_by_user_code(
+        user_code: &str,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, device_code_hash, user_code, client_id, scopes, resources, status as "status: DeviceCodeStatus",
+            user_id as "user_id: DBUserId", poll_interval, last_polled_at, expires_at, created_at
+            from oauth2_device_codes where user_code = $1"#,
+            user_code
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
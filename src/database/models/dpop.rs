@@ -0,0 +1,91 @@
+use crate::database::ids::UlidId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBDPoPNonceId = UlidId;
+
+/// A nonce handed out to clients to put in their next DPoP proofs. (RFC 9449 section 8)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBDPoPNonce {
+    #[builder(default = DBDPoPNonceId::new())]
+    pub id: DBDPoPNonceId,
+    pub nonce_hash: Vec<u8>,
+    pub expires_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBDPoPNonce {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into oauth2_dpop_nonces (id, nonce_hash, expires_at, created_at) values ($1, $2, $3, $4)",
+            self.id as DBDPoPNonceId,
+            self.nonce_hash,
+            self.expires_at,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Only finds nonces that haven't expired yet.
+    pub async fn find_by_hash(
+        nonce_hash: &[u8],
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from oauth2_dpop_nonces where nonce_hash = $1 and expires_at > now()",
+            nonce_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete_expired(transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!("delete from oauth2_dpop_nonces where expires_at <= now()")
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(())
+    }
+}
+
+/// A DPoP proof that was already used, remembered until it would be too old to be accepted anyway.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBDPoPProof {
+    /// Hash of the key thumbprint and the proof's jti
+    pub jti_hash: Vec<u8>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl DBDPoPProof {
+    /// Returns false when the proof was seen before.
+    pub async fn insert_unique(
+        &self,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "insert into oauth2_dpop_proofs (jti_hash, expires_at) values ($1, $2) on conflict do nothing",
+            self.jti_hash,
+            self.expires_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn delete_expired(transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!("delete from oauth2_dpop_proofs where expires_at <= now()")
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,96 @@
+use crate::database::ids::UlidId;
+use crate::database::models::client::DBClientId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBGrantId = UlidId;
+
+/// A user's consent for a client to access the listed scopes. One per user and client.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBGrant {
+    #[builder(default = DBGrantId::new())]
+    pub id: DBGrantId,
+    pub user_id: DBUserId,
+    pub client_id: DBClientId,
+    #[builder(default)]
+    pub scopes: Vec<String>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DBGrant {
+    /// Inserts the grant, or replaces the scopes of the one the user already gave this client.
+    pub async fn upsert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into grants (id, user_id, client_id, scopes, created_at, updated_at) values ($1, $2, $3, $4, $5, $6)
+            on conflict (user_id, client_id) do update set scopes = excluded.scopes, updated_at = excluded.updated_at",
+            self.id as DBGrantId,
+            self.user_id as DBUserId,
+            self.client_id as DBClientId,
+            &self.scopes,
+            self.created_at,
+            self.updated_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!("delete from grants where id = $1", self.id as DBGrantId)
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(id: DBGrantId, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from grants where id = $1", id as DBGrantId)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_by_user_and_client(
+        user_id: DBUserId,
+        client_id: DBClientId,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from grants where user_id = $1 and client_id = $2",
+            user_id as DBUserId,
+            client_id as DBClientId
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_user(
+        user_id: DBUserId,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from grants where user_id = $1 order by created_at",
+            user_id as DBUserId
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// Whether the user already consented to every one of these scopes.
+    pub fn covers(&self, scopes: &[String]) -> bool {
+        scopes.iter().all(|scope| self.scopes.contains(scope))
+    }
+}
@@ -0,0 +1,205 @@
+use crate::database::ids::UlidId;
+use crate::database::models::organization::DBOrganizationId;
+use crate::database::tenant::Tenant;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBGroupId = UlidId;
+
+/// A group of users of a tenant, managed by its identity provider over SCIM.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBGroup {
+    #[builder(default = DBGroupId::new())]
+    pub id: DBGroupId,
+    pub tenant_id: DBOrganizationId,
+    pub display_name: String,
+    /// The identity provider's own id for the group
+    #[builder(default)]
+    pub external_id: Option<String>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DBGroup {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into groups (id, tenant_id, display_name, external_id, created_at, updated_at) values ($1, $2, $3, $4, $5, $6)",
+            self.id as DBGroupId,
+            self.tenant_id as DBOrganizationId,
+            self.display_name,
+            self.external_id,
+            self.created_at,
+            self.updated_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "update groups set display_name = $2, external_id = $3, updated_at = $4 where id = $1",
+            self.id as DBGroupId,
+            self.display_name,
+            self.external_id,
+            self.updated_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!("delete from groups where id = $1", self.id as DBGroupId)
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(
+        tenant: &Tenant,
+        id: DBGroupId,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from groups where tenant_id = $1 and id = $2",
+            tenant.id() as DBOrganizationId,
+            id as DBGroupId
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// Locks the group, so concurrent changes can't both pass the same ETag check.
+    pub async fn find_by_id_for_update(
+        tenant: &Tenant,
+        id: DBGroupId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from groups where tenant_id = $1 and id = $2 for update",
+            tenant.id() as DBOrganizationId,
+            id as DBGroupId
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// Display names are compared without regard to case, the way SCIM filters on them.
+    pub async fn find_by_display_name(
+        tenant: &Tenant,
+        display_name: &str,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from groups where tenant_id = $1 and lower(display_name) = lower($2)",
+            tenant.id() as DBOrganizationId,
+            display_name
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_tenant(
+        tenant: &Tenant,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from groups where tenant_id = $1 order by created_at",
+            tenant.id() as DBOrganizationId,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_ids(
+        tenant: &Tenant,
+        ids: &[DBGroupId],
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from groups where tenant_id = $1 and id = ANY($2)",
+            tenant.id() as DBOrganizationId,
+            ids as &[DBGroupId]
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// How many groups of the tenant there are, only counting those with the given display name and external id
+    /// when there are any. Both are compared without regard to case, the way SCIM filters on them.
+    pub async fn count_by_tenant(
+        tenant: &Tenant,
+        display_name: Option<&str>,
+        external_id: Option<&str>,
+        pool: &PgPool,
+    ) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            r#"
+            select count(*) as "count!" from groups
+            where tenant_id = $1
+                and ($2::text is null or lower(display_name) = lower($2))
+                and ($3::text is null or lower(external_id) = lower($3))
+            "#,
+            tenant.id() as DBOrganizationId,
+            display_name,
+            external_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// A page of the groups [`Self::count_by_tenant`] counts, in the order they were created.
+    pub async fn find_page_by_tenant(
+        tenant: &Tenant,
+        display_name: Option<&str>,
+        external_id: Option<&str>,
+        offset: i64,
+        limit: i64,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"
+            select * from groups
+            where tenant_id = $1
+                and ($2::text is null or lower(display_name) = lower($2))
+                and ($3::text is null or lower(external_id) = lower($3))
+            order by created_at, id
+            offset $4 limit $5
+            "#,
+            tenant.id() as DBOrganizationId,
+            display_name,
+            external_id,
+            offset,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,84 @@
+use crate::database::models::group::DBGroupId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+/// A user in a group.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBGroupMember {
+    pub group_id: DBGroupId,
+    pub user_id: DBUserId,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBGroupMember {
+    /// Makes the users the only members of the group, members staying in it keep when they joined.
+    pub async fn replace(
+        group_id: DBGroupId,
+        user_ids: &[DBUserId],
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from group_members where group_id = $1 and not (user_id = ANY($2))",
+            group_id as DBGroupId,
+            user_ids as &[DBUserId]
+        )
+        .execute(&mut **transaction)
+        .await?;
+        sqlx::query!(
+            "insert into group_members (group_id, user_id) select $1, unnest($2::uuid[]) on conflict do nothing",
+            group_id as DBGroupId,
+            user_ids as &[DBUserId]
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_by_user(
+        user_id: DBUserId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from group_members where user_id = $1",
+            user_id as DBUserId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_many_by_groups(
+        group_ids: &[DBGroupId],
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from group_members where group_id = ANY($1) order by created_at",
+            group_ids as &[DBGroupId]
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_users(
+        user_ids: &[DBUserId],
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from group_members where user_id = ANY($1)",
+            user_ids as &[DBUserId]
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+}
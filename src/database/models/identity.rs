@@ -0,0 +1,109 @@
+use crate::database::ids::UlidId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBIdentityId = UlidId;
+
+/// A user's account at an upstream provider, which they can sign in with.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBIdentity {
+    #[builder(default = DBIdentityId::new())]
+    pub id: DBIdentityId,
+    pub user_id: DBUserId,
+    /// Id of the provider in the settings
+    pub provider: String,
+    /// The user's id at the provider
+    pub subject: String,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBIdentity {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into identities (id, user_id, provider, subject, created_at) values ($1, $2, $3, $4, $5)",
+            self.id as DBIdentityId,
+            self.user_id as DBUserId,
+            self.provider,
+            self.subject,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_subject(
+        provider: &str,
+        subject: &str,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from identities where provider = $1 and subject = $2",
+            provider,
+            subject
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_by_id(id: DBIdentityId, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from identities where id = $1",
+            id as DBIdentityId
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_user(
+        user_id: DBUserId,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from identities where user_id = $1 order by created_at",
+            user_id as DBUserId
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// Locks the user's identities, so concurrent unlinks can't both think another one is left.
+    pub async fn find_many_by_user_for_update(
+        user_id: DBUserId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from identities where user_id = $1 for update",
+            user_id as DBUserId
+        )
+        .fetch_all(&mut **transaction)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from identities where id = $1",
+            self.id as DBIdentityId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+}
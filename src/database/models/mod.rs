@@ -1 +1,36 @@
+//! Models of things belonging to an organization carry its id as `tenant_id` and take a
+//! [`Tenant`](crate::database::tenant::Tenant) in every query, like [`organization_member`],
+//! [`organization_invitation`], the SCIM users and groups an organization's identity provider provisions and the
+//! roles users have in one. The rest is shared by every organization.
+
+pub mod access_token;
+pub mod authorization_code;
+pub mod backchannel_logout;
+pub mod client;
+pub mod client_assertion;
+pub mod device_code;
+pub mod dpop;
+pub mod grant;
+pub mod group;
+pub mod group_member;
+pub mod identity;
+pub mod organization;
+pub mod organization_invitation;
+pub mod organization_member;
+pub mod pushed_authorization_request;
+pub mod refresh_token;
+pub mod resource;
+pub mod role;
+pub mod role_assignment;
+pub mod saml_login;
+pub mod saml_service_provider;
+pub mod saml_session;
+pub mod scim_token;
+pub mod scim_user;
+pub mod scope;
+pub mod session;
+pub mod session_client;
+pub mod signing_key;
+pub mod upstream_login;
+pub mod user;
 pub mod world;
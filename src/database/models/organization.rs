@@ -0,0 +1,102 @@
+use crate::database::ids::UlidId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBOrganizationId = UlidId;
+
+/// A customer of the deployment, the tenant everything belonging to it is scoped to.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBOrganization {
+    #[builder(default = DBOrganizationId::new())]
+    pub id: DBOrganizationId,
+    /// Names the organization in paths and headers
+    pub slug: String,
+    pub name: String,
+    /// Host names requests for the organization come in at, lowercase
+    #[builder(default)]
+    pub domains: Vec<String>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBOrganization {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into organizations (id, slug, name, domains, created_at) values ($1, $2, $3, $4, $5)",
+            self.id as DBOrganizationId,
+            self.slug,
+            self.name,
+            &self.domains,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether there was an organization with this slug. Everything scoped to it goes with it.
+    pub async fn delete_by_slug(
+        slug: &str,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("delete from organizations where slug = $1", slug)
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_by_id(
+        id: DBOrganizationId,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from organizations where id = $1",
+            id as DBOrganizationId
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_by_slug(slug: &str, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from organizations where slug = $1", slug)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_by_domain(domain: &str, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from organizations where domains @> array[lower($1)]",
+            domain
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// Organizations the user is a member of.
+    pub async fn find_many_by_member(
+        user_id: DBUserId,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select o.* from organizations o join organization_members m on m.tenant_id = o.id where m.user_id = $1 order by o.name",
+            user_id as DBUserId
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+}
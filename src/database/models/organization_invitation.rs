@@ -0,0 +1,157 @@
+use crate::database::ids::UlidId;
+use crate::database::models::organization::DBOrganizationId;
+use crate::database::models::organization_member::MembershipRole;
+use crate::database::models::user::DBUserId;
+use crate::database::tenant::Tenant;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBOrganizationInvitationId = UlidId;
+
+/// Someone invited to an organization by email. Only the hash of the token in the emailed link is stored.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBOrganizationInvitation {
+    #[builder(default = DBOrganizationInvitationId::new())]
+    pub id: DBOrganizationInvitationId,
+    pub tenant_id: DBOrganizationId,
+    pub email: String,
+    /// What the invitee is in the organization once they accept
+    #[builder(default)]
+    pub role: MembershipRole,
+    #[serde(skip_serializing)]
+    pub token_hash: Vec<u8>,
+    /// None when the user who invited them is gone
+    pub invited_by: Option<DBUserId>,
+    pub expires_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBOrganizationInvitation {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into organization_invitations (id, tenant_id, email, role, token_hash, invited_by, expires_at, created_at) values ($1, $2, $3, $4, $5, $6, $7, $8)",
+            self.id as DBOrganizationInvitationId,
+            self.tenant_id as DBOrganizationId,
+            self.email,
+            self.role as MembershipRole,
+            self.token_hash,
+            self.invited_by as Option<DBUserId>,
+            self.expires_at,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stores the new token and expiry of a resent invitation, the old link stops working.
+    pub async fn update_token(
+        &self,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "update organization_invitations set token_hash = $3, expires_at = $4 where tenant_id = $1 and id = $2",
+            self.tenant_id as DBOrganizationId,
+            self.id as DBOrganizationInvitationId,
+            self.token_hash,
+            self.expires_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether there was such an invitation.
+    pub async fn delete(
+        tenant: &Tenant,
+        id: DBOrganizationInvitationId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "delete from organization_invitations where tenant_id = $1 and id = $2",
+            tenant.id() as DBOrganizationId,
+            id as DBOrganizationInvitationId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_by_id(
+        tenant: &Tenant,
+        id: DBOrganizationInvitationId,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, tenant_id, email, role as "role: MembershipRole", token_hash, invited_by as "invited_by: DBUserId",
+            expires_at, created_at
+            from organization_invitations where tenant_id = $1 and id = $2"#,
+            tenant.id() as DBOrganizationId,
+            id as DBOrganizationInvitationId
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// Expired invitations are found too, so they can be resent.
+    pub async fn find_by_email(
+        tenant: &Tenant,
+        email: &str,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, tenant_id, email, role as "role: MembershipRole", token_hash, invited_by as "invited_by: DBUserId",
+            expires_at, created_at
+            from organization_invitations where tenant_id = $1 and lower(email) = lower($2)"#,
+            tenant.id() as DBOrganizationId,
+            email
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// Only invitations that haven't expired.
+    pub async fn find_by_token_hash(
+        tenant: &Tenant,
+        token_hash: &[u8],
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, tenant_id, email, role as "role: MembershipRole", token_hash, invited_by as "invited_by: DBUserId",
+            expires_at, created_at
+            from organization_invitations where tenant_id = $1 and token_hash = $2 and expires_at > now()"#,
+            tenant.id() as DBOrganizationId,
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many(tenant: &Tenant, pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, tenant_id, email, role as "role: MembershipRole", token_hash, invited_by as "invited_by: DBUserId",
+            expires_at, created_at
+            from organization_invitations where tenant_id = $1 order by created_at"#,
+            tenant.id() as DBOrganizationId
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,145 @@
+use crate::database::models::organization::DBOrganizationId;
+use crate::database::models::user::DBUserId;
+use crate::database::tenant::Tenant;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+/// What a member may do in their organization, besides what their roles grant.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    sqlx::Type,
+    clap::ValueEnum,
+    utoipa::ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "membership_role", rename_all = "snake_case")]
+pub enum MembershipRole {
+    /// Manages members, owners included
+    Owner,
+    /// Manages members besides owners
+    Admin,
+    #[default]
+    Member,
+}
+
+impl MembershipRole {
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Admin => "admin",
+            Self::Member => "member",
+        }
+    }
+}
+
+/// A user belonging to an organization.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBOrganizationMember {
+    pub tenant_id: DBOrganizationId,
+    pub user_id: DBUserId,
+    #[builder(default)]
+    pub role: MembershipRole,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBOrganizationMember {
+    /// Adding someone who already is a member is fine, their role stays what it was.
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into organization_members (tenant_id, user_id, role, created_at) values ($1, $2, $3, $4) on conflict do nothing",
+            self.tenant_id as DBOrganizationId,
+            self.user_id as DBUserId,
+            self.role as MembershipRole,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_role(
+        &self,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "update organization_members set role = $3 where tenant_id = $1 and user_id = $2",
+            self.tenant_id as DBOrganizationId,
+            self.user_id as DBUserId,
+            self.role as MembershipRole
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether the user was a member.
+    pub async fn delete(
+        tenant: &Tenant,
+        user_id: DBUserId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "delete from organization_members where tenant_id = $1 and user_id = $2",
+            tenant.id() as DBOrganizationId,
+            user_id as DBUserId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_by_user(
+        tenant: &Tenant,
+        user_id: DBUserId,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select tenant_id, user_id, role as "role: MembershipRole", created_at
+            from organization_members where tenant_id = $1 and user_id = $2"#,
+            tenant.id() as DBOrganizationId,
+            user_id as DBUserId
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many(tenant: &Tenant, pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select tenant_id, user_id, role as "role: MembershipRole", created_at
+            from organization_members where tenant_id = $1 order by created_at"#,
+            tenant.id() as DBOrganizationId
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// How many owners the organization has, it shouldn't be left without one.
+    pub async fn count_owners(tenant: &Tenant, pool: &PgPool) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            r#"select count(*) as "count!" from organization_members where tenant_id = $1 and role = 'owner'"#,
+            tenant.id() as DBOrganizationId
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+}
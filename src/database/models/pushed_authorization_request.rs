@@ -0,0 +1,71 @@
+use crate::database::ids::UlidId;
+use crate::database::models::client::DBClientId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBPushedAuthorizationRequestId = UlidId;
+
+/// Authorization request parameters a client pushed ahead of redirecting the user. (RFC 9126)
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBPushedAuthorizationRequest {
+    #[builder(default = DBPushedAuthorizationRequestId::new())]
+    pub id: DBPushedAuthorizationRequestId,
+    /// Hash of the random part of the request_uri handed to the client
+    pub reference_hash: Vec<u8>,
+    pub client_id: DBClientId,
+    /// The pushed parameters as a JSON object
+    pub parameters: serde_json::Value,
+    pub expires_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBPushedAuthorizationRequest {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into oauth2_pushed_authorization_requests (id, reference_hash, client_id, parameters, expires_at, created_at)
+            values ($1, $2, $3, $4, $5, $6)",
+            self.id as DBPushedAuthorizationRequestId,
+            self.reference_hash,
+            self.client_id as DBClientId,
+            self.parameters,
+            self.expires_at,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_hash(
+        reference_hash: &[u8],
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from oauth2_pushed_authorization_requests where reference_hash = $1",
+            reference_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_pushed_authorization_requests where id = $1",
+            self.id as DBPushedAuthorizationRequestId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
@@ -0,0 +1,147 @@
+use crate::database::ids::UlidId;
+use crate::database::models::client::DBClientId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBRefreshTokenId = UlidId;
+
+/// An issued refresh token. Like access tokens, only the hash is stored.
+///
+/// Every refresh token (and the access tokens next to it) belongs to a family: everything issued from
+/// one authorization, across rotations. Revoking a refresh token revokes its whole family.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBRefreshToken {
+    #[builder(default = DBRefreshTokenId::new())]
+    pub id: DBRefreshTokenId,
+    pub token_hash: Vec<u8>,
+    pub client_id: DBClientId,
+    pub user_id: DBUserId,
+    pub family_id: UlidId,
+    #[builder(default)]
+    pub scopes: Vec<String>,
+    #[builder(default)]
+    pub audiences: Vec<String>,
+    /// JWK thumbprint of the DPoP key the token is bound to (RFC 9449)
+    #[builder(default)]
+    pub dpop_jkt: Option<String>,
+    pub expires_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBRefreshToken {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into oauth2_refresh_tokens (id, token_hash, client_id, user_id, family_id, scopes, audiences, dpop_jkt, expires_at, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10)",
+            self.id as DBRefreshTokenId,
+            self.token_hash,
+            self.client_id as DBClientId,
+            self.user_id as DBUserId,
+            self.family_id as UlidId,
+            &self.scopes,
+            &self.audiences,
+            self.dpop_jkt,
+            self.expires_at,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_hash(
+        token_hash: &[u8],
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from oauth2_refresh_tokens where token_hash = $1",
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// Locks the token row so concurrent refreshes cannot both rotate it.
+    pub async fn find_by_hash_for_update(
+        token_hash: &[u8],
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from oauth2_refresh_tokens where token_hash = $1 for update",
+            token_hash
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_refresh_tokens where id = $1",
+            self.id as DBRefreshTokenId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete_by_family(
+        family_id: UlidId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_refresh_tokens where family_id = $1",
+            family_id as UlidId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Revokes everything every client holds for the user, for when the user is deactivated.
+    pub async fn delete_by_user(
+        user_id: DBUserId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_refresh_tokens where user_id = $1",
+            user_id as DBUserId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Revokes everything the client holds for the user, for when the user takes back their consent.
+    pub async fn delete_by_user_and_client(
+        user_id: DBUserId,
+        client_id: DBClientId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from oauth2_refresh_tokens where user_id = $1 and client_id = $2",
+            user_id as DBUserId,
+            client_id as DBClientId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
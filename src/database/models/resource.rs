@@ -0,0 +1,66 @@
+use crate::database::ids::UlidId;
+use crate::database::models::client::AccessTokenFormat;
+use chrono::{DateTime, Utc};
+use sqlx::PgTransaction;
+use typed_builder::TypedBuilder;
+
+pub type DBResourceId = UlidId;
+
+/// A protected resource tokens can be issued for, identified by the audience its tokens carry.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBResource {
+    #[builder(default = DBResourceId::new())]
+    pub id: DBResourceId,
+    pub identifier: String,
+    /// Overrides the format chosen by the client. JWTs win when resources disagree
+    #[builder(default)]
+    pub access_token_format: Option<AccessTokenFormat>,
+    /// Scopes tokens for this resource may carry, any scope when empty
+    #[builder(default)]
+    pub scopes: Vec<String>,
+    /// Lifetime of access tokens for this resource in seconds, overriding the default
+    #[builder(default)]
+    pub access_token_lifetime: Option<i64>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBResource {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into oauth2_resources (id, identifier, access_token_format, scopes, access_token_lifetime, created_at)
+            values ($1, $2, $3, $4, $5, $6)",
+            self.id as DBResourceId,
+            self.identifier,
+            self.access_token_format as Option<AccessTokenFormat>,
+            &self.scopes,
+            self.access_token_lifetime,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_many_by_identifier(
+        identifiers: &[String],
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, identifier, access_token_format as "access_token_format: AccessTokenFormat", scopes,
+            access_token_lifetime, created_at
+            from oauth2_resources where identifier = ANY($1)"#,
+            identifiers
+        )
+        .fetch_all(&mut **transaction)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub fn allows_scope(&self, scope: &str) -> bool {
+        self.scopes.is_empty() || self.scopes.iter().any(|s| s == scope)
+    }
+}
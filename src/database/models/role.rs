@@ -0,0 +1,97 @@
+use crate::database::ids::UlidId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBRoleId = UlidId;
+
+/// A set of permissions users are assigned, everywhere or within an organization.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBRole {
+    #[builder(default = DBRoleId::new())]
+    pub id: DBRoleId,
+    pub name: String,
+    #[builder(default)]
+    pub description: String,
+    /// What users with the role may do, the names handlers require them by
+    #[builder(default)]
+    pub permissions: Vec<String>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBRole {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into roles (id, name, description, permissions, created_at) values ($1, $2, $3, $4, $5)",
+            self.id as DBRoleId,
+            self.name,
+            self.description,
+            &self.permissions,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether there was a role with this name. Its assignments go with it.
+    pub async fn delete_by_name(
+        name: &str,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("delete from roles where name = $1", name)
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Returns whether there was such a role. Its assignments go with it.
+    pub async fn delete(
+        id: DBRoleId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("delete from roles where id = $1", id as DBRoleId)
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_by_id(id: DBRoleId, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from roles where id = $1", id as DBRoleId)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_by_name(name: &str, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from roles where name = $1", name)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_name(
+        names: &[String],
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from roles where name = ANY($1)", names)
+            .fetch_all(pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_all(pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from roles order by name")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(data)
+    }
+}
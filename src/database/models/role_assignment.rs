@@ -0,0 +1,116 @@
+use crate::database::ids::UlidId;
+use crate::database::models::organization::DBOrganizationId;
+use crate::database::models::role::{DBRole, DBRoleId};
+use crate::database::models::user::DBUserId;
+use crate::database::tenant::Tenant;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBRoleAssignmentId = UlidId;
+
+/// A role a user has. Assignments without an organization hold in every organization.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBRoleAssignment {
+    #[builder(default = DBRoleAssignmentId::new())]
+    pub id: DBRoleAssignmentId,
+    pub user_id: DBUserId,
+    pub role_id: DBRoleId,
+    /// The organization the role is limited to, none for everywhere
+    #[builder(default)]
+    pub tenant_id: Option<DBOrganizationId>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBRoleAssignment {
+    /// Assigning a role the user already has is fine, nothing changes.
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into role_assignments (id, user_id, role_id, tenant_id, created_at) values ($1, $2, $3, $4, $5) on conflict do nothing",
+            self.id as DBRoleAssignmentId,
+            self.user_id as DBUserId,
+            self.role_id as DBRoleId,
+            self.tenant_id as Option<DBOrganizationId>,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether the user had the role there.
+    pub async fn delete_by_user_and_role(
+        user_id: DBUserId,
+        role_id: DBRoleId,
+        tenant: Option<&Tenant>,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!(
+            "delete from role_assignments where user_id = $1 and role_id = $2 and tenant_id is not distinct from $3",
+            user_id as DBUserId,
+            role_id as DBRoleId,
+            tenant.map(Tenant::id) as Option<DBOrganizationId>
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Takes away every role the user has in the organization, like when they leave it.
+    pub async fn delete_by_user_in_tenant(
+        tenant: &Tenant,
+        user_id: DBUserId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from role_assignments where tenant_id = $1 and user_id = $2",
+            tenant.id() as DBOrganizationId,
+            user_id as DBUserId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_many_by_user(
+        user_id: DBUserId,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, user_id, role_id, tenant_id as "tenant_id: DBOrganizationId", created_at
+               from role_assignments where user_id = $1 order by created_at"#,
+            user_id as DBUserId
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// The roles the user has in the organization, the ones they have everywhere included. Without an organization
+    /// only the ones they have everywhere.
+    pub async fn find_roles(
+        user_id: DBUserId,
+        tenant: Option<&Tenant>,
+        pool: &PgPool,
+    ) -> Result<Vec<DBRole>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            DBRole,
+            "select distinct roles.* from roles join role_assignments on role_assignments.role_id = roles.id
+             where role_assignments.user_id = $1
+               and (role_assignments.tenant_id is null or role_assignments.tenant_id = $2)
+             order by roles.name",
+            user_id as DBUserId,
+            tenant.map(Tenant::id) as Option<DBOrganizationId>
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,78 @@
+use crate::database::ids::UlidId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBSamlLoginId = UlidId;
+
+/// A login started at an upstream SAML identity provider, waiting for its response.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBSamlLogin {
+    #[builder(default = DBSamlLoginId::new())]
+    pub id: DBSamlLoginId,
+    /// Hash of the state sent along as RelayState, the browser keeps the state itself in a cookie
+    pub state_hash: Vec<u8>,
+    pub provider: String,
+    /// ID of the authentication request, the response has to be to it
+    pub request_id: String,
+    pub return_to: String,
+    /// Set when a signed in user links the provider to their account, instead of signing in with it
+    #[builder(default)]
+    pub user_id: Option<DBUserId>,
+    pub expires_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBSamlLogin {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into saml_logins (id, state_hash, provider, request_id, return_to, user_id, expires_at, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8)",
+            self.id as DBSamlLoginId,
+            self.state_hash,
+            self.provider,
+            self.request_id,
+            self.return_to,
+            self.user_id as Option<DBUserId>,
+            self.expires_at,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_hash(
+        state_hash: &[u8],
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, state_hash, provider, request_id, return_to, user_id as "user_id: DBUserId",
+            expires_at, created_at from saml_logins where state_hash = $1"#,
+            state_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from saml_logins where id = $1",
+            self.id as DBSamlLoginId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub fn is_expired(&self) -> bool {
+        self.expires_at <= Utc::now()
+    }
+}
@@ -0,0 +1,133 @@
+use crate::database::ids::UlidId;
+use crate::database::models::user::DBUser;
+use crate::saml::{NAME_ID_EMAIL, NAME_ID_PERSISTENT};
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{PgPool, PgTransaction};
+use std::collections::BTreeMap;
+use typed_builder::TypedBuilder;
+
+pub type DBSamlServiceProviderId = UlidId;
+
+/// How messages are sent to a service provider's endpoint.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    Eq,
+    PartialEq,
+    serde::Serialize,
+    serde::Deserialize,
+    sqlx::Type,
+    clap::ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+#[sqlx(type_name = "saml_binding", rename_all = "snake_case")]
+pub enum SamlBinding {
+    /// Deflated into the query string, signed over the query
+    #[default]
+    Redirect,
+    /// In an auto-submitted form, signed inside the XML
+    Post,
+}
+
+/// A SAML service provider users can sign into, imported from its metadata.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBSamlServiceProvider {
+    #[builder(default = DBSamlServiceProviderId::new())]
+    pub id: DBSamlServiceProviderId,
+    pub entity_id: String,
+    pub name: String,
+    /// Assertion consumer service, where responses are posted to
+    pub acs_url: String,
+    /// Single logout service
+    #[builder(default)]
+    pub slo_url: Option<String>,
+    #[builder(default)]
+    pub slo_binding: SamlBinding,
+    /// DER certificate the service provider signs its requests with
+    #[builder(default)]
+    pub certificate: Option<Vec<u8>>,
+    pub name_id_format: String,
+    /// SAML attribute names and the user claims they are filled from
+    #[builder(default)]
+    pub attributes: Json<BTreeMap<String, String>>,
+    /// Authentication requests without a valid signature are refused
+    #[builder(default)]
+    pub authn_requests_signed: bool,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBSamlServiceProvider {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into saml_service_providers (id, entity_id, name, acs_url, slo_url, slo_binding, certificate, name_id_format, attributes, authn_requests_signed, created_at)
+            values ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)",
+            self.id as DBSamlServiceProviderId,
+            self.entity_id,
+            self.name,
+            self.acs_url,
+            self.slo_url,
+            self.slo_binding as SamlBinding,
+            self.certificate,
+            self.name_id_format,
+            self.attributes as _,
+            self.authn_requests_signed,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_entity_id(
+        entity_id: &str,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, entity_id, name, acs_url, slo_url, slo_binding as "slo_binding: SamlBinding", certificate,
+            name_id_format, attributes as "attributes: Json<BTreeMap<String, String>>", authn_requests_signed, created_at
+            from saml_service_providers where entity_id = $1"#,
+            entity_id
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_id(
+        ids: &[DBSamlServiceProviderId],
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, entity_id, name, acs_url, slo_url, slo_binding as "slo_binding: SamlBinding", certificate,
+            name_id_format, attributes as "attributes: Json<BTreeMap<String, String>>", authn_requests_signed, created_at
+            from saml_service_providers where id = ANY($1)"#,
+            ids as &[DBSamlServiceProviderId]
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// What the service provider knows the user by, in its name ID format. Persistent IDs are pairwise, so service
+    /// providers can't correlate users. Users without an email have none for the email format.
+    pub fn name_id(&self, user: &DBUser, salt: &str) -> Option<String> {
+        match self.name_id_format.as_str() {
+            NAME_ID_PERSISTENT => Some(crate::crypto::pairwise_subject(
+                &self.entity_id,
+                &user.id.to_string(),
+                salt,
+            )),
+            NAME_ID_EMAIL => user.email.clone(),
+            _ => Some(user.username.clone()),
+        }
+    }
+}
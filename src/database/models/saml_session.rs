@@ -0,0 +1,63 @@
+use crate::database::models::saml_service_provider::DBSamlServiceProviderId;
+use crate::database::models::session::DBSessionId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+/// A service provider the user signed into during a login session, with what it knows them by. It gets a logout
+/// request when the session ends.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBSamlSession {
+    pub session_id: DBSessionId,
+    pub service_provider_id: DBSamlServiceProviderId,
+    pub name_id: String,
+    pub session_index: String,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBSamlSession {
+    /// Does nothing when the service provider was already recorded for the session.
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into saml_sessions (session_id, service_provider_id, name_id, session_index, created_at)
+            values ($1, $2, $3, $4, $5) on conflict do nothing",
+            self.session_id as DBSessionId,
+            self.service_provider_id as DBSamlServiceProviderId,
+            self.name_id,
+            self.session_index,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_many_by_session(
+        session_id: DBSessionId,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from saml_sessions where session_id = $1",
+            session_id as DBSessionId
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from saml_sessions where session_id = $1 and service_provider_id = $2",
+            self.session_id as DBSessionId,
+            self.service_provider_id as DBSamlServiceProviderId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+use crate::database::ids::UlidId;
+use crate::database::models::organization::DBOrganizationId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBScimTokenId = UlidId;
+
+/// A bearer token a tenant's identity provider provisions users with over SCIM. Only the hash is stored.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBScimToken {
+    #[builder(default = DBScimTokenId::new())]
+    pub id: DBScimTokenId,
+    pub token_hash: Vec<u8>,
+    /// The organization whose users and groups the token sees
+    pub tenant_id: DBOrganizationId,
+    /// What the token is for, the identity provider holding it
+    pub name: String,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBScimToken {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into scim_tokens (id, token_hash, tenant_id, name, created_at) values ($1, $2, $3, $4, $5)",
+            self.id as DBScimTokenId,
+            self.token_hash,
+            self.tenant_id as DBOrganizationId,
+            self.name,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_hash(
+        token_hash: &[u8],
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from scim_tokens where token_hash = $1",
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_by_id(id: DBScimTokenId, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from scim_tokens where id = $1",
+            id as DBScimTokenId
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_all(pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from scim_tokens order by created_at")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "delete from scim_tokens where id = $1",
+            self.id as DBScimTokenId
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+}
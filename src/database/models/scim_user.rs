@@ -0,0 +1,173 @@
+use crate::database::models::organization::DBOrganizationId;
+use crate::database::models::user::DBUserId;
+use crate::database::tenant::Tenant;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+/// A user a tenant's identity provider provisioned over SCIM. Only that tenant sees them.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBScimUser {
+    pub user_id: DBUserId,
+    pub tenant_id: DBOrganizationId,
+    /// The identity provider's own id for the user
+    #[builder(default)]
+    pub external_id: Option<String>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DBScimUser {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into scim_users (user_id, tenant_id, external_id, created_at, updated_at) values ($1, $2, $3, $4, $5)",
+            self.user_id as DBUserId,
+            self.tenant_id as DBOrganizationId,
+            self.external_id,
+            self.created_at,
+            self.updated_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "update scim_users set external_id = $2, updated_at = $3 where user_id = $1",
+            self.user_id as DBUserId,
+            self.external_id,
+            self.updated_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_user(
+        tenant: &Tenant,
+        user_id: DBUserId,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from scim_users where tenant_id = $1 and user_id = $2",
+            tenant.id() as DBOrganizationId,
+            user_id as DBUserId
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// Locks the user, so concurrent changes can't both pass the same ETag check.
+    pub async fn find_by_user_for_update(
+        tenant: &Tenant,
+        user_id: DBUserId,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from scim_users where tenant_id = $1 and user_id = $2 for update",
+            tenant.id() as DBOrganizationId,
+            user_id as DBUserId
+        )
+        .fetch_optional(&mut **transaction)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_users(
+        tenant: &Tenant,
+        user_ids: &[DBUserId],
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from scim_users where tenant_id = $1 and user_id = ANY($2)",
+            tenant.id() as DBOrganizationId,
+            user_ids as &[DBUserId]
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_tenant(
+        tenant: &Tenant,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from scim_users where tenant_id = $1 order by created_at",
+            tenant.id() as DBOrganizationId,
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    /// How many users of the tenant there are, only counting those with the given username and external id when
+    /// there are any. Both are compared without regard to case, the way SCIM filters on them.
+    pub async fn count_by_tenant(
+        tenant: &Tenant,
+        username: Option<&str>,
+        external_id: Option<&str>,
+        pool: &PgPool,
+    ) -> Result<i64, sqlx::Error> {
+        let count = sqlx::query_scalar!(
+            r#"
+            select count(*) as "count!" from scim_users join users on users.id = scim_users.user_id
+            where scim_users.tenant_id = $1
+                and ($2::text is null or lower(users.username) = lower($2))
+                and ($3::text is null or lower(scim_users.external_id) = lower($3))
+            "#,
+            tenant.id() as DBOrganizationId,
+            username,
+            external_id
+        )
+        .fetch_one(pool)
+        .await?;
+
+        Ok(count)
+    }
+
+    /// A page of the users [`Self::count_by_tenant`] counts, in the order they were provisioned.
+    pub async fn find_page_by_tenant(
+        tenant: &Tenant,
+        username: Option<&str>,
+        external_id: Option<&str>,
+        offset: i64,
+        limit: i64,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"
+            select scim_users.* from scim_users join users on users.id = scim_users.user_id
+            where scim_users.tenant_id = $1
+                and ($2::text is null or lower(users.username) = lower($2))
+                and ($3::text is null or lower(scim_users.external_id) = lower($3))
+            order by scim_users.created_at, scim_users.user_id
+            offset $4 limit $5
+            "#,
+            tenant.id() as DBOrganizationId,
+            username,
+            external_id,
+            offset,
+            limit
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+}
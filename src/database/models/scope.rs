@@ -0,0 +1,51 @@
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+/// A scope added at runtime, on top of the ones in the settings.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBScope {
+    pub name: String,
+    #[builder(default)]
+    pub description: String,
+    #[builder(default)]
+    pub claims: Vec<String>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBScope {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into oauth2_scopes (name, description, claims, created_at) values ($1, $2, $3, $4)",
+            self.name,
+            self.description,
+            &self.claims,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Returns whether there was a scope with this name.
+    pub async fn delete_by_name(
+        name: &str,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query!("delete from oauth2_scopes where name = $1", name)
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn find_all(pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from oauth2_scopes order by name")
+            .fetch_all(pool)
+            .await?;
+
+        Ok(data)
+    }
+}
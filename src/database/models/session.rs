@@ -0,0 +1,75 @@
+use crate::database::ids::UlidId;
+use crate::database::models::user::DBUserId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBSessionId = UlidId;
+
+/// A browser login session, referenced by the hash of the session cookie.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBSession {
+    #[builder(default = DBSessionId::new())]
+    pub id: DBSessionId,
+    pub token_hash: Vec<u8>,
+    pub user_id: DBUserId,
+    pub expires_at: DateTime<Utc>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBSession {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into sessions (id, token_hash, user_id, expires_at, created_at) values ($1, $2, $3, $4, $5)",
+            self.id as DBSessionId,
+            self.token_hash,
+            self.user_id as DBUserId,
+            self.expires_at,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Only returns sessions that did not expire yet.
+    pub async fn find_by_hash(
+        token_hash: &[u8],
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from sessions where token_hash = $1 and expires_at > now()",
+            token_hash
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_user(
+        user_id: DBUserId,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from sessions where user_id = $1",
+            user_id as DBUserId
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!("delete from sessions where id = $1", self.id as DBSessionId)
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,45 @@
+use crate::database::models::client::DBClientId;
+use crate::database::models::session::DBSessionId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+/// A client the user signed into during a login session, told about it when the session ends.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBSessionClient {
+    pub session_id: DBSessionId,
+    pub client_id: DBClientId,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBSessionClient {
+    /// Does nothing when the client was already recorded for the session.
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into session_clients (session_id, client_id, created_at) values ($1, $2, $3) on conflict do nothing",
+            self.session_id as DBSessionId,
+            self.client_id as DBClientId,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_many_by_session(
+        session_id: DBSessionId,
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from session_clients where session_id = $1",
+            session_id as DBSessionId
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+}
@@ -0,0 +1,65 @@
+use crate::database::ids::UlidId;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::Jwk;
+use sqlx::types::Json;
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBSigningKeyId = UlidId;
+
+/// A key this server signs its JWTs with. The id doubles as the `kid`.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBSigningKey {
+    #[builder(default = DBSigningKeyId::new())]
+    pub id: DBSigningKeyId,
+    /// JWS algorithm name, e.g. `ES256`
+    pub algorithm: String,
+    /// PKCS#8 DER
+    #[serde(skip_serializing)]
+    pub private_key: Vec<u8>,
+    pub public_jwk: Json<Jwk>,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBSigningKey {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into signing_keys (id, algorithm, private_key, public_jwk, created_at) values ($1, $2, $3, $4, $5)",
+            self.id as DBSigningKeyId,
+            self.algorithm,
+            self.private_key,
+            self.public_jwk as _,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// The newest key, the one new tokens are signed with.
+    pub async fn find_latest(pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, algorithm, private_key, public_jwk as "public_jwk: Json<Jwk>", created_at
+            from signing_keys order by created_at desc limit 1"#
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_all(pool: &PgPool) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            r#"select id, algorithm, private_key, public_jwk as "public_jwk: Json<Jwk>", created_at
+            from signing_keys order by created_at desc"#
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+}
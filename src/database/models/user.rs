@@ -0,0 +1,131 @@
+use crate::database::ids::UlidId;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, PgTransaction};
+use typed_builder::TypedBuilder;
+
+pub type DBUserId = UlidId;
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize, TypedBuilder)]
+pub struct DBUser {
+    #[builder(default = DBUserId::new())]
+    pub id: DBUserId,
+    pub username: String,
+    #[builder(default)]
+    pub email: Option<String>,
+    /// Argon2 PHC string. Users without one cannot log in with a password
+    #[builder(default)]
+    #[serde(skip_serializing)]
+    pub password_hash: Option<String>,
+    /// Custom attributes as a JSON object, released as claims by scopes asking for them
+    #[builder(default = serde_json::Value::Object(Default::default()))]
+    pub attributes: serde_json::Value,
+    /// Inactive users can't sign in, their identity provider deprovisioned them
+    #[builder(default = true)]
+    pub active: bool,
+    #[builder(default = Utc::now())]
+    pub created_at: DateTime<Utc>,
+}
+
+impl DBUser {
+    pub async fn insert(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "insert into users (id, username, email, password_hash, attributes, active, created_at) values ($1, $2, $3, $4, $5, $6, $7)",
+            self.id as DBUserId,
+            self.username,
+            self.email,
+            self.password_hash,
+            self.attributes,
+            self.active,
+            self.created_at
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn update_attributes(
+        &self,
+        transaction: &mut PgTransaction<'_>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "update users set attributes = $2 where id = $1",
+            self.id as DBUserId,
+            self.attributes
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Saves everything an identity provider manages about the user, the password hash aside.
+    pub async fn update(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!(
+            "update users set username = $2, email = $3, attributes = $4, active = $5 where id = $1",
+            self.id as DBUserId,
+            self.username,
+            self.email,
+            self.attributes,
+            self.active
+        )
+        .execute(&mut **transaction)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn delete(&self, transaction: &mut PgTransaction<'_>) -> Result<(), sqlx::Error> {
+        sqlx::query!("delete from users where id = $1", self.id as DBUserId)
+            .execute(&mut **transaction)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn find_by_id(id: DBUserId, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from users where id = $1", id as DBUserId)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_by_username(
+        username: &str,
+        pool: &PgPool,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(Self, "select * from users where username = $1", username)
+            .fetch_optional(pool)
+            .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_by_email(email: &str, pool: &PgPool) -> Result<Option<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from users where lower(email) = lower($1)",
+            email
+        )
+        .fetch_optional(pool)
+        .await?;
+
+        Ok(data)
+    }
+
+    pub async fn find_many_by_id(
+        ids: &[DBUserId],
+        pool: &PgPool,
+    ) -> Result<Vec<Self>, sqlx::Error> {
+        let data = sqlx::query_as!(
+            Self,
+            "select * from users where id = ANY($1)",
+            ids as &[DBUserId]
+        )
+        .fetch_all(pool)
+        .await?;
+
+        Ok(data)
+    }
+}
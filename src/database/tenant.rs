@@ -0,0 +1,22 @@
+use crate::database::models::organization::{DBOrganization, DBOrganizationId};
+
+/// The organization a request is for. Models of things belonging to an organization keep its id as `tenant_id` and
+/// take a tenant in every query, so there is no way to read them without saying whose they are.
+#[derive(Debug, Clone)]
+pub struct Tenant(DBOrganization);
+
+impl Tenant {
+    /// Only this crate makes tenants, out of the organization a request or a command names, so nothing outside it
+    /// can claim to be for whichever organization it likes.
+    pub(crate) const fn new(organization: DBOrganization) -> Self {
+        Self(organization)
+    }
+
+    pub const fn id(&self) -> DBOrganizationId {
+        self.0.id
+    }
+
+    pub const fn organization(&self) -> &DBOrganization {
+        &self.0
+    }
+}
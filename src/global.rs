@@ -1,11 +1,23 @@
 use crate::database::PostgresDatabase;
+use crate::keys::SigningKey;
+use crate::mail::Mailer;
+use crate::saml::metadata::IdentityProviderCache;
+use crate::saml::signature::SamlKey;
 use crate::settings::Settings;
 use anyhow::Context;
 use sqlx::PgPool;
 
 pub struct GlobalState {
-    settings: Settings,
-    database: PgPool,
+    pub settings: Settings,
+    pub database: PgPool,
+    pub signing_key: SigningKey,
+    /// For calls to other servers, e.g. delivering logout tokens or signing in at upstream providers. Redirects are not followed
+    pub http_client: reqwest::Client,
+    /// Only there when SAML is configured
+    pub saml_key: Option<SamlKey>,
+    /// Metadata of the upstream SAML identity providers read so far
+    pub saml_metadata: IdentityProviderCache,
+    pub mailer: Mailer,
 }
 
 impl GlobalState {
@@ -16,7 +28,38 @@ impl GlobalState {
             .await
             .context("Failed connecting to the postgres database")?;
 
+        let signing_key = SigningKey::load_or_generate(&database)
+            .await
+            .context("Failed loading the signing key")?;
+
+        let http_client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .user_agent(concat!("meow_auth/", env!("CARGO_PKG_VERSION")))
+            .build()
+            .context("Failed building the http client")?;
+
+        let saml_key = match (
+            &settings.saml.private_key_path,
+            &settings.saml.certificate_path,
+        ) {
+            (Some(private_key_path), Some(certificate_path)) => Some(
+                SamlKey::load(private_key_path, certificate_path)
+                    .context("Failed loading the SAML key")?,
+            ),
+            _ => None,
+        };
+
+        let mailer = Mailer::new(&settings.mail).context("Failed setting up the mailer")?;
+
         tracing::info!("Finalized creating the global state.");
-        Ok(Self { settings, database })
+        Ok(Self {
+            settings,
+            database,
+            signing_key,
+            http_client,
+            saml_key,
+            saml_metadata: IdentityProviderCache::default(),
+            mailer,
+        })
     }
 }
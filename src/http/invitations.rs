@@ -0,0 +1,271 @@
+//! Invitations to organizations: the email with the link, and the page invitees accept on, signed in or by creating
+//! an account for the invited email.
+
+use crate::database::models::organization_invitation::DBOrganizationInvitation;
+use crate::database::models::organization_member::DBOrganizationMember;
+use crate::database::models::user::DBUser;
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::internal_error;
+use crate::http::session::{SessionUser, login_url, start_session};
+use crate::http::template::{HtmlTemplate, MessageTemplate};
+use crate::http::tenant::RequestTenant;
+use crate::mail::MailError;
+use askama::Template;
+use axum::Form;
+use axum::extract::{OriginalUri, Query, State};
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum_extra::extract::CookieJar;
+use std::sync::Arc;
+use url::Url;
+use utoipa_axum::router::OpenApiRouter;
+
+const EXPIRED: &str =
+    "This invitation has expired or was revoked. Ask whoever invited you for a new one.";
+
+fn expired() -> Response {
+    let page = HtmlTemplate(MessageTemplate {
+        title: "Invitation expired",
+        message: EXPIRED,
+    });
+    (StatusCode::NOT_FOUND, page).into_response()
+}
+
+/// Where the invitee accepts. The link has to name the organization, so it goes through the path prefix or the
+/// organization's own host name. None when it has neither.
+pub fn accept_url(global: &GlobalState, tenant: &Tenant, token: &str) -> Option<String> {
+    let organization = tenant.organization();
+    let issuer = &global.settings.oauth2.issuer;
+    let prefix = &global.settings.tenancy.path_prefix;
+    let mut url = if !prefix.is_empty() {
+        Url::parse(&format!(
+            "{issuer}{prefix}/{}/invitation",
+            organization.slug
+        ))
+        .ok()?
+    } else {
+        let mut url = Url::parse(issuer).ok()?;
+        url.set_host(Some(organization.domains.first()?)).ok()?;
+        url.set_path("/invitation");
+        url
+    };
+    url.query_pairs_mut().append_pair("token", token);
+    Some(url.into())
+}
+
+/// Emails the invitee the link to accept with.
+pub async fn send_invitation(
+    global: &GlobalState,
+    tenant: &Tenant,
+    invitation: &DBOrganizationInvitation,
+    url: &str,
+    inviter: &DBUser,
+) -> Result<(), MailError> {
+    let organization = &tenant.organization().name;
+    let body = format!(
+        "{} invited you to join {organization} as {}.\n\n\
+        Accept the invitation before {}:\n{url}\n\n\
+        If you weren't expecting this, you can ignore this email.\n",
+        inviter.username,
+        invitation.role.as_str(),
+        invitation.expires_at.format("%B %-d, %Y %H:%M UTC"),
+    );
+    global
+        .mailer
+        .send(
+            &invitation.email,
+            &format!("You're invited to join {organization}"),
+            body,
+        )
+        .await
+}
+
+#[derive(Template)]
+#[template(path = "invitation.html")]
+struct InvitationTemplate<'a> {
+    organization: &'a str,
+    role: &'a str,
+    email: &'a str,
+    /// The signed in user, who accepts with their account
+    username: Option<&'a str>,
+    login_url: String,
+    form_username: &'a str,
+    error: Option<&'a str>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct InvitationQuery {
+    token: String,
+}
+
+/// Only filled in by invitees creating an account.
+#[derive(Debug, serde::Deserialize)]
+struct InvitationForm {
+    username: Option<String>,
+    password: Option<String>,
+}
+
+async fn find_invitation(
+    global: &GlobalState,
+    tenant: &Tenant,
+    token: &str,
+) -> Result<Option<DBOrganizationInvitation>, sqlx::Error> {
+    let token_hash = crate::crypto::hash_token(token);
+    DBOrganizationInvitation::find_by_token_hash(tenant, &token_hash, &global.database).await
+}
+
+fn invitation_page(
+    tenant: &Tenant,
+    invitation: &DBOrganizationInvitation,
+    current: Option<&DBUser>,
+    uri: &OriginalUri,
+    form_username: &str,
+    error: Option<&str>,
+) -> Response {
+    let return_to = uri.path_and_query().map_or("/", |pq| pq.as_str());
+    HtmlTemplate(InvitationTemplate {
+        organization: &tenant.organization().name,
+        role: invitation.role.as_str(),
+        email: &invitation.email,
+        username: current.map(|user| user.username.as_str()),
+        login_url: login_url(return_to),
+        form_username,
+        error,
+    })
+    .into_response()
+}
+
+async fn invitation(
+    State(global): State<Arc<GlobalState>>,
+    RequestTenant(tenant): RequestTenant,
+    uri: OriginalUri,
+    jar: CookieJar,
+    Query(query): Query<InvitationQuery>,
+) -> Result<Response, StatusCode> {
+    let Some(tenant) = tenant else {
+        return Ok(expired());
+    };
+    let Some(invitation) = find_invitation(&global, &tenant, &query.token)
+        .await
+        .map_err(internal_error)?
+    else {
+        return Ok(expired());
+    };
+    let current = SessionUser::from_jar(&global, &jar)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(invitation_page(
+        &tenant,
+        &invitation,
+        current.as_ref().map(|current| &current.user),
+        &uri,
+        "",
+        None,
+    ))
+}
+
+/// Accepts the invitation as the signed in user, or creates an account with the invited email first.
+async fn accept(
+    State(global): State<Arc<GlobalState>>,
+    RequestTenant(tenant): RequestTenant,
+    uri: OriginalUri,
+    jar: CookieJar,
+    Query(query): Query<InvitationQuery>,
+    Form(form): Form<InvitationForm>,
+) -> Result<Response, StatusCode> {
+    let Some(tenant) = tenant else {
+        return Ok(expired());
+    };
+    let Some(invitation) = find_invitation(&global, &tenant, &query.token)
+        .await
+        .map_err(internal_error)?
+    else {
+        return Ok(expired());
+    };
+
+    let current = SessionUser::from_jar(&global, &jar)
+        .await
+        .map_err(internal_error)?;
+    let (jar, user) = match current {
+        Some(current) => (jar, current.user),
+        None => {
+            let username = form.username.as_deref().unwrap_or_default().trim();
+            let password = form.password.as_deref().unwrap_or_default();
+            let failed = |error| {
+                let page = invitation_page(&tenant, &invitation, None, &uri, username, Some(error));
+                (StatusCode::BAD_REQUEST, page).into_response()
+            };
+            if username.is_empty() || password.is_empty() {
+                return Ok(failed("Choose a username and a password."));
+            }
+            if DBUser::find_by_username(username, &global.database)
+                .await
+                .map_err(internal_error)?
+                .is_some()
+            {
+                return Ok(failed("That username is taken."));
+            }
+            if DBUser::find_by_email(&invitation.email, &global.database)
+                .await
+                .map_err(internal_error)?
+                .is_some()
+            {
+                return Ok(failed(
+                    "There is an account with this email already, sign in to accept with it.",
+                ));
+            }
+
+            let user = DBUser::builder()
+                .username(username.to_string())
+                .email(Some(invitation.email.clone()))
+                .password_hash(Some(
+                    crate::crypto::hash_password(password).map_err(internal_error)?,
+                ))
+                .build();
+            let mut transaction = global.database.begin().await.map_err(internal_error)?;
+            user.insert(&mut transaction)
+                .await
+                .map_err(internal_error)?;
+            transaction.commit().await.map_err(internal_error)?;
+            tracing::info!(
+                "Created user {} accepting an invitation to {}",
+                user.username,
+                tenant.organization().slug
+            );
+
+            let jar = start_session(&global, jar, &user)
+                .await
+                .map_err(internal_error)?;
+            (jar, user)
+        }
+    };
+
+    // members accepting another invitation keep the role they have
+    let mut transaction = global.database.begin().await.map_err(internal_error)?;
+    DBOrganizationMember::builder()
+        .tenant_id(tenant.id())
+        .user_id(user.id)
+        .role(invitation.role)
+        .build()
+        .insert(&mut transaction)
+        .await
+        .map_err(internal_error)?;
+    DBOrganizationInvitation::delete(&tenant, invitation.id, &mut transaction)
+        .await
+        .map_err(internal_error)?;
+    transaction.commit().await.map_err(internal_error)?;
+
+    let message = format!("You are a member of {} now.", tenant.organization().name);
+    let page = HtmlTemplate(MessageTemplate {
+        title: "Invitation accepted",
+        message: &message,
+    });
+    Ok((jar, page).into_response())
+}
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new().route("/invitation", get(invitation).post(accept))
+}
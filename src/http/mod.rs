@@ -1,12 +1,28 @@
 use crate::global::GlobalState;
+use axum::ServiceExt;
+use axum::extract::Request;
+use axum::http::StatusCode;
+use axum::middleware;
 use axum::routing::get;
 use std::sync::Arc;
-use tokio::net::TcpSocket;
+use tokio::net::{TcpListener, TcpSocket};
 use tokio::sync::oneshot;
+use tower::Layer;
 use utoipa::OpenApi;
 use utoipa_axum::router::OpenApiRouter;
 use utoipa_scalar::{Scalar, Servable};
 
+pub mod invitations;
+pub mod oauth2;
+pub mod provisioning;
+pub mod saml;
+pub mod scim;
+pub mod session;
+pub mod template;
+pub mod tenant;
+pub mod upstream;
+pub mod v1;
+
 #[derive(OpenApi)]
 struct ApiDocs;
 
@@ -14,9 +30,22 @@ fn router(global: Arc<GlobalState>) -> OpenApiRouter {
     let openapi = ApiDocs::openapi();
     OpenApiRouter::with_openapi(openapi)
         .route("/", get(|| async { "Hello, World!" }))
+        .merge(invitations::router())
+        .merge(oauth2::router())
+        .merge(saml::router())
+        .merge(scim::router())
+        .merge(session::router())
+        .merge(upstream::router())
+        .merge(v1::router())
         .with_state(global)
 }
 
+/// Logs an unexpected error and hides it behind a plain 500 response.
+pub fn internal_error(e: impl std::fmt::Display) -> StatusCode {
+    tracing::error!("Internal error while handling a request: {e}");
+    StatusCode::INTERNAL_SERVER_ERROR
+}
+
 pub async fn run(
     global_state: Arc<GlobalState>,
     shutdown: oneshot::Receiver<()>,
@@ -30,10 +59,21 @@ pub async fn run(
     socket.bind("0.0.0.0:3000".parse()?)?;
     let listener = socket.listen(1024)?;
 
-    let (router, openapi) = router(global_state).split_for_parts();
+    serve(listener, global_state, shutdown).await
+}
+
+/// Serves the app on an already bound listener, until shutdown is signalled.
+pub async fn serve(
+    listener: TcpListener,
+    global_state: Arc<GlobalState>,
+    shutdown: oneshot::Receiver<()>,
+) -> anyhow::Result<()> {
+    let (router, openapi) = router(global_state.clone()).split_for_parts();
     let router = router.merge(Scalar::with_url("/scalar", openapi));
+    // layers of the router only run after routing, too late to take the prefix off
+    let app = middleware::from_fn_with_state(global_state, tenant::strip_path_prefix).layer(router);
 
-    axum::serve(listener, router)
+    axum::serve(listener, ServiceExt::<Request>::into_make_service(app))
         .with_graceful_shutdown(async move {
             let _ = shutdown.await;
             tracing::info!("goodnight, sweet bits and packets...");
@@ -0,0 +1,608 @@
+use crate::database::ids::UlidId;
+use crate::database::models::authorization_code::DBAuthorizationCode;
+use crate::database::models::client::{ApplicationType, DBClient, DBClientId};
+use crate::database::models::grant::DBGrant;
+use crate::database::models::pushed_authorization_request::DBPushedAuthorizationRequest;
+use crate::database::models::session_client::DBSessionClient;
+use crate::global::GlobalState;
+use crate::http::internal_error;
+use crate::http::oauth2::claims::ScopeRegistry;
+use crate::http::oauth2::client_auth::verify_client_jwt;
+use crate::http::oauth2::error::OAuth2Error;
+use crate::http::oauth2::par::REQUEST_URI_PREFIX;
+use crate::http::oauth2::token::{
+    AUTHORIZATION_CODE, deserialize_resources, resolve_resources, resolve_scopes,
+};
+use crate::http::session::{SessionUser, login_url};
+use crate::http::template::{HtmlTemplate, MessageTemplate};
+use askama::Template;
+use axum::extract::{OriginalUri, State};
+use axum::http::StatusCode;
+use axum::http::header::X_FRAME_OPTIONS;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum_extra::extract::{CookieJar, Form, Query};
+use chrono::{Duration, Utc};
+use jsonwebtoken::Validation;
+use std::sync::Arc;
+use url::Url;
+use utoipa::ToSchema;
+
+/// The parameters of an authorization request. (RFC 6749 section 4.1.1)
+#[derive(Debug, Clone, Default, serde::Serialize, serde::Deserialize, ToSchema)]
+pub struct AuthorizationRequest {
+    pub response_type: Option<String>,
+    pub client_id: Option<String>,
+    pub redirect_uri: Option<String>,
+    pub scope: Option<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    /// Handed back in the id token, so the client can tie it to this request (OpenID Connect Core 1.0 section 3.1.2.1)
+    pub nonce: Option<String>,
+    /// Resources the tokens are for. Can be repeated (RFC 8707)
+    #[serde(default, deserialize_with = "deserialize_resources")]
+    pub resource: Vec<String>,
+    /// Signed request object carrying the parameters, replacing everything but client_id (RFC 9101)
+    pub request: Option<String>,
+    /// Reference to parameters pushed to the PAR endpoint beforehand (RFC 9126), or where to fetch a request object from
+    pub request_uri: Option<String>,
+}
+
+impl AuthorizationRequest {
+    /// The parameters as hidden form fields, so the consent form can send the same request back.
+    fn params(&self) -> Vec<(&'static str, &str)> {
+        let resources = self
+            .resource
+            .iter()
+            .map(|resource| ("resource", resource.as_str()));
+
+        [
+            ("response_type", &self.response_type),
+            ("client_id", &self.client_id),
+            ("redirect_uri", &self.redirect_uri),
+            ("scope", &self.scope),
+            ("state", &self.state),
+            ("code_challenge", &self.code_challenge),
+            ("code_challenge_method", &self.code_challenge_method),
+            ("nonce", &self.nonce),
+            ("request", &self.request),
+            ("request_uri", &self.request_uri),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_deref().map(|value| (name, value)))
+        .chain(resources)
+        .collect()
+    }
+}
+
+/// An authorization request that passed validation.
+pub struct ValidatedRequest {
+    pub client: DBClient,
+    pub redirect_uri: String,
+    /// Whether the request named the redirect uri, the token request has to repeat it then (RFC 6749 section 4.1.3)
+    pub redirect_uri_given: bool,
+    pub scopes: Vec<String>,
+    pub resources: Vec<String>,
+    pub state: Option<String>,
+    pub code_challenge: Option<String>,
+    pub code_challenge_method: Option<String>,
+    pub nonce: Option<String>,
+    /// Set when the parameters were pushed beforehand, it is used up together with the request
+    pub pushed: Option<DBPushedAuthorizationRequest>,
+}
+
+pub enum AuthorizeError {
+    /// The client or redirect uri can't be trusted, so the error is shown to the user instead.
+    Page(&'static str),
+    /// Errors sent back to the client at its redirect uri. (RFC 6749 section 4.1.2.1)
+    Redirect {
+        redirect_uri: String,
+        state: Option<String>,
+        error: &'static str,
+        description: String,
+    },
+    Internal(sqlx::Error),
+}
+
+impl From<sqlx::Error> for AuthorizeError {
+    fn from(value: sqlx::Error) -> Self {
+        Self::Internal(value)
+    }
+}
+
+impl IntoResponse for AuthorizeError {
+    fn into_response(self) -> Response {
+        match self {
+            Self::Page(message) => {
+                let page = HtmlTemplate(MessageTemplate {
+                    title: "Something went wrong",
+                    message,
+                });
+                (StatusCode::BAD_REQUEST, page).into_response()
+            }
+            Self::Redirect {
+                redirect_uri,
+                state,
+                error,
+                description,
+            } => redirect_with(
+                &redirect_uri,
+                &[
+                    ("error", Some(error)),
+                    ("error_description", Some(&description)),
+                    ("state", state.as_deref()),
+                ],
+            ),
+            Self::Internal(e) => internal_error(e).into_response(),
+        }
+    }
+}
+
+/// Redirects to the uri with the given parameters added to its query.
+pub fn redirect_with(redirect_uri: &str, params: &[(&str, Option<&str>)]) -> Response {
+    let Ok(mut url) = Url::parse(redirect_uri) else {
+        return internal_error(format!(
+            "registered redirect uri '{redirect_uri}' is invalid"
+        ))
+        .into_response();
+    };
+
+    {
+        let mut query = url.query_pairs_mut();
+        for (name, value) in params {
+            if let Some(value) = value {
+                query.append_pair(name, value);
+            }
+        }
+    }
+
+    Redirect::to(url.as_str()).into_response()
+}
+
+const INVALID_REQUEST_URI: &str =
+    "This sign in request is invalid or has expired. Go back to the application and try again.";
+
+const INVALID_REQUEST_OBJECT: &str =
+    "This sign in request could not be verified. Go back to the application and try again.";
+
+/// The `typ` of request objects. (RFC 9101 section 4)
+pub const REQUEST_OBJECT_TYPE: &str = "oauth-authz-req+jwt";
+
+pub async fn validate(
+    global: &GlobalState,
+    request: &AuthorizationRequest,
+) -> Result<ValidatedRequest, AuthorizeError> {
+    let client = match request
+        .client_id
+        .as_deref()
+        .and_then(|id| id.parse::<DBClientId>().ok())
+    {
+        Some(id) => DBClient::find_by_id(id, &global.database).await?,
+        None => None,
+    }
+    .ok_or(AuthorizeError::Page(
+        "The application that sent you here is unknown.",
+    ))?;
+
+    let pushed_uri = request
+        .request_uri
+        .as_deref()
+        .filter(|uri| uri.starts_with(REQUEST_URI_PREFIX));
+
+    let (parameters, pushed) = match (pushed_uri, &request.request, &request.request_uri) {
+        (Some(_), Some(_), _) | (None, Some(_), Some(_)) => {
+            return Err(AuthorizeError::Page(INVALID_REQUEST_OBJECT));
+        }
+        (Some(request_uri), None, _) => {
+            let pushed = find_pushed(global, &client, request_uri)
+                .await?
+                .ok_or(AuthorizeError::Page(INVALID_REQUEST_URI))?;
+            let parameters = serde_json::from_value(pushed.parameters.clone())
+                .map_err(|_| AuthorizeError::Page(INVALID_REQUEST_URI))?;
+            (parameters, Some(pushed))
+        }
+        (None, Some(object), None) => (
+            request_object_parameters(global, &client, object)
+                .await
+                .map_err(request_object_error)?,
+            None,
+        ),
+        (None, None, Some(request_uri)) => {
+            let object = fetch_request_object(global, &client, request_uri)
+                .await
+                .map_err(request_object_error)?;
+            (
+                request_object_parameters(global, &client, object.trim())
+                    .await
+                    .map_err(request_object_error)?,
+                None,
+            )
+        }
+        (None, None, None) => (request.clone(), None),
+    };
+
+    let validated = validate_parameters(client, &parameters)?;
+    if pushed.is_none() && validated.client.require_pushed_authorization_requests {
+        return Err(AuthorizeError::Redirect {
+            redirect_uri: validated.redirect_uri,
+            state: validated.state,
+            error: "invalid_request",
+            description: "authorization requests have to be pushed first".into(),
+        });
+    }
+
+    Ok(ValidatedRequest {
+        pushed,
+        ..validated
+    })
+}
+
+/// Nothing in a request that failed verification can be trusted, not even its redirect_uri.
+fn request_object_error(error: OAuth2Error) -> AuthorizeError {
+    match error {
+        OAuth2Error::Database(e) => AuthorizeError::Internal(e),
+        _ => AuthorizeError::Page(INVALID_REQUEST_OBJECT),
+    }
+}
+
+/// Verifies a request object signed by the client and returns the parameters inside. (RFC 9101 section 6)
+pub async fn request_object_parameters(
+    global: &GlobalState,
+    client: &DBClient,
+    object: &str,
+) -> Result<AuthorizationRequest, OAuth2Error> {
+    let invalid = |reason: &'static str| OAuth2Error::InvalidRequestObject(reason.into());
+
+    let header =
+        jsonwebtoken::decode_header(object).map_err(|_| invalid("malformed request object"))?;
+    // other JWTs of the client, like its assertions, must not pass as request objects
+    if header
+        .typ
+        .as_deref()
+        .is_some_and(|typ| typ != REQUEST_OBJECT_TYPE && !typ.eq_ignore_ascii_case("JWT"))
+    {
+        return Err(invalid(
+            "request objects must have the oauth-authz-req+jwt type",
+        ));
+    }
+
+    let client_id = client.id.to_string();
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&client_id]);
+    validation.set_audience(&[&global.settings.oauth2.issuer]);
+    validation.set_required_spec_claims(&["exp", "iss", "aud"]);
+
+    let parameters: AuthorizationRequest =
+        verify_client_jwt(global, client, object, &header, &validation)
+            .await?
+            .ok_or(invalid("invalid request object"))?;
+
+    if parameters.client_id.as_deref() != Some(&client_id) {
+        return Err(invalid("client_id does not match the request object"));
+    }
+
+    if parameters.request.is_some() || parameters.request_uri.is_some() {
+        return Err(invalid("request objects cannot be nested"));
+    }
+
+    Ok(parameters)
+}
+
+/// Fetches a request object by reference, only from uris the client registered. (RFC 9101 section 5.2)
+async fn fetch_request_object(
+    global: &GlobalState,
+    client: &DBClient,
+    request_uri: &str,
+) -> Result<String, OAuth2Error> {
+    if !client.allows_request_uri(request_uri) {
+        return Err(OAuth2Error::InvalidRequestUri(
+            "request_uri is not registered for this client".into(),
+        ));
+    }
+
+    global
+        .http_client
+        .get(request_uri)
+        .timeout(std::time::Duration::from_secs(
+            global.settings.oauth2.client_fetch_timeout,
+        ))
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|_| OAuth2Error::InvalidRequestUri("could not fetch the request object".into()))?
+        .text()
+        .await
+        .map_err(|_| OAuth2Error::InvalidRequestUri("could not fetch the request object".into()))
+}
+
+/// Looks up the pushed request a request_uri refers to. Requests pushed by other clients don't exist as far as this one is concerned.
+async fn find_pushed(
+    global: &GlobalState,
+    client: &DBClient,
+    request_uri: &str,
+) -> Result<Option<DBPushedAuthorizationRequest>, sqlx::Error> {
+    let Some(reference) = request_uri.strip_prefix(REQUEST_URI_PREFIX) else {
+        return Ok(None);
+    };
+
+    let pushed = DBPushedAuthorizationRequest::find_by_hash(
+        &crate::crypto::hash_token(reference),
+        &global.database,
+    )
+    .await?;
+
+    Ok(pushed.filter(|pushed| pushed.client_id == client.id && !pushed.is_expired()))
+}
+
+/// Checks the parameters of an authorization request made by the client.
+pub fn validate_parameters(
+    client: DBClient,
+    request: &AuthorizationRequest,
+) -> Result<ValidatedRequest, AuthorizeError> {
+    let redirect_uri = match request.redirect_uri.as_deref() {
+        Some(uri) if client.allows_redirect_uri(uri) => uri.to_string(),
+        None if client.redirect_uris.len() == 1 => client.redirect_uris[0].clone(),
+        _ => {
+            return Err(AuthorizeError::Page(
+                "The application that sent you here asked to send you somewhere it is not allowed to.",
+            ));
+        }
+    };
+
+    let fail = |error: &'static str, description: String| AuthorizeError::Redirect {
+        redirect_uri: redirect_uri.clone(),
+        state: request.state.clone(),
+        error,
+        description,
+    };
+
+    if request.response_type.as_deref() != Some("code") {
+        return Err(fail(
+            "unsupported_response_type",
+            "only the code response type is supported".into(),
+        ));
+    }
+
+    if !client.allows_grant_type(AUTHORIZATION_CODE) {
+        return Err(fail(
+            "unauthorized_client",
+            "client is not allowed to use the authorization code grant".into(),
+        ));
+    }
+
+    let scopes = resolve_scopes(request.scope.as_deref(), &client.scopes).map_err(|e| match e {
+        OAuth2Error::InvalidScope(description) => fail("invalid_scope", description.into()),
+        _ => fail("invalid_request", "invalid scope".into()),
+    })?;
+
+    let resources =
+        resolve_resources(&request.resource, &client.audiences).map_err(|e| match e {
+            OAuth2Error::InvalidTarget(description) => fail("invalid_target", description.into()),
+            _ => fail("invalid_request", "invalid resource".into()),
+        })?;
+
+    let code_challenge_method = match (&request.code_challenge, &request.code_challenge_method) {
+        (None, _) if !client.token_endpoint_auth_method.is_confidential() => {
+            return Err(fail(
+                "invalid_request",
+                "public clients have to use PKCE".into(),
+            ));
+        }
+        // anything on the device may be listening on a loopback port or claim a private-use scheme (RFC 8252 section 8.1)
+        (None, _) if client.application_type == ApplicationType::Native => {
+            return Err(fail(
+                "invalid_request",
+                "native clients have to use PKCE".into(),
+            ));
+        }
+        (None, _) => None,
+        (Some(_), Some(method)) if method == "S256" => Some(method.clone()),
+        (Some(_), _) => {
+            return Err(fail(
+                "invalid_request",
+                "only the S256 code_challenge_method is supported".into(),
+            ));
+        }
+    };
+
+    Ok(ValidatedRequest {
+        client,
+        redirect_uri,
+        redirect_uri_given: request.redirect_uri.is_some(),
+        scopes,
+        resources,
+        state: request.state.clone(),
+        code_challenge: request.code_challenge.clone(),
+        code_challenge_method,
+        nonce: request.nonce.clone(),
+        pushed: None,
+    })
+}
+
+/// Issues an authorization code and sends the user back to the client with it.
+pub async fn issue_code(
+    global: &GlobalState,
+    current: &SessionUser,
+    request: &ValidatedRequest,
+) -> Result<Response, sqlx::Error> {
+    let code = crate::crypto::generate_token();
+
+    let mut transaction = global.database.begin().await?;
+    DBAuthorizationCode::builder()
+        .code_hash(crate::crypto::hash_token(&code))
+        .client_id(request.client.id)
+        .user_id(current.user.id)
+        .session_id(current.session.id)
+        .redirect_uri(
+            request
+                .redirect_uri_given
+                .then(|| request.redirect_uri.clone()),
+        )
+        .scopes(request.scopes.clone())
+        .resources(request.resources.clone())
+        .code_challenge(request.code_challenge.clone())
+        .code_challenge_method(request.code_challenge_method.clone())
+        .nonce(request.nonce.clone())
+        .expires_at(
+            Utc::now() + Duration::seconds(global.settings.oauth2.authorization_code_lifetime),
+        )
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    if let Some(pushed) = &request.pushed {
+        pushed.delete(&mut transaction).await?;
+    }
+    DBSessionClient::builder()
+        .session_id(current.session.id)
+        .client_id(request.client.id)
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    Ok(redirect_with(
+        &request.redirect_uri,
+        &[
+            ("code", Some(&code)),
+            ("state", request.state.as_deref()),
+            // RFC 9207, lets clients talking to multiple servers detect mix-up attacks
+            ("iss", Some(&global.settings.oauth2.issuer)),
+        ],
+    ))
+}
+
+/// Users only have to consent again when a third party client asks for more than they already allowed.
+pub async fn needs_consent(
+    global: &GlobalState,
+    current: &SessionUser,
+    request: &ValidatedRequest,
+) -> Result<bool, sqlx::Error> {
+    if request.client.first_party {
+        return Ok(false);
+    }
+
+    let grant =
+        DBGrant::find_by_user_and_client(current.user.id, request.client.id, &global.database)
+            .await?;
+    Ok(!grant.is_some_and(|grant| grant.covers(&request.scopes)))
+}
+
+/// Remembers the user's consent, on top of whatever they allowed the client before.
+pub async fn record_consent(
+    global: &GlobalState,
+    transaction: &mut sqlx::PgTransaction<'_>,
+    user_id: UlidId,
+    client: &DBClient,
+    scopes: &[String],
+) -> Result<(), sqlx::Error> {
+    if client.first_party {
+        return Ok(());
+    }
+
+    let mut granted = DBGrant::find_by_user_and_client(user_id, client.id, &global.database)
+        .await?
+        .map(|grant| grant.scopes)
+        .unwrap_or_default();
+    for scope in scopes {
+        if !granted.contains(scope) {
+            granted.push(scope.clone());
+        }
+    }
+
+    DBGrant::builder()
+        .user_id(user_id)
+        .client_id(client.id)
+        .scopes(granted)
+        .build()
+        .upsert(transaction)
+        .await
+}
+
+#[derive(Template)]
+#[template(path = "consent.html")]
+struct ConsentTemplate<'a> {
+    username: &'a str,
+    client_name: &'a str,
+    redirect_uri: &'a str,
+    /// Descriptions of the requested scopes
+    scopes: Vec<&'a str>,
+    params: Vec<(&'static str, &'a str)>,
+}
+
+/// The authorization endpoint. (RFC 6749 section 3.1)
+pub async fn authorize(
+    State(global): State<Arc<GlobalState>>,
+    OriginalUri(uri): OriginalUri,
+    jar: CookieJar,
+    Query(request): Query<AuthorizationRequest>,
+) -> Result<Response, AuthorizeError> {
+    let validated = validate(&global, &request).await?;
+
+    let Some(current) = SessionUser::from_jar(&global, &jar).await? else {
+        let return_to = uri.path_and_query().map_or("/", |pq| pq.as_str());
+        return Ok(Redirect::to(&login_url(return_to)).into_response());
+    };
+
+    if !needs_consent(&global, &current, &validated).await? {
+        return Ok(issue_code(&global, &current, &validated).await?);
+    }
+
+    let registry = ScopeRegistry::load(&global).await?;
+    let page = HtmlTemplate(ConsentTemplate {
+        username: &current.user.username,
+        client_name: &validated.client.name,
+        redirect_uri: &validated.redirect_uri,
+        scopes: validated
+            .scopes
+            .iter()
+            .map(|s| registry.describe(s))
+            .collect(),
+        params: request.params(),
+    });
+
+    // the consent page must never end up in someone else's iframe
+    Ok(([(X_FRAME_OPTIONS, "DENY")], page).into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct ConsentForm {
+    #[serde(flatten)]
+    request: AuthorizationRequest,
+    decision: String,
+}
+
+pub async fn consent(
+    State(global): State<Arc<GlobalState>>,
+    current: SessionUser,
+    Form(form): Form<ConsentForm>,
+) -> Result<Response, AuthorizeError> {
+    let validated = validate(&global, &form.request).await?;
+
+    if form.decision != "approve" {
+        if let Some(pushed) = &validated.pushed {
+            let mut transaction = global.database.begin().await?;
+            pushed.delete(&mut transaction).await?;
+            transaction.commit().await?;
+        }
+
+        return Err(AuthorizeError::Redirect {
+            redirect_uri: validated.redirect_uri,
+            state: validated.state,
+            error: "access_denied",
+            description: "the user denied the request".into(),
+        });
+    }
+
+    let mut transaction = global.database.begin().await?;
+    record_consent(
+        &global,
+        &mut transaction,
+        current.user.id,
+        &validated.client,
+        &validated.scopes,
+    )
+    .await?;
+    transaction.commit().await?;
+
+    Ok(issue_code(&global, &current, &validated).await?)
+}
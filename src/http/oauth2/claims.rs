@@ -0,0 +1,101 @@
+use crate::database::models::scope::DBScope;
+use crate::database::models::user::DBUser;
+use crate::global::GlobalState;
+use crate::settings::Scope;
+use serde_json::{Map, Value};
+
+/// Every known scope, from the settings and the database. Scopes defined in both places release the claims of both.
+#[derive(Debug, Clone, Default)]
+pub struct ScopeRegistry {
+    scopes: Vec<Scope>,
+}
+
+impl ScopeRegistry {
+    pub async fn load(global: &GlobalState) -> Result<Self, sqlx::Error> {
+        let mut registry = Self {
+            scopes: global.settings.scopes.clone(),
+        };
+
+        for scope in DBScope::find_all(&global.database).await? {
+            registry.add(Scope {
+                name: scope.name,
+                description: scope.description,
+                claims: scope.claims,
+            });
+        }
+
+        Ok(registry)
+    }
+
+    fn add(&mut self, scope: Scope) {
+        let Some(existing) = self.scopes.iter_mut().find(|s| s.name == scope.name) else {
+            self.scopes.push(scope);
+            return;
+        };
+
+        if existing.description.is_empty() {
+            existing.description = scope.description;
+        }
+        for claim in scope.claims {
+            if !existing.claims.contains(&claim) {
+                existing.claims.push(claim);
+            }
+        }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Scope> {
+        self.scopes.iter().find(|scope| scope.name == name)
+    }
+
+    /// What to show users for a scope, its name when nobody described it.
+    pub fn describe<'a>(&'a self, name: &'a str) -> &'a str {
+        self.get(name)
+            .map(|scope| scope.description.as_str())
+            .filter(|description| !description.is_empty())
+            .unwrap_or(name)
+    }
+
+    /// Every claim released by the given scopes.
+    pub fn claims_for(&self, scopes: &[String]) -> Vec<&str> {
+        let mut claims: Vec<&str> = Vec::new();
+        for claim in scopes
+            .iter()
+            .filter_map(|name| self.get(name))
+            .flat_map(|scope| &scope.claims)
+        {
+            if !claims.contains(&claim.as_str()) {
+                claims.push(claim);
+            }
+        }
+
+        claims
+    }
+
+    /// The user's claims released by the given scopes. `sub` is always included, as the client gets to see it.
+    pub fn user_claims(
+        &self,
+        user: &DBUser,
+        subject: String,
+        scopes: &[String],
+    ) -> Map<String, Value> {
+        let mut claims = Map::new();
+        claims.insert("sub".into(), subject.into());
+
+        for claim in self.claims_for(scopes).into_iter().filter(|c| *c != "sub") {
+            if let Some(value) = user_claim(user, claim) {
+                claims.insert(claim.into(), value);
+            }
+        }
+
+        claims
+    }
+}
+
+/// Standard claims come from the user itself, everything else from their custom attributes.
+pub fn user_claim(user: &DBUser, claim: &str) -> Option<Value> {
+    match claim {
+        "preferred_username" => Some(user.username.clone().into()),
+        "email" => user.email.clone().map(Value::from),
+        _ => user.attributes.get(claim).cloned(),
+    }
+}
@@ -0,0 +1,244 @@
+use crate::database::models::client::{ClientAuthMethod, DBClient, DBClientId};
+use crate::database::models::client_assertion::DBClientAssertion;
+use crate::global::GlobalState;
+use crate::http::oauth2::error::OAuth2Error;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::Basic;
+use chrono::{DateTime, Utc};
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Header, Validation};
+use serde::de::DeserializeOwned;
+use utoipa::ToSchema;
+
+pub const JWT_BEARER_ASSERTION_TYPE: &str =
+    "urn:ietf:params:oauth:client-assertion-type:jwt-bearer";
+
+/// Client authentication parameters that can be sent in the request body (RFC 6749 section 2.3 and RFC 7523).
+#[derive(Debug, Default, Clone, serde::Deserialize, ToSchema)]
+pub struct ClientCredentials {
+    pub client_id: Option<String>,
+    pub client_secret: Option<String>,
+    pub client_assertion_type: Option<String>,
+    pub client_assertion: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct AssertionClaims {
+    pub iss: String,
+    pub sub: String,
+    pub jti: Option<String>,
+    pub exp: i64,
+}
+
+enum Presented<'a> {
+    Secret(ClientAuthMethod, &'a str),
+    Assertion(&'a str),
+    Nothing,
+}
+
+/// Authenticates the calling client using whichever method it presented, making sure it is the one it registered with.
+pub async fn authenticate_client(
+    global: &GlobalState,
+    basic: Option<&Authorization<Basic>>,
+    credentials: &ClientCredentials,
+) -> Result<DBClient, OAuth2Error> {
+    let (client_id, presented) = match (
+        basic,
+        credentials.client_assertion.as_deref(),
+        credentials.client_secret.as_deref(),
+    ) {
+        (Some(basic), None, None) => (
+            basic.username().to_string(),
+            Presented::Secret(ClientAuthMethod::ClientSecretBasic, basic.password()),
+        ),
+        (None, Some(assertion), None) => {
+            if credentials.client_assertion_type.as_deref() != Some(JWT_BEARER_ASSERTION_TYPE) {
+                return Err(OAuth2Error::InvalidRequest(
+                    "unsupported client_assertion_type".into(),
+                ));
+            }
+
+            (
+                assertion_subject(assertion)?,
+                Presented::Assertion(assertion),
+            )
+        }
+        (None, None, Some(secret)) => (
+            credentials
+                .client_id
+                .clone()
+                .ok_or(OAuth2Error::InvalidRequest("missing client_id".into()))?,
+            Presented::Secret(ClientAuthMethod::ClientSecretPost, secret),
+        ),
+        (None, None, None) => (
+            credentials
+                .client_id
+                .clone()
+                .ok_or(OAuth2Error::InvalidClient(
+                    "missing client authentication".into(),
+                ))?,
+            Presented::Nothing,
+        ),
+        _ => {
+            return Err(OAuth2Error::InvalidRequest(
+                "more than one client authentication method was used".into(),
+            ));
+        }
+    };
+
+    if let Some(body_client_id) = &credentials.client_id
+        && *body_client_id != client_id
+    {
+        return Err(OAuth2Error::InvalidClient("client_id mismatch".into()));
+    }
+
+    let client = match client_id.parse::<DBClientId>() {
+        Ok(id) => DBClient::find_by_id(id, &global.database).await?,
+        Err(_) => None,
+    }
+    .ok_or(OAuth2Error::InvalidClient("unknown client".into()))?;
+
+    let method = client.token_endpoint_auth_method;
+    match presented {
+        Presented::Secret(presented_method, secret) if presented_method == method => {
+            let valid = client
+                .secret_hash
+                .as_deref()
+                .is_some_and(|hash| crate::crypto::verify_token(secret, hash));
+
+            if !valid {
+                return Err(OAuth2Error::InvalidClient("invalid client secret".into()));
+            }
+        }
+        Presented::Assertion(assertion) if method == ClientAuthMethod::PrivateKeyJwt => {
+            verify_client_assertion(global, &client, assertion).await?;
+        }
+        Presented::Nothing if method == ClientAuthMethod::None => {}
+        _ => {
+            return Err(OAuth2Error::InvalidClient(
+                "client authentication method does not match the registered one".into(),
+            ));
+        }
+    }
+
+    Ok(client)
+}
+
+/// Reads the `sub` of a client assertion without verifying it, so we know whose keys to verify it with.
+fn assertion_subject(assertion: &str) -> Result<String, OAuth2Error> {
+    let mut validation = Validation::default();
+    validation.insecure_disable_signature_validation();
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.required_spec_claims.clear();
+
+    jsonwebtoken::decode::<AssertionClaims>(assertion, &DecodingKey::from_secret(&[]), &validation)
+        .map(|data| data.claims.sub)
+        .map_err(|_| OAuth2Error::InvalidClient("malformed client assertion".into()))
+}
+
+/// Verifies a client assertion and makes sure it is used only once. (RFC 7523 section 3)
+pub async fn verify_client_assertion(
+    global: &GlobalState,
+    client: &DBClient,
+    assertion: &str,
+) -> Result<AssertionClaims, OAuth2Error> {
+    let header = jsonwebtoken::decode_header(assertion)
+        .map_err(|_| OAuth2Error::InvalidClient("malformed client assertion".into()))?;
+
+    let client_id = client.id.to_string();
+    let issuer = &global.settings.oauth2.issuer;
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[&client_id]);
+    validation.sub = Some(client_id.clone());
+    validation.set_audience(&[issuer.clone(), format!("{issuer}/oauth2/token")]);
+    validation.set_required_spec_claims(&["exp", "iss", "sub", "aud"]);
+
+    let claims: AssertionClaims =
+        verify_client_jwt(global, client, assertion, &header, &validation)
+            .await?
+            .ok_or(OAuth2Error::InvalidClient(
+                "invalid client assertion".into(),
+            ))?;
+
+    let jti = claims.jti.as_deref().ok_or(OAuth2Error::InvalidClient(
+        "client assertion has no jti".into(),
+    ))?;
+    let expires_at = DateTime::from_timestamp(claims.exp, 0).unwrap_or_else(Utc::now);
+
+    let mut transaction = global.database.begin().await?;
+    DBClientAssertion::delete_expired(&mut transaction).await?;
+    let fresh = DBClientAssertion::builder()
+        .jti_hash(crate::crypto::hash_token(&format!("{client_id}:{jti}")))
+        .expires_at(expires_at)
+        .build()
+        .insert_unique(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    if !fresh {
+        return Err(OAuth2Error::InvalidClient(
+            "client assertion was already used".into(),
+        ));
+    }
+
+    Ok(claims)
+}
+
+/// Verifies a JWT signed with one of the client's keys. Returns nothing when none of them fits.
+pub async fn verify_client_jwt<T: DeserializeOwned>(
+    global: &GlobalState,
+    client: &DBClient,
+    token: &str,
+    header: &Header,
+    validation: &Validation,
+) -> Result<Option<T>, OAuth2Error> {
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err(OAuth2Error::InvalidClient(
+            "client JWTs must be signed with an asymmetric key".into(),
+        ));
+    }
+
+    let jwks = client_keys(global, client).await?;
+    let candidates: Vec<&Jwk> = match &header.kid {
+        Some(kid) => jwks.find(kid).into_iter().collect(),
+        None => jwks.keys.iter().collect(),
+    };
+
+    Ok(candidates
+        .into_iter()
+        .filter_map(|jwk| DecodingKey::from_jwk(jwk).ok())
+        .find_map(|key| jsonwebtoken::decode::<T>(token, &key, validation).ok())
+        .map(|data| data.claims))
+}
+
+/// The client's registered keys, or the ones currently published at its jwks_uri.
+async fn client_keys(global: &GlobalState, client: &DBClient) -> Result<JwkSet, OAuth2Error> {
+    if let Some(jwks) = &client.jwks {
+        return Ok(jwks.0.clone());
+    }
+
+    let Some(jwks_uri) = &client.jwks_uri else {
+        return Err(OAuth2Error::InvalidClient(
+            "client has no registered keys".into(),
+        ));
+    };
+
+    let unavailable = |_| OAuth2Error::InvalidClient("could not fetch the client's keys".into());
+    global
+        .http_client
+        .get(jwks_uri)
+        .timeout(std::time::Duration::from_secs(
+            global.settings.oauth2.client_fetch_timeout,
+        ))
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(unavailable)?
+        .json::<JwkSet>()
+        .await
+        .map_err(unavailable)
+}
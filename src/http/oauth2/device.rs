@@ -0,0 +1,263 @@
+use crate::database::models::client::DBClient;
+use crate::database::models::device_code::{DBDeviceCode, DeviceCodeStatus};
+use crate::global::GlobalState;
+use crate::http::internal_error;
+use crate::http::oauth2::authorize::record_consent;
+use crate::http::oauth2::claims::ScopeRegistry;
+use crate::http::oauth2::client_auth::{ClientCredentials, authenticate_client};
+use crate::http::oauth2::error::{OAuth2Error, OAuth2ErrorResponse};
+use crate::http::oauth2::token::{
+    DEVICE_CODE, deserialize_resources, resolve_resources, resolve_scopes,
+};
+use crate::http::session::SessionUser;
+use crate::http::template::{HtmlTemplate, MessageTemplate};
+use askama::Template;
+use axum::Json;
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::http::header::CACHE_CONTROL;
+use axum::response::{IntoResponse, Response};
+use axum_extra::TypedHeader;
+use axum_extra::extract::Form;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::Basic;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+#[derive(Debug, serde::Deserialize, ToSchema)]
+pub struct DeviceAuthorizationRequest {
+    /// Space delimited list of requested scopes
+    pub scope: Option<String>,
+    /// Resources the tokens are for. Can be repeated (RFC 8707)
+    #[serde(default, deserialize_with = "deserialize_resources")]
+    pub resource: Vec<String>,
+    #[serde(flatten)]
+    pub client: ClientCredentials,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct DeviceAuthorizationResponse {
+    pub device_code: String,
+    pub user_code: String,
+    pub verification_uri: String,
+    pub verification_uri_complete: String,
+    pub expires_in: i64,
+    pub interval: i32,
+}
+
+/// Starts a device authorization. (RFC 8628 section 3.1)
+#[utoipa::path(
+    post,
+    path = "/oauth2/device_authorization",
+    tag = "oauth2",
+    request_body(content = DeviceAuthorizationRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, body = DeviceAuthorizationResponse),
+        (status = 400, body = OAuth2ErrorResponse),
+        (status = 401, body = OAuth2ErrorResponse),
+    )
+)]
+pub async fn device_authorization(
+    State(global): State<Arc<GlobalState>>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(request): Form<DeviceAuthorizationRequest>,
+) -> Result<impl IntoResponse, OAuth2Error> {
+    let client = authenticate_client(&global, basic.as_deref(), &request.client).await?;
+
+    if !client.allows_grant_type(DEVICE_CODE) {
+        return Err(OAuth2Error::UnauthorizedClient(
+            "client is not allowed to use the device authorization grant".into(),
+        ));
+    }
+
+    let scopes = resolve_scopes(request.scope.as_deref(), &client.scopes)?;
+    let resources = resolve_resources(&request.resource, &client.audiences)?;
+    let settings = &global.settings.oauth2;
+    let device_code = crate::crypto::generate_token();
+    let user_code = crate::crypto::generate_user_code();
+
+    let mut transaction = global.database.begin().await?;
+    DBDeviceCode::builder()
+        .device_code_hash(crate::crypto::hash_token(&device_code))
+        .user_code(user_code.clone())
+        .client_id(client.id)
+        .scopes(scopes)
+        .resources(resources)
+        .poll_interval(settings.device_code_interval)
+        .expires_at(Utc::now() + Duration::seconds(settings.device_code_lifetime))
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    let user_code = display_user_code(&user_code);
+    let verification_uri = format!("{}/device", settings.issuer);
+    let response = DeviceAuthorizationResponse {
+        device_code,
+        verification_uri_complete: format!("{verification_uri}?user_code={user_code}"),
+        verification_uri,
+        user_code,
+        expires_in: settings.device_code_lifetime,
+        interval: settings.device_code_interval,
+    };
+
+    Ok(([(CACHE_CONTROL, "no-store")], Json(response)))
+}
+
+/// Formats a stored user code the way users get to see it. (`WDJBMJHT` -> `WDJB-MJHT`)
+fn display_user_code(user_code: &str) -> String {
+    let (left, right) = user_code.split_at(user_code.len() / 2);
+    format!("{left}-{right}")
+}
+
+/// Users are sloppy typists, so ignore dashes, spaces and casing.
+fn normalize_user_code(user_code: &str) -> String {
+    user_code
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_uppercase())
+        .collect()
+}
+
+/// Finds a device code that is still waiting for a user, along with the client that asked for it.
+async fn find_pending(
+    global: &GlobalState,
+    user_code: &str,
+) -> Result<Option<(DBDeviceCode, DBClient)>, sqlx::Error> {
+    let device = DBDeviceCode::find_by_user_code(&normalize_user_code(user_code), &global.database)
+        .await?
+        .filter(|device| device.status == DeviceCodeStatus::Pending && !device.is_expired());
+
+    let Some(device) = device else {
+        return Ok(None);
+    };
+
+    let client = DBClient::find_by_id(device.client_id, &global.database).await?;
+    Ok(client.map(|client| (device, client)))
+}
+
+#[derive(Template)]
+#[template(path = "device.html")]
+struct DeviceTemplate<'a> {
+    error: Option<&'a str>,
+    user_code: &'a str,
+}
+
+#[derive(Template)]
+#[template(path = "device_confirm.html")]
+struct DeviceConfirmTemplate<'a> {
+    username: &'a str,
+    client_name: &'a str,
+    user_code: &'a str,
+    /// Descriptions of the requested scopes
+    scopes: Vec<&'a str>,
+}
+
+const INVALID_CODE: &str = "That code is invalid or has expired. Check your device and try again.";
+
+#[derive(Debug, serde::Deserialize)]
+pub struct VerificationQuery {
+    user_code: Option<String>,
+}
+
+/// Where the user types in the code shown on their device. (RFC 8628 section 3.3)
+pub async fn verification_page(
+    State(global): State<Arc<GlobalState>>,
+    current: SessionUser,
+    Query(query): Query<VerificationQuery>,
+) -> Result<Response, StatusCode> {
+    let Some(user_code) = query.user_code.filter(|c| !c.trim().is_empty()) else {
+        return Ok(HtmlTemplate(DeviceTemplate {
+            error: None,
+            user_code: "",
+        })
+        .into_response());
+    };
+
+    let Some((device, client)) = find_pending(&global, &user_code)
+        .await
+        .map_err(internal_error)?
+    else {
+        let page = HtmlTemplate(DeviceTemplate {
+            error: Some(INVALID_CODE),
+            user_code: &user_code,
+        });
+        return Ok((StatusCode::BAD_REQUEST, page).into_response());
+    };
+
+    let registry = ScopeRegistry::load(&global).await.map_err(internal_error)?;
+    Ok(HtmlTemplate(DeviceConfirmTemplate {
+        username: &current.user.username,
+        client_name: &client.name,
+        user_code: &display_user_code(&device.user_code),
+        scopes: device.scopes.iter().map(|s| registry.describe(s)).collect(),
+    })
+    .into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct VerificationForm {
+    user_code: String,
+    decision: String,
+}
+
+pub async fn verification_submit(
+    State(global): State<Arc<GlobalState>>,
+    current: SessionUser,
+    Form(form): Form<VerificationForm>,
+) -> Result<Response, StatusCode> {
+    let Some((mut device, client)) = find_pending(&global, &form.user_code)
+        .await
+        .map_err(internal_error)?
+    else {
+        let page = HtmlTemplate(DeviceTemplate {
+            error: Some(INVALID_CODE),
+            user_code: &form.user_code,
+        });
+        return Ok((StatusCode::BAD_REQUEST, page).into_response());
+    };
+
+    let approved = form.decision == "approve";
+    device.status = match approved {
+        true => DeviceCodeStatus::Approved,
+        false => DeviceCodeStatus::Denied,
+    };
+    device.user_id = Some(current.user.id);
+
+    let mut transaction = global.database.begin().await.map_err(internal_error)?;
+    device
+        .update(&mut transaction)
+        .await
+        .map_err(internal_error)?;
+    if approved {
+        record_consent(
+            &global,
+            &mut transaction,
+            current.user.id,
+            &client,
+            &device.scopes,
+        )
+        .await
+        .map_err(internal_error)?;
+    }
+    transaction.commit().await.map_err(internal_error)?;
+
+    let message = match approved {
+        true => format!(
+            "{} is now connected to your account. You can go back to your device.",
+            client.name
+        ),
+        false => format!("{} was denied access to your account.", client.name),
+    };
+
+    Ok(HtmlTemplate(MessageTemplate {
+        title: if approved {
+            "Device connected"
+        } else {
+            "Access denied"
+        },
+        message: &message,
+    })
+    .into_response())
+}
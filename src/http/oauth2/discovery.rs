@@ -0,0 +1,79 @@
+use crate::global::GlobalState;
+use crate::http::oauth2::error::DPOP_ALGORITHMS;
+use crate::http::oauth2::token::SUPPORTED_GRANT_TYPES;
+use axum::Json;
+use axum::extract::State;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+/// Where clients find this server's endpoints and what it supports. (OpenID Connect Discovery 1.0 section 3)
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct ProviderMetadata {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    pub userinfo_endpoint: String,
+    pub jwks_uri: String,
+    pub introspection_endpoint: String,
+    pub revocation_endpoint: String,
+    pub device_authorization_endpoint: String,
+    pub pushed_authorization_request_endpoint: String,
+    pub end_session_endpoint: String,
+    pub response_types_supported: &'static [&'static str],
+    pub grant_types_supported: &'static [&'static str],
+    pub subject_types_supported: &'static [&'static str],
+    pub id_token_signing_alg_values_supported: &'static [&'static str],
+    pub token_endpoint_auth_methods_supported: &'static [&'static str],
+    pub code_challenge_methods_supported: &'static [&'static str],
+    pub dpop_signing_alg_values_supported: Vec<&'static str>,
+    /// (OpenID Connect Front-Channel Logout 1.0 section 3)
+    pub frontchannel_logout_supported: bool,
+    pub frontchannel_logout_session_supported: bool,
+    /// (OpenID Connect Back-Channel Logout 1.0 section 2.1)
+    pub backchannel_logout_supported: bool,
+    pub backchannel_logout_session_supported: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/.well-known/openid-configuration",
+    tag = "oauth2",
+    responses(
+        (status = 200, body = ProviderMetadata),
+    )
+)]
+pub async fn openid_configuration(
+    State(global): State<Arc<GlobalState>>,
+) -> Json<ProviderMetadata> {
+    let issuer = &global.settings.oauth2.issuer;
+    let endpoint = |path: &str| format!("{issuer}/oauth2/{path}");
+
+    Json(ProviderMetadata {
+        issuer: issuer.clone(),
+        authorization_endpoint: endpoint("authorize"),
+        token_endpoint: endpoint("token"),
+        userinfo_endpoint: endpoint("userinfo"),
+        jwks_uri: endpoint("jwks"),
+        introspection_endpoint: endpoint("introspect"),
+        revocation_endpoint: endpoint("revoke"),
+        device_authorization_endpoint: endpoint("device_authorization"),
+        pushed_authorization_request_endpoint: endpoint("par"),
+        end_session_endpoint: endpoint("logout"),
+        response_types_supported: &["code"],
+        grant_types_supported: SUPPORTED_GRANT_TYPES,
+        subject_types_supported: &["public", "pairwise"],
+        id_token_signing_alg_values_supported: &["ES256"],
+        token_endpoint_auth_methods_supported: &[
+            "client_secret_basic",
+            "client_secret_post",
+            "private_key_jwt",
+            "none",
+        ],
+        code_challenge_methods_supported: &["S256"],
+        dpop_signing_alg_values_supported: DPOP_ALGORITHMS.split(' ').collect(),
+        frontchannel_logout_supported: true,
+        frontchannel_logout_session_supported: true,
+        backchannel_logout_supported: true,
+        backchannel_logout_session_supported: true,
+    })
+}
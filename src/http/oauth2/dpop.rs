@@ -0,0 +1,170 @@
+use crate::database::models::dpop::{DBDPoPNonce, DBDPoPProof};
+use crate::global::GlobalState;
+use crate::http::oauth2::error::OAuth2Error;
+use axum::http::{HeaderMap, HeaderName};
+use chrono::{Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use url::Url;
+
+pub const DPOP: HeaderName = HeaderName::from_static("dpop");
+
+/// How far in the future a proof's `iat` may be, for clients with clocks running ahead.
+const CLOCK_SKEW: i64 = 60;
+
+#[derive(Debug, serde::Deserialize)]
+struct ProofClaims {
+    jti: String,
+    htm: String,
+    htu: String,
+    iat: i64,
+    nonce: Option<String>,
+    ath: Option<String>,
+}
+
+/// What a request is expected to look like, checked against the claims of its DPoP proof.
+pub struct ExpectedProof<'a> {
+    pub method: &'a str,
+    pub url: &'a str,
+    /// The access token presented with the proof, for requests to protected resources
+    pub access_token: Option<&'a str>,
+    /// Whether the proof has to carry a nonce issued by this server
+    pub require_nonce: bool,
+}
+
+/// The single DPoP proof sent with a request, if any. (RFC 9449 section 4.3)
+pub fn proof_header(headers: &HeaderMap) -> Result<Option<&str>, OAuth2Error> {
+    let mut proofs = headers.get_all(DPOP).iter();
+    let Some(proof) = proofs.next() else {
+        return Ok(None);
+    };
+
+    if proofs.next().is_some() {
+        return Err(OAuth2Error::InvalidDPoPProof(
+            "more than one DPoP proof".into(),
+        ));
+    }
+
+    proof
+        .to_str()
+        .map(Some)
+        .map_err(|_| OAuth2Error::InvalidDPoPProof("malformed DPoP proof".into()))
+}
+
+/// Verifies a DPoP proof and returns the thumbprint of the key it was signed with. (RFC 9449 section 4.3)
+///
+/// Every proof can be used once, replays are rejected.
+pub async fn verify_proof(
+    global: &GlobalState,
+    proof: &str,
+    expected: &ExpectedProof<'_>,
+) -> Result<String, OAuth2Error> {
+    let invalid = |reason: &'static str| OAuth2Error::InvalidDPoPProof(reason.into());
+
+    let header = jsonwebtoken::decode_header(proof).map_err(|_| invalid("malformed DPoP proof"))?;
+    if header.typ.as_deref() != Some("dpop+jwt") {
+        return Err(invalid("DPoP proofs must have the dpop+jwt type"));
+    }
+
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err(invalid("DPoP proofs must be signed with an asymmetric key"));
+    }
+
+    let jwk = header
+        .jwk
+        .ok_or(invalid("DPoP proofs must carry their public key"))?;
+    let jkt = crate::crypto::jwk_thumbprint(&jwk).ok_or(invalid("unsupported DPoP key"))?;
+    let key = DecodingKey::from_jwk(&jwk).map_err(|_| invalid("unsupported DPoP key"))?;
+
+    let mut validation = Validation::new(header.alg);
+    validation.validate_exp = false;
+    validation.validate_aud = false;
+    validation.required_spec_claims.clear();
+
+    let claims = jsonwebtoken::decode::<ProofClaims>(proof, &key, &validation)
+        .map_err(|_| invalid("invalid DPoP proof"))?
+        .claims;
+
+    if !claims.htm.eq_ignore_ascii_case(expected.method) {
+        return Err(invalid("DPoP proof is for another method"));
+    }
+
+    if !same_target(&claims.htu, expected.url) {
+        return Err(invalid("DPoP proof is for another url"));
+    }
+
+    let now = Utc::now().timestamp();
+    let lifetime = global.settings.oauth2.dpop_proof_lifetime;
+    if claims.iat < now - lifetime || claims.iat > now + CLOCK_SKEW {
+        return Err(invalid("DPoP proof is too old or from the future"));
+    }
+
+    match (expected.access_token, claims.ath.as_deref()) {
+        (Some(token), Some(ath)) if crate::crypto::verify_pkce_s256(token, ath) => {}
+        (Some(_), _) => return Err(invalid("DPoP proof is for another access token")),
+        (None, _) => {}
+    }
+
+    match claims.nonce.as_deref() {
+        Some(nonce) => {
+            let known =
+                DBDPoPNonce::find_by_hash(&crate::crypto::hash_token(nonce), &global.database)
+                    .await?;
+            if known.is_none() {
+                return Err(OAuth2Error::UseDPoPNonce(issue_nonce(global).await?));
+            }
+        }
+        None if expected.require_nonce => {
+            return Err(OAuth2Error::UseDPoPNonce(issue_nonce(global).await?));
+        }
+        None => {}
+    }
+
+    let mut transaction = global.database.begin().await?;
+    DBDPoPProof::delete_expired(&mut transaction).await?;
+    let fresh = DBDPoPProof::builder()
+        .jti_hash(crate::crypto::hash_token(&format!("{jkt}:{}", claims.jti)))
+        .expires_at(Utc::now() + Duration::seconds(lifetime + CLOCK_SKEW))
+        .build()
+        .insert_unique(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    if !fresh {
+        return Err(invalid("DPoP proof was already used"));
+    }
+
+    Ok(jkt)
+}
+
+/// Hands out a new nonce for clients to put in their next proofs.
+pub async fn issue_nonce(global: &GlobalState) -> Result<String, sqlx::Error> {
+    let nonce = crate::crypto::generate_token();
+
+    let mut transaction = global.database.begin().await?;
+    DBDPoPNonce::delete_expired(&mut transaction).await?;
+    DBDPoPNonce::builder()
+        .nonce_hash(crate::crypto::hash_token(&nonce))
+        .expires_at(Utc::now() + Duration::seconds(global.settings.oauth2.dpop_nonce_lifetime))
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    Ok(nonce)
+}
+
+/// `htu` is compared without its query and fragment. (RFC 9449 section 4.3)
+fn same_target(htu: &str, expected: &str) -> bool {
+    let strip = |url: &str| {
+        Url::parse(url).ok().map(|mut url| {
+            url.set_query(None);
+            url.set_fragment(None);
+            url
+        })
+    };
+
+    matches!((strip(htu), strip(expected)), (Some(a), Some(b)) if a == b)
+}
@@ -0,0 +1,183 @@
+use axum::Json;
+use axum::http::header::{CACHE_CONTROL, WWW_AUTHENTICATE};
+use axum::http::{HeaderName, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use std::borrow::Cow;
+use utoipa::ToSchema;
+
+pub const DPOP_NONCE: HeaderName = HeaderName::from_static("dpop-nonce");
+
+/// What DPoP proofs may be signed with, announced in challenges. (RFC 9449 section 7.1)
+pub const DPOP_ALGORITHMS: &str = "ES256 ES384 EdDSA PS256 PS384 PS512 RS256 RS384 RS512";
+
+/// Errors returned by the oauth2 endpoints, rendered as described in RFC 6749 section 5.2.
+#[derive(Debug, thiserror::Error)]
+pub enum OAuth2Error {
+    #[error("invalid_request: {0}")]
+    InvalidRequest(Cow<'static, str>),
+    #[error("invalid_client: {0}")]
+    InvalidClient(Cow<'static, str>),
+    #[error("invalid_grant: {0}")]
+    InvalidGrant(Cow<'static, str>),
+    #[error("unauthorized_client: {0}")]
+    UnauthorizedClient(Cow<'static, str>),
+    #[error("unsupported_grant_type")]
+    UnsupportedGrantType,
+    #[error("unsupported_response_type")]
+    UnsupportedResponseType,
+    #[error("invalid_scope: {0}")]
+    InvalidScope(Cow<'static, str>),
+    /// The requested audience or resource is not one the client may get tokens for. (RFC 8707 section 2)
+    #[error("invalid_target: {0}")]
+    InvalidTarget(Cow<'static, str>),
+    /// (RFC 9101 section 6.3)
+    #[error("invalid_request_object: {0}")]
+    InvalidRequestObject(Cow<'static, str>),
+    #[error("invalid_request_uri: {0}")]
+    InvalidRequestUri(Cow<'static, str>),
+    #[error("access_denied")]
+    AccessDenied,
+    #[error("authorization_pending")]
+    AuthorizationPending,
+    #[error("slow_down")]
+    SlowDown,
+    #[error("expired_token")]
+    ExpiredToken,
+    /// The bearer token sent to a protected endpoint is unknown or expired. (RFC 6750 section 3.1)
+    #[error("invalid_token: {0}")]
+    InvalidToken(Cow<'static, str>),
+    /// (RFC 9449 section 5)
+    #[error("invalid_dpop_proof: {0}")]
+    InvalidDPoPProof(Cow<'static, str>),
+    /// The DPoP proof has to carry the nonce sent along in the `DPoP-Nonce` header. (RFC 9449 section 8)
+    #[error("use_dpop_nonce")]
+    UseDPoPNonce(String),
+    /// A protected resource turning down a request, challenging the client to authenticate with DPoP instead.
+    /// (RFC 9449 section 7.1)
+    #[error("{0}")]
+    DPoPChallenge(Box<OAuth2Error>),
+    #[error("database error: {0}")]
+    Database(#[from] sqlx::Error),
+    #[error("jwt error: {0}")]
+    Jwt(#[from] jsonwebtoken::errors::Error),
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct OAuth2ErrorResponse {
+    pub error: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_description: Option<String>,
+}
+
+impl OAuth2Error {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidRequest(_) => "invalid_request",
+            Self::InvalidClient(_) => "invalid_client",
+            Self::InvalidGrant(_) => "invalid_grant",
+            Self::UnauthorizedClient(_) => "unauthorized_client",
+            Self::UnsupportedGrantType => "unsupported_grant_type",
+            Self::UnsupportedResponseType => "unsupported_response_type",
+            Self::InvalidScope(_) => "invalid_scope",
+            Self::InvalidTarget(_) => "invalid_target",
+            Self::InvalidRequestObject(_) => "invalid_request_object",
+            Self::InvalidRequestUri(_) => "invalid_request_uri",
+            Self::AccessDenied => "access_denied",
+            Self::AuthorizationPending => "authorization_pending",
+            Self::SlowDown => "slow_down",
+            Self::ExpiredToken => "expired_token",
+            Self::InvalidToken(_) => "invalid_token",
+            Self::InvalidDPoPProof(_) => "invalid_dpop_proof",
+            Self::UseDPoPNonce(_) => "use_dpop_nonce",
+            Self::DPoPChallenge(error) => error.code(),
+            Self::Database(_) | Self::Jwt(_) => "server_error",
+        }
+    }
+
+    fn description(&self) -> Option<String> {
+        match self {
+            Self::InvalidRequest(d)
+            | Self::InvalidClient(d)
+            | Self::InvalidGrant(d)
+            | Self::UnauthorizedClient(d)
+            | Self::InvalidScope(d)
+            | Self::InvalidTarget(d)
+            | Self::InvalidRequestObject(d)
+            | Self::InvalidRequestUri(d)
+            | Self::InvalidToken(d)
+            | Self::InvalidDPoPProof(d) => Some(d.to_string()),
+            Self::DPoPChallenge(error) => error.description(),
+            Self::UnsupportedGrantType
+            | Self::UnsupportedResponseType
+            | Self::AccessDenied
+            | Self::AuthorizationPending
+            | Self::SlowDown
+            | Self::ExpiredToken
+            | Self::UseDPoPNonce(_)
+            | Self::Database(_)
+            | Self::Jwt(_) => None,
+        }
+    }
+
+    fn status(&self) -> StatusCode {
+        match self {
+            Self::InvalidClient(_) | Self::InvalidToken(_) | Self::DPoPChallenge(_) => {
+                StatusCode::UNAUTHORIZED
+            }
+            Self::Database(_) | Self::Jwt(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+impl IntoResponse for OAuth2Error {
+    fn into_response(self) -> Response {
+        match &self {
+            Self::Database(e) => {
+                tracing::error!("Database error while handling an oauth2 request: {e}");
+            }
+            Self::Jwt(e) => tracing::error!("Failed signing a token: {e}"),
+            _ => {}
+        }
+
+        let body = OAuth2ErrorResponse {
+            error: self.code(),
+            error_description: self.description(),
+        };
+
+        let mut response = (self.status(), Json(body)).into_response();
+        let headers = response.headers_mut();
+        headers.insert(CACHE_CONTROL, HeaderValue::from_static("no-store"));
+        match self {
+            Self::InvalidClient(_) => {
+                headers.insert(WWW_AUTHENTICATE, HeaderValue::from_static("Basic"));
+            }
+            Self::InvalidToken(_) => {
+                headers.insert(
+                    WWW_AUTHENTICATE,
+                    HeaderValue::from_static(r#"Bearer error="invalid_token""#),
+                );
+            }
+            Self::UseDPoPNonce(nonce) => {
+                if let Ok(nonce) = HeaderValue::from_str(&nonce) {
+                    headers.insert(DPOP_NONCE, nonce);
+                }
+            }
+            Self::DPoPChallenge(error) => {
+                let challenge =
+                    format!(r#"DPoP error="{}", algs="{DPOP_ALGORITHMS}""#, error.code());
+                if let Ok(challenge) = HeaderValue::from_str(&challenge) {
+                    headers.insert(WWW_AUTHENTICATE, challenge);
+                }
+                if let Self::UseDPoPNonce(nonce) = *error
+                    && let Ok(nonce) = HeaderValue::from_str(&nonce)
+                {
+                    headers.insert(DPOP_NONCE, nonce);
+                }
+            }
+            _ => {}
+        }
+
+        response
+    }
+}
@@ -0,0 +1,203 @@
+use crate::database::models::access_token::{Actor, DBAccessToken};
+use crate::database::models::client::DBClient;
+use crate::database::models::organization::DBOrganization;
+use crate::database::models::refresh_token::DBRefreshToken;
+use crate::database::models::role_assignment::DBRoleAssignment;
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::oauth2::client_auth::{ClientCredentials, authenticate_client};
+use crate::http::oauth2::error::{OAuth2Error, OAuth2ErrorResponse};
+use axum::extract::State;
+use axum::http::header::CACHE_CONTROL;
+use axum::response::IntoResponse;
+use axum::{Form, Json};
+use axum_extra::TypedHeader;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::Basic;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+#[derive(Debug, serde::Deserialize, ToSchema)]
+pub struct IntrospectionRequest {
+    pub token: String,
+    /// `access_token` or `refresh_token`, where to look first
+    pub token_type_hint: Option<String>,
+    #[serde(flatten)]
+    pub client: ClientCredentials,
+}
+
+#[derive(Debug, Default, serde::Serialize, ToSchema)]
+pub struct IntrospectionResponse {
+    pub active: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token_type: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub exp: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iat: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    /// The key the token is bound to
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<Confirmation>,
+    /// Who is acting on behalf of the subject, for exchanged tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub act: Option<Actor>,
+    /// Roles the user has everywhere and in the organization the token is for, when tokens carry roles
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roles: Option<Vec<String>>,
+    /// The organization the token is for
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub org_id: Option<String>,
+}
+
+/// (RFC 7800, RFC 9449 section 6)
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct Confirmation {
+    /// JWK SHA-256 thumbprint of the DPoP key
+    pub jkt: String,
+}
+
+/// Tells resource servers whether a token is active and what it grants. (RFC 7662)
+///
+/// Tokens the calling client is not allowed to see are reported as inactive, same as unknown ones.
+#[utoipa::path(
+    post,
+    path = "/oauth2/introspect",
+    tag = "oauth2",
+    request_body(content = IntrospectionRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, body = IntrospectionResponse),
+        (status = 400, body = OAuth2ErrorResponse),
+        (status = 401, body = OAuth2ErrorResponse),
+    )
+)]
+pub async fn introspect(
+    State(global): State<Arc<GlobalState>>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(request): Form<IntrospectionRequest>,
+) -> Result<impl IntoResponse, OAuth2Error> {
+    let caller = authenticate_client(&global, basic.as_deref(), &request.client).await?;
+
+    if !caller.token_endpoint_auth_method.is_confidential() {
+        return Err(OAuth2Error::UnauthorizedClient(
+            "public clients cannot introspect tokens".into(),
+        ));
+    }
+
+    let token_hash = crate::crypto::hash_token(&request.token);
+    let response = match request.token_type_hint.as_deref() {
+        Some("refresh_token") => {
+            match introspect_refresh_token(&global, &caller, &token_hash).await? {
+                Some(response) => Some(response),
+                None => introspect_access_token(&global, &caller, &token_hash).await?,
+            }
+        }
+        _ => match introspect_access_token(&global, &caller, &token_hash).await? {
+            Some(response) => Some(response),
+            None => introspect_refresh_token(&global, &caller, &token_hash).await?,
+        },
+    };
+
+    Ok((
+        [(CACHE_CONTROL, "no-store")],
+        Json(response.unwrap_or_default()),
+    ))
+}
+
+async fn introspect_access_token(
+    global: &GlobalState,
+    caller: &DBClient,
+    token_hash: &[u8],
+) -> Result<Option<IntrospectionResponse>, sqlx::Error> {
+    let token = DBAccessToken::find_by_hash(token_hash, &global.database)
+        .await?
+        .filter(|token| !token.is_expired())
+        .filter(|token| caller.can_introspect(token.client_id, &token.audiences));
+
+    let Some(token) = token else {
+        return Ok(None);
+    };
+
+    // the subject is the one the token's client sees, it might be pairwise
+    let sub = match token.user_id {
+        Some(user_id) if token.client_id == caller.id => {
+            caller.subject(user_id, &global.settings.oauth2.pairwise_salt)
+        }
+        Some(user_id) => match DBClient::find_by_id(token.client_id, &global.database).await? {
+            Some(client) => client.subject(user_id, &global.settings.oauth2.pairwise_salt),
+            None => return Ok(None),
+        },
+        None => token.client_id.to_string(),
+    };
+
+    // the roles the user has now, opaque tokens get the same ones as JWTs without going stale
+    let roles = match token.user_id {
+        Some(user_id) if global.settings.rbac.roles_in_tokens => {
+            let tenant = match token.organization_id {
+                Some(id) => DBOrganization::find_by_id(id, &global.database)
+                    .await?
+                    .map(Tenant::new),
+                None => None,
+            };
+            let roles =
+                DBRoleAssignment::find_roles(user_id, tenant.as_ref(), &global.database).await?;
+            Some(roles.into_iter().map(|role| role.name).collect())
+        }
+        _ => None,
+    };
+
+    Ok(Some(IntrospectionResponse {
+        active: true,
+        scope: Some(token.scopes.join(" ")),
+        client_id: Some(token.client_id.to_string()),
+        sub: Some(sub),
+        token_type: Some(if token.dpop_jkt.is_some() {
+            "DPoP"
+        } else {
+            "Bearer"
+        }),
+        exp: Some(token.expires_at.timestamp()),
+        iat: Some(token.created_at.timestamp()),
+        aud: (!token.audiences.is_empty()).then_some(token.audiences),
+        iss: Some(global.settings.oauth2.issuer.clone()),
+        cnf: token.dpop_jkt.map(|jkt| Confirmation { jkt }),
+        act: token.act.map(|act| act.0),
+        roles,
+        org_id: token.organization_id.map(|id| id.to_string()),
+    }))
+}
+
+/// Refresh tokens are only ever shown to the client they were issued to.
+async fn introspect_refresh_token(
+    global: &GlobalState,
+    caller: &DBClient,
+    token_hash: &[u8],
+) -> Result<Option<IntrospectionResponse>, sqlx::Error> {
+    let token = DBRefreshToken::find_by_hash(token_hash, &global.database)
+        .await?
+        .filter(|token| !token.is_expired() && token.client_id == caller.id);
+
+    Ok(token.map(|token| IntrospectionResponse {
+        active: true,
+        scope: Some(token.scopes.join(" ")),
+        client_id: Some(token.client_id.to_string()),
+        // the caller is the token's client, it sees the same subject it always does
+        sub: Some(caller.subject(token.user_id, &global.settings.oauth2.pairwise_salt)),
+        token_type: Some("refresh_token"),
+        exp: Some(token.expires_at.timestamp()),
+        iat: Some(token.created_at.timestamp()),
+        aud: (!token.audiences.is_empty()).then_some(token.audiences),
+        iss: Some(global.settings.oauth2.issuer.clone()),
+        cnf: token.dpop_jkt.map(|jkt| Confirmation { jkt }),
+        ..Default::default()
+    }))
+}
@@ -0,0 +1,29 @@
+use crate::database::models::signing_key::DBSigningKey;
+use crate::global::GlobalState;
+use crate::http::internal_error;
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use jsonwebtoken::jwk::JwkSet;
+use std::sync::Arc;
+
+/// The public keys JWTs issued by this server are signed with, so they can be validated without calling back.
+///
+/// Older keys stay listed so tokens signed before a rotation remain valid until they expire.
+#[utoipa::path(
+    get,
+    path = "/oauth2/jwks",
+    tag = "oauth2",
+    responses(
+        (status = 200, description = "A JWK Set (RFC 7517 section 5)", body = Object),
+    )
+)]
+pub async fn jwks(State(global): State<Arc<GlobalState>>) -> Result<Json<JwkSet>, StatusCode> {
+    let keys = DBSigningKey::find_all(&global.database)
+        .await
+        .map_err(internal_error)?;
+
+    Ok(Json(JwkSet {
+        keys: keys.into_iter().map(|key| key.public_jwk.0).collect(),
+    }))
+}
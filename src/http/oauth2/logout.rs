@@ -0,0 +1,469 @@
+use crate::database::models::backchannel_logout::DBBackchannelLogout;
+use crate::database::models::client::{DBClient, DBClientId};
+use crate::database::models::session::DBSession;
+use crate::database::models::session_client::DBSessionClient;
+use crate::database::models::signing_key::DBSigningKey;
+use crate::global::GlobalState;
+use crate::http::oauth2::authorize::AuthorizeError;
+use crate::http::session::SessionUser;
+use crate::http::template::{HtmlTemplate, MessageTemplate};
+use askama::Template;
+use axum::Form;
+use axum::extract::{Query, State};
+use axum::http::header::X_FRAME_OPTIONS;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::CookieJar;
+use axum_extra::extract::cookie::Cookie;
+use chrono::{Duration, Utc};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use std::sync::Arc;
+use url::Url;
+
+const BACKCHANNEL_LOGOUT_EVENT: &str = "http://schemas.openid.net/event/backchannel-logout";
+
+/// Logout tokens are meant to be used right away, clients should reject old ones.
+const LOGOUT_TOKEN_LIFETIME: i64 = 120;
+
+/// Seconds the logged out page waits for front-channel logouts before moving on.
+const REFRESH_DELAY: u32 = 2;
+
+/// The parameters of a logout request. (OpenID Connect RP-Initiated Logout 1.0 section 2)
+#[derive(Debug, Clone, Default, serde::Deserialize)]
+pub struct EndSessionRequest {
+    /// A token previously issued by this server to the client, identifying the user and client
+    pub id_token_hint: Option<String>,
+    pub client_id: Option<String>,
+    pub post_logout_redirect_uri: Option<String>,
+    pub state: Option<String>,
+}
+
+impl EndSessionRequest {
+    /// The parameters as hidden form fields, so the confirmation form can send the same request back.
+    fn params(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("id_token_hint", &self.id_token_hint),
+            ("client_id", &self.client_id),
+            ("post_logout_redirect_uri", &self.post_logout_redirect_uri),
+            ("state", &self.state),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_deref().map(|value| (name, value)))
+        .collect()
+    }
+}
+
+/// A logout request that passed validation.
+struct ValidatedLogout {
+    client: Option<DBClient>,
+    /// The user the hint was issued for
+    subject: Option<String>,
+    /// The login session the hint was issued in
+    session_id: Option<String>,
+    /// The registered post logout redirect uri with the state added
+    redirect_uri: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct HintClaims {
+    sub: String,
+    /// The client the id token was issued to
+    aud: serde_json::Value,
+    sid: Option<String>,
+}
+
+impl HintClaims {
+    fn client_id(&self) -> Option<&str> {
+        match &self.aud {
+            serde_json::Value::String(aud) => Some(aud),
+            serde_json::Value::Array(aud) if aud.len() == 1 => aud[0].as_str(),
+            _ => None,
+        }
+    }
+}
+
+const INVALID_LOGOUT: &str =
+    "This sign out request is invalid. Go back to the application and try again.";
+
+/// Checks a token is an id token signed by this server. Expired tokens are fine, users often log out long after
+/// signing in.
+///
+/// Access and logout tokens are signed with the same keys, their `typ` keeps them from passing as hints.
+async fn verify_hint(global: &GlobalState, hint: &str) -> Result<HintClaims, AuthorizeError> {
+    let header =
+        jsonwebtoken::decode_header(hint).map_err(|_| AuthorizeError::Page(INVALID_LOGOUT))?;
+    if header
+        .typ
+        .as_deref()
+        .is_some_and(|typ| !typ.eq_ignore_ascii_case("JWT"))
+    {
+        return Err(AuthorizeError::Page(INVALID_LOGOUT));
+    }
+    let keys = DBSigningKey::find_all(&global.database).await?;
+    let key = keys
+        .iter()
+        .find(|key| header.kid.as_deref() == Some(&key.id.to_string()))
+        .and_then(|key| DecodingKey::from_jwk(&key.public_jwk).ok())
+        .ok_or(AuthorizeError::Page(INVALID_LOGOUT))?;
+
+    let mut validation = Validation::new(Algorithm::ES256);
+    validation.validate_exp = false;
+    // checked against the client below, there is only one it can be for
+    validation.validate_aud = false;
+    validation.required_spec_claims.clear();
+    validation.set_issuer(&[&global.settings.oauth2.issuer]);
+
+    let claims = jsonwebtoken::decode::<HintClaims>(hint, &key, &validation)
+        .map(|data| data.claims)
+        .map_err(|_| AuthorizeError::Page(INVALID_LOGOUT))?;
+    if claims.client_id().is_none() {
+        return Err(AuthorizeError::Page(INVALID_LOGOUT));
+    }
+    Ok(claims)
+}
+
+/// Only registered post logout redirect uris are used, and only when the client they belong to is known.
+async fn validate(
+    global: &GlobalState,
+    request: &EndSessionRequest,
+) -> Result<ValidatedLogout, AuthorizeError> {
+    let hint = match &request.id_token_hint {
+        Some(hint) => Some(verify_hint(global, hint).await?),
+        None => None,
+    };
+
+    let client_id = match (
+        request.client_id.as_deref(),
+        hint.as_ref().and_then(HintClaims::client_id),
+    ) {
+        (Some(a), Some(b)) if a != b => return Err(AuthorizeError::Page(INVALID_LOGOUT)),
+        (Some(client_id), _) | (None, Some(client_id)) => Some(client_id),
+        (None, None) => None,
+    };
+
+    let client = match client_id {
+        Some(client_id) => {
+            let id = client_id
+                .parse::<DBClientId>()
+                .map_err(|_| AuthorizeError::Page(INVALID_LOGOUT))?;
+            Some(
+                DBClient::find_by_id(id, &global.database)
+                    .await?
+                    .ok_or(AuthorizeError::Page(INVALID_LOGOUT))?,
+            )
+        }
+        None => None,
+    };
+
+    let redirect_uri = match (&request.post_logout_redirect_uri, &client) {
+        (Some(uri), Some(client)) if client.allows_post_logout_redirect_uri(uri) => {
+            let mut url = Url::parse(uri).map_err(|_| AuthorizeError::Page(INVALID_LOGOUT))?;
+            if let Some(state) = &request.state {
+                url.query_pairs_mut().append_pair("state", state);
+            }
+            Some(url.into())
+        }
+        (Some(_), _) => return Err(AuthorizeError::Page(INVALID_LOGOUT)),
+        (None, _) => None,
+    };
+
+    let (subject, session_id) = match hint {
+        Some(hint) => (Some(hint.sub), hint.sid),
+        None => (None, None),
+    };
+
+    Ok(ValidatedLogout {
+        client,
+        subject,
+        session_id,
+        redirect_uri,
+    })
+}
+
+#[derive(Template)]
+#[template(path = "logout_confirm.html")]
+struct LogoutConfirmTemplate<'a> {
+    username: &'a str,
+    client_name: Option<&'a str>,
+    params: Vec<(&'static str, &'a str)>,
+}
+
+#[derive(Template)]
+#[template(path = "logged_out.html")]
+struct LoggedOutTemplate<'a> {
+    continue_to: &'a str,
+    refresh_delay: u32,
+    frontchannel_uris: Vec<String>,
+}
+
+/// The end session endpoint. (OpenID Connect RP-Initiated Logout 1.0 section 2)
+///
+/// Users are asked to confirm unless the hint shows the request comes from a client they are signed into, in the
+/// session they are signed in with now.
+pub async fn end_session_page(
+    State(global): State<Arc<GlobalState>>,
+    jar: CookieJar,
+    Query(request): Query<EndSessionRequest>,
+) -> Result<Response, AuthorizeError> {
+    let validated = validate(&global, &request).await?;
+
+    let Some(current) = SessionUser::from_jar(&global, &jar).await? else {
+        return Ok(logged_out(
+            &global,
+            jar,
+            Vec::new(),
+            validated.redirect_uri.as_deref(),
+        ));
+    };
+
+    // the hint carries the subject its client sees, not necessarily the user id
+    let subject = match &validated.client {
+        Some(client) => client.subject(current.user.id, &global.settings.oauth2.pairwise_salt),
+        None => current.user.id.to_string(),
+    };
+    let session_id = current.session.id.to_string();
+    if validated.subject.as_deref() == Some(&subject)
+        && validated.session_id.as_deref() == Some(&session_id)
+    {
+        let frontchannel_uris = end_session(&global, &current.session).await?;
+        return Ok(logged_out(
+            &global,
+            jar,
+            frontchannel_uris,
+            validated.redirect_uri.as_deref(),
+        ));
+    }
+
+    let page = HtmlTemplate(LogoutConfirmTemplate {
+        username: &current.user.username,
+        client_name: validated.client.as_ref().map(|client| client.name.as_str()),
+        params: request.params(),
+    });
+
+    Ok(([(X_FRAME_OPTIONS, "DENY")], page).into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct EndSessionForm {
+    #[serde(flatten)]
+    request: EndSessionRequest,
+    decision: String,
+}
+
+pub async fn end_session_submit(
+    State(global): State<Arc<GlobalState>>,
+    jar: CookieJar,
+    Form(form): Form<EndSessionForm>,
+) -> Result<Response, AuthorizeError> {
+    let validated = validate(&global, &form.request).await?;
+
+    if form.decision != "logout" {
+        return Ok(HtmlTemplate(MessageTemplate {
+            title: "Still signed in",
+            message: "You are still signed in. You can close this page.",
+        })
+        .into_response());
+    }
+
+    let mut frontchannel_uris = Vec::new();
+    if let Some(current) = SessionUser::from_jar(&global, &jar).await? {
+        frontchannel_uris = end_session(&global, &current.session).await?;
+    }
+
+    Ok(logged_out(
+        &global,
+        jar,
+        frontchannel_uris,
+        validated.redirect_uri.as_deref(),
+    ))
+}
+
+/// Ends a login session and queues logout tokens for every client signed into it with a back-channel logout uri.
+///
+/// Returns the front-channel logout uris the browser still has to load, SAML logout requests included.
+///
+/// The `sid` sent on both channels is the id of our login session, the same one the id tokens issued in it carry.
+pub async fn end_session(
+    global: &GlobalState,
+    session: &DBSession,
+) -> Result<Vec<String>, sqlx::Error> {
+    let client_ids: Vec<DBClientId> =
+        DBSessionClient::find_many_by_session(session.id, &global.database)
+            .await?
+            .into_iter()
+            .map(|session_client| session_client.client_id)
+            .collect();
+    let clients = DBClient::find_many_by_id(&client_ids, &global.database).await?;
+    // looked up before the session and what it knows about service providers go
+    let saml_uris = crate::http::saml::logout::frontchannel_logout_urls(global, session).await?;
+
+    let mut transaction = global.database.begin().await?;
+    session.delete(&mut transaction).await?;
+    for client in clients
+        .iter()
+        .filter(|c| c.backchannel_logout_uri.is_some())
+    {
+        DBBackchannelLogout::builder()
+            .client_id(client.id)
+            .user_id(session.user_id)
+            .session_id(session.id)
+            .build()
+            .insert(&mut transaction)
+            .await?;
+    }
+    transaction.commit().await?;
+
+    let issuer = &global.settings.oauth2.issuer;
+    let session_id = session.id.to_string();
+    Ok(clients
+        .iter()
+        .filter_map(|client| client.frontchannel_logout_uri.as_deref())
+        .filter_map(|uri| Url::parse(uri).ok())
+        .map(|mut url| {
+            url.query_pairs_mut()
+                .append_pair("iss", issuer)
+                .append_pair("sid", &session_id);
+            url.into()
+        })
+        .chain(saml_uris)
+        .collect())
+}
+
+/// Removes the session cookie and shows the logged out page, which loads the front-channel logout uris before moving on.
+pub fn logged_out(
+    global: &GlobalState,
+    jar: CookieJar,
+    frontchannel_uris: Vec<String>,
+    continue_to: Option<&str>,
+) -> Response {
+    let jar = jar.remove(Cookie::build(global.settings.session.cookie_name.clone()).path("/"));
+    let page = HtmlTemplate(LoggedOutTemplate {
+        continue_to: continue_to.unwrap_or("/login"),
+        refresh_delay: REFRESH_DELAY,
+        frontchannel_uris,
+    });
+
+    (jar, page).into_response()
+}
+
+/// Claims of a logout token. (OpenID Connect Back-Channel Logout 1.0 section 2.4)
+#[derive(Debug, serde::Serialize)]
+struct LogoutTokenClaims<'a> {
+    iss: &'a str,
+    sub: String,
+    aud: String,
+    iat: i64,
+    exp: i64,
+    jti: String,
+    /// Our login session, see [`end_session`]
+    sid: String,
+    events: serde_json::Value,
+}
+
+/// Delivers queued logout tokens every few seconds, until the server shuts down.
+pub async fn run_backchannel_worker(global: Arc<GlobalState>) {
+    let interval = std::time::Duration::from_secs(global.settings.logout.backchannel_poll_interval);
+    loop {
+        if let Err(e) = deliver_backchannel_logouts(&global).await {
+            tracing::error!("Failed delivering back-channel logouts: {e}");
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Logout tokens delivered per round.
+const BACKCHANNEL_BATCH_SIZE: i64 = 16;
+
+/// Delivers the logout tokens that are due. Failed deliveries are retried later with a growing delay.
+///
+/// Deliveries are claimed in a transaction of their own and sent after it committed, no locks are held while
+/// waiting on clients. A server that stops halfway leaves the rest to be picked up once their lease runs out.
+pub async fn deliver_backchannel_logouts(global: &GlobalState) -> Result<(), sqlx::Error> {
+    let settings = &global.settings.logout;
+    // long enough for every delivery of the round to time out
+    let lease = settings.backchannel_timeout as i64 * BACKCHANNEL_BATCH_SIZE
+        + settings.backchannel_poll_interval as i64;
+
+    let mut transaction = global.database.begin().await?;
+    let deliveries = DBBackchannelLogout::claim_due(
+        BACKCHANNEL_BATCH_SIZE,
+        Utc::now() + Duration::seconds(lease),
+        &mut transaction,
+    )
+    .await?;
+    transaction.commit().await?;
+
+    for mut delivery in deliveries {
+        let result = deliver(global, &delivery).await;
+
+        let mut transaction = global.database.begin().await?;
+        match result {
+            Ok(()) => delivery.delete(&mut transaction).await?,
+            Err(error) => {
+                delivery.attempts += 1;
+                if delivery.attempts >= settings.backchannel_max_attempts {
+                    tracing::warn!(
+                        "Giving up on back-channel logout {} for client {}: {error}",
+                        delivery.id,
+                        delivery.client_id
+                    );
+                    delivery.delete(&mut transaction).await?;
+                } else {
+                    let delay = settings.backchannel_retry_delay << (delivery.attempts - 1).min(16);
+                    delivery.next_attempt_at = Utc::now() + Duration::seconds(delay);
+                    delivery.last_error = Some(error);
+                    delivery.update(&mut transaction).await?;
+                }
+            }
+        }
+        transaction.commit().await?;
+    }
+
+    Ok(())
+}
+
+/// POSTs a fresh logout token to the client. (OpenID Connect Back-Channel Logout 1.0 section 2.5)
+async fn deliver(global: &GlobalState, delivery: &DBBackchannelLogout) -> Result<(), String> {
+    let client = DBClient::find_by_id(delivery.client_id, &global.database)
+        .await
+        .map_err(|e| e.to_string())?;
+    // nothing to deliver to anymore
+    let Some((client, uri)) = client.and_then(|client| {
+        let uri = client.backchannel_logout_uri.clone()?;
+        Some((client, uri))
+    }) else {
+        return Ok(());
+    };
+
+    let now = Utc::now().timestamp();
+    let claims = LogoutTokenClaims {
+        iss: &global.settings.oauth2.issuer,
+        sub: client.subject(delivery.user_id, &global.settings.oauth2.pairwise_salt),
+        aud: delivery.client_id.to_string(),
+        iat: now,
+        exp: now + LOGOUT_TOKEN_LIFETIME,
+        jti: crate::crypto::generate_token(),
+        sid: delivery.session_id.to_string(),
+        events: serde_json::json!({ BACKCHANNEL_LOGOUT_EVENT: {} }),
+    };
+    let token = global
+        .signing_key
+        .sign("logout+jwt", &claims)
+        .map_err(|e| e.to_string())?;
+
+    let response = global
+        .http_client
+        .post(&uri)
+        .timeout(std::time::Duration::from_secs(
+            global.settings.logout.backchannel_timeout,
+        ))
+        .form(&[("logout_token", token)])
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    if !response.status().is_success() {
+        return Err(format!("client answered with {}", response.status()));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,45 @@
+use crate::global::GlobalState;
+use axum::routing::get;
+use std::sync::Arc;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+pub mod authorize;
+pub mod claims;
+pub mod client_auth;
+pub mod device;
+pub mod discovery;
+pub mod dpop;
+pub mod error;
+pub mod introspect;
+pub mod jwks;
+pub mod logout;
+pub mod par;
+pub mod resource;
+pub mod revoke;
+pub mod token;
+pub mod userinfo;
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .routes(routes!(token::token))
+        .routes(routes!(device::device_authorization))
+        .routes(routes!(discovery::openid_configuration))
+        .routes(routes!(introspect::introspect))
+        .routes(routes!(jwks::jwks))
+        .routes(routes!(par::pushed_authorization_request))
+        .routes(routes!(revoke::revoke))
+        .routes(routes!(userinfo::userinfo))
+        .route(
+            "/oauth2/authorize",
+            get(authorize::authorize).post(authorize::consent),
+        )
+        .route(
+            "/oauth2/logout",
+            get(logout::end_session_page).post(logout::end_session_submit),
+        )
+        .route(
+            "/device",
+            get(device::verification_page).post(device::verification_submit),
+        )
+}
@@ -0,0 +1,122 @@
+use crate::database::models::pushed_authorization_request::DBPushedAuthorizationRequest;
+use crate::global::GlobalState;
+use crate::http::oauth2::authorize::{
+    AuthorizationRequest, AuthorizeError, request_object_parameters, validate_parameters,
+};
+use crate::http::oauth2::client_auth::{ClientCredentials, authenticate_client};
+use crate::http::oauth2::error::{OAuth2Error, OAuth2ErrorResponse};
+use axum::Json;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::http::header::CACHE_CONTROL;
+use axum::response::IntoResponse;
+use axum_extra::TypedHeader;
+use axum_extra::extract::Form;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::Basic;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+pub const REQUEST_URI_PREFIX: &str = "urn:ietf:params:oauth:request_uri:";
+
+#[derive(Debug, serde::Deserialize, ToSchema)]
+pub struct PushedAuthorizationRequest {
+    // client comes first so it gets the client_id, the authorization request gets it back after authentication
+    #[serde(flatten)]
+    pub client: ClientCredentials,
+    #[serde(flatten)]
+    pub request: AuthorizationRequest,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct PushedAuthorizationResponse {
+    /// Send the user to the authorization endpoint with this and the client_id
+    pub request_uri: String,
+    pub expires_in: i64,
+}
+
+/// Lets clients send the parameters of an authorization request over the back channel, before redirecting the user. (RFC 9126)
+///
+/// The parameters are validated right away, so a bad request fails here instead of in front of the user.
+#[utoipa::path(
+    post,
+    path = "/oauth2/par",
+    tag = "oauth2",
+    request_body(content = PushedAuthorizationRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 201, body = PushedAuthorizationResponse),
+        (status = 400, body = OAuth2ErrorResponse),
+        (status = 401, body = OAuth2ErrorResponse),
+    )
+)]
+pub async fn pushed_authorization_request(
+    State(global): State<Arc<GlobalState>>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(pushed): Form<PushedAuthorizationRequest>,
+) -> Result<impl IntoResponse, OAuth2Error> {
+    let client = authenticate_client(&global, basic.as_deref(), &pushed.client).await?;
+
+    let mut request = pushed.request;
+    if request.request_uri.is_some() {
+        return Err(OAuth2Error::InvalidRequest(
+            "request_uri cannot be pushed".into(),
+        ));
+    }
+    request.client_id = Some(client.id.to_string());
+
+    // a pushed request object is verified right away, only the parameters inside are stored (RFC 9126 section 3)
+    if let Some(object) = &request.request {
+        request = request_object_parameters(&global, &client, object).await?;
+    }
+
+    if request
+        .redirect_uri
+        .as_deref()
+        .is_some_and(|uri| !client.allows_redirect_uri(uri))
+    {
+        return Err(OAuth2Error::InvalidRequest(
+            "redirect_uri is not registered for this client".into(),
+        ));
+    }
+
+    let validated = validate_parameters(client, &request).map_err(|e| match e {
+        AuthorizeError::Redirect {
+            error, description, ..
+        } => match error {
+            "unsupported_response_type" => OAuth2Error::UnsupportedResponseType,
+            "unauthorized_client" => OAuth2Error::UnauthorizedClient(description.into()),
+            "invalid_scope" => OAuth2Error::InvalidScope(description.into()),
+            "invalid_target" => OAuth2Error::InvalidTarget(description.into()),
+            _ => OAuth2Error::InvalidRequest(description.into()),
+        },
+        AuthorizeError::Page(message) => OAuth2Error::InvalidRequest(message.into()),
+        AuthorizeError::Internal(e) => OAuth2Error::Database(e),
+    })?;
+
+    let lifetime = global.settings.oauth2.pushed_authorization_request_lifetime;
+    let reference = crate::crypto::generate_token();
+    let parameters = serde_json::to_value(&request).map_err(|e| {
+        OAuth2Error::InvalidRequest(format!("could not store the request: {e}").into())
+    })?;
+
+    let mut transaction = global.database.begin().await?;
+    DBPushedAuthorizationRequest::builder()
+        .reference_hash(crate::crypto::hash_token(&reference))
+        .client_id(validated.client.id)
+        .parameters(parameters)
+        .expires_at(Utc::now() + Duration::seconds(lifetime))
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    Ok((
+        StatusCode::CREATED,
+        [(CACHE_CONTROL, "no-store")],
+        Json(PushedAuthorizationResponse {
+            request_uri: format!("{REQUEST_URI_PREFIX}{reference}"),
+            expires_in: lifetime,
+        }),
+    ))
+}
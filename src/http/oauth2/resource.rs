@@ -0,0 +1,73 @@
+use crate::database::models::access_token::DBAccessToken;
+use crate::global::GlobalState;
+use crate::http::oauth2::dpop::{self, ExpectedProof};
+use crate::http::oauth2::error::OAuth2Error;
+use axum::http::header::AUTHORIZATION;
+use axum::http::{HeaderMap, Method};
+
+/// Finds the access token a request to a protected endpoint carries.
+///
+/// Bearer tokens are sent as `Bearer`, DPoP bound ones as `DPoP` together with a proof for this request. (RFC 6750, RFC 9449 section 7)
+pub async fn authenticate_token(
+    global: &GlobalState,
+    method: &Method,
+    headers: &HeaderMap,
+    url: &str,
+) -> Result<DBAccessToken, OAuth2Error> {
+    let (scheme, presented) = headers
+        .get(AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split_once(' '))
+        .ok_or(OAuth2Error::InvalidToken("missing access token".into()))?;
+    let presented = presented.trim();
+
+    let token =
+        DBAccessToken::find_by_hash(&crate::crypto::hash_token(presented), &global.database)
+            .await?
+            .filter(|token| !token.is_expired())
+            .ok_or(OAuth2Error::InvalidToken("invalid access token".into()))?;
+
+    match (scheme.to_ascii_lowercase().as_str(), &token.dpop_jkt) {
+        ("bearer", None) => Ok(token),
+        ("dpop", Some(jkt)) => {
+            let proof = dpop::proof_header(headers)
+                .map_err(challenge)?
+                .ok_or_else(|| {
+                    challenge(OAuth2Error::InvalidDPoPProof("missing DPoP proof".into()))
+                })?;
+            let expected = ExpectedProof {
+                method: method.as_str(),
+                url,
+                access_token: Some(presented),
+                require_nonce: global.settings.oauth2.dpop_resource_require_nonce,
+            };
+
+            if dpop::verify_proof(global, proof, &expected)
+                .await
+                .map_err(challenge)?
+                != *jkt
+            {
+                return Err(challenge(OAuth2Error::InvalidToken(
+                    "access token is bound to another DPoP key".into(),
+                )));
+            }
+
+            Ok(token)
+        }
+        ("bearer", Some(_)) => Err(challenge(OAuth2Error::InvalidToken(
+            "DPoP bound access tokens cannot be used as bearer tokens".into(),
+        ))),
+        _ => Err(OAuth2Error::InvalidToken(
+            "unsupported authorization scheme".into(),
+        )),
+    }
+}
+
+/// Errors of requests for DPoP bound tokens challenge the client to use DPoP, with a 401 like every other
+/// authentication error at protected resources. The token endpoint answers bad proofs with a plain 400 instead.
+fn challenge(error: OAuth2Error) -> OAuth2Error {
+    match error {
+        OAuth2Error::Database(_) | OAuth2Error::Jwt(_) => error,
+        error => OAuth2Error::DPoPChallenge(Box::new(error)),
+    }
+}
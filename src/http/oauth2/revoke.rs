@@ -0,0 +1,69 @@
+use crate::database::models::access_token::DBAccessToken;
+use crate::database::models::refresh_token::DBRefreshToken;
+use crate::global::GlobalState;
+use crate::http::oauth2::client_auth::{ClientCredentials, authenticate_client};
+use crate::http::oauth2::error::{OAuth2Error, OAuth2ErrorResponse};
+use axum::Form;
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum_extra::TypedHeader;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::Basic;
+use std::sync::Arc;
+use utoipa::ToSchema;
+
+#[derive(Debug, serde::Deserialize, ToSchema)]
+pub struct RevocationRequest {
+    pub token: String,
+    /// `access_token` or `refresh_token`. Accepted, but both kinds are always looked up
+    pub token_type_hint: Option<String>,
+    #[serde(flatten)]
+    pub client: ClientCredentials,
+}
+
+/// Revokes a token. (RFC 7009)
+///
+/// Revoking a refresh token also revokes every token issued from the same authorization.
+/// Unknown tokens are not an error, the client wanted the token gone and it is.
+#[utoipa::path(
+    post,
+    path = "/oauth2/revoke",
+    tag = "oauth2",
+    request_body(content = RevocationRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, description = "The token is no longer valid"),
+        (status = 400, body = OAuth2ErrorResponse),
+        (status = 401, body = OAuth2ErrorResponse),
+    )
+)]
+pub async fn revoke(
+    State(global): State<Arc<GlobalState>>,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(request): Form<RevocationRequest>,
+) -> Result<StatusCode, OAuth2Error> {
+    let client = authenticate_client(&global, basic.as_deref(), &request.client).await?;
+    let token_hash = crate::crypto::hash_token(&request.token);
+    let not_yours =
+        || OAuth2Error::UnauthorizedClient("token was not issued to this client".into());
+
+    let mut transaction = global.database.begin().await?;
+    if let Some(token) =
+        DBRefreshToken::find_by_hash_for_update(&token_hash, &mut transaction).await?
+    {
+        if token.client_id != client.id {
+            return Err(not_yours());
+        }
+
+        DBRefreshToken::delete_by_family(token.family_id, &mut transaction).await?;
+        DBAccessToken::delete_by_family(token.family_id, &mut transaction).await?;
+    } else if let Some(token) = DBAccessToken::find_by_hash(&token_hash, &global.database).await? {
+        if token.client_id != client.id {
+            return Err(not_yours());
+        }
+
+        token.delete(&mut transaction).await?;
+    }
+    transaction.commit().await?;
+
+    Ok(StatusCode::OK)
+}
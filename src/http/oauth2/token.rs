@@ -0,0 +1,915 @@
+use crate::database::ids::UlidId;
+use crate::database::models::access_token::{Actor, DBAccessToken};
+use crate::database::models::authorization_code::DBAuthorizationCode;
+use crate::database::models::client::{AccessTokenFormat, DBClient};
+use crate::database::models::device_code::{DBDeviceCode, DeviceCodeStatus};
+use crate::database::models::refresh_token::DBRefreshToken;
+use crate::database::models::resource::DBResource;
+use crate::database::models::role_assignment::DBRoleAssignment;
+use crate::database::models::user::DBUserId;
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::oauth2::client_auth::{ClientCredentials, authenticate_client};
+use crate::http::oauth2::dpop::{self, ExpectedProof};
+use crate::http::oauth2::error::{DPOP_NONCE, OAuth2Error, OAuth2ErrorResponse};
+use crate::http::oauth2::introspect::Confirmation;
+use crate::http::tenant::RequestTenant;
+use axum::Json;
+use axum::extract::State;
+use axum::http::header::{CACHE_CONTROL, PRAGMA};
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum_extra::TypedHeader;
+use axum_extra::extract::Form;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::Basic;
+use chrono::{Duration, Utc};
+use std::sync::Arc;
+use typed_builder::TypedBuilder;
+use url::Url;
+use utoipa::ToSchema;
+
+pub const AUTHORIZATION_CODE: &str = "authorization_code";
+pub const CLIENT_CREDENTIALS: &str = "client_credentials";
+pub const DEVICE_CODE: &str = "urn:ietf:params:oauth:grant-type:device_code";
+pub const REFRESH_TOKEN: &str = "refresh_token";
+pub const TOKEN_EXCHANGE: &str = "urn:ietf:params:oauth:grant-type:token-exchange";
+
+/// The scope that makes an authorization request an OpenID Connect one, answered with an id token.
+pub const OPENID: &str = "openid";
+
+pub const SUPPORTED_GRANT_TYPES: &[&str] = &[
+    AUTHORIZATION_CODE,
+    CLIENT_CREDENTIALS,
+    DEVICE_CODE,
+    REFRESH_TOKEN,
+    TOKEN_EXCHANGE,
+];
+
+/// Token type identifiers used by token exchange. (RFC 8693 section 3)
+pub const ACCESS_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:access_token";
+pub const JWT_TOKEN_TYPE: &str = "urn:ietf:params:oauth:token-type:jwt";
+
+#[derive(Debug, serde::Deserialize, ToSchema)]
+pub struct TokenRequest {
+    pub grant_type: String,
+    /// Space delimited list of requested scopes
+    pub scope: Option<String>,
+    /// Required for the authorization code grant
+    pub code: Option<String>,
+    /// Has to match the redirect_uri of the authorization request
+    pub redirect_uri: Option<String>,
+    /// PKCE verifier, required when the authorization request had a code_challenge
+    pub code_verifier: Option<String>,
+    /// Required for the device code grant
+    pub device_code: Option<String>,
+    /// Required for the refresh token grant
+    pub refresh_token: Option<String>,
+    /// The token being exchanged, required for the token exchange grant
+    pub subject_token: Option<String>,
+    pub subject_token_type: Option<String>,
+    /// A token of whoever is acting on behalf of the subject, the client itself when left out
+    pub actor_token: Option<String>,
+    pub actor_token_type: Option<String>,
+    /// Only access tokens can be requested
+    pub requested_token_type: Option<String>,
+    /// Where the exchanged token will be used, one of the client's audiences
+    pub audience: Option<String>,
+    /// Resources the access token is for, narrowing down the ones granted. Can be repeated (RFC 8707)
+    #[serde(default, deserialize_with = "deserialize_resources")]
+    pub resource: Vec<String>,
+    #[serde(flatten)]
+    pub client: ClientCredentials,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct TokenResponse {
+    pub access_token: String,
+    pub token_type: &'static str,
+    pub expires_in: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope: Option<String>,
+    /// Issued from authorization codes when the openid scope was granted (OpenID Connect Core 1.0 section 3.1.3.3)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id_token: Option<String>,
+    /// Set for token exchange responses
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub issued_token_type: Option<&'static str>,
+}
+
+/// Issues tokens. (RFC 6749 section 3.2)
+#[utoipa::path(
+    post,
+    path = "/oauth2/token",
+    tag = "oauth2",
+    request_body(content = TokenRequest, content_type = "application/x-www-form-urlencoded"),
+    responses(
+        (status = 200, body = TokenResponse),
+        (status = 400, body = OAuth2ErrorResponse),
+        (status = 401, body = OAuth2ErrorResponse),
+    )
+)]
+pub async fn token(
+    State(global): State<Arc<GlobalState>>,
+    RequestTenant(tenant): RequestTenant,
+    headers: HeaderMap,
+    basic: Option<TypedHeader<Authorization<Basic>>>,
+    Form(request): Form<TokenRequest>,
+) -> Result<Response, OAuth2Error> {
+    let client = authenticate_client(&global, basic.as_deref(), &request.client).await?;
+
+    if !SUPPORTED_GRANT_TYPES.contains(&request.grant_type.as_str()) {
+        return Err(OAuth2Error::UnsupportedGrantType);
+    }
+
+    if !client.allows_grant_type(&request.grant_type) {
+        return Err(OAuth2Error::UnauthorizedClient(
+            "client is not allowed to use this grant type".into(),
+        ));
+    }
+
+    let dpop_jkt = match dpop::proof_header(&headers)? {
+        Some(proof) => {
+            let expected = ExpectedProof {
+                method: "POST",
+                url: &format!("{}/oauth2/token", global.settings.oauth2.issuer),
+                access_token: None,
+                require_nonce: global.settings.oauth2.dpop_require_nonce,
+            };
+            Some(dpop::verify_proof(&global, proof, &expected).await?)
+        }
+        None if client.dpop_bound_access_tokens => {
+            return Err(OAuth2Error::InvalidDPoPProof(
+                "client has to send DPoP proofs".into(),
+            ));
+        }
+        None => None,
+    };
+    let dpop_jkt = dpop_jkt.as_deref();
+    // tokens requested for an organization carry the user's roles there
+    let tenant = tenant.as_ref();
+
+    let response = match request.grant_type.as_str() {
+        AUTHORIZATION_CODE => {
+            authorization_code(&global, &client, &request, dpop_jkt, tenant).await?
+        }
+        CLIENT_CREDENTIALS => {
+            client_credentials(&global, &client, &request, dpop_jkt, tenant).await?
+        }
+        DEVICE_CODE => device_code(&global, &client, &request, dpop_jkt, tenant).await?,
+        REFRESH_TOKEN => refresh_token(&global, &client, &request, dpop_jkt, tenant).await?,
+        TOKEN_EXCHANGE => token_exchange(&global, &client, &request, dpop_jkt, tenant).await?,
+        _ => return Err(OAuth2Error::UnsupportedGrantType),
+    };
+
+    let mut response = (
+        [(CACHE_CONTROL, "no-store"), (PRAGMA, "no-cache")],
+        Json(response),
+    )
+        .into_response();
+
+    // hand out the nonce for the next proof right away, saves the client a round trip
+    if dpop_jkt.is_some() && global.settings.oauth2.dpop_require_nonce {
+        let nonce = dpop::issue_nonce(&global).await?;
+        if let Ok(nonce) = HeaderValue::from_str(&nonce) {
+            response.headers_mut().insert(DPOP_NONCE, nonce);
+        }
+    }
+
+    Ok(response)
+}
+
+/// What a set of tokens is being issued for.
+#[derive(Debug, Clone, TypedBuilder)]
+pub struct TokenGrant {
+    /// Empty when the client acts on its own behalf
+    #[builder(default)]
+    pub user_id: Option<DBUserId>,
+    /// Shared by every token issued from the same authorization. Required for refresh tokens
+    #[builder(default)]
+    pub family_id: Option<UlidId>,
+    pub scopes: Vec<String>,
+    pub audiences: Vec<String>,
+    /// Thumbprint of the DPoP key the tokens are bound to
+    #[builder(default)]
+    pub dpop_jkt: Option<String>,
+    /// The delegation chain of exchanged tokens
+    #[builder(default)]
+    pub act: Option<Actor>,
+    /// The organization the tokens are requested for. Access tokens carry the user's roles there
+    #[builder(default)]
+    pub tenant: Option<Tenant>,
+}
+
+/// Trades an authorization code for tokens. Codes can be used only once. (RFC 6749 section 4.1.3)
+async fn authorization_code(
+    global: &GlobalState,
+    client: &DBClient,
+    request: &TokenRequest,
+    dpop_jkt: Option<&str>,
+    tenant: Option<&Tenant>,
+) -> Result<TokenResponse, OAuth2Error> {
+    let code = request
+        .code
+        .as_deref()
+        .ok_or(OAuth2Error::InvalidRequest("missing code".into()))?;
+
+    let mut transaction = global.database.begin().await?;
+    let Some(authorization) = DBAuthorizationCode::find_by_hash_for_update(
+        &crate::crypto::hash_token(code),
+        &mut transaction,
+    )
+    .await?
+    .filter(|authorization| authorization.client_id == client.id) else {
+        return Err(OAuth2Error::InvalidGrant("invalid code".into()));
+    };
+
+    // the code is gone whatever happens next, a failed attempt burns it too
+    authorization.delete(&mut transaction).await?;
+    transaction.commit().await?;
+
+    if authorization.is_expired() {
+        return Err(OAuth2Error::InvalidGrant("code expired".into()));
+    }
+
+    match (
+        authorization.redirect_uri.as_deref(),
+        request.redirect_uri.as_deref(),
+    ) {
+        (Some(_), None) => {
+            return Err(OAuth2Error::InvalidRequest("missing redirect_uri".into()));
+        }
+        (Some(expected), Some(uri)) if uri != expected => {
+            return Err(OAuth2Error::InvalidGrant(
+                "redirect_uri does not match".into(),
+            ));
+        }
+        (None, Some(uri)) if !client.redirect_uris.iter().any(|allowed| allowed == uri) => {
+            return Err(OAuth2Error::InvalidGrant(
+                "redirect_uri does not match".into(),
+            ));
+        }
+        _ => {}
+    }
+
+    match (
+        &authorization.code_challenge,
+        request.code_verifier.as_deref(),
+    ) {
+        (Some(challenge), Some(verifier)) => {
+            if !crate::crypto::verify_pkce_s256(verifier, challenge) {
+                return Err(OAuth2Error::InvalidGrant("invalid code_verifier".into()));
+            }
+        }
+        (Some(_), None) => {
+            return Err(OAuth2Error::InvalidRequest("missing code_verifier".into()));
+        }
+        (None, Some(_)) => {
+            return Err(OAuth2Error::InvalidGrant(
+                "code_verifier sent but no code_challenge was".into(),
+            ));
+        }
+        (None, None) => {}
+    }
+
+    let grant = TokenGrant::builder()
+        .user_id(Some(authorization.user_id))
+        .family_id(Some(UlidId::new()))
+        .scopes(authorization.scopes.clone())
+        .audiences(authorization.resources.clone())
+        .dpop_jkt(dpop_jkt.map(String::from))
+        .tenant(tenant.cloned())
+        .build();
+
+    let mut transaction = global.database.begin().await?;
+    let mut response =
+        issue_tokens(global, &mut transaction, client, &grant, &request.resource).await?;
+    transaction.commit().await?;
+
+    if authorization.scopes.iter().any(|scope| scope == OPENID) {
+        response.id_token = Some(sign_id_token(global, client, &authorization)?);
+    }
+
+    Ok(response)
+}
+
+/// Service to service tokens, the client acts on its own behalf. (RFC 6749 section 4.4)
+async fn client_credentials(
+    global: &GlobalState,
+    client: &DBClient,
+    request: &TokenRequest,
+    dpop_jkt: Option<&str>,
+    tenant: Option<&Tenant>,
+) -> Result<TokenResponse, OAuth2Error> {
+    if !client.token_endpoint_auth_method.is_confidential() {
+        return Err(OAuth2Error::UnauthorizedClient(
+            "public clients cannot use the client_credentials grant".into(),
+        ));
+    }
+
+    let grant = TokenGrant::builder()
+        .scopes(resolve_scopes(request.scope.as_deref(), &client.scopes)?)
+        .audiences(resolve_resources(&request.resource, &client.audiences)?)
+        .dpop_jkt(dpop_jkt.map(String::from))
+        .tenant(tenant.cloned())
+        .build();
+
+    let mut transaction = global.database.begin().await?;
+    let response = issue_access_token(global, &mut transaction, client, &grant).await?;
+    transaction.commit().await?;
+
+    Ok(response)
+}
+
+/// The device polls here until the user approved or denied it. (RFC 8628 section 3.4)
+async fn device_code(
+    global: &GlobalState,
+    client: &DBClient,
+    request: &TokenRequest,
+    dpop_jkt: Option<&str>,
+    tenant: Option<&Tenant>,
+) -> Result<TokenResponse, OAuth2Error> {
+    let code = request
+        .device_code
+        .as_deref()
+        .ok_or(OAuth2Error::InvalidRequest("missing device_code".into()))?;
+
+    let mut transaction = global.database.begin().await?;
+    let device =
+        DBDeviceCode::find_by_hash_for_update(&crate::crypto::hash_token(code), &mut transaction)
+            .await?
+            .filter(|device| device.client_id == client.id);
+
+    let Some(mut device) = device else {
+        return Err(OAuth2Error::InvalidGrant("unknown device_code".into()));
+    };
+
+    if device.is_expired() {
+        device.delete(&mut transaction).await?;
+        transaction.commit().await?;
+        return Err(OAuth2Error::ExpiredToken);
+    }
+
+    match (device.status, device.user_id) {
+        (DeviceCodeStatus::Approved, Some(user_id)) => {
+            device.delete(&mut transaction).await?;
+            let grant = TokenGrant::builder()
+                .user_id(Some(user_id))
+                .family_id(Some(UlidId::new()))
+                .scopes(device.scopes)
+                .audiences(device.resources)
+                .dpop_jkt(dpop_jkt.map(String::from))
+                .tenant(tenant.cloned())
+                .build();
+
+            let response =
+                issue_tokens(global, &mut transaction, client, &grant, &request.resource).await?;
+            transaction.commit().await?;
+
+            Ok(response)
+        }
+        (DeviceCodeStatus::Denied, _) => {
+            device.delete(&mut transaction).await?;
+            transaction.commit().await?;
+            Err(OAuth2Error::AccessDenied)
+        }
+        _ => {
+            let now = Utc::now();
+            let too_fast = device
+                .last_polled_at
+                .is_some_and(|last| now < last + Duration::seconds(device.poll_interval.into()));
+
+            // every time a device polls too fast it has to wait 5 seconds longer from then on
+            if too_fast {
+                device.poll_interval += 5;
+            }
+            device.last_polled_at = Some(now);
+            device.update(&mut transaction).await?;
+            transaction.commit().await?;
+
+            Err(match too_fast {
+                true => OAuth2Error::SlowDown,
+                false => OAuth2Error::AuthorizationPending,
+            })
+        }
+    }
+}
+
+/// Trades a refresh token for new tokens. Refresh tokens are rotated, each one can be used only once. (RFC 6749 section 6)
+async fn refresh_token(
+    global: &GlobalState,
+    client: &DBClient,
+    request: &TokenRequest,
+    dpop_jkt: Option<&str>,
+    tenant: Option<&Tenant>,
+) -> Result<TokenResponse, OAuth2Error> {
+    let presented = request
+        .refresh_token
+        .as_deref()
+        .ok_or(OAuth2Error::InvalidRequest("missing refresh_token".into()))?;
+
+    let mut transaction = global.database.begin().await?;
+    let token = DBRefreshToken::find_by_hash_for_update(
+        &crate::crypto::hash_token(presented),
+        &mut transaction,
+    )
+    .await?
+    .filter(|token| token.client_id == client.id && !token.is_expired())
+    .ok_or(OAuth2Error::InvalidGrant("invalid refresh token".into()))?;
+
+    // refresh tokens of public clients are bound to the key they were issued for (RFC 9449 section 5)
+    if token.dpop_jkt.is_some() && token.dpop_jkt.as_deref() != dpop_jkt {
+        return Err(OAuth2Error::InvalidGrant(
+            "refresh token is bound to another DPoP key".into(),
+        ));
+    }
+
+    token.delete(&mut transaction).await?;
+
+    // the new access token can be narrowed down, the refresh token keeps everything originally granted
+    let refresh_grant = TokenGrant::builder()
+        .user_id(Some(token.user_id))
+        .family_id(Some(token.family_id))
+        .scopes(token.scopes)
+        .audiences(token.audiences)
+        .dpop_jkt(dpop_jkt.map(String::from))
+        .tenant(tenant.cloned())
+        .build();
+    let access_grant = TokenGrant {
+        scopes: resolve_scopes(request.scope.as_deref(), &refresh_grant.scopes)?,
+        audiences: resolve_resources(&request.resource, &refresh_grant.audiences)?,
+        ..refresh_grant.clone()
+    };
+
+    let mut response = issue_access_token(global, &mut transaction, client, &access_grant).await?;
+    response.refresh_token =
+        issue_refresh_token(global, &mut transaction, client, &refresh_grant).await?;
+    transaction.commit().await?;
+
+    Ok(response)
+}
+
+/// Trades a token the client received for a new one, so it can call another service on behalf of the same user.
+///
+/// The new token can only carry scopes both the subject token and the exchanging client have, and records who is
+/// acting in its `act` claim. (RFC 8693)
+async fn token_exchange(
+    global: &GlobalState,
+    client: &DBClient,
+    request: &TokenRequest,
+    dpop_jkt: Option<&str>,
+    tenant: Option<&Tenant>,
+) -> Result<TokenResponse, OAuth2Error> {
+    if !client.token_endpoint_auth_method.is_confidential() {
+        return Err(OAuth2Error::UnauthorizedClient(
+            "public clients cannot exchange tokens".into(),
+        ));
+    }
+
+    if request
+        .requested_token_type
+        .as_deref()
+        .is_some_and(|token_type| token_type != ACCESS_TOKEN_TYPE)
+    {
+        return Err(OAuth2Error::InvalidRequest(
+            "only access tokens can be requested".into(),
+        ));
+    }
+
+    let subject = exchanged_token(
+        global,
+        client,
+        "subject_token",
+        request.subject_token.as_deref(),
+        request.subject_token_type.as_deref(),
+    )
+    .await?;
+    let Some(user_id) = subject.user_id else {
+        return Err(OAuth2Error::InvalidGrant(
+            "subject_token was not issued for a user".into(),
+        ));
+    };
+
+    let actor = match request.actor_token.as_deref() {
+        Some(token) => {
+            let actor = exchanged_token(
+                global,
+                client,
+                "actor_token",
+                Some(token),
+                request.actor_token_type.as_deref(),
+            )
+            .await?;
+            actor.user_id.unwrap_or(actor.client_id)
+        }
+        None => client.id,
+    };
+
+    // the current actor goes on the outside, everyone who acted before is nested inside
+    let act = Actor {
+        sub: actor.to_string(),
+        act: subject.act.map(|act| Box::new(act.0)),
+    };
+
+    // the audience is a logical name and the resource a uri (RFC 8693 section 2.1), tokens can be for both
+    let audiences = match (request.audience.as_deref(), request.resource.is_empty()) {
+        (Some(audience), _) if !client.audiences.iter().any(|aud| aud == audience) => {
+            return Err(OAuth2Error::InvalidTarget(
+                "client may not get tokens for this audience".into(),
+            ));
+        }
+        (Some(audience), true) => vec![audience.to_string()],
+        (Some(audience), false) => {
+            let mut audiences = resolve_resources(&request.resource, &client.audiences)?;
+            if !audiences.iter().any(|aud| aud == audience) {
+                audiences.insert(0, audience.to_string());
+            }
+            audiences
+        }
+        (None, _) => resolve_resources(&request.resource, &client.audiences)?,
+    };
+
+    // neither more than the subject token had nor more than this client may get on its own
+    let allowed: Vec<String> = subject
+        .scopes
+        .iter()
+        .filter(|scope| client.scopes.contains(scope))
+        .cloned()
+        .collect();
+
+    let grant = TokenGrant::builder()
+        .user_id(Some(user_id))
+        // revoking the original authorization takes the exchanged tokens with it
+        .family_id(subject.family_id)
+        .scopes(resolve_scopes(request.scope.as_deref(), &allowed)?)
+        .audiences(audiences)
+        .dpop_jkt(dpop_jkt.map(String::from))
+        .tenant(tenant.cloned())
+        .act(Some(act))
+        .build();
+
+    let mut transaction = global.database.begin().await?;
+    let mut response = issue_access_token(global, &mut transaction, client, &grant).await?;
+    transaction.commit().await?;
+
+    response.issued_token_type = Some(ACCESS_TOKEN_TYPE);
+    Ok(response)
+}
+
+/// Looks up a token presented for exchange. Clients can only exchange tokens they could introspect, i.e. ones meant for them.
+async fn exchanged_token(
+    global: &GlobalState,
+    client: &DBClient,
+    name: &str,
+    token: Option<&str>,
+    token_type: Option<&str>,
+) -> Result<DBAccessToken, OAuth2Error> {
+    let token =
+        token.ok_or_else(|| OAuth2Error::InvalidRequest(format!("missing {name}").into()))?;
+
+    match token_type {
+        Some(ACCESS_TOKEN_TYPE | JWT_TOKEN_TYPE) => {}
+        Some(_) => {
+            return Err(OAuth2Error::InvalidRequest(
+                format!("unsupported {name}_type").into(),
+            ));
+        }
+        None => {
+            return Err(OAuth2Error::InvalidRequest(
+                format!("missing {name}_type").into(),
+            ));
+        }
+    }
+
+    DBAccessToken::find_by_hash(&crate::crypto::hash_token(token), &global.database)
+        .await?
+        .filter(|token| !token.is_expired())
+        .filter(|token| client.can_introspect(token.client_id, &token.audiences))
+        .ok_or_else(|| OAuth2Error::InvalidGrant(format!("invalid {name}").into()))
+}
+
+/// Checks the requested scopes against the allowed ones. Requesting nothing grants everything allowed.
+pub fn resolve_scopes(
+    requested: Option<&str>,
+    allowed: &[String],
+) -> Result<Vec<String>, OAuth2Error> {
+    let Some(requested) = requested.filter(|s| !s.trim().is_empty()) else {
+        return Ok(allowed.to_vec());
+    };
+
+    let mut scopes: Vec<String> = Vec::new();
+    for scope in requested.split_whitespace() {
+        if !allowed.iter().any(|s| s == scope) {
+            return Err(OAuth2Error::InvalidScope(
+                format!("scope '{scope}' is not allowed for this client").into(),
+            ));
+        }
+
+        if !scopes.iter().any(|s| s == scope) {
+            scopes.push(scope.to_string());
+        }
+    }
+
+    Ok(scopes)
+}
+
+/// Resource indicators have to be absolute uris without a fragment. (RFC 8707 section 2)
+fn is_resource_indicator(resource: &str) -> bool {
+    Url::parse(resource).is_ok_and(|url| url.fragment().is_none())
+}
+
+/// Checks the requested resources against the allowed ones. Requesting nothing grants everything allowed.
+pub fn resolve_resources(
+    requested: &[String],
+    allowed: &[String],
+) -> Result<Vec<String>, OAuth2Error> {
+    if requested.is_empty() {
+        return Ok(allowed.to_vec());
+    }
+
+    let mut resources: Vec<String> = Vec::new();
+    for resource in requested {
+        if !is_resource_indicator(resource) {
+            return Err(OAuth2Error::InvalidTarget(
+                "resources have to be absolute uris without a fragment".into(),
+            ));
+        }
+
+        if !allowed.contains(resource) {
+            return Err(OAuth2Error::InvalidTarget(
+                format!("resource '{resource}' is not allowed for this client").into(),
+            ));
+        }
+
+        if !resources.contains(resource) {
+            resources.push(resource.clone());
+        }
+    }
+
+    Ok(resources)
+}
+
+/// The `resource` parameter can be repeated in forms, and is a string or an array in request objects.
+pub fn deserialize_resources<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match serde::Deserialize::deserialize(deserializer)? {
+        OneOrMany::One(resource) => vec![resource],
+        OneOrMany::Many(resources) => resources,
+    })
+}
+
+/// Issues an access token, plus a refresh token when the client is allowed to use them.
+///
+/// The refresh token is for every granted resource, the access token only for the requested ones.
+pub async fn issue_tokens(
+    global: &GlobalState,
+    transaction: &mut sqlx::PgTransaction<'_>,
+    client: &DBClient,
+    grant: &TokenGrant,
+    resources: &[String],
+) -> Result<TokenResponse, OAuth2Error> {
+    let access_grant = TokenGrant {
+        audiences: resolve_resources(resources, &grant.audiences)?,
+        ..grant.clone()
+    };
+
+    let mut response = issue_access_token(global, transaction, client, &access_grant).await?;
+    response.refresh_token = issue_refresh_token(global, transaction, client, grant).await?;
+
+    Ok(response)
+}
+
+/// Issues an access token. Resources it is for can shorten its lifetime and leave out scopes they don't accept.
+pub async fn issue_access_token(
+    global: &GlobalState,
+    transaction: &mut sqlx::PgTransaction<'_>,
+    client: &DBClient,
+    grant: &TokenGrant,
+) -> Result<TokenResponse, OAuth2Error> {
+    let resources = DBResource::find_many_by_identifier(&grant.audiences, transaction).await?;
+    let scopes: Vec<String> = grant
+        .scopes
+        .iter()
+        .filter(|scope| {
+            resources
+                .iter()
+                .all(|resource| resource.allows_scope(scope))
+        })
+        .cloned()
+        .collect();
+    let lifetime = resources
+        .iter()
+        .filter_map(|resource| resource.access_token_lifetime)
+        .fold(global.settings.oauth2.access_token_lifetime, i64::min);
+    let scope = (!scopes.is_empty()).then(|| scopes.join(" "));
+    let now = Utc::now();
+    let record = DBAccessToken::builder()
+        .token_hash(Vec::new())
+        .client_id(client.id)
+        .user_id(grant.user_id)
+        .family_id(grant.family_id)
+        .scopes(scopes)
+        .audiences(grant.audiences.clone())
+        .dpop_jkt(grant.dpop_jkt.clone())
+        .act(grant.act.clone().map(sqlx::types::Json))
+        .organization_id(grant.tenant.as_ref().map(Tenant::id))
+        .expires_at(now + Duration::seconds(lifetime))
+        .created_at(now)
+        .build();
+
+    let token = match access_token_format(client, &resources) {
+        AccessTokenFormat::Opaque => crate::crypto::generate_token(),
+        AccessTokenFormat::Jwt => {
+            let roles = match grant.user_id {
+                Some(user_id) if global.settings.rbac.roles_in_tokens => Some(
+                    DBRoleAssignment::find_roles(user_id, grant.tenant.as_ref(), &global.database)
+                        .await?
+                        .into_iter()
+                        .map(|role| role.name)
+                        .collect(),
+                ),
+                _ => None,
+            };
+            sign_access_token(global, client, &record, roles)?
+        }
+    };
+
+    // JWTs are stored too, so they can still be introspected and revoked
+    DBAccessToken {
+        token_hash: crate::crypto::hash_token(&token),
+        ..record
+    }
+    .insert(transaction)
+    .await?;
+
+    Ok(TokenResponse {
+        access_token: token,
+        token_type: match grant.dpop_jkt {
+            Some(_) => "DPoP",
+            None => "Bearer",
+        },
+        expires_in: lifetime,
+        refresh_token: None,
+        scope,
+        id_token: None,
+        issued_token_type: None,
+    })
+}
+
+/// Resources the token is meant for override the client's choice. When they disagree JWTs win, those can be introspected too.
+fn access_token_format(client: &DBClient, resources: &[DBResource]) -> AccessTokenFormat {
+    let formats: Vec<AccessTokenFormat> = resources
+        .iter()
+        .filter_map(|resource| resource.access_token_format)
+        .collect();
+
+    if formats.contains(&AccessTokenFormat::Jwt) {
+        AccessTokenFormat::Jwt
+    } else {
+        formats
+            .first()
+            .copied()
+            .unwrap_or(client.access_token_format)
+    }
+}
+
+/// (RFC 9068 section 2.2)
+#[derive(Debug, serde::Serialize)]
+struct AccessTokenClaims<'a> {
+    iss: &'a str,
+    /// The user, or the client itself when it acts on its own behalf
+    sub: String,
+    aud: Vec<String>,
+    exp: i64,
+    iat: i64,
+    jti: String,
+    client_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scope: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cnf: Option<Confirmation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    act: Option<Actor>,
+    /// Roles the user has everywhere and in the organization the token is for (RFC 9068 section 2.2.3.1)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    roles: Option<Vec<String>>,
+    /// The organization the token is for, none for tokens requested outside of any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    org_id: Option<String>,
+}
+
+fn sign_access_token(
+    global: &GlobalState,
+    client: &DBClient,
+    token: &DBAccessToken,
+    roles: Option<Vec<String>>,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let issuer = &global.settings.oauth2.issuer;
+    let claims = AccessTokenClaims {
+        iss: issuer,
+        sub: match token.user_id {
+            Some(user_id) => client.subject(user_id, &global.settings.oauth2.pairwise_salt),
+            None => token.client_id.to_string(),
+        },
+        // tokens without an audience are only good for this server's own endpoints, like userinfo
+        aud: match token.audiences.is_empty() {
+            true => vec![issuer.clone()],
+            false => token.audiences.clone(),
+        },
+        exp: token.expires_at.timestamp(),
+        iat: token.created_at.timestamp(),
+        jti: token.id.to_string(),
+        client_id: token.client_id.to_string(),
+        scope: (!token.scopes.is_empty()).then(|| token.scopes.join(" ")),
+        cnf: token.dpop_jkt.clone().map(|jkt| Confirmation { jkt }),
+        act: token.act.clone().map(|act| act.0),
+        roles,
+        org_id: token.organization_id.map(|id| id.to_string()),
+    };
+
+    global.signing_key.sign("at+jwt", &claims)
+}
+
+/// (OpenID Connect Core 1.0 section 2)
+#[derive(Debug, serde::Serialize)]
+struct IdTokenClaims<'a> {
+    iss: &'a str,
+    sub: String,
+    aud: String,
+    exp: i64,
+    iat: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    nonce: Option<&'a str>,
+    /// The login session, the same `sid` logout tokens and front-channel logouts carry
+    sid: String,
+}
+
+fn sign_id_token(
+    global: &GlobalState,
+    client: &DBClient,
+    authorization: &DBAuthorizationCode,
+) -> Result<String, jsonwebtoken::errors::Error> {
+    let now = Utc::now().timestamp();
+    let claims = IdTokenClaims {
+        iss: &global.settings.oauth2.issuer,
+        sub: client.subject(authorization.user_id, &global.settings.oauth2.pairwise_salt),
+        aud: client.id.to_string(),
+        exp: now + global.settings.oauth2.id_token_lifetime,
+        iat: now,
+        nonce: authorization.nonce.as_deref(),
+        sid: authorization.session_id.to_string(),
+    };
+
+    global.signing_key.sign("JWT", &claims)
+}
+
+/// Issues a refresh token, but only to clients that are allowed to use them and only for users.
+pub async fn issue_refresh_token(
+    global: &GlobalState,
+    transaction: &mut sqlx::PgTransaction<'_>,
+    client: &DBClient,
+    grant: &TokenGrant,
+) -> Result<Option<String>, OAuth2Error> {
+    let (Some(user_id), Some(family_id)) = (grant.user_id, grant.family_id) else {
+        return Ok(None);
+    };
+
+    if !client.allows_grant_type(REFRESH_TOKEN) {
+        return Ok(None);
+    }
+
+    let lifetime = global.settings.oauth2.refresh_token_lifetime;
+    let token = crate::crypto::generate_token();
+
+    DBRefreshToken::builder()
+        .token_hash(crate::crypto::hash_token(&token))
+        .client_id(client.id)
+        .user_id(user_id)
+        .family_id(family_id)
+        .scopes(grant.scopes.clone())
+        .audiences(grant.audiences.clone())
+        // confidential clients already prove who they are when refreshing, only public clients need the binding
+        .dpop_jkt(
+            grant
+                .dpop_jkt
+                .clone()
+                .filter(|_| !client.token_endpoint_auth_method.is_confidential()),
+        )
+        .expires_at(Utc::now() + Duration::seconds(lifetime))
+        .build()
+        .insert(transaction)
+        .await?;
+
+    Ok(Some(token))
+}
@@ -0,0 +1,49 @@
+use crate::database::models::client::DBClient;
+use crate::database::models::user::DBUser;
+use crate::global::GlobalState;
+use crate::http::oauth2::claims::ScopeRegistry;
+use crate::http::oauth2::error::{OAuth2Error, OAuth2ErrorResponse};
+use crate::http::oauth2::resource::authenticate_token;
+use axum::Json;
+use axum::extract::State;
+use axum::http::header::CACHE_CONTROL;
+use axum::http::{HeaderMap, Method};
+use axum::response::IntoResponse;
+use std::sync::Arc;
+
+/// Returns the claims of the user the access token was issued for, limited to what its scopes release. (OpenID Connect Core 1.0 section 5.3)
+#[utoipa::path(
+    method(get, post),
+    path = "/oauth2/userinfo",
+    tag = "oauth2",
+    responses(
+        (status = 200, description = "The released claims", body = Object),
+        (status = 401, body = OAuth2ErrorResponse),
+    )
+)]
+pub async fn userinfo(
+    State(global): State<Arc<GlobalState>>,
+    method: Method,
+    headers: HeaderMap,
+) -> Result<impl IntoResponse, OAuth2Error> {
+    let url = format!("{}/oauth2/userinfo", global.settings.oauth2.issuer);
+    let token = authenticate_token(&global, &method, &headers, &url).await?;
+
+    let user_id = token.user_id.ok_or(OAuth2Error::InvalidToken(
+        "access token was not issued for a user".into(),
+    ))?;
+    let user = DBUser::find_by_id(user_id, &global.database)
+        .await?
+        .ok_or(OAuth2Error::InvalidToken("invalid access token".into()))?;
+
+    let client = DBClient::find_by_id(token.client_id, &global.database)
+        .await?
+        .ok_or(OAuth2Error::InvalidToken("invalid access token".into()))?;
+    let subject = client.subject(user.id, &global.settings.oauth2.pairwise_salt);
+
+    let registry = ScopeRegistry::load(&global).await?;
+    Ok((
+        [(CACHE_CONTROL, "no-store")],
+        Json(registry.user_claims(&user, subject, &token.scopes)),
+    ))
+}
@@ -0,0 +1,162 @@
+//! Just-in-time provisioning of users signing in with upstream providers, by the rules in the settings.
+
+use crate::database::models::organization::DBOrganization;
+use crate::database::models::organization_member::DBOrganizationMember;
+use crate::database::models::role::DBRole;
+use crate::database::models::role_assignment::DBRoleAssignment;
+use crate::database::models::user::DBUser;
+use crate::global::GlobalState;
+use crate::http::upstream::Profile;
+use crate::settings::ProvisioningRule;
+use serde_json::{Map, Value};
+
+/// The first rule matching someone signing in with the provider.
+pub fn matching_rule<'a>(
+    rules: &'a [ProvisioningRule],
+    provider_id: &str,
+    profile: &Profile,
+) -> Option<&'a ProvisioningRule> {
+    rules
+        .iter()
+        .find(|rule| matches(rule, provider_id, profile))
+}
+
+fn matches(rule: &ProvisioningRule, provider_id: &str, profile: &Profile) -> bool {
+    if !rule.providers.is_empty() && !rule.providers.iter().any(|id| id == provider_id) {
+        return false;
+    }
+
+    if !rule.email_domains.is_empty() {
+        let Some((_, domain)) = profile
+            .email
+            .as_deref()
+            .and_then(|email| email.rsplit_once('@'))
+        else {
+            return false;
+        };
+        if !rule
+            .email_domains
+            .iter()
+            .any(|wanted| wanted.eq_ignore_ascii_case(domain))
+        {
+            return false;
+        }
+    }
+
+    rule.claims
+        .iter()
+        .all(|(name, wanted)| match profile.claims.get(name) {
+            Some(Value::Array(values)) => values.iter().any(|value| claim_is(value, wanted)),
+            Some(value) => claim_is(value, wanted),
+            None => false,
+        })
+}
+
+fn claim_is(value: &Value, wanted: &str) -> bool {
+    match value {
+        Value::String(value) => value == wanted,
+        Value::Bool(_) | Value::Number(_) => {
+            serde_json::from_str::<Value>(wanted).is_ok_and(|wanted| wanted == *value)
+        }
+        _ => false,
+    }
+}
+
+/// The attributes a new user gets, the ones from the provider and the organization the rule assigns.
+pub fn new_user_attributes(
+    rule: Option<&ProvisioningRule>,
+    profile: &Profile,
+) -> Map<String, Value> {
+    let mut attributes = profile.attributes.clone();
+    if let Some(organization) = rule.and_then(|rule| rule.organization.as_ref()) {
+        attributes.insert("organization".into(), organization.clone().into());
+    }
+    attributes
+}
+
+/// Makes a new user a member of the organization the rule assigns, and gives them its roles everywhere.
+pub async fn assign(
+    global: &GlobalState,
+    user: &DBUser,
+    rule: &ProvisioningRule,
+) -> Result<(), sqlx::Error> {
+    let organization = match &rule.organization {
+        Some(slug) => {
+            let organization = DBOrganization::find_by_slug(slug, &global.database).await?;
+            if organization.is_none() {
+                tracing::warn!(
+                    "Provisioning rule {} assigns organization {slug}, which doesn't exist",
+                    rule.name
+                );
+            }
+            organization
+        }
+        None => None,
+    };
+
+    let roles = DBRole::find_many_by_name(&rule.roles, &global.database).await?;
+    for name in &rule.roles {
+        if !roles.iter().any(|role| &role.name == name) {
+            tracing::warn!(
+                "Provisioning rule {} assigns role {name}, which doesn't exist",
+                rule.name
+            );
+        }
+    }
+
+    let mut transaction = global.database.begin().await?;
+    if let Some(organization) = organization {
+        DBOrganizationMember::builder()
+            .tenant_id(organization.id)
+            .user_id(user.id)
+            .build()
+            .insert(&mut transaction)
+            .await?;
+    }
+    for role in &roles {
+        DBRoleAssignment::builder()
+            .user_id(user.id)
+            .role_id(role.id)
+            .build()
+            .insert(&mut transaction)
+            .await?;
+    }
+    transaction.commit().await
+}
+
+/// Updates the attributes the rule syncs to what the provider says now. Attributes it doesn't send anymore are
+/// removed, an email it doesn't send is kept.
+pub async fn sync(
+    global: &GlobalState,
+    mut user: DBUser,
+    rule: &ProvisioningRule,
+    profile: &Profile,
+) -> Result<DBUser, sqlx::Error> {
+    let mut email = user.email.clone();
+    let mut attributes = match &user.attributes {
+        Value::Object(attributes) => attributes.clone(),
+        _ => Map::new(),
+    };
+    for name in &rule.sync {
+        if name == "email" {
+            if profile.email.is_some() {
+                email = profile.email.clone();
+            }
+            continue;
+        }
+        match profile.attributes.get(name) {
+            Some(value) => attributes.insert(name.clone(), value.clone()),
+            None => attributes.remove(name),
+        };
+    }
+
+    let attributes = Value::Object(attributes);
+    if email != user.email || attributes != user.attributes {
+        user.email = email;
+        user.attributes = attributes;
+        let mut transaction = global.database.begin().await?;
+        user.update(&mut transaction).await?;
+        transaction.commit().await?;
+    }
+    Ok(user)
+}
@@ -0,0 +1,276 @@
+use crate::database::models::saml_service_provider::DBSamlServiceProvider;
+use crate::database::models::saml_session::DBSamlSession;
+use crate::global::GlobalState;
+use crate::http::oauth2::authorize::AuthorizeError;
+use crate::http::oauth2::claims::user_claim;
+use crate::http::saml::{
+    INVALID_MESSAGE, PostTemplate, SamlParams, UNKNOWN_SERVICE_PROVIDER, entity_id, saml_key,
+};
+use crate::http::session::{SessionUser, login_url};
+use crate::http::template::HtmlTemplate;
+use crate::saml::response::{Assertion, error_response, response};
+use crate::saml::{AuthnRequest, HTTP_POST, STATUS_NO_PASSIVE, metadata};
+use axum::Form;
+use axum::extract::{OriginalUri, Query, RawQuery, State};
+use axum::http::header::{CONTENT_TYPE, X_FRAME_OPTIONS};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum_extra::extract::CookieJar;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use serde_json::Value;
+use std::sync::Arc;
+
+/// This server's identity provider metadata.
+pub async fn metadata(State(global): State<Arc<GlobalState>>) -> Result<Response, AuthorizeError> {
+    let key = saml_key(&global)?;
+    let xml = metadata::identity_provider(&entity_id(&global), &global.settings.oauth2.issuer, key);
+
+    Ok(([(CONTENT_TYPE, "application/samlmetadata+xml")], xml).into_response())
+}
+
+/// Authentication requests with the HTTP-Redirect binding. Signed ones are checked against the service provider's
+/// certificate.
+pub async fn sso_redirect(
+    State(global): State<Arc<GlobalState>>,
+    jar: CookieJar,
+    OriginalUri(uri): OriginalUri,
+    RawQuery(raw_query): RawQuery,
+    Query(params): Query<SamlParams>,
+) -> Result<Response, AuthorizeError> {
+    let message = params
+        .saml_request
+        .as_deref()
+        .ok_or(AuthorizeError::Page(INVALID_MESSAGE))?;
+    let xml = crate::saml::decode_redirect(message).map_err(invalid_message)?;
+    let request = AuthnRequest::parse(&xml).map_err(invalid_message)?;
+    let sp = find_service_provider(&global, &request.issuer).await?;
+
+    if sp.authn_requests_signed {
+        let signed = sp.certificate.as_deref().is_some_and(|certificate| {
+            crate::saml::verify_redirect(
+                raw_query.as_deref().unwrap_or_default(),
+                "SAMLRequest",
+                certificate,
+            )
+        });
+        if !signed {
+            return Err(AuthorizeError::Page(INVALID_MESSAGE));
+        }
+    }
+
+    // the request as it came in, signature and all
+    let resume = uri.path_and_query().map_or("/saml/sso", |pq| pq.as_str());
+    single_sign_on(
+        &global,
+        &jar,
+        &sp,
+        &request,
+        params.relay_state.as_deref(),
+        resume,
+    )
+    .await
+}
+
+/// Authentication requests with the HTTP-POST binding.
+///
+/// The service provider's page posts these cross-site, so the session cookie, being `SameSite=Lax`, stays behind.
+/// The request is handed on to the HTTP-Redirect binding instead, the browser follows with a top-level GET that has
+/// the cookie along.
+pub async fn sso_post(
+    State(global): State<Arc<GlobalState>>,
+    Form(params): Form<SamlParams>,
+) -> Result<Response, AuthorizeError> {
+    let message = params
+        .saml_request
+        .as_deref()
+        .ok_or(AuthorizeError::Page(INVALID_MESSAGE))?;
+    let xml = crate::saml::decode_post(message).map_err(invalid_message)?;
+    let request = AuthnRequest::parse(&xml).map_err(invalid_message)?;
+    let sp = find_service_provider(&global, &request.issuer).await?;
+
+    // signatures are only checked on the query string, not inside the XML
+    if sp.authn_requests_signed {
+        return Err(AuthorizeError::Page(
+            "The application that sent you here has to send signed sign in requests with the HTTP-Redirect binding.",
+        ));
+    }
+
+    let mut location = format!(
+        "/saml/sso?SAMLRequest={}",
+        encode(&crate::saml::encode_redirect(&xml))
+    );
+    if let Some(relay_state) = &params.relay_state {
+        location.push_str(&format!("&RelayState={}", encode(relay_state)));
+    }
+    Ok(Redirect::to(&location).into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct InitiateQuery {
+    /// The service provider to sign into
+    entity_id: String,
+    #[serde(rename = "RelayState")]
+    relay_state: Option<String>,
+}
+
+/// IdP-initiated sign in, posting an unsolicited response to a service provider. (SAML Profiles section 4.1.5)
+pub async fn initiate(
+    State(global): State<Arc<GlobalState>>,
+    current: SessionUser,
+    Query(query): Query<InitiateQuery>,
+) -> Result<Response, AuthorizeError> {
+    let sp = find_service_provider(&global, &query.entity_id).await?;
+    respond(&global, &sp, &current, None, query.relay_state.as_deref()).await
+}
+
+fn encode(value: &str) -> String {
+    url::form_urlencoded::byte_serialize(value.as_bytes()).collect()
+}
+
+fn invalid_message(e: crate::saml::SamlError) -> AuthorizeError {
+    tracing::debug!("Invalid SAML message: {e}");
+    AuthorizeError::Page(INVALID_MESSAGE)
+}
+
+async fn find_service_provider(
+    global: &GlobalState,
+    entity_id: &str,
+) -> Result<DBSamlServiceProvider, AuthorizeError> {
+    DBSamlServiceProvider::find_by_entity_id(entity_id, &global.database)
+        .await?
+        .ok_or(AuthorizeError::Page(UNKNOWN_SERVICE_PROVIDER))
+}
+
+/// Answers a checked authentication request, sending the user to sign in first when needed.
+async fn single_sign_on(
+    global: &GlobalState,
+    jar: &CookieJar,
+    sp: &DBSamlServiceProvider,
+    request: &AuthnRequest,
+    relay_state: Option<&str>,
+    resume: &str,
+) -> Result<Response, AuthorizeError> {
+    let sso_url = format!("{}/saml/sso", global.settings.oauth2.issuer);
+    if request
+        .destination
+        .as_deref()
+        .is_some_and(|destination| destination != sso_url)
+    {
+        return Err(AuthorizeError::Page(INVALID_MESSAGE));
+    }
+    // responses only ever go to the registered endpoint, with the POST binding
+    if request
+        .assertion_consumer_service_url
+        .as_deref()
+        .is_some_and(|url| url != sp.acs_url)
+        || request
+            .protocol_binding
+            .as_deref()
+            .is_some_and(|binding| binding != HTTP_POST)
+    {
+        return Err(AuthorizeError::Page(INVALID_MESSAGE));
+    }
+
+    if let Some(current) = SessionUser::from_jar(global, jar).await? {
+        return respond(global, sp, &current, Some(&request.id), relay_state).await;
+    }
+
+    if request.is_passive {
+        let key = saml_key(global)?;
+        let xml = error_response(
+            key,
+            &entity_id(global),
+            &sp.acs_url,
+            &request.id,
+            STATUS_NO_PASSIVE,
+        );
+        return Ok(post_page(&sp.acs_url, "SAMLResponse", &xml, relay_state));
+    }
+
+    Ok(Redirect::to(&login_url(resume)).into_response())
+}
+
+/// Posts a signed assertion about the signed in user to the service provider, and remembers the service provider
+/// for single logout.
+async fn respond(
+    global: &GlobalState,
+    sp: &DBSamlServiceProvider,
+    current: &SessionUser,
+    in_response_to: Option<&str>,
+    relay_state: Option<&str>,
+) -> Result<Response, AuthorizeError> {
+    let key = saml_key(global)?;
+    let name_id = sp
+        .name_id(&current.user, &global.settings.oauth2.pairwise_salt)
+        .ok_or(AuthorizeError::Page(
+            "The application needs your email address to sign you in, and your account has none.",
+        ))?;
+    let session_index = format!("_{}", current.session.id);
+
+    let attributes = sp
+        .attributes
+        .iter()
+        .filter_map(|(name, claim)| {
+            let values = match user_claim(&current.user, claim)? {
+                Value::Array(values) => values.iter().filter_map(attribute_value).collect(),
+                value => vec![attribute_value(&value)?],
+            };
+            Some((name.clone(), values))
+        })
+        .collect();
+
+    let mut transaction = global.database.begin().await?;
+    DBSamlSession::builder()
+        .session_id(current.session.id)
+        .service_provider_id(sp.id)
+        .name_id(name_id.clone())
+        .session_index(session_index.clone())
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    let issuer = entity_id(global);
+    let xml = response(
+        key,
+        &Assertion {
+            issuer: &issuer,
+            recipient: &sp.acs_url,
+            in_response_to,
+            audience: &sp.entity_id,
+            name_id: &name_id,
+            name_id_format: &sp.name_id_format,
+            session_index: &session_index,
+            attributes,
+            lifetime: global.settings.saml.assertion_lifetime,
+        },
+    );
+
+    Ok(post_page(&sp.acs_url, "SAMLResponse", &xml, relay_state))
+}
+
+fn attribute_value(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Number(_) | Value::Bool(_) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// The auto-submitted form carrying a message with the HTTP-POST binding.
+pub fn post_page(
+    action: &str,
+    parameter: &'static str,
+    xml: &str,
+    relay_state: Option<&str>,
+) -> Response {
+    let page = HtmlTemplate(PostTemplate {
+        action,
+        parameter,
+        message: BASE64_STANDARD.encode(xml),
+        relay_state,
+        frontchannel_uris: Vec::new(),
+    });
+
+    ([(X_FRAME_OPTIONS, "DENY")], page).into_response()
+}
@@ -0,0 +1,211 @@
+use crate::database::models::saml_service_provider::{DBSamlServiceProvider, SamlBinding};
+use crate::database::models::saml_session::DBSamlSession;
+use crate::database::models::session::DBSession;
+use crate::global::GlobalState;
+use crate::http::oauth2::authorize::AuthorizeError;
+use crate::http::oauth2::logout::{end_session, logged_out};
+use crate::http::saml::{
+    INVALID_MESSAGE, PostTemplate, SamlParams, UNKNOWN_SERVICE_PROVIDER, entity_id, saml_key,
+};
+use crate::http::session::SessionUser;
+use crate::http::template::{HtmlTemplate, MessageTemplate};
+use crate::saml::response::{logout_request, logout_response};
+use crate::saml::{LogoutRequest, LogoutResponse, STATUS_REQUESTER, STATUS_SUCCESS};
+use axum::Form;
+use axum::extract::{Query, RawQuery, State};
+use axum::http::header::X_FRAME_OPTIONS;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum_extra::extract::CookieJar;
+use axum_extra::extract::cookie::Cookie;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use std::sync::Arc;
+
+/// Logout messages with the HTTP-Redirect binding.
+pub async fn slo_redirect(
+    State(global): State<Arc<GlobalState>>,
+    jar: CookieJar,
+    RawQuery(raw_query): RawQuery,
+    Query(params): Query<SamlParams>,
+) -> Result<Response, AuthorizeError> {
+    let raw_query = raw_query.unwrap_or_default();
+    single_logout(&global, jar, params, Some(&raw_query)).await
+}
+
+/// Logout messages with the HTTP-POST binding.
+pub async fn slo_post(
+    State(global): State<Arc<GlobalState>>,
+    jar: CookieJar,
+    Form(params): Form<SamlParams>,
+) -> Result<Response, AuthorizeError> {
+    single_logout(&global, jar, params, None).await
+}
+
+/// Ends the session a service provider asks to end, and answers it. Service providers with a certificate have to sign
+/// their logout requests, with the HTTP-Redirect binding. (SAML Profiles section 4.4.4.1)
+///
+/// The raw query is only given for the HTTP-Redirect binding.
+async fn single_logout(
+    global: &GlobalState,
+    jar: CookieJar,
+    params: SamlParams,
+    raw_query: Option<&str>,
+) -> Result<Response, AuthorizeError> {
+    let key = saml_key(global)?;
+    let decode = |message: &str| match raw_query {
+        Some(_) => crate::saml::decode_redirect(message),
+        None => crate::saml::decode_post(message),
+    };
+
+    // the answers to the logout requests sent to service providers come back here, inside hidden iframes
+    if let Some(message) = &params.saml_response {
+        let response = decode(message)
+            .and_then(|xml| LogoutResponse::parse(&xml))
+            .map_err(|_| AuthorizeError::Page(INVALID_MESSAGE))?;
+        tracing::debug!(
+            "{} answered logout request {:?} with {:?}",
+            response.issuer,
+            response.in_response_to,
+            response.status
+        );
+        return Ok(HtmlTemplate(MessageTemplate {
+            title: "Signed out",
+            message: "You have been signed out.",
+        })
+        .into_response());
+    }
+
+    let message = params
+        .saml_request
+        .as_deref()
+        .ok_or(AuthorizeError::Page(INVALID_MESSAGE))?;
+    let request = decode(message)
+        .and_then(|xml| LogoutRequest::parse(&xml))
+        .map_err(|_| AuthorizeError::Page(INVALID_MESSAGE))?;
+    let sp = DBSamlServiceProvider::find_by_entity_id(&request.issuer, &global.database)
+        .await?
+        .ok_or(AuthorizeError::Page(UNKNOWN_SERVICE_PROVIDER))?;
+
+    if let Some(certificate) = &sp.certificate {
+        let signed = raw_query.is_some_and(|raw_query| {
+            crate::saml::verify_redirect(raw_query, "SAMLRequest", certificate)
+        });
+        if !signed {
+            return Err(AuthorizeError::Page(INVALID_MESSAGE));
+        }
+    }
+    let Some(slo_url) = &sp.slo_url else {
+        return Err(AuthorizeError::Page(INVALID_MESSAGE));
+    };
+
+    // only the session the service provider knows about is ended, nobody gets logged out by someone else's request
+    let mut status = STATUS_SUCCESS;
+    let mut ended = false;
+    let mut frontchannel_uris = Vec::new();
+    if let Some(current) = SessionUser::from_jar(global, &jar).await? {
+        let participant = DBSamlSession::find_many_by_session(current.session.id, &global.database)
+            .await?
+            .into_iter()
+            .find(|participant| participant.service_provider_id == sp.id);
+
+        match participant {
+            Some(participant)
+                if participant.name_id == request.name_id
+                    && (request.session_indexes.is_empty()
+                        || request.session_indexes.contains(&participant.session_index)) =>
+            {
+                // it already knows, so it doesn't get a logout request of its own
+                let mut transaction = global.database.begin().await?;
+                participant.delete(&mut transaction).await?;
+                transaction.commit().await?;
+
+                frontchannel_uris = end_session(global, &current.session).await?;
+                ended = true;
+            }
+            _ => status = STATUS_REQUESTER,
+        }
+    }
+
+    let response = logout_response(&entity_id(global), slo_url, &request.id, status);
+    let relay_state = params.relay_state.as_deref();
+    match sp.slo_binding {
+        SamlBinding::Redirect => {
+            let continue_to = crate::saml::redirect_url(
+                key,
+                slo_url,
+                "SAMLResponse",
+                &response.unsigned(),
+                relay_state,
+            );
+            if ended {
+                Ok(logged_out(
+                    global,
+                    jar,
+                    frontchannel_uris,
+                    Some(&continue_to),
+                ))
+            } else {
+                Ok(Redirect::to(&continue_to).into_response())
+            }
+        }
+        SamlBinding::Post => {
+            let jar = if ended {
+                jar.remove(Cookie::build(global.settings.session.cookie_name.clone()).path("/"))
+            } else {
+                jar
+            };
+            let page = HtmlTemplate(PostTemplate {
+                action: slo_url,
+                parameter: "SAMLResponse",
+                message: BASE64_STANDARD.encode(response.signed(key)),
+                relay_state,
+                frontchannel_uris,
+            });
+            Ok((jar, [(X_FRAME_OPTIONS, "DENY")], page).into_response())
+        }
+    }
+}
+
+/// Signed logout requests for the service providers signed into a session, to be loaded by the browser before the
+/// session ends. Only service providers taking the HTTP-Redirect binding can be logged out this way.
+pub async fn frontchannel_logout_urls(
+    global: &GlobalState,
+    session: &DBSession,
+) -> Result<Vec<String>, sqlx::Error> {
+    let Some(key) = &global.saml_key else {
+        return Ok(Vec::new());
+    };
+
+    let participants = DBSamlSession::find_many_by_session(session.id, &global.database).await?;
+    let ids: Vec<_> = participants
+        .iter()
+        .map(|participant| participant.service_provider_id)
+        .collect();
+    let service_providers = DBSamlServiceProvider::find_many_by_id(&ids, &global.database).await?;
+
+    let issuer = entity_id(global);
+    Ok(participants
+        .iter()
+        .filter_map(|participant| {
+            let sp = service_providers
+                .iter()
+                .find(|sp| sp.id == participant.service_provider_id)
+                .filter(|sp| sp.slo_binding == SamlBinding::Redirect)?;
+            let slo_url = sp.slo_url.as_deref()?;
+            let request = logout_request(
+                &issuer,
+                slo_url,
+                &participant.name_id,
+                &sp.name_id_format,
+                &participant.session_index,
+            );
+            Some(crate::saml::redirect_url(
+                key,
+                slo_url,
+                "SAMLRequest",
+                &request.unsigned(),
+                None,
+            ))
+        })
+        .collect())
+}
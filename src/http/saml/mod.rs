@@ -0,0 +1,75 @@
+use crate::global::GlobalState;
+use crate::http::oauth2::authorize::AuthorizeError;
+use crate::saml::signature::SamlKey;
+use askama::Template;
+use axum::routing::{get, post};
+use std::sync::Arc;
+use utoipa_axum::router::OpenApiRouter;
+
+pub mod idp;
+pub mod logout;
+pub mod sp;
+
+const NOT_CONFIGURED: &str = "Signing in with SAML is not set up on this server.";
+
+const INVALID_MESSAGE: &str = "The application that sent you here sent an invalid sign in request. Go back to it and try again.";
+
+const UNKNOWN_SERVICE_PROVIDER: &str =
+    "The application that sent you here is not allowed to sign you in with this server.";
+
+/// Hands a message to the browser to post to a service provider. (SAML Bindings section 3.5)
+#[derive(Template)]
+#[template(path = "saml_post.html")]
+pub struct PostTemplate<'a> {
+    pub action: &'a str,
+    /// `SAMLRequest` or `SAMLResponse`
+    pub parameter: &'static str,
+    /// The base64 encoded message
+    pub message: String,
+    pub relay_state: Option<&'a str>,
+    /// Front-channel logouts to go through before posting
+    pub frontchannel_uris: Vec<String>,
+}
+
+/// Parameters of messages coming in with either binding.
+#[derive(Debug, serde::Deserialize)]
+pub struct SamlParams {
+    #[serde(rename = "SAMLRequest")]
+    pub saml_request: Option<String>,
+    #[serde(rename = "SAMLResponse")]
+    pub saml_response: Option<String>,
+    #[serde(rename = "RelayState")]
+    pub relay_state: Option<String>,
+}
+
+fn saml_key(global: &GlobalState) -> Result<&SamlKey, AuthorizeError> {
+    global
+        .saml_key
+        .as_ref()
+        .ok_or(AuthorizeError::Page(NOT_CONFIGURED))
+}
+
+/// Who this server is to service providers.
+pub fn entity_id(global: &GlobalState) -> String {
+    global
+        .settings
+        .saml
+        .entity_id
+        .clone()
+        .unwrap_or_else(|| format!("{}/saml/metadata", global.settings.oauth2.issuer))
+}
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .route("/saml/metadata", get(idp::metadata))
+        .route("/saml/sso", get(idp::sso_redirect).post(idp::sso_post))
+        .route("/saml/initiate", get(idp::initiate))
+        .route(
+            "/saml/slo",
+            get(logout::slo_redirect).post(logout::slo_post),
+        )
+        .route("/login/saml/metadata", get(sp::metadata))
+        .route("/login/saml/acs", post(sp::acs))
+        .route("/login/saml/{provider}", get(sp::start))
+        .route("/login/saml/{provider}/link", post(sp::link))
+}
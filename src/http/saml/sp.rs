@@ -0,0 +1,318 @@
+use crate::database::models::saml_login::DBSamlLogin;
+use crate::database::models::user::DBUserId;
+use crate::global::GlobalState;
+use crate::http::saml::SamlParams;
+use crate::http::saml::idp::post_page;
+use crate::http::session::{SessionUser, safe_return_to};
+use crate::http::upstream::{
+    DENIED_LOGIN, EXPIRED_LOGIN, Profile, UNKNOWN_PROVIDER, UpstreamError, finish_login,
+};
+use crate::saml::SamlError;
+use crate::saml::assertion::{ExpectedResponse, NAME_ID_TRANSIENT, verify_response};
+use crate::saml::metadata::{self, IdentityProviderMetadata};
+use crate::saml::response::authn_request;
+use crate::settings::SamlProvider;
+use axum::Form;
+use axum::extract::{Path, Query, State};
+use axum::http::header::CONTENT_TYPE;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum_extra::extract::CookieJar;
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use chrono::{Duration, Utc};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use url::Url;
+
+/// Who this server is to upstream identity providers.
+fn entity_id(global: &GlobalState) -> String {
+    format!("{}/login/saml/metadata", global.settings.oauth2.issuer)
+}
+
+fn acs_url(global: &GlobalState) -> String {
+    format!("{}/login/saml/acs", global.settings.oauth2.issuer)
+}
+
+fn state_cookie_name(global: &GlobalState) -> String {
+    format!("{}_saml", global.settings.session.cookie_name)
+}
+
+/// This server's service provider metadata, for identity providers to import.
+pub async fn metadata(State(global): State<Arc<GlobalState>>) -> Response {
+    let xml = metadata::service_provider(
+        &entity_id(&global),
+        &acs_url(&global),
+        global.saml_key.as_ref(),
+    );
+
+    ([(CONTENT_TYPE, "application/samlmetadata+xml")], xml).into_response()
+}
+
+/// The identity provider's metadata, read from its file or url when it isn't cached or was cached for too long.
+async fn load_metadata(
+    global: &GlobalState,
+    provider: &SamlProvider,
+) -> Result<Arc<IdentityProviderMetadata>, UpstreamError> {
+    let lifetime = std::time::Duration::from_secs(global.settings.upstream.saml_metadata_lifetime);
+    if let Some(metadata) = global.saml_metadata.get(&provider.id, lifetime) {
+        return Ok(metadata);
+    }
+
+    let xml =
+        if provider.metadata.starts_with("https://") || provider.metadata.starts_with("http://") {
+            global
+                .http_client
+                .get(&provider.metadata)
+                .timeout(std::time::Duration::from_secs(
+                    global.settings.upstream.timeout,
+                ))
+                .send()
+                .await
+                .and_then(reqwest::Response::error_for_status)
+                .map_err(|e| UpstreamError::Provider(e.to_string()))?
+                .text()
+                .await
+                .map_err(|e| UpstreamError::Provider(e.to_string()))?
+        } else {
+            tokio::fs::read_to_string(&provider.metadata)
+                .await
+                .map_err(|e| UpstreamError::Provider(format!("{}: {e}", provider.metadata)))?
+        };
+
+    let metadata = IdentityProviderMetadata::parse(&xml).map_err(|e| {
+        UpstreamError::Provider(format!("invalid metadata of {}: {e}", provider.id))
+    })?;
+    let metadata = Arc::new(metadata);
+    global
+        .saml_metadata
+        .insert(provider.id.clone(), metadata.clone());
+    Ok(metadata)
+}
+
+#[derive(Debug, serde::Deserialize)]
+pub struct StartQuery {
+    return_to: Option<String>,
+}
+
+/// Sends the user to the identity provider to sign in.
+pub async fn start(
+    State(global): State<Arc<GlobalState>>,
+    Path(provider_id): Path<String>,
+    jar: CookieJar,
+    Query(query): Query<StartQuery>,
+) -> Result<Response, UpstreamError> {
+    redirect_to_provider(&global, jar, &provider_id, query.return_to.as_deref(), None).await
+}
+
+/// Sends the signed in user to the identity provider, to link their account there to this one.
+pub async fn link(
+    State(global): State<Arc<GlobalState>>,
+    current: SessionUser,
+    Path(provider_id): Path<String>,
+    jar: CookieJar,
+    Form(form): Form<StartQuery>,
+) -> Result<Response, UpstreamError> {
+    redirect_to_provider(
+        &global,
+        jar,
+        &provider_id,
+        form.return_to.as_deref(),
+        Some(current.user.id),
+    )
+    .await
+}
+
+/// The state goes along as RelayState and into a cookie, so only the browser that started the login can finish it.
+/// Requests are signed when this server has a SAML key.
+async fn redirect_to_provider(
+    global: &GlobalState,
+    jar: CookieJar,
+    provider_id: &str,
+    return_to: Option<&str>,
+    user_id: Option<DBUserId>,
+) -> Result<Response, UpstreamError> {
+    let provider = global
+        .settings
+        .upstream
+        .saml_provider(provider_id)
+        .ok_or(UpstreamError::Page(UNKNOWN_PROVIDER))?;
+    let metadata = load_metadata(global, provider).await?;
+    let (location, binding) = &metadata.single_sign_on_service;
+
+    let request = authn_request(&entity_id(global), location, &acs_url(global));
+    let state = crate::crypto::generate_token();
+    let lifetime = global.settings.upstream.login_lifetime;
+
+    let mut transaction = global.database.begin().await?;
+    DBSamlLogin::builder()
+        .state_hash(crate::crypto::hash_token(&state))
+        .provider(provider.id.clone())
+        .request_id(request.id.clone())
+        .return_to(safe_return_to(return_to).to_string())
+        .user_id(user_id)
+        .expires_at(Utc::now() + Duration::seconds(lifetime))
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    // the response is posted back from the provider's site, so the cookie has to go along on cross-site requests.
+    // Browsers only take those over https, or from localhost
+    let cookie = Cookie::build((state_cookie_name(global), state.clone()))
+        .path("/login/saml")
+        .http_only(true)
+        .same_site(SameSite::None)
+        .secure(true)
+        .max_age(time::Duration::seconds(lifetime));
+    let jar = jar.add(cookie);
+
+    let response = match (binding.as_str(), &global.saml_key) {
+        (crate::saml::HTTP_POST, Some(key)) => {
+            post_page(location, "SAMLRequest", &request.signed(key), Some(&state))
+        }
+        (crate::saml::HTTP_POST, None) => {
+            post_page(location, "SAMLRequest", &request.unsigned(), Some(&state))
+        }
+        (_, Some(key)) => Redirect::to(&crate::saml::redirect_url(
+            key,
+            location,
+            "SAMLRequest",
+            &request.unsigned(),
+            Some(&state),
+        ))
+        .into_response(),
+        (_, None) => {
+            let mut url =
+                Url::parse(location).map_err(|e| UpstreamError::Provider(e.to_string()))?;
+            url.query_pairs_mut()
+                .append_pair(
+                    "SAMLRequest",
+                    &crate::saml::encode_redirect(&request.unsigned()),
+                )
+                .append_pair("RelayState", &state);
+            Redirect::to(url.as_str()).into_response()
+        }
+    };
+
+    Ok((jar, response).into_response())
+}
+
+/// The assertion consumer service identity providers post their responses to. Signs the user in, creating their
+/// account the first time, or links the provider to the account of whoever started linking it.
+pub async fn acs(
+    State(global): State<Arc<GlobalState>>,
+    jar: CookieJar,
+    Form(params): Form<SamlParams>,
+) -> Result<Response, UpstreamError> {
+    let cookie_name = state_cookie_name(&global);
+    let expected = jar
+        .get(&cookie_name)
+        .map(|cookie| cookie.value().to_string());
+    let jar = jar.remove(Cookie::build(cookie_name).path("/login/saml"));
+
+    // IdP-initiated responses answer no request, they are refused
+    let state = params
+        .relay_state
+        .filter(|state| expected.as_ref() == Some(state))
+        .ok_or(UpstreamError::Page(EXPIRED_LOGIN))?;
+    let login = DBSamlLogin::find_by_hash(&crate::crypto::hash_token(&state), &global.database)
+        .await?
+        .filter(|login| !login.is_expired())
+        .ok_or(UpstreamError::Page(EXPIRED_LOGIN))?;
+
+    // a request is good for one response, whatever its outcome
+    let mut transaction = global.database.begin().await?;
+    login.delete(&mut transaction).await?;
+    transaction.commit().await?;
+
+    let provider = global
+        .settings
+        .upstream
+        .saml_provider(&login.provider)
+        .ok_or(UpstreamError::Page(UNKNOWN_PROVIDER))?;
+    let metadata = load_metadata(&global, provider).await?;
+
+    let message = params
+        .saml_response
+        .ok_or(UpstreamError::Page(DENIED_LOGIN))?;
+    let xml =
+        crate::saml::decode_post(&message).map_err(|e| UpstreamError::Provider(e.to_string()))?;
+    let assertion = verify_response(
+        &xml,
+        &ExpectedResponse {
+            issuer: &metadata.entity_id,
+            certificates: &metadata.signing_certificates,
+            audience: &entity_id(&global),
+            recipient: &acs_url(&global),
+            in_response_to: &login.request_id,
+        },
+    )
+    .map_err(|e| match e {
+        SamlError::Status(status) => {
+            tracing::debug!("SAML provider {} answered with {status}", provider.id);
+            UpstreamError::Page(DENIED_LOGIN)
+        }
+        e => UpstreamError::Provider(format!("invalid response from {}: {e}", provider.id)),
+    })?;
+
+    let mapping = &provider.attributes;
+    let first = |name: &str| {
+        assertion
+            .attributes
+            .get(name)
+            .and_then(|values| values.first())
+            .filter(|value| !value.is_empty())
+            .cloned()
+    };
+    let subject = match &mapping.subject {
+        Some(name) => {
+            first(name).ok_or_else(|| UpstreamError::Provider(format!("no {name} attribute")))?
+        }
+        None if assertion.name_id_format.as_deref() == Some(NAME_ID_TRANSIENT) => {
+            return Err(UpstreamError::Provider(format!(
+                "{} sends transient NameIDs, set a subject attribute",
+                provider.id
+            )));
+        }
+        None => assertion.name_id.clone(),
+    };
+
+    let mut attributes = Map::new();
+    for (name, user_attribute) in &mapping.attributes {
+        let value = match assertion.attributes.get(name).map(Vec::as_slice) {
+            None | Some([]) => continue,
+            Some([value]) => Value::from(value.clone()),
+            Some(values) => Value::from(values.to_vec()),
+        };
+        attributes.insert(user_attribute.clone(), value);
+    }
+    let claims = assertion
+        .attributes
+        .iter()
+        .map(|(name, values)| {
+            let value = match values.as_slice() {
+                [value] => Value::from(value.clone()),
+                values => Value::from(values.to_vec()),
+            };
+            (name.clone(), value)
+        })
+        .collect();
+    let profile = Profile {
+        username: first(&mapping.username),
+        email: first(&mapping.email),
+        attributes,
+        claims,
+    };
+
+    // unlike with the upstream callback, the session cookie doesn't come along on the provider's cross-site post.
+    // The state cookie does, and it was set for whoever was signed in when linking started
+    finish_login(
+        &global,
+        jar,
+        &provider.id,
+        &subject,
+        login.user_id,
+        &login.return_to,
+        profile,
+    )
+    .await
+}
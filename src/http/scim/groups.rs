@@ -0,0 +1,339 @@
+use crate::database::models::group::{DBGroup, DBGroupId};
+use crate::database::models::group_member::DBGroupMember;
+use crate::database::models::scim_user::DBScimUser;
+use crate::database::models::user::{DBUser, DBUserId};
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::scim::{
+    AttributesQuery, ListQuery, Page, ScimApiError, ScimTenant, check_if_match,
+    filtered_list_response, from_object, json_object, list_filter, list_response,
+    resource_response, with_version,
+};
+use crate::scim::patch::PatchRequest;
+use crate::scim::{GROUP_SCHEMA, ScimError};
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value, json};
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+/// Every attribute name of the representation, for reading it whatever case the client spelled them in.
+const ATTRIBUTES: &[&str] = &[
+    "schemas",
+    "id",
+    "externalId",
+    "displayName",
+    "members",
+    "value",
+    "display",
+    "meta",
+];
+
+/// The attributes a client writes. The rest of the representation is read-only and ignored.
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GroupResource {
+    display_name: String,
+    external_id: Option<String>,
+    members: Option<Vec<Member>>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Member {
+    value: String,
+}
+
+pub async fn list(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, ScimApiError> {
+    let filter = list_filter(&query)?;
+    let (display_name, external_id) = match &filter {
+        None => (None, None),
+        Some(filter) => match (
+            filter.equality("displayName"),
+            filter.equality("externalId"),
+        ) {
+            (None, None) => {
+                // anything but looking a group up is filtered here, over every group of the tenant
+                let groups = DBGroup::find_many_by_tenant(&tenant, &global.database).await?;
+                let resources = representations(&global, &groups).await?;
+                return Ok(filtered_list_response(&global, &query, filter, resources));
+            }
+            equalities => equalities,
+        },
+    };
+
+    let page = Page::new(&global, &query);
+    let total =
+        DBGroup::count_by_tenant(&tenant, display_name, external_id, &global.database).await?;
+    let groups = DBGroup::find_page_by_tenant(
+        &tenant,
+        display_name,
+        external_id,
+        page.offset(),
+        page.count,
+        &global.database,
+    )
+    .await?;
+    let resources = representations(&global, &groups).await?;
+    Ok(list_response(&global, &query, total, resources))
+}
+
+pub async fn show(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Path(id): Path<String>,
+    Query(query): Query<AttributesQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ScimApiError> {
+    let resource = current(&global, &tenant, &id).await?;
+    Ok(resource_response(
+        StatusCode::OK,
+        &headers,
+        &query,
+        resource,
+    ))
+}
+
+pub async fn create(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Query(query): Query<AttributesQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ScimApiError> {
+    let resource: GroupResource = from_object(json_object(&body)?, ATTRIBUTES)?;
+    check_display_name(&global, &tenant, &resource.display_name, None).await?;
+    let members = members(&global, &tenant, resource.members.as_deref()).await?;
+
+    let group = DBGroup::builder()
+        .tenant_id(tenant.id())
+        .display_name(resource.display_name)
+        .external_id(resource.external_id)
+        .build();
+    let mut transaction = global.database.begin().await?;
+    group.insert(&mut transaction).await?;
+    DBGroupMember::replace(group.id, &members, &mut transaction).await?;
+    transaction.commit().await?;
+
+    let resource = current(&global, &tenant, &group.id.to_string()).await?;
+    Ok(resource_response(
+        StatusCode::CREATED,
+        &headers,
+        &query,
+        resource,
+    ))
+}
+
+pub async fn replace(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Path(id): Path<String>,
+    Query(query): Query<AttributesQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ScimApiError> {
+    let object = json_object(&body)?;
+    update(&global, &tenant, &id, &headers, |_| Ok(object)).await?;
+
+    let resource = current(&global, &tenant, &id).await?;
+    Ok(resource_response(
+        StatusCode::OK,
+        &headers,
+        &query,
+        resource,
+    ))
+}
+
+pub async fn patch(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Path(id): Path<String>,
+    Query(query): Query<AttributesQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ScimApiError> {
+    let request: PatchRequest = serde_json::from_slice(&body)
+        .map_err(|e| ScimError::InvalidSyntax(format!("invalid PATCH request: {e}")))?;
+    update(&global, &tenant, &id, &headers, |resource| {
+        let Value::Object(mut object) = resource else {
+            return Ok(Map::new());
+        };
+        crate::scim::patch::apply(&mut object, &request)?;
+        Ok(object)
+    })
+    .await?;
+
+    let resource = current(&global, &tenant, &id).await?;
+    Ok(resource_response(
+        StatusCode::OK,
+        &headers,
+        &query,
+        resource,
+    ))
+}
+
+pub async fn delete(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ScimApiError> {
+    let id = id.parse().map_err(|_| ScimApiError::NotFound)?;
+
+    let mut transaction = global.database.begin().await?;
+    let group = DBGroup::find_by_id_for_update(&tenant, id, &mut transaction)
+        .await?
+        .ok_or(ScimApiError::NotFound)?;
+    check_if_match(&headers, &representation_of(&global, &group).await?)?;
+    group.delete(&mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Changes a group to the resource `change` makes of its current representation, under a lock so nothing changes
+/// it in between checking the ETag and saving.
+async fn update(
+    global: &GlobalState,
+    tenant: &Tenant,
+    id: &str,
+    headers: &HeaderMap,
+    change: impl FnOnce(Value) -> Result<Map<String, Value>, ScimApiError>,
+) -> Result<(), ScimApiError> {
+    let id = id.parse().map_err(|_| ScimApiError::NotFound)?;
+
+    let mut transaction = global.database.begin().await?;
+    let mut group = DBGroup::find_by_id_for_update(tenant, id, &mut transaction)
+        .await?
+        .ok_or(ScimApiError::NotFound)?;
+    let current = representation_of(global, &group).await?;
+    check_if_match(headers, &current)?;
+
+    let resource: GroupResource = from_object(change(current)?, ATTRIBUTES)?;
+    check_display_name(global, tenant, &resource.display_name, Some(id)).await?;
+    let members = members(global, tenant, resource.members.as_deref()).await?;
+
+    group.display_name = resource.display_name;
+    group.external_id = resource.external_id;
+    group.updated_at = Utc::now();
+    group.update(&mut transaction).await?;
+    DBGroupMember::replace(group.id, &members, &mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok(())
+}
+
+async fn current(global: &GlobalState, tenant: &Tenant, id: &str) -> Result<Value, ScimApiError> {
+    let id = id.parse().map_err(|_| ScimApiError::NotFound)?;
+    let group = DBGroup::find_by_id(tenant, id, &global.database)
+        .await?
+        .ok_or(ScimApiError::NotFound)?;
+    representation_of(global, &group).await
+}
+
+async fn check_display_name(
+    global: &GlobalState,
+    tenant: &Tenant,
+    display_name: &str,
+    group_id: Option<DBGroupId>,
+) -> Result<(), ScimApiError> {
+    if display_name.trim().is_empty() {
+        return Err(ScimError::InvalidValue("displayName is required".into()).into());
+    }
+    match DBGroup::find_by_display_name(tenant, display_name, &global.database).await? {
+        Some(existing) if Some(existing.id) != group_id => Err(ScimError::Uniqueness(format!(
+            "displayName {display_name} is already taken"
+        ))
+        .into()),
+        _ => Ok(()),
+    }
+}
+
+/// The users the members point at. Only users the tenant provisioned can be members of its groups.
+async fn members(
+    global: &GlobalState,
+    tenant: &Tenant,
+    members: Option<&[Member]>,
+) -> Result<Vec<DBUserId>, ScimApiError> {
+    let not_a_user = |value: &str| ScimError::InvalidValue(format!("member {value} is not a user"));
+    let ids = members
+        .unwrap_or_default()
+        .iter()
+        .map(|member| member.value.parse().map_err(|_| not_a_user(&member.value)))
+        .collect::<Result<BTreeSet<DBUserId>, _>>()?;
+
+    let ids: Vec<DBUserId> = ids.into_iter().collect();
+    let found = DBScimUser::find_many_by_users(tenant, &ids, &global.database).await?;
+    if let Some(missing) = ids
+        .iter()
+        .find(|id| !found.iter().any(|user| user.user_id == **id))
+    {
+        return Err(not_a_user(&missing.to_string()).into());
+    }
+    Ok(ids)
+}
+
+async fn representation_of(global: &GlobalState, group: &DBGroup) -> Result<Value, ScimApiError> {
+    representations(global, std::slice::from_ref(group))
+        .await?
+        .pop()
+        .ok_or(ScimApiError::NotFound)
+}
+
+/// The representations of the groups, with their members. (RFC 7643 section 4.2)
+async fn representations(
+    global: &GlobalState,
+    groups: &[DBGroup],
+) -> Result<Vec<Value>, sqlx::Error> {
+    let ids: Vec<DBGroupId> = groups.iter().map(|group| group.id).collect();
+    let members = DBGroupMember::find_many_by_groups(&ids, &global.database).await?;
+    let user_ids: Vec<DBUserId> = members.iter().map(|member| member.user_id).collect();
+    let users: HashMap<DBUserId, DBUser> = DBUser::find_many_by_id(&user_ids, &global.database)
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect();
+
+    let issuer = &global.settings.oauth2.issuer;
+    Ok(groups
+        .iter()
+        .map(|group| {
+            let members: Vec<Value> = members
+                .iter()
+                .filter(|member| member.group_id == group.id)
+                .filter_map(|member| users.get(&member.user_id))
+                .map(|user| {
+                    json!({
+                        "value": user.id.to_string(),
+                        "display": user.username,
+                        "$ref": format!("{issuer}/scim/v2/Users/{}", user.id),
+                        "type": "User",
+                    })
+                })
+                .collect();
+
+            let mut resource = json!({
+                "schemas": [GROUP_SCHEMA],
+                "id": group.id.to_string(),
+                "displayName": group.display_name,
+                "members": members,
+                "meta": {
+                    "resourceType": "Group",
+                    "created": group.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    "lastModified": group.updated_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+                    "location": format!("{issuer}/scim/v2/Groups/{}", group.id),
+                },
+            });
+            if let Some(external_id) = &group.external_id {
+                resource["externalId"] = external_id.as_str().into();
+            }
+            with_version(resource)
+        })
+        .collect())
+}
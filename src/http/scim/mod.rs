@@ -0,0 +1,352 @@
+use crate::database::models::organization::DBOrganization;
+use crate::database::models::scim_token::DBScimToken;
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::internal_error;
+use crate::scim::filter::Filter;
+use crate::scim::{ERROR, LIST_RESPONSE, SERVICE_PROVIDER_CONFIG, ScimError, project};
+use axum::body::Bytes;
+use axum::extract::{FromRequestParts, State};
+use axum::http::header::{AUTHORIZATION, CONTENT_TYPE, ETAG, IF_MATCH, IF_NONE_MATCH, LOCATION};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use serde_json::{Map, Value, json};
+use std::sync::Arc;
+use utoipa_axum::router::OpenApiRouter;
+
+pub mod groups;
+pub mod users;
+
+const CONTENT_TYPE_SCIM: &str = "application/scim+json";
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .route(
+            "/scim/v2/ServiceProviderConfig",
+            get(service_provider_config),
+        )
+        .route("/scim/v2/Users", get(users::list).post(users::create))
+        .route(
+            "/scim/v2/Users/{id}",
+            get(users::show)
+                .put(users::replace)
+                .patch(users::patch)
+                .delete(users::delete),
+        )
+        .route("/scim/v2/Groups", get(groups::list).post(groups::create))
+        .route(
+            "/scim/v2/Groups/{id}",
+            get(groups::show)
+                .put(groups::replace)
+                .patch(groups::patch)
+                .delete(groups::delete),
+        )
+}
+
+/// The organization whose identity provider is making the request, by the bearer token it holds.
+pub struct ScimTenant(pub Tenant);
+
+impl FromRequestParts<Arc<GlobalState>> for ScimTenant {
+    type Rejection = ScimApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<GlobalState>,
+    ) -> Result<Self, Self::Rejection> {
+        let token = parts
+            .headers
+            .get(AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split_once(' '))
+            .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+            .map(|(_, token)| token.trim())
+            .ok_or(ScimApiError::Unauthorized)?;
+
+        let Some(token) =
+            DBScimToken::find_by_hash(&crate::crypto::hash_token(token), &state.database).await?
+        else {
+            return Err(ScimApiError::Unauthorized);
+        };
+        DBOrganization::find_by_id(token.tenant_id, &state.database)
+            .await?
+            .map(|organization| Self(Tenant::new(organization)))
+            .ok_or(ScimApiError::Unauthorized)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ScimApiError {
+    #[error("a valid SCIM bearer token is required")]
+    Unauthorized,
+    #[error("resource not found")]
+    NotFound,
+    #[error("the resource has changed since it was read")]
+    PreconditionFailed,
+    #[error(transparent)]
+    Scim(#[from] ScimError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+impl IntoResponse for ScimApiError {
+    fn into_response(self) -> Response {
+        let (status, scim_type) = match &self {
+            Self::Unauthorized => (StatusCode::UNAUTHORIZED, None),
+            Self::NotFound => (StatusCode::NOT_FOUND, None),
+            Self::PreconditionFailed => (StatusCode::PRECONDITION_FAILED, None),
+            Self::Scim(e @ ScimError::Uniqueness(_)) => (StatusCode::CONFLICT, Some(e.scim_type())),
+            Self::Scim(e) => (StatusCode::BAD_REQUEST, Some(e.scim_type())),
+            Self::Database(e) => return internal_error(e).into_response(),
+        };
+
+        let mut error = json!({
+            "schemas": [ERROR],
+            "status": status.as_u16().to_string(),
+            "detail": self.to_string(),
+        });
+        if let Some(scim_type) = scim_type {
+            error["scimType"] = scim_type.into();
+        }
+        scim_json(status, &error)
+    }
+}
+
+/// Query of list requests. (RFC 7644 section 3.4.2)
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListQuery {
+    filter: Option<String>,
+    start_index: Option<i64>,
+    count: Option<i64>,
+    attributes: Option<String>,
+    excluded_attributes: Option<String>,
+}
+
+/// Which attributes to return for a single resource. (RFC 7644 section 3.9)
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttributesQuery {
+    attributes: Option<String>,
+    excluded_attributes: Option<String>,
+}
+
+/// The part of the resources a list request asks for. startIndex is 1-based, and both are taken as their nearest
+/// sensible value when out of range.
+struct Page {
+    start_index: i64,
+    count: i64,
+}
+
+impl Page {
+    fn new(global: &GlobalState, query: &ListQuery) -> Self {
+        Self {
+            start_index: query.start_index.unwrap_or(1).max(1),
+            count: query
+                .count
+                .unwrap_or(i64::MAX)
+                .clamp(0, global.settings.scim.max_results as i64),
+        }
+    }
+
+    /// How many resources come before the page.
+    fn offset(&self) -> i64 {
+        self.start_index - 1
+    }
+}
+
+/// The filter of a list request, if it has one.
+fn list_filter(query: &ListQuery) -> Result<Option<Filter>, ScimError> {
+    query.filter.as_deref().map(Filter::parse).transpose()
+}
+
+/// Lists a page of resources the database already filtered and paged, out of `total` matching ones.
+fn list_response(
+    global: &GlobalState,
+    query: &ListQuery,
+    total: i64,
+    resources: Vec<Value>,
+) -> Response {
+    let page = Page::new(global, query);
+    let resources: Vec<Value> = resources
+        .into_iter()
+        .map(|mut resource| {
+            if let Value::Object(resource) = &mut resource {
+                project(
+                    resource,
+                    query.attributes.as_deref(),
+                    query.excluded_attributes.as_deref(),
+                );
+            }
+            resource
+        })
+        .collect();
+
+    let list = json!({
+        "schemas": [LIST_RESPONSE],
+        "totalResults": total,
+        "startIndex": page.start_index,
+        "itemsPerPage": resources.len(),
+        "Resources": resources,
+    });
+    scim_json(StatusCode::OK, &list)
+}
+
+/// Filters and pages through the representations of every resource of a tenant, for filters the database can't
+/// apply.
+fn filtered_list_response(
+    global: &GlobalState,
+    query: &ListQuery,
+    filter: &Filter,
+    resources: Vec<Value>,
+) -> Response {
+    let page = Page::new(global, query);
+    let matching: Vec<Value> = resources
+        .into_iter()
+        .filter(|resource| filter.matches(resource))
+        .collect();
+    let total = matching.len() as i64;
+    let resources = matching
+        .into_iter()
+        .skip(page.offset() as usize)
+        .take(page.count as usize)
+        .collect();
+    list_response(global, query, total, resources)
+}
+
+/// Puts the version into a representation's `meta`, a weak ETag of everything else in it. (RFC 7644 section 3.14)
+fn with_version(mut resource: Value) -> Value {
+    let version = format!(
+        "W/\"{}\"",
+        crate::crypto::sha256_base64url(&resource.to_string())
+    );
+    resource["meta"]["version"] = version.into();
+    resource
+}
+
+fn version(resource: &Value) -> &str {
+    resource["meta"]["version"].as_str().unwrap_or_default()
+}
+
+/// Refuses changing a resource when the client read it in another version than the current one.
+fn check_if_match(headers: &HeaderMap, resource: &Value) -> Result<(), ScimApiError> {
+    match headers.get(IF_MATCH).and_then(|value| value.to_str().ok()) {
+        Some(if_match) if !etag_matches(if_match, version(resource)) => {
+            Err(ScimApiError::PreconditionFailed)
+        }
+        _ => Ok(()),
+    }
+}
+
+fn etag_matches(header: &str, version: &str) -> bool {
+    // weak comparison, `W/` doesn't matter
+    let opaque = |etag: &str| etag.trim().trim_start_matches("W/").to_string();
+    header
+        .split(',')
+        .any(|etag| etag.trim() == "*" || opaque(etag) == opaque(version))
+}
+
+/// Answers with a single resource, or 304 when the client already has its current version.
+fn resource_response(
+    status: StatusCode,
+    headers: &HeaderMap,
+    query: &AttributesQuery,
+    resource: Value,
+) -> Response {
+    let version = version(&resource).to_string();
+    if status == StatusCode::OK
+        && let Some(if_none_match) = headers
+            .get(IF_NONE_MATCH)
+            .and_then(|value| value.to_str().ok())
+        && etag_matches(if_none_match, &version)
+    {
+        return (StatusCode::NOT_MODIFIED, [(ETAG, version)]).into_response();
+    }
+
+    let location = resource["meta"]["location"].as_str().map(str::to_string);
+    let mut resource = resource;
+    if let Value::Object(resource) = &mut resource {
+        project(
+            resource,
+            query.attributes.as_deref(),
+            query.excluded_attributes.as_deref(),
+        );
+    }
+
+    let mut response = scim_json(status, &resource);
+    if let Ok(version) = HeaderValue::from_str(&version) {
+        response.headers_mut().insert(ETAG, version);
+    }
+    if status == StatusCode::CREATED
+        && let Some(location) = location.and_then(|location| HeaderValue::from_str(&location).ok())
+    {
+        response.headers_mut().insert(LOCATION, location);
+    }
+    response
+}
+
+fn scim_json(status: StatusCode, body: &Value) -> Response {
+    (
+        status,
+        [(CONTENT_TYPE, CONTENT_TYPE_SCIM)],
+        body.to_string(),
+    )
+        .into_response()
+}
+
+/// A request body as a JSON object. Clients send `application/scim+json` as well as `application/json`, so the
+/// content type isn't checked.
+fn json_object(body: &Bytes) -> Result<Map<String, Value>, ScimApiError> {
+    serde_json::from_slice(body)
+        .map_err(|e| ScimApiError::Scim(ScimError::InvalidSyntax(format!("invalid JSON: {e}"))))
+}
+
+/// A resource read out of its JSON representation, with the attributes named the way the schema spells them.
+fn from_object<T: serde::de::DeserializeOwned>(
+    mut object: Map<String, Value>,
+    names: &[&str],
+) -> Result<T, ScimApiError> {
+    crate::scim::canonicalize(&mut object, names);
+    serde_json::from_value(Value::Object(object))
+        .map_err(|e| ScimApiError::Scim(ScimError::InvalidValue(e.to_string())))
+}
+
+/// Booleans the way some identity providers send them, as `"True"` and `"False"` strings.
+fn lenient_bool<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+    match <Value as serde::Deserialize>::deserialize(deserializer)? {
+        Value::Bool(value) => Ok(value),
+        Value::String(value) if value.eq_ignore_ascii_case("true") => Ok(true),
+        Value::String(value) if value.eq_ignore_ascii_case("false") => Ok(false),
+        value => Err(serde::de::Error::custom(format!(
+            "expected a boolean, got {value}"
+        ))),
+    }
+}
+
+/// What the SCIM endpoints support. (RFC 7643 section 5)
+async fn service_provider_config(
+    State(global): State<Arc<GlobalState>>,
+    _tenant: ScimTenant,
+) -> Response {
+    let config = json!({
+        "schemas": [SERVICE_PROVIDER_CONFIG],
+        "patch": { "supported": true },
+        "bulk": { "supported": false, "maxOperations": 0, "maxPayloadSize": 0 },
+        "filter": { "supported": true, "maxResults": global.settings.scim.max_results },
+        "changePassword": { "supported": false },
+        "sort": { "supported": false },
+        "etag": { "supported": true },
+        "authenticationSchemes": [{
+            "type": "oauthbearertoken",
+            "name": "Bearer token",
+            "description": "A token created for the organization with belt scim create-token",
+            "primary": true,
+        }],
+        "meta": {
+            "resourceType": "ServiceProviderConfig",
+            "location": format!("{}/scim/v2/ServiceProviderConfig", global.settings.oauth2.issuer),
+        },
+    });
+    scim_json(StatusCode::OK, &config)
+}
@@ -0,0 +1,485 @@
+use crate::database::models::access_token::DBAccessToken;
+use crate::database::models::authorization_code::DBAuthorizationCode;
+use crate::database::models::device_code::DBDeviceCode;
+use crate::database::models::group::{DBGroup, DBGroupId};
+use crate::database::models::group_member::DBGroupMember;
+use crate::database::models::refresh_token::DBRefreshToken;
+use crate::database::models::scim_user::DBScimUser;
+use crate::database::models::session::DBSession;
+use crate::database::models::user::{DBUser, DBUserId};
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::oauth2::logout::end_session;
+use crate::http::scim::{
+    AttributesQuery, ListQuery, Page, ScimApiError, ScimTenant, check_if_match,
+    filtered_list_response, from_object, json_object, lenient_bool, list_filter, list_response,
+    resource_response, with_version,
+};
+use crate::scim::patch::PatchRequest;
+use crate::scim::{ScimError, USER_SCHEMA};
+use axum::body::Bytes;
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use chrono::{SecondsFormat, Utc};
+use serde_json::{Map, Value, json};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// Every attribute name of the representation, for reading it whatever case the client spelled them in.
+const ATTRIBUTES: &[&str] = &[
+    "schemas",
+    "id",
+    "externalId",
+    "userName",
+    "name",
+    "givenName",
+    "familyName",
+    "displayName",
+    "emails",
+    "value",
+    "primary",
+    "active",
+    "groups",
+    "meta",
+];
+
+/// The attributes a client writes. The rest of the representation is read-only and ignored.
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct UserResource {
+    user_name: String,
+    external_id: Option<String>,
+    name: Option<Name>,
+    display_name: Option<String>,
+    emails: Option<Vec<Email>>,
+    #[serde(default = "default_active", deserialize_with = "lenient_bool")]
+    active: bool,
+}
+
+#[derive(Debug, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Name {
+    given_name: Option<String>,
+    family_name: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct Email {
+    value: String,
+    #[serde(default, deserialize_with = "lenient_bool")]
+    primary: bool,
+}
+
+fn default_active() -> bool {
+    true
+}
+
+pub async fn list(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Query(query): Query<ListQuery>,
+) -> Result<Response, ScimApiError> {
+    let filter = list_filter(&query)?;
+    let (username, external_id) = match &filter {
+        None => (None, None),
+        Some(filter) => match (filter.equality("userName"), filter.equality("externalId")) {
+            (None, None) => {
+                // anything but looking a user up is filtered here, over every user of the tenant
+                let scim_users = DBScimUser::find_many_by_tenant(&tenant, &global.database).await?;
+                let resources = representations(&global, &tenant, &scim_users).await?;
+                return Ok(filtered_list_response(&global, &query, filter, resources));
+            }
+            equalities => equalities,
+        },
+    };
+
+    let page = Page::new(&global, &query);
+    let total =
+        DBScimUser::count_by_tenant(&tenant, username, external_id, &global.database).await?;
+    let scim_users = DBScimUser::find_page_by_tenant(
+        &tenant,
+        username,
+        external_id,
+        page.offset(),
+        page.count,
+        &global.database,
+    )
+    .await?;
+    let resources = representations(&global, &tenant, &scim_users).await?;
+    Ok(list_response(&global, &query, total, resources))
+}
+
+pub async fn show(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Path(id): Path<String>,
+    Query(query): Query<AttributesQuery>,
+    headers: HeaderMap,
+) -> Result<Response, ScimApiError> {
+    let id = id.parse().map_err(|_| ScimApiError::NotFound)?;
+    let scim_user = DBScimUser::find_by_user(&tenant, id, &global.database)
+        .await?
+        .ok_or(ScimApiError::NotFound)?;
+
+    let resource = representation_of(&global, &tenant, &scim_user).await?;
+    Ok(resource_response(
+        StatusCode::OK,
+        &headers,
+        &query,
+        resource,
+    ))
+}
+
+pub async fn create(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Query(query): Query<AttributesQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ScimApiError> {
+    let resource: UserResource = from_object(json_object(&body)?, ATTRIBUTES)?;
+    check_username(&global, &resource.user_name, None).await?;
+
+    let mut user = DBUser::builder().username(String::new()).build();
+    let mut scim_user = DBScimUser::builder()
+        .user_id(user.id)
+        .tenant_id(tenant.id())
+        .build();
+    apply(&mut user, &mut scim_user, resource);
+    check_email(&global, user.email.as_deref(), None).await?;
+
+    let mut transaction = global.database.begin().await?;
+    user.insert(&mut transaction).await?;
+    scim_user.insert(&mut transaction).await?;
+    transaction.commit().await?;
+
+    let resource = representation_of(&global, &tenant, &scim_user).await?;
+    Ok(resource_response(
+        StatusCode::CREATED,
+        &headers,
+        &query,
+        resource,
+    ))
+}
+
+pub async fn replace(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Path(id): Path<String>,
+    Query(query): Query<AttributesQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ScimApiError> {
+    let object = json_object(&body)?;
+    update(&global, &tenant, &id, &headers, |_| Ok(object)).await?;
+
+    let resource = show_after_update(&global, &tenant, &id).await?;
+    Ok(resource_response(
+        StatusCode::OK,
+        &headers,
+        &query,
+        resource,
+    ))
+}
+
+pub async fn patch(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Path(id): Path<String>,
+    Query(query): Query<AttributesQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Response, ScimApiError> {
+    let request: PatchRequest = serde_json::from_slice(&body)
+        .map_err(|e| ScimError::InvalidSyntax(format!("invalid PATCH request: {e}")))?;
+    update(&global, &tenant, &id, &headers, |resource| {
+        let Value::Object(mut object) = resource else {
+            return Ok(Map::new());
+        };
+        crate::scim::patch::apply(&mut object, &request)?;
+        Ok(object)
+    })
+    .await?;
+
+    let resource = show_after_update(&global, &tenant, &id).await?;
+    Ok(resource_response(
+        StatusCode::OK,
+        &headers,
+        &query,
+        resource,
+    ))
+}
+
+/// Deleting a user signs them out everywhere first, so clients get back-channel logouts for their sessions.
+pub async fn delete(
+    State(global): State<Arc<GlobalState>>,
+    ScimTenant(tenant): ScimTenant,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Result<Response, ScimApiError> {
+    let id = id.parse().map_err(|_| ScimApiError::NotFound)?;
+
+    let mut transaction = global.database.begin().await?;
+    let scim_user = DBScimUser::find_by_user_for_update(&tenant, id, &mut transaction)
+        .await?
+        .ok_or(ScimApiError::NotFound)?;
+    check_if_match(
+        &headers,
+        &representation_of(&global, &tenant, &scim_user).await?,
+    )?;
+    let user = DBUser::find_by_id(id, &global.database)
+        .await?
+        .ok_or(ScimApiError::NotFound)?;
+
+    end_sessions(&global, id).await?;
+    user.delete(&mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+/// Changes a user to the resource `change` makes of their current representation, under a lock so nothing changes
+/// them in between checking the ETag and saving.
+async fn update(
+    global: &GlobalState,
+    tenant: &Tenant,
+    id: &str,
+    headers: &HeaderMap,
+    change: impl FnOnce(Value) -> Result<Map<String, Value>, ScimApiError>,
+) -> Result<(), ScimApiError> {
+    let id = id.parse().map_err(|_| ScimApiError::NotFound)?;
+
+    let mut transaction = global.database.begin().await?;
+    let mut scim_user = DBScimUser::find_by_user_for_update(tenant, id, &mut transaction)
+        .await?
+        .ok_or(ScimApiError::NotFound)?;
+    let current = representation_of(global, tenant, &scim_user).await?;
+    check_if_match(headers, &current)?;
+    let mut user = DBUser::find_by_id(id, &global.database)
+        .await?
+        .ok_or(ScimApiError::NotFound)?;
+
+    let resource: UserResource = from_object(change(current)?, ATTRIBUTES)?;
+    check_username(global, &resource.user_name, Some(id)).await?;
+
+    let was_active = user.active;
+    apply(&mut user, &mut scim_user, resource);
+    check_email(global, user.email.as_deref(), Some(id)).await?;
+    scim_user.updated_at = Utc::now();
+    user.update(&mut transaction).await?;
+    scim_user.update(&mut transaction).await?;
+
+    let deactivated = was_active && !user.active;
+    if deactivated {
+        DBRefreshToken::delete_by_user(id, &mut transaction).await?;
+        DBAccessToken::delete_by_user(id, &mut transaction).await?;
+        DBAuthorizationCode::delete_by_user(id, &mut transaction).await?;
+        DBDeviceCode::delete_by_user(id, &mut transaction).await?;
+    }
+    transaction.commit().await?;
+
+    // signing in checks for active users, so no new sessions start once the change is committed
+    if deactivated {
+        end_sessions(global, id).await?;
+    }
+    Ok(())
+}
+
+async fn show_after_update(
+    global: &GlobalState,
+    tenant: &Tenant,
+    id: &str,
+) -> Result<Value, ScimApiError> {
+    let id = id.parse().map_err(|_| ScimApiError::NotFound)?;
+    let scim_user = DBScimUser::find_by_user(tenant, id, &global.database)
+        .await?
+        .ok_or(ScimApiError::NotFound)?;
+    representation_of(global, tenant, &scim_user).await
+}
+
+/// Usernames are unique across tenants, so a clash can be with a user the tenant doesn't see.
+async fn check_username(
+    global: &GlobalState,
+    username: &str,
+    user_id: Option<DBUserId>,
+) -> Result<(), ScimApiError> {
+    if username.trim().is_empty() {
+        return Err(ScimError::InvalidValue("userName is required".into()).into());
+    }
+    match DBUser::find_by_username(username, &global.database).await? {
+        Some(existing) if Some(existing.id) != user_id => {
+            Err(ScimError::Uniqueness(format!("userName {username} is already taken")).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Emails are unique across tenants too, of the emails a client writes the one kept on the user is checked.
+async fn check_email(
+    global: &GlobalState,
+    email: Option<&str>,
+    user_id: Option<DBUserId>,
+) -> Result<(), ScimApiError> {
+    let Some(email) = email else {
+        return Ok(());
+    };
+    match DBUser::find_by_email(email, &global.database).await? {
+        Some(existing) if Some(existing.id) != user_id => {
+            Err(ScimError::Uniqueness(format!("email {email} is already taken")).into())
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Ends every session of the user and queues the back-channel logouts of their clients.
+async fn end_sessions(global: &GlobalState, user_id: DBUserId) -> Result<(), sqlx::Error> {
+    for session in DBSession::find_many_by_user(user_id, &global.database).await? {
+        end_session(global, &session).await?;
+    }
+    Ok(())
+}
+
+/// Copies what a client wrote onto the user. Names are kept in the attributes under the claims they are released as,
+/// and of the emails only the primary one, or else the first.
+fn apply(user: &mut DBUser, scim_user: &mut DBScimUser, resource: UserResource) {
+    user.username = resource.user_name;
+    user.active = resource.active;
+    let emails = resource.emails.unwrap_or_default();
+    user.email = emails
+        .iter()
+        .find(|email| email.primary)
+        .or(emails.first())
+        .map(|email| email.value.clone());
+    scim_user.external_id = resource.external_id;
+
+    let mut attributes = match &user.attributes {
+        Value::Object(attributes) => attributes.clone(),
+        _ => Map::new(),
+    };
+    let name = resource.name;
+    let managed = [
+        (
+            "given_name",
+            name.as_ref().and_then(|name| name.given_name.clone()),
+        ),
+        (
+            "family_name",
+            name.as_ref().and_then(|name| name.family_name.clone()),
+        ),
+        ("name", resource.display_name),
+    ];
+    for (claim, value) in managed {
+        match value {
+            Some(value) => attributes.insert(claim.to_string(), value.into()),
+            None => attributes.remove(claim),
+        };
+    }
+    user.attributes = Value::Object(attributes);
+}
+
+async fn representation_of(
+    global: &GlobalState,
+    tenant: &Tenant,
+    scim_user: &DBScimUser,
+) -> Result<Value, ScimApiError> {
+    representations(global, tenant, std::slice::from_ref(scim_user))
+        .await?
+        .pop()
+        .ok_or(ScimApiError::NotFound)
+}
+
+/// The representations of the users, with the groups of the tenant they are in. (RFC 7643 section 4.1)
+async fn representations(
+    global: &GlobalState,
+    tenant: &Tenant,
+    scim_users: &[DBScimUser],
+) -> Result<Vec<Value>, sqlx::Error> {
+    let ids: Vec<DBUserId> = scim_users.iter().map(|user| user.user_id).collect();
+    let users: HashMap<DBUserId, DBUser> = DBUser::find_many_by_id(&ids, &global.database)
+        .await?
+        .into_iter()
+        .map(|user| (user.id, user))
+        .collect();
+    let members = DBGroupMember::find_many_by_users(&ids, &global.database).await?;
+    let group_ids: Vec<DBGroupId> = members.iter().map(|member| member.group_id).collect();
+    let groups: HashMap<DBGroupId, DBGroup> =
+        DBGroup::find_many_by_ids(tenant, &group_ids, &global.database)
+            .await?
+            .into_iter()
+            .map(|group| (group.id, group))
+            .collect();
+    let mut memberships: HashMap<DBUserId, Vec<&DBGroup>> = HashMap::new();
+    for member in members {
+        if let Some(group) = groups.get(&member.group_id) {
+            memberships.entry(member.user_id).or_default().push(group);
+        }
+    }
+
+    let issuer = &global.settings.oauth2.issuer;
+    Ok(scim_users
+        .iter()
+        .filter_map(|scim_user| {
+            let user = users.get(&scim_user.user_id)?;
+            let groups = memberships.get(&user.id).map_or(&[][..], Vec::as_slice);
+            Some(representation(issuer, scim_user, user, groups))
+        })
+        .collect())
+}
+
+fn representation(
+    issuer: &str,
+    scim_user: &DBScimUser,
+    user: &DBUser,
+    groups: &[&DBGroup],
+) -> Value {
+    let text = |claim: &str| user.attributes.get(claim).and_then(Value::as_str);
+
+    let mut resource = json!({
+        "schemas": [USER_SCHEMA],
+        "id": user.id.to_string(),
+        "userName": user.username,
+        "active": user.active,
+        "meta": {
+            "resourceType": "User",
+            "created": scim_user.created_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "lastModified": scim_user.updated_at.to_rfc3339_opts(SecondsFormat::Secs, true),
+            "location": format!("{issuer}/scim/v2/Users/{}", user.id),
+        },
+    });
+    if let Some(external_id) = &scim_user.external_id {
+        resource["externalId"] = external_id.as_str().into();
+    }
+
+    let mut name = Map::new();
+    if let Some(given_name) = text("given_name") {
+        name.insert("givenName".into(), given_name.into());
+    }
+    if let Some(family_name) = text("family_name") {
+        name.insert("familyName".into(), family_name.into());
+    }
+    if !name.is_empty() {
+        resource["name"] = Value::Object(name);
+    }
+    if let Some(display_name) = text("name") {
+        resource["displayName"] = display_name.into();
+    }
+    if let Some(email) = &user.email {
+        resource["emails"] = json!([{ "value": email, "type": "work", "primary": true }]);
+    }
+    if !groups.is_empty() {
+        resource["groups"] = groups
+            .iter()
+            .map(|group| {
+                json!({
+                    "value": group.id.to_string(),
+                    "display": group.display_name,
+                    "$ref": format!("{issuer}/scim/v2/Groups/{}", group.id),
+                    "type": "direct",
+                })
+            })
+            .collect();
+    }
+
+    with_version(resource)
+}
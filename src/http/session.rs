@@ -0,0 +1,397 @@
+use crate::database::models::identity::DBIdentity;
+use crate::database::models::session::DBSession;
+use crate::database::models::user::DBUser;
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::internal_error;
+use crate::http::oauth2::logout::{end_session, logged_out};
+use crate::http::template::HtmlTemplate;
+use crate::http::tenant::{RequestTenant, host_name};
+use crate::http::upstream::{Profile, create_user};
+use crate::ldap::DirectoryUser;
+use crate::settings::{LOCAL_BACKEND, LdapDirectory};
+use askama::Template;
+use axum::Form;
+use axum::extract::{FromRequestParts, OriginalUri, Query, State};
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum_extra::extract::CookieJar;
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use chrono::{Duration, Utc};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use url::Url;
+use utoipa_axum::router::OpenApiRouter;
+
+/// The user signed into the browser making the request. Pages using this redirect to the login page when nobody is.
+pub struct SessionUser {
+    pub user: DBUser,
+    pub session: DBSession,
+}
+
+impl SessionUser {
+    pub async fn from_jar(
+        global: &GlobalState,
+        jar: &CookieJar,
+    ) -> Result<Option<Self>, sqlx::Error> {
+        let Some(cookie) = jar.get(&global.settings.session.cookie_name) else {
+            return Ok(None);
+        };
+
+        let token_hash = crate::crypto::hash_token(cookie.value());
+        let Some(session) = DBSession::find_by_hash(&token_hash, &global.database).await? else {
+            return Ok(None);
+        };
+
+        // sessions of deactivated users are ended, this covers the moment in between
+        let user = DBUser::find_by_id(session.user_id, &global.database).await?;
+        Ok(user
+            .filter(|user| user.active)
+            .map(|user| Self { user, session }))
+    }
+}
+
+impl FromRequestParts<Arc<GlobalState>> for SessionUser {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<GlobalState>,
+    ) -> Result<Self, Self::Rejection> {
+        let jar = CookieJar::from_headers(&parts.headers);
+        match Self::from_jar(state, &jar).await {
+            Ok(Some(user)) => Ok(user),
+            Ok(None) => {
+                // the uri the request came in with, organization path prefix and all
+                let uri = parts
+                    .extensions
+                    .get::<OriginalUri>()
+                    .map_or(&parts.uri, |original| &original.0);
+                let return_to = uri.path_and_query().map_or("/", |pq| pq.as_str());
+                Err(Redirect::to(&login_url(return_to)).into_response())
+            }
+            Err(e) => Err(internal_error(e).into_response()),
+        }
+    }
+}
+
+pub fn login_url(return_to: &str) -> String {
+    let return_to: String = url::form_urlencoded::byte_serialize(return_to.as_bytes()).collect();
+    format!("/login?return_to={return_to}")
+}
+
+/// Only allows redirecting back to paths on this server, so the login page can't be used as an open redirect.
+pub fn safe_return_to(return_to: Option<&str>) -> &str {
+    match return_to {
+        Some(path)
+            if path.starts_with('/') && !path.starts_with("//") && !path.starts_with("/\\") =>
+        {
+            path
+        }
+        _ => "/",
+    }
+}
+
+#[derive(Template)]
+#[template(path = "login.html")]
+struct LoginTemplate<'a> {
+    error: Option<&'a str>,
+    csrf_token: &'a str,
+    return_to: &'a str,
+    username: &'a str,
+    /// Names and links of the upstream providers users can sign in with instead
+    providers: Vec<(&'a str, String)>,
+}
+
+fn provider_links<'a>(global: &'a GlobalState, return_to: &str) -> Vec<(&'a str, String)> {
+    let return_to: String = url::form_urlencoded::byte_serialize(return_to.as_bytes()).collect();
+    global
+        .settings
+        .upstream
+        .providers
+        .iter()
+        .map(|provider| {
+            (
+                provider.name.as_str(),
+                format!("/login/upstream/{}?return_to={return_to}", provider.id),
+            )
+        })
+        .chain(
+            global
+                .settings
+                .upstream
+                .saml_providers
+                .iter()
+                .map(|provider| {
+                    (
+                        provider.name.as_str(),
+                        format!("/login/saml/{}?return_to={return_to}", provider.id),
+                    )
+                }),
+        )
+        .collect()
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LoginQuery {
+    return_to: Option<String>,
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct LoginForm {
+    username: String,
+    password: String,
+    return_to: Option<String>,
+    #[serde(default)]
+    csrf_token: String,
+}
+
+fn csrf_cookie_name(global: &GlobalState) -> String {
+    format!("{}_csrf", global.settings.session.cookie_name)
+}
+
+/// The token the login form has to send back, the same as in the cookie next to it. Other sites can't read or set
+/// the cookie, so they can't sign browsers into an account of theirs. The one the browser has already is kept, so
+/// login pages open in several tabs all work.
+fn csrf_token(global: &GlobalState, jar: CookieJar) -> (CookieJar, String) {
+    let name = csrf_cookie_name(global);
+    if let Some(cookie) = jar.get(&name) {
+        let token = cookie.value().to_string();
+        return (jar, token);
+    }
+
+    let token = crate::crypto::generate_token();
+    let cookie = Cookie::build((name, token.clone()))
+        .path("/login")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(global.settings.oauth2.issuer.starts_with("https://"));
+    (jar.add(cookie), token)
+}
+
+async fn login_page(
+    State(global): State<Arc<GlobalState>>,
+    jar: CookieJar,
+    Query(query): Query<LoginQuery>,
+) -> Response {
+    let return_to = safe_return_to(query.return_to.as_deref());
+    let (jar, csrf_token) = csrf_token(&global, jar);
+    let page = HtmlTemplate(LoginTemplate {
+        error: None,
+        csrf_token: &csrf_token,
+        return_to,
+        username: "",
+        providers: provider_links(&global, return_to),
+    });
+    (jar, page).into_response()
+}
+
+async fn login(
+    State(global): State<Arc<GlobalState>>,
+    RequestTenant(tenant): RequestTenant,
+    headers: HeaderMap,
+    jar: CookieJar,
+    Form(form): Form<LoginForm>,
+) -> Result<Response, StatusCode> {
+    let return_to = safe_return_to(form.return_to.as_deref());
+    let expected = jar
+        .get(&csrf_cookie_name(&global))
+        .map(|cookie| cookie.value().to_string());
+    let (jar, csrf_token) = csrf_token(&global, jar);
+    let failed = |status, error| {
+        let page = HtmlTemplate(LoginTemplate {
+            error: Some(error),
+            csrf_token: &csrf_token,
+            return_to,
+            username: &form.username,
+            providers: provider_links(&global, return_to),
+        });
+        (status, jar.clone(), page).into_response()
+    };
+
+    if !expected.is_some_and(|expected| crate::crypto::secrets_match(&expected, &form.csrf_token)) {
+        return Ok(failed(
+            StatusCode::BAD_REQUEST,
+            "This sign in form expired. Try again.",
+        ));
+    }
+
+    let authentication = &global.settings.authentication;
+    let Some(backend) = password_backend(&global, tenant.as_ref(), &headers) else {
+        return Ok(failed(
+            StatusCode::MISDIRECTED_REQUEST,
+            "Passwords can't be checked at this address.",
+        ));
+    };
+    let user = if backend == LOCAL_BACKEND {
+        let user = DBUser::find_by_username(&form.username, &global.database)
+            .await
+            .map_err(internal_error)?;
+        match user.as_ref().and_then(|user| user.password_hash.as_deref()) {
+            Some(hash) if crate::crypto::verify_password(&form.password, hash) => user,
+            Some(_) => None,
+            None => {
+                crate::crypto::verify_unusable_password(&form.password);
+                None
+            }
+        }
+    } else {
+        let directory = authentication.directory(backend).ok_or_else(|| {
+            internal_error(format!("no directory {backend} to check passwords with"))
+        })?;
+        match crate::ldap::authenticate(directory, &form.username, &form.password).await {
+            Ok(Some(found)) => Some(
+                directory_user(&global, directory, found)
+                    .await
+                    .map_err(internal_error)?,
+            ),
+            Ok(None) => None,
+            Err(e) => {
+                tracing::warn!(
+                    "Checking a password with directory {} failed: {e}",
+                    directory.id
+                );
+                return Ok(failed(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "Passwords can't be checked right now. Try again later.",
+                ));
+            }
+        }
+    };
+
+    let Some(user) = user.filter(|user| user.active) else {
+        return Ok(failed(
+            StatusCode::UNAUTHORIZED,
+            "Invalid username or password.",
+        ));
+    };
+
+    let jar = start_session(&global, jar, &user)
+        .await
+        .map_err(internal_error)?;
+    Ok((jar, Redirect::to(return_to)).into_response())
+}
+
+/// What checks passwords for the organization the request is for. Requests for none only get the default backend at
+/// the issuer's own host, the Host header is whatever the caller sent.
+fn password_backend<'a>(
+    global: &'a GlobalState,
+    tenant: Option<&Tenant>,
+    headers: &HeaderMap,
+) -> Option<&'a str> {
+    let authentication = &global.settings.authentication;
+    if let Some(tenant) = tenant {
+        return Some(authentication.backend(&tenant.organization().slug));
+    }
+
+    let issuer = Url::parse(&global.settings.oauth2.issuer).ok()?;
+    let host = host_name(headers)?;
+    issuer
+        .host_str()
+        .is_some_and(|issuer_host| issuer_host.eq_ignore_ascii_case(host))
+        .then_some(authentication.backend.as_str())
+}
+
+/// The account of someone the directory accepted, created the first time. The directory is where the mapped
+/// attributes are managed, so they are updated on every login.
+async fn directory_user(
+    global: &GlobalState,
+    directory: &LdapDirectory,
+    found: DirectoryUser,
+) -> Result<DBUser, sqlx::Error> {
+    let identity =
+        DBIdentity::find_by_subject(&directory.id, &found.subject, &global.database).await?;
+    let Some(mut user) = (match identity {
+        Some(identity) => DBUser::find_by_id(identity.user_id, &global.database).await?,
+        None => None,
+    }) else {
+        let profile = Profile {
+            username: found.username,
+            email: found.email,
+            attributes: found.attributes,
+            claims: Map::new(),
+        };
+        return create_user(global, &directory.id, &found.subject, profile).await;
+    };
+
+    let mut attributes = match &user.attributes {
+        Value::Object(attributes) => attributes.clone(),
+        _ => Map::new(),
+    };
+    let managed = directory
+        .attributes
+        .attributes
+        .values()
+        .map(String::as_str)
+        .chain(directory.group_base_dn.as_ref().map(|_| "groups"));
+    for name in managed {
+        match found.attributes.get(name) {
+            Some(value) => attributes.insert(name.to_string(), value.clone()),
+            None => attributes.remove(name),
+        };
+    }
+
+    let attributes = Value::Object(attributes);
+    if attributes != user.attributes {
+        user.attributes = attributes;
+        let mut transaction = global.database.begin().await?;
+        user.update_attributes(&mut transaction).await?;
+        transaction.commit().await?;
+    }
+    Ok(user)
+}
+
+/// Creates a new login session for the user and puts its cookie into the jar.
+pub async fn start_session(
+    global: &GlobalState,
+    jar: CookieJar,
+    user: &DBUser,
+) -> Result<CookieJar, sqlx::Error> {
+    let settings = &global.settings.session;
+    let token = crate::crypto::generate_token();
+
+    let mut transaction = global.database.begin().await?;
+    DBSession::builder()
+        .token_hash(crate::crypto::hash_token(&token))
+        .user_id(user.id)
+        .expires_at(Utc::now() + Duration::seconds(settings.lifetime))
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    let cookie = Cookie::build((settings.cookie_name.clone(), token))
+        .path("/")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(global.settings.oauth2.issuer.starts_with("https://"))
+        .max_age(time::Duration::seconds(settings.lifetime));
+
+    Ok(jar.add(cookie))
+}
+
+/// Signs out of this server and every client the session was used with.
+async fn logout(
+    State(global): State<Arc<GlobalState>>,
+    jar: CookieJar,
+) -> Result<Response, StatusCode> {
+    let mut frontchannel_uris = Vec::new();
+    if let Some(current) = SessionUser::from_jar(&global, &jar)
+        .await
+        .map_err(internal_error)?
+    {
+        frontchannel_uris = end_session(&global, &current.session)
+            .await
+            .map_err(internal_error)?;
+    }
+
+    Ok(logged_out(&global, jar, frontchannel_uris, None))
+}
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .route("/login", get(login_page).post(login))
+        .route("/logout", post(logout))
+}
@@ -0,0 +1,26 @@
+use askama::Template;
+use axum::http::StatusCode;
+use axum::response::{Html, IntoResponse, Response};
+
+/// Renders an askama template as an html response.
+pub struct HtmlTemplate<T>(pub T);
+
+impl<T: Template> IntoResponse for HtmlTemplate<T> {
+    fn into_response(self) -> Response {
+        match self.0.render() {
+            Ok(html) => Html(html).into_response(),
+            Err(e) => {
+                tracing::error!("Failed rendering a template: {e}");
+                StatusCode::INTERNAL_SERVER_ERROR.into_response()
+            }
+        }
+    }
+}
+
+/// A page with just a title and a sentence, for when a flow ends in the browser.
+#[derive(Template)]
+#[template(path = "message.html")]
+pub struct MessageTemplate<'a> {
+    pub title: &'a str,
+    pub message: &'a str,
+}
@@ -0,0 +1,104 @@
+//! Which organization a request is for: named by a header, by a path prefix, or by the host name it came in at.
+
+use crate::database::models::organization::DBOrganization;
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::internal_error;
+use axum::extract::{FromRequestParts, OriginalUri, Request, State};
+use axum::http::header::HOST;
+use axum::http::request::Parts;
+use axum::http::{HeaderMap, StatusCode, Uri};
+use axum::middleware::Next;
+use axum::response::Response;
+use std::sync::Arc;
+
+/// The slug of the path prefix a request came in with.
+#[derive(Debug, Clone)]
+struct PathSlug(String);
+
+/// Takes `/o/acme` off `/o/acme/v1/organization` before routing, so the request is handled like any other and
+/// still knows whose it is. The uri it came in with stays around as [`OriginalUri`], for links back to it.
+pub async fn strip_path_prefix(
+    State(global): State<Arc<GlobalState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let prefix = &global.settings.tenancy.path_prefix;
+    let stripped = request
+        .uri()
+        .path()
+        .strip_prefix(prefix.as_str())
+        .and_then(|rest| rest.strip_prefix('/'))
+        .filter(|_| !prefix.is_empty())
+        .map(|rest| match rest.split_once('/') {
+            Some((slug, path)) => (slug.to_string(), format!("/{path}")),
+            None => (rest.to_string(), "/".to_string()),
+        });
+
+    if let Some((slug, path)) = stripped
+        && !slug.is_empty()
+    {
+        let path_and_query = match request.uri().query() {
+            Some(query) => format!("{path}?{query}"),
+            None => path,
+        };
+        let mut parts = request.uri().clone().into_parts();
+        parts.path_and_query = path_and_query.parse().ok();
+        if let Ok(uri) = Uri::from_parts(parts) {
+            let original = std::mem::replace(request.uri_mut(), uri);
+            request.extensions_mut().insert(OriginalUri(original));
+            request.extensions_mut().insert(PathSlug(slug));
+        }
+    }
+
+    next.run(request).await
+}
+
+/// The organization a page is requested for, if any.
+pub struct RequestTenant(pub Option<Tenant>);
+
+impl FromRequestParts<Arc<GlobalState>> for RequestTenant {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<GlobalState>,
+    ) -> Result<Self, Self::Rejection> {
+        resolve(state, parts)
+            .await
+            .map(Self)
+            .map_err(internal_error)
+    }
+}
+
+/// The organization the request is for, if any. One named by the header or the path that doesn't exist is none,
+/// whatever the host name says.
+pub async fn resolve(global: &GlobalState, parts: &Parts) -> Result<Option<Tenant>, sqlx::Error> {
+    let header = &global.settings.tenancy.header;
+    let named = parts
+        .headers
+        .get(header.as_str())
+        .filter(|_| !header.is_empty())
+        .and_then(|slug| slug.to_str().ok())
+        .or_else(|| {
+            parts
+                .extensions
+                .get::<PathSlug>()
+                .map(|slug| slug.0.as_str())
+        });
+
+    let organization = match named {
+        Some(slug) => DBOrganization::find_by_slug(slug, &global.database).await?,
+        None => match host_name(&parts.headers) {
+            Some(host) => DBOrganization::find_by_domain(host, &global.database).await?,
+            None => None,
+        },
+    };
+    Ok(organization.map(Tenant::new))
+}
+
+/// The host name of the request, without the port.
+pub(crate) fn host_name(headers: &HeaderMap) -> Option<&str> {
+    let host = headers.get(HOST)?.to_str().ok()?;
+    Some(host.rsplit_once(':').map_or(host, |(host, _)| host))
+}
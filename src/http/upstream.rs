@@ -0,0 +1,640 @@
+use crate::database::models::identity::DBIdentity;
+use crate::database::models::upstream_login::DBUpstreamLogin;
+use crate::database::models::user::{DBUser, DBUserId};
+use crate::global::GlobalState;
+use crate::http::internal_error;
+use crate::http::provisioning;
+use crate::http::session::{SessionUser, safe_return_to, start_session};
+use crate::http::template::{HtmlTemplate, MessageTemplate};
+use crate::settings::UpstreamProvider;
+use axum::Form;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::http::header::ACCEPT;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum_extra::extract::CookieJar;
+use axum_extra::extract::cookie::{Cookie, SameSite};
+use chrono::{Duration, Utc};
+use jsonwebtoken::jwk::{Jwk, JwkSet};
+use jsonwebtoken::{Algorithm, DecodingKey, Validation};
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use url::Url;
+use utoipa_axum::router::OpenApiRouter;
+
+pub const UNKNOWN_PROVIDER: &str = "There is no way to sign in like that.";
+
+pub const EXPIRED_LOGIN: &str =
+    "This sign in has expired or was started in another browser. Try again.";
+
+const DISABLED: &str = "This account has been disabled.";
+
+const NOT_PROVISIONED: &str =
+    "You can't get an account by signing in like that. Ask your administrator for access.";
+
+const LINKED_ELSEWHERE: &str =
+    "That account already signs in to another user. Unlink it there before linking it here.";
+
+pub const DENIED_LOGIN: &str = "The sign in was cancelled at the provider.";
+
+pub enum UpstreamError {
+    /// Something the user can fix by starting over.
+    Page(&'static str),
+    /// The provider misbehaved or is misconfigured. Only the logs get the details.
+    Provider(String),
+    Internal(sqlx::Error),
+}
+
+impl From<sqlx::Error> for UpstreamError {
+    fn from(value: sqlx::Error) -> Self {
+        Self::Internal(value)
+    }
+}
+
+impl IntoResponse for UpstreamError {
+    fn into_response(self) -> Response {
+        let (status, message) = match self {
+            Self::Page(message) => (StatusCode::BAD_REQUEST, message),
+            Self::Provider(e) => {
+                tracing::warn!("Signing in with an upstream provider failed: {e}");
+                (
+                    StatusCode::BAD_GATEWAY,
+                    "Signing in with the provider did not work. Try again later.",
+                )
+            }
+            Self::Internal(e) => return internal_error(e).into_response(),
+        };
+
+        let page = HtmlTemplate(MessageTemplate {
+            title: "Something went wrong",
+            message,
+        });
+        (status, page).into_response()
+    }
+}
+
+/// Endpoints of a provider, the configured ones or else the ones from its discovery document.
+#[derive(Debug, Default, serde::Deserialize)]
+struct ProviderMetadata {
+    issuer: Option<String>,
+    authorization_endpoint: Option<String>,
+    token_endpoint: Option<String>,
+    userinfo_endpoint: Option<String>,
+    jwks_uri: Option<String>,
+}
+
+impl ProviderMetadata {
+    async fn load(
+        global: &GlobalState,
+        provider: &UpstreamProvider,
+    ) -> Result<Self, UpstreamError> {
+        let configured = Self {
+            issuer: provider.issuer.clone(),
+            authorization_endpoint: provider.authorization_endpoint.clone(),
+            token_endpoint: provider.token_endpoint.clone(),
+            userinfo_endpoint: provider.userinfo_endpoint.clone(),
+            jwks_uri: provider.jwks_uri.clone(),
+        };
+
+        let Some(issuer) = &provider.issuer else {
+            return Ok(configured);
+        };
+        if configured.authorization_endpoint.is_some()
+            && configured.token_endpoint.is_some()
+            && configured.jwks_uri.is_some()
+        {
+            return Ok(configured);
+        }
+
+        // OpenID Connect Discovery 1.0 section 4
+        let url = format!(
+            "{}/.well-known/openid-configuration",
+            issuer.trim_end_matches('/')
+        );
+        let discovered: Self = fetch_json(global, global.http_client.get(&url)).await?;
+        if discovered.issuer.as_deref() != Some(issuer) {
+            return Err(UpstreamError::Provider(format!(
+                "{url} is for issuer {:?}, not {issuer}",
+                discovered.issuer
+            )));
+        }
+
+        Ok(Self {
+            issuer: configured.issuer,
+            authorization_endpoint: configured
+                .authorization_endpoint
+                .or(discovered.authorization_endpoint),
+            token_endpoint: configured.token_endpoint.or(discovered.token_endpoint),
+            userinfo_endpoint: configured
+                .userinfo_endpoint
+                .or(discovered.userinfo_endpoint),
+            jwks_uri: configured.jwks_uri.or(discovered.jwks_uri),
+        })
+    }
+}
+
+fn required<'a>(endpoint: &'a Option<String>, name: &str) -> Result<&'a str, UpstreamError> {
+    endpoint
+        .as_deref()
+        .ok_or_else(|| UpstreamError::Provider(format!("the provider has no {name}")))
+}
+
+async fn fetch_json<T: serde::de::DeserializeOwned>(
+    global: &GlobalState,
+    request: reqwest::RequestBuilder,
+) -> Result<T, UpstreamError> {
+    request
+        .header(ACCEPT, "application/json")
+        .timeout(std::time::Duration::from_secs(
+            global.settings.upstream.timeout,
+        ))
+        .send()
+        .await
+        .and_then(reqwest::Response::error_for_status)
+        .map_err(|e| UpstreamError::Provider(e.to_string()))?
+        .json()
+        .await
+        .map_err(|e| UpstreamError::Provider(e.to_string()))
+}
+
+fn state_cookie_name(global: &GlobalState) -> String {
+    format!("{}_upstream", global.settings.session.cookie_name)
+}
+
+fn redirect_uri(global: &GlobalState, provider: &UpstreamProvider) -> String {
+    format!(
+        "{}/login/upstream/{}/callback",
+        global.settings.oauth2.issuer, provider.id
+    )
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct StartQuery {
+    return_to: Option<String>,
+}
+
+/// Sends the user to the provider to sign in.
+async fn start(
+    State(global): State<Arc<GlobalState>>,
+    Path(provider_id): Path<String>,
+    jar: CookieJar,
+    Query(query): Query<StartQuery>,
+) -> Result<Response, UpstreamError> {
+    redirect_to_provider(&global, jar, &provider_id, query.return_to.as_deref(), None).await
+}
+
+/// Sends the signed in user to the provider, to link their account there to this one. A form posts here, so other
+/// sites can't link accounts behind the user's back.
+async fn link(
+    State(global): State<Arc<GlobalState>>,
+    current: SessionUser,
+    Path(provider_id): Path<String>,
+    jar: CookieJar,
+    Form(form): Form<StartQuery>,
+) -> Result<Response, UpstreamError> {
+    redirect_to_provider(
+        &global,
+        jar,
+        &provider_id,
+        form.return_to.as_deref(),
+        Some(current.user.id),
+    )
+    .await
+}
+
+/// The state is kept in a cookie too, so only the browser that started the login can finish it.
+async fn redirect_to_provider(
+    global: &GlobalState,
+    jar: CookieJar,
+    provider_id: &str,
+    return_to: Option<&str>,
+    user_id: Option<DBUserId>,
+) -> Result<Response, UpstreamError> {
+    let provider = global
+        .settings
+        .upstream
+        .provider(provider_id)
+        .ok_or(UpstreamError::Page(UNKNOWN_PROVIDER))?;
+    let metadata = ProviderMetadata::load(global, provider).await?;
+    let mut url = Url::parse(required(
+        &metadata.authorization_endpoint,
+        "authorization_endpoint",
+    )?)
+    .map_err(|e| UpstreamError::Provider(e.to_string()))?;
+
+    let state = crate::crypto::generate_token();
+    let nonce = crate::crypto::generate_token();
+    let code_verifier = crate::crypto::generate_token();
+    let lifetime = global.settings.upstream.login_lifetime;
+
+    let mut transaction = global.database.begin().await?;
+    DBUpstreamLogin::builder()
+        .state_hash(crate::crypto::hash_token(&state))
+        .provider(provider.id.clone())
+        .nonce(nonce.clone())
+        .code_verifier(code_verifier.clone())
+        .return_to(safe_return_to(return_to).to_string())
+        .user_id(user_id)
+        .expires_at(Utc::now() + Duration::seconds(lifetime))
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    {
+        let mut params = url.query_pairs_mut();
+        params
+            .append_pair("response_type", "code")
+            .append_pair("client_id", &provider.client_id)
+            .append_pair("redirect_uri", &redirect_uri(global, provider))
+            .append_pair("scope", &provider.scopes.join(" "))
+            .append_pair("state", &state)
+            .append_pair(
+                "code_challenge",
+                &crate::crypto::sha256_base64url(&code_verifier),
+            )
+            .append_pair("code_challenge_method", "S256");
+        if metadata.issuer.is_some() {
+            params.append_pair("nonce", &nonce);
+        }
+    }
+
+    let cookie = Cookie::build((state_cookie_name(global), state))
+        .path("/login/upstream")
+        .http_only(true)
+        .same_site(SameSite::Lax)
+        .secure(global.settings.oauth2.issuer.starts_with("https://"))
+        .max_age(time::Duration::seconds(lifetime));
+
+    Ok((jar.add(cookie), Redirect::to(url.as_str())).into_response())
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct CallbackQuery {
+    code: Option<String>,
+    state: Option<String>,
+    error: Option<String>,
+}
+
+/// Where the provider sends the user back to. Signs them in, creating their account the first time, or links the
+/// provider to the account of whoever started linking it.
+async fn callback(
+    State(global): State<Arc<GlobalState>>,
+    Path(provider_id): Path<String>,
+    jar: CookieJar,
+    Query(query): Query<CallbackQuery>,
+) -> Result<Response, UpstreamError> {
+    let cookie_name = state_cookie_name(&global);
+    let expected = jar
+        .get(&cookie_name)
+        .map(|cookie| cookie.value().to_string());
+    let jar = jar.remove(Cookie::build(cookie_name).path("/login/upstream"));
+
+    let state = query
+        .state
+        .filter(|state| expected.as_ref() == Some(state))
+        .ok_or(UpstreamError::Page(EXPIRED_LOGIN))?;
+    let login = DBUpstreamLogin::find_by_hash(&crate::crypto::hash_token(&state), &global.database)
+        .await?
+        .filter(|login| login.provider == provider_id && !login.is_expired())
+        .ok_or(UpstreamError::Page(EXPIRED_LOGIN))?;
+
+    // a state is good for one attempt, whatever its outcome
+    let mut transaction = global.database.begin().await?;
+    login.delete(&mut transaction).await?;
+    transaction.commit().await?;
+
+    if let Some(error) = query.error {
+        tracing::debug!("Upstream provider {provider_id} returned {error}");
+        return Err(UpstreamError::Page(DENIED_LOGIN));
+    }
+    let code = query.code.ok_or(UpstreamError::Page(DENIED_LOGIN))?;
+
+    let provider = global
+        .settings
+        .upstream
+        .provider(&provider_id)
+        .ok_or(UpstreamError::Page(UNKNOWN_PROVIDER))?;
+    let claims = fetch_claims(&global, provider, &login, &code).await?;
+    let subject = claim_string(&claims, &provider.claims.subject)
+        .ok_or_else(|| UpstreamError::Provider(format!("no {} claim", provider.claims.subject)))?;
+
+    // whoever is signed in now has to be who started linking
+    if let Some(user_id) = login.user_id {
+        let current = SessionUser::from_jar(&global, &jar).await?;
+        if current.is_none_or(|current| current.user.id != user_id) {
+            return Err(UpstreamError::Page(EXPIRED_LOGIN));
+        }
+    }
+
+    let attributes = provider
+        .claims
+        .attributes
+        .iter()
+        .filter_map(|(claim, attribute)| Some((attribute.clone(), claims.get(claim)?.clone())))
+        .collect();
+    let profile = Profile {
+        username: claim_string(&claims, &provider.claims.username),
+        email: claim_string(&claims, &provider.claims.email)
+            .filter(|_| claims.get("email_verified") != Some(&Value::Bool(false))),
+        attributes,
+        claims,
+    };
+    finish_login(
+        &global,
+        jar,
+        &provider.id,
+        &subject,
+        login.user_id,
+        &login.return_to,
+        profile,
+    )
+    .await
+}
+
+/// What a provider tells about someone, for the account they get when signing in for the first time.
+pub struct Profile {
+    pub username: Option<String>,
+    pub email: Option<String>,
+    /// Custom attributes of the new user
+    pub attributes: Map<String, Value>,
+    /// Everything the provider sent, for the provisioning rules to look at
+    pub claims: Map<String, Value>,
+}
+
+/// Signs the user in with their identity at a provider, creating their account the first time if the provisioning
+/// rules let them have one. When a signed in user started linking the provider, the identity is linked to their
+/// account instead, callers check it's still them.
+pub async fn finish_login(
+    global: &GlobalState,
+    jar: CookieJar,
+    provider_id: &str,
+    subject: &str,
+    link_to: Option<DBUserId>,
+    return_to: &str,
+    profile: Profile,
+) -> Result<Response, UpstreamError> {
+    if let Some(user_id) = link_to {
+        link_identity(global, user_id, provider_id, subject).await?;
+        return Ok((jar, Redirect::to(return_to)).into_response());
+    }
+
+    let rules = &global.settings.upstream.provisioning;
+    let rule = provisioning::matching_rule(rules, provider_id, &profile);
+    let user = match DBIdentity::find_by_subject(provider_id, subject, &global.database).await? {
+        Some(identity) => {
+            let user = DBUser::find_by_id(identity.user_id, &global.database).await?;
+            match (user, rule) {
+                (Some(user), Some(rule)) => {
+                    Some(provisioning::sync(global, user, rule, &profile).await?)
+                }
+                (user, _) => user,
+            }
+        }
+        None if !rules.is_empty() && !rule.is_some_and(|rule| rule.create) => {
+            tracing::info!(
+                "Not creating a user for {subject} at {provider_id}, turned away by {}",
+                rule.map_or("no rule matching", |rule| rule.name.as_str())
+            );
+            return Err(UpstreamError::Page(NOT_PROVISIONED));
+        }
+        None => {
+            let attributes = provisioning::new_user_attributes(rule, &profile);
+            let profile = Profile {
+                attributes,
+                ..profile
+            };
+            let user = create_user(global, provider_id, subject, profile).await?;
+            if let Some(rule) = rule {
+                provisioning::assign(global, &user, rule).await?;
+            }
+            Some(user)
+        }
+    }
+    .ok_or(UpstreamError::Page(EXPIRED_LOGIN))?;
+    if !user.active {
+        return Err(UpstreamError::Page(DISABLED));
+    }
+
+    let jar = start_session(global, jar, &user).await?;
+    Ok((jar, Redirect::to(return_to)).into_response())
+}
+
+async fn link_identity(
+    global: &GlobalState,
+    user_id: DBUserId,
+    provider_id: &str,
+    subject: &str,
+) -> Result<(), UpstreamError> {
+    match DBIdentity::find_by_subject(provider_id, subject, &global.database).await? {
+        Some(identity) if identity.user_id == user_id => Ok(()),
+        Some(_) => Err(UpstreamError::Page(LINKED_ELSEWHERE)),
+        None => {
+            let mut transaction = global.database.begin().await?;
+            DBIdentity::builder()
+                .user_id(user_id)
+                .provider(provider_id.to_string())
+                .subject(subject.to_string())
+                .build()
+                .insert(&mut transaction)
+                .await?;
+            transaction.commit().await?;
+            Ok(())
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize)]
+struct ProviderTokens {
+    access_token: String,
+    id_token: Option<String>,
+}
+
+/// Redeems the code and collects what the provider tells about the user, from the ID token and the userinfo endpoint.
+async fn fetch_claims(
+    global: &GlobalState,
+    provider: &UpstreamProvider,
+    login: &DBUpstreamLogin,
+    code: &str,
+) -> Result<Map<String, Value>, UpstreamError> {
+    let metadata = ProviderMetadata::load(global, provider).await?;
+
+    // the credentials are form encoded before going into the header (RFC 6749 section 2.3.1)
+    let encode =
+        |value: &str| url::form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>();
+    let request = global
+        .http_client
+        .post(required(&metadata.token_endpoint, "token_endpoint")?)
+        .basic_auth(
+            encode(&provider.client_id),
+            Some(encode(&provider.client_secret)),
+        )
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", &redirect_uri(global, provider)),
+            ("code_verifier", &login.code_verifier),
+        ]);
+    let tokens: ProviderTokens = fetch_json(global, request).await?;
+
+    let mut claims = match &metadata.issuer {
+        Some(issuer) => {
+            let id_token = tokens
+                .id_token
+                .as_deref()
+                .ok_or_else(|| UpstreamError::Provider("no id_token".into()))?;
+            verify_id_token(global, provider, &metadata, issuer, id_token, &login.nonce).await?
+        }
+        None => Map::new(),
+    };
+
+    if let Some(userinfo_endpoint) = &metadata.userinfo_endpoint {
+        let request = global
+            .http_client
+            .get(userinfo_endpoint)
+            .bearer_auth(&tokens.access_token);
+        let userinfo: Map<String, Value> = fetch_json(global, request).await?;
+
+        // OpenID Connect Core 1.0 section 5.3.2
+        if let (Some(sub), Some(userinfo_sub)) = (claims.get("sub"), userinfo.get("sub"))
+            && sub != userinfo_sub
+        {
+            return Err(UpstreamError::Provider(
+                "userinfo is about someone else".into(),
+            ));
+        }
+
+        for (name, value) in userinfo {
+            claims.entry(name).or_insert(value);
+        }
+    } else if metadata.issuer.is_none() {
+        return Err(UpstreamError::Provider(
+            "plain OAuth2 providers need a userinfo_endpoint".into(),
+        ));
+    }
+
+    Ok(claims)
+}
+
+async fn verify_id_token(
+    global: &GlobalState,
+    provider: &UpstreamProvider,
+    metadata: &ProviderMetadata,
+    issuer: &str,
+    id_token: &str,
+    nonce: &str,
+) -> Result<Map<String, Value>, UpstreamError> {
+    let invalid =
+        |e: jsonwebtoken::errors::Error| UpstreamError::Provider(format!("invalid id_token: {e}"));
+    let header = jsonwebtoken::decode_header(id_token).map_err(invalid)?;
+    if matches!(
+        header.alg,
+        Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+    ) {
+        return Err(UpstreamError::Provider(
+            "id_token is not signed with an asymmetric key".into(),
+        ));
+    }
+
+    let request = global
+        .http_client
+        .get(required(&metadata.jwks_uri, "jwks_uri")?);
+    let jwks: JwkSet = fetch_json(global, request).await?;
+    let candidates: Vec<&Jwk> = match &header.kid {
+        Some(kid) => jwks.find(kid).into_iter().collect(),
+        None => jwks.keys.iter().collect(),
+    };
+
+    let mut validation = Validation::new(header.alg);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[&provider.client_id]);
+    validation.set_required_spec_claims(&["exp", "iss", "sub", "aud"]);
+
+    let claims: Map<String, Value> = candidates
+        .into_iter()
+        .filter_map(|jwk| DecodingKey::from_jwk(jwk).ok())
+        .find_map(|key| jsonwebtoken::decode(id_token, &key, &validation).ok())
+        .map(|data| data.claims)
+        .ok_or_else(|| UpstreamError::Provider("id_token signature does not verify".into()))?;
+
+    if claims.get("nonce").and_then(Value::as_str) != Some(nonce) {
+        return Err(UpstreamError::Provider(
+            "id_token nonce does not match".into(),
+        ));
+    }
+
+    Ok(claims)
+}
+
+/// Claims are strings for most providers, but some use numbers for ids.
+fn claim_string(claims: &Map<String, Value>, name: &str) -> Option<String> {
+    match claims.get(name)? {
+        Value::String(value) if !value.is_empty() => Some(value.clone()),
+        Value::Number(value) => Some(value.to_string()),
+        _ => None,
+    }
+}
+
+/// Creates the account of someone signing in with a provider for the first time. Existing accounts with the same
+/// username or email are left alone, taking them over would only need a matching claim at any provider.
+pub async fn create_user(
+    global: &GlobalState,
+    provider_id: &str,
+    subject: &str,
+    profile: Profile,
+) -> Result<DBUser, sqlx::Error> {
+    let wanted = profile
+        .username
+        .unwrap_or_else(|| format!("{provider_id}-{subject}"));
+    let mut username = wanted.clone();
+    while DBUser::find_by_username(&username, &global.database)
+        .await?
+        .is_some()
+    {
+        username = format!("{wanted}-{}", &crate::crypto::generate_token()[..6]);
+    }
+
+    // the account still gets created, without the email someone else already has
+    let email = match profile.email {
+        Some(email)
+            if DBUser::find_by_email(&email, &global.database)
+                .await?
+                .is_some() =>
+        {
+            None
+        }
+        email => email,
+    };
+
+    let user = DBUser::builder()
+        .username(username)
+        .email(email)
+        .attributes(Value::Object(profile.attributes))
+        .build();
+    let mut transaction = global.database.begin().await?;
+    user.insert(&mut transaction).await?;
+    DBIdentity::builder()
+        .user_id(user.id)
+        .provider(provider_id.to_string())
+        .subject(subject.to_string())
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    tracing::info!(
+        "Created user {} for {} at {}",
+        user.username,
+        subject,
+        provider_id
+    );
+    Ok(user)
+}
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .route("/login/upstream/{provider}", get(start))
+        .route("/login/upstream/{provider}/callback", get(callback))
+        .route("/login/upstream/{provider}/link", post(link))
+}
@@ -0,0 +1,100 @@
+use crate::database::models::access_token::DBAccessToken;
+use crate::database::models::client::DBClient;
+use crate::database::models::grant::{DBGrant, DBGrantId};
+use crate::database::models::refresh_token::DBRefreshToken;
+use crate::global::GlobalState;
+use crate::http::v1::{ApiError, ApiErrorResponse, ApiUser};
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .routes(routes!(list_grants))
+        .routes(routes!(delete_grant))
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct GrantResponse {
+    pub id: String,
+    pub client_id: String,
+    pub client_name: String,
+    pub scopes: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Lists the applications the signed in user gave access to their account.
+#[utoipa::path(
+    get,
+    path = "/me/grants",
+    tag = "me",
+    responses(
+        (status = 200, body = Vec<GrantResponse>),
+        (status = 401, body = ApiErrorResponse),
+    )
+)]
+pub async fn list_grants(
+    State(global): State<Arc<GlobalState>>,
+    ApiUser(current): ApiUser,
+) -> Result<Json<Vec<GrantResponse>>, ApiError> {
+    let grants = DBGrant::find_many_by_user(current.user.id, &global.database).await?;
+    let client_ids: Vec<_> = grants.iter().map(|grant| grant.client_id).collect();
+    let clients = DBClient::find_many_by_id(&client_ids, &global.database).await?;
+
+    let response = grants
+        .into_iter()
+        .filter_map(|grant| {
+            let client = clients.iter().find(|client| client.id == grant.client_id)?;
+            Some(GrantResponse {
+                id: grant.id.to_string(),
+                client_id: grant.client_id.to_string(),
+                client_name: client.name.clone(),
+                scopes: grant.scopes,
+                created_at: grant.created_at,
+                updated_at: grant.updated_at,
+            })
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+/// Takes back an application's access, revoking every token it holds for the user.
+#[utoipa::path(
+    delete,
+    path = "/me/grants/{id}",
+    tag = "me",
+    params(("id" = String, Path, description = "The grant to revoke")),
+    responses(
+        (status = 204, description = "The application no longer has access"),
+        (status = 401, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+    )
+)]
+pub async fn delete_grant(
+    State(global): State<Arc<GlobalState>>,
+    ApiUser(current): ApiUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let id: DBGrantId = id.parse().map_err(|_| ApiError::NotFound)?;
+    let grant = DBGrant::find_by_id(id, &global.database)
+        .await?
+        .filter(|grant| grant.user_id == current.user.id)
+        .ok_or(ApiError::NotFound)?;
+
+    let mut transaction = global.database.begin().await?;
+    grant.delete(&mut transaction).await?;
+    DBRefreshToken::delete_by_user_and_client(grant.user_id, grant.client_id, &mut transaction)
+        .await?;
+    DBAccessToken::delete_by_user_and_client(grant.user_id, grant.client_id, &mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
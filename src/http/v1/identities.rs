@@ -0,0 +1,117 @@
+use crate::database::models::identity::{DBIdentity, DBIdentityId};
+use crate::database::models::user::DBUser;
+use crate::global::GlobalState;
+use crate::http::v1::{ApiError, ApiErrorResponse, ApiUser};
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .routes(routes!(list_identities))
+        .routes(routes!(delete_identity))
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct IdentityResponse {
+    pub id: String,
+    /// Id of the upstream provider or LDAP directory, more providers are linked at
+    /// `POST /login/upstream/{provider}/link` or `POST /login/saml/{provider}/link`
+    pub provider: String,
+    pub provider_name: String,
+    /// The user's id at the provider
+    pub subject: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Lists the upstream accounts the signed in user can sign in with.
+#[utoipa::path(
+    get,
+    path = "/me/identities",
+    tag = "me",
+    responses(
+        (status = 200, body = Vec<IdentityResponse>),
+        (status = 401, body = ApiErrorResponse),
+    )
+)]
+pub async fn list_identities(
+    State(global): State<Arc<GlobalState>>,
+    ApiUser(current): ApiUser,
+) -> Result<Json<Vec<IdentityResponse>>, ApiError> {
+    let identities = DBIdentity::find_many_by_user(current.user.id, &global.database).await?;
+
+    let response = identities
+        .into_iter()
+        .map(|identity| IdentityResponse {
+            id: identity.id.to_string(),
+            provider_name: global
+                .settings
+                .upstream
+                .provider_name(&identity.provider)
+                .or_else(|| {
+                    global
+                        .settings
+                        .authentication
+                        .directory(&identity.provider)
+                        .map(|directory| directory.name.as_str())
+                })
+                .unwrap_or(&identity.provider)
+                .to_string(),
+            provider: identity.provider,
+            subject: identity.subject,
+            created_at: identity.created_at,
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+/// Unlinks an upstream account. The last way to sign in can't be unlinked, the account would be lost.
+#[utoipa::path(
+    delete,
+    path = "/me/identities/{id}",
+    tag = "me",
+    params(("id" = String, Path, description = "The identity to unlink")),
+    responses(
+        (status = 204, description = "The upstream account no longer signs in to this one"),
+        (status = 401, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+        (status = 409, body = ApiErrorResponse),
+    )
+)]
+pub async fn delete_identity(
+    State(global): State<Arc<GlobalState>>,
+    ApiUser(current): ApiUser,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let id: DBIdentityId = id.parse().map_err(|_| ApiError::NotFound)?;
+
+    let mut transaction = global.database.begin().await?;
+    let identities =
+        DBIdentity::find_many_by_user_for_update(current.user.id, &mut transaction).await?;
+    let identity = identities
+        .iter()
+        .find(|identity| identity.id == id)
+        .ok_or(ApiError::NotFound)?;
+
+    if !has_other_login(&current.user, &identities) {
+        return Err(ApiError::Conflict(
+            "this is the last way to sign in to the account",
+        ));
+    }
+
+    identity.delete(&mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Whether the user can still sign in after unlinking one of their identities.
+fn has_other_login(user: &DBUser, identities: &[DBIdentity]) -> bool {
+    user.password_hash.is_some() || identities.len() > 1
+}
@@ -0,0 +1,269 @@
+use crate::database::models::organization_invitation::{
+    DBOrganizationInvitation, DBOrganizationInvitationId,
+};
+use crate::database::models::organization_member::{DBOrganizationMember, MembershipRole};
+use crate::database::models::user::DBUser;
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::invitations::{accept_url, send_invitation};
+use crate::http::v1::organizations::ManageOwners;
+use crate::http::v1::{ApiError, ApiErrorResponse, Authorized, Permission, require};
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Duration, Utc};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .routes(routes!(list_invitations, create_invitation))
+        .routes(routes!(delete_invitation))
+        .routes(routes!(resend_invitation))
+}
+
+/// Seeing the invitations nobody accepted yet
+pub struct ReadInvitations;
+
+impl Permission for ReadInvitations {
+    const NAME: &'static str = "invitations:read";
+}
+
+/// Inviting people, resending invitations and revoking them. Invitations to become an owner need [`ManageOwners`] too
+pub struct WriteInvitations;
+
+impl Permission for WriteInvitations {
+    const NAME: &'static str = "invitations:write";
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct InvitationResponse {
+    pub id: String,
+    pub email: String,
+    /// What the invitee is in the organization once they accept
+    pub role: MembershipRole,
+    /// None when the user who sent it is gone
+    pub invited_by: Option<String>,
+    /// Expired invitations can be resent
+    pub expires_at: DateTime<Utc>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<DBOrganizationInvitation> for InvitationResponse {
+    fn from(invitation: DBOrganizationInvitation) -> Self {
+        Self {
+            id: invitation.id.to_string(),
+            email: invitation.email,
+            role: invitation.role,
+            invited_by: invitation.invited_by.map(|id| id.to_string()),
+            expires_at: invitation.expires_at,
+            created_at: invitation.created_at,
+        }
+    }
+}
+
+#[derive(Debug, serde::Deserialize, ToSchema)]
+pub struct CreateInvitationRequest {
+    pub email: String,
+    #[serde(default)]
+    pub role: MembershipRole,
+}
+
+/// Lists the invitations nobody accepted yet. Needs the `invitations:read` permission, admins and owners have it.
+#[utoipa::path(
+    get,
+    path = "/organization/invitations",
+    tag = "organization",
+    responses(
+        (status = 200, body = Vec<InvitationResponse>),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+    )
+)]
+pub async fn list_invitations(
+    State(global): State<Arc<GlobalState>>,
+    authorized: Authorized<ReadInvitations>,
+) -> Result<Json<Vec<InvitationResponse>>, ApiError> {
+    let tenant = authorized.tenant()?;
+    let invitations = DBOrganizationInvitation::find_many(tenant, &global.database).await?;
+    Ok(Json(
+        invitations
+            .into_iter()
+            .map(InvitationResponse::from)
+            .collect(),
+    ))
+}
+
+/// Invites someone to the organization by email, with the role they get. Needs the `invitations:write` permission,
+/// and `organizations:owners` to invite owners. Admins have the first, owners both.
+#[utoipa::path(
+    post,
+    path = "/organization/invitations",
+    tag = "organization",
+    request_body = CreateInvitationRequest,
+    responses(
+        (status = 201, body = InvitationResponse),
+        (status = 400, body = ApiErrorResponse),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+        (status = 409, body = ApiErrorResponse),
+        (status = 502, body = ApiErrorResponse),
+    )
+)]
+pub async fn create_invitation(
+    State(global): State<Arc<GlobalState>>,
+    authorized: Authorized<WriteInvitations>,
+    Json(request): Json<CreateInvitationRequest>,
+) -> Result<(StatusCode, Json<InvitationResponse>), ApiError> {
+    let tenant = authorized.tenant()?;
+    require_for_role(&global, &authorized, request.role).await?;
+    let email = request.email.trim();
+    if !crate::mail::is_valid_address(email) {
+        return Err(ApiError::BadRequest("that is not an email address"));
+    }
+
+    if let Some(user) = DBUser::find_by_email(email, &global.database).await?
+        && DBOrganizationMember::find_by_user(tenant, user.id, &global.database)
+            .await?
+            .is_some()
+    {
+        return Err(ApiError::Conflict("they are a member already"));
+    }
+    if DBOrganizationInvitation::find_by_email(tenant, email, &global.database)
+        .await?
+        .is_some()
+    {
+        return Err(ApiError::Conflict(
+            "they were invited already, resend the invitation instead",
+        ));
+    }
+
+    let token = crate::crypto::generate_token();
+    let url = accept_url(&global, tenant, &token).ok_or(ApiError::Conflict(
+        "the organization needs a domain to accept invitations at, path prefixes are off",
+    ))?;
+    let invitation = DBOrganizationInvitation::builder()
+        .tenant_id(tenant.id())
+        .email(email.to_string())
+        .role(request.role)
+        .token_hash(crate::crypto::hash_token(&token))
+        .invited_by(Some(authorized.current.user.id))
+        .expires_at(Utc::now() + Duration::seconds(global.settings.invitations.lifetime))
+        .build();
+
+    // nothing is stored when the email can't be sent
+    let mut transaction = global.database.begin().await?;
+    invitation.insert(&mut transaction).await?;
+    send_invitation(&global, tenant, &invitation, &url, &authorized.current.user).await?;
+    transaction.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(invitation.into())))
+}
+
+/// Revokes an invitation, its link stops working.
+#[utoipa::path(
+    delete,
+    path = "/organization/invitations/{id}",
+    tag = "organization",
+    params(("id" = String, Path, description = "The invitation to revoke")),
+    responses(
+        (status = 204, description = "The invitation can't be accepted anymore"),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+    )
+)]
+pub async fn delete_invitation(
+    State(global): State<Arc<GlobalState>>,
+    authorized: Authorized<WriteInvitations>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let tenant = authorized.tenant()?;
+    let invitation = find_invitation(&global, &authorized, tenant, &id).await?;
+
+    let mut transaction = global.database.begin().await?;
+    DBOrganizationInvitation::delete(tenant, invitation.id, &mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Sends the invitation again with a new link, which is good for the whole lifetime of invitations again. The old
+/// link stops working.
+#[utoipa::path(
+    post,
+    path = "/organization/invitations/{id}/resend",
+    tag = "organization",
+    params(("id" = String, Path, description = "The invitation to resend")),
+    responses(
+        (status = 200, body = InvitationResponse),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+        (status = 409, body = ApiErrorResponse),
+        (status = 502, body = ApiErrorResponse),
+    )
+)]
+pub async fn resend_invitation(
+    State(global): State<Arc<GlobalState>>,
+    authorized: Authorized<WriteInvitations>,
+    Path(id): Path<String>,
+) -> Result<Json<InvitationResponse>, ApiError> {
+    let tenant = authorized.tenant()?;
+    let invitation = find_invitation(&global, &authorized, tenant, &id).await?;
+
+    let token = crate::crypto::generate_token();
+    let url = accept_url(&global, tenant, &token).ok_or(ApiError::Conflict(
+        "the organization needs a domain to accept invitations at, path prefixes are off",
+    ))?;
+    let invitation = DBOrganizationInvitation {
+        token_hash: crate::crypto::hash_token(&token),
+        expires_at: Utc::now() + Duration::seconds(global.settings.invitations.lifetime),
+        ..invitation
+    };
+
+    let mut transaction = global.database.begin().await?;
+    invitation.update_token(&mut transaction).await?;
+    send_invitation(&global, tenant, &invitation, &url, &authorized.current.user).await?;
+    transaction.commit().await?;
+
+    Ok(Json(invitation.into()))
+}
+
+/// An invitation the user may manage, to a role they could invite with.
+async fn find_invitation(
+    global: &GlobalState,
+    authorized: &Authorized<WriteInvitations>,
+    tenant: &Tenant,
+    id: &str,
+) -> Result<DBOrganizationInvitation, ApiError> {
+    let id: DBOrganizationInvitationId = id.parse().map_err(|_| ApiError::NotFound)?;
+    let invitation = DBOrganizationInvitation::find_by_id(tenant, id, &global.database)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+    require_for_role(global, authorized, invitation.role).await?;
+    Ok(invitation)
+}
+
+/// Invitations to become an owner are only for those who manage owners.
+async fn require_for_role(
+    global: &GlobalState,
+    authorized: &Authorized<WriteInvitations>,
+    role: MembershipRole,
+) -> Result<(), ApiError> {
+    match role {
+        MembershipRole::Owner => {
+            require::<ManageOwners>(
+                global,
+                authorized.current.user.id,
+                authorized.tenant.as_ref(),
+            )
+            .await
+        }
+        MembershipRole::Admin | MembershipRole::Member => Ok(()),
+    }
+}
@@ -0,0 +1,246 @@
+use crate::database::models::organization_member::{DBOrganizationMember, MembershipRole};
+use crate::database::models::role_assignment::DBRoleAssignment;
+use crate::database::models::user::DBUserId;
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::internal_error;
+use crate::http::session::SessionUser;
+use crate::http::tenant;
+use crate::mail::MailError;
+use axum::Json;
+use axum::extract::FromRequestParts;
+use axum::http::StatusCode;
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::CookieJar;
+use std::marker::PhantomData;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use utoipa_axum::router::OpenApiRouter;
+
+pub mod grants;
+pub mod identities;
+pub mod invitations;
+pub mod organizations;
+pub mod roles;
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new().nest(
+        "/v1",
+        OpenApiRouter::new()
+            .merge(grants::router())
+            .merge(identities::router())
+            .merge(invitations::router())
+            .merge(organizations::router())
+            .merge(roles::router()),
+    )
+}
+
+/// The signed in user, for JSON endpoints. Answers 401 instead of redirecting to the login page.
+pub struct ApiUser(pub SessionUser);
+
+impl FromRequestParts<Arc<GlobalState>> for ApiUser {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<GlobalState>,
+    ) -> Result<Self, Self::Rejection> {
+        let jar = CookieJar::from_headers(&parts.headers);
+        SessionUser::from_jar(state, &jar)
+            .await?
+            .map(Self)
+            .ok_or(ApiError::Unauthorized)
+    }
+}
+
+/// The organization the request is for. Answers 404 when there is none.
+pub struct ApiTenant(pub Tenant);
+
+impl FromRequestParts<Arc<GlobalState>> for ApiTenant {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<GlobalState>,
+    ) -> Result<Self, Self::Rejection> {
+        tenant::resolve(state, parts)
+            .await?
+            .map(Self)
+            .ok_or(ApiError::NotFound)
+    }
+}
+
+/// The signed in user, if they are a member of the organization the request is for. Answers 403 otherwise.
+pub struct ApiMember {
+    pub current: SessionUser,
+    pub tenant: Tenant,
+    pub membership: DBOrganizationMember,
+}
+
+impl FromRequestParts<Arc<GlobalState>> for ApiMember {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<GlobalState>,
+    ) -> Result<Self, Self::Rejection> {
+        let ApiUser(current) = ApiUser::from_request_parts(parts, state).await?;
+        let ApiTenant(tenant) = ApiTenant::from_request_parts(parts, state).await?;
+        let membership =
+            DBOrganizationMember::find_by_user(&tenant, current.user.id, &state.database)
+                .await?
+                .ok_or(ApiError::Forbidden)?;
+        Ok(Self {
+            current,
+            tenant,
+            membership,
+        })
+    }
+}
+
+/// A permission handlers can require with [`Authorized`], granted by the roles users have.
+pub trait Permission {
+    const NAME: &'static str;
+    /// Whether only roles users have everywhere grant the permission, for things no one organization owns.
+    const EVERYWHERE: bool = false;
+}
+
+/// The signed in user, if one of the roles they have everywhere or in the organization the request is for grants
+/// the permission. Answers 403 otherwise.
+pub struct Authorized<P> {
+    pub current: SessionUser,
+    /// The organization the request is for, if any
+    pub tenant: Option<Tenant>,
+    permission: PhantomData<fn() -> P>,
+}
+
+impl<P: Permission> FromRequestParts<Arc<GlobalState>> for Authorized<P> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &Arc<GlobalState>,
+    ) -> Result<Self, Self::Rejection> {
+        let ApiUser(current) = ApiUser::from_request_parts(parts, state).await?;
+        let tenant = tenant::resolve(state, parts).await?;
+        require::<P>(state, current.user.id, tenant.as_ref()).await?;
+        Ok(Self {
+            current,
+            tenant,
+            permission: PhantomData,
+        })
+    }
+}
+
+impl<P> Authorized<P> {
+    /// The organization the request is for, for endpoints about one. Answers 404 when there is none.
+    pub fn tenant(&self) -> Result<&Tenant, ApiError> {
+        self.tenant.as_ref().ok_or(ApiError::NotFound)
+    }
+}
+
+/// Answers 403 unless the user has the permission, like [`Authorized`] does. For permissions a handler only needs
+/// depending on what the request is about.
+pub async fn require<P: Permission>(
+    global: &GlobalState,
+    user_id: DBUserId,
+    tenant: Option<&Tenant>,
+) -> Result<(), ApiError> {
+    let scope = tenant.filter(|_| !P::EVERYWHERE);
+    match has_permission(global, user_id, P::NAME, scope).await? {
+        true => Ok(()),
+        false => Err(ApiError::Forbidden),
+    }
+}
+
+/// Whether the user has the permission in the organization, or everywhere without one. In an organization their
+/// membership role grants permissions too, see [`membership_permissions`].
+pub async fn has_permission(
+    global: &GlobalState,
+    user_id: DBUserId,
+    permission: &str,
+    tenant: Option<&Tenant>,
+) -> Result<bool, sqlx::Error> {
+    let roles = DBRoleAssignment::find_roles(user_id, tenant, &global.database).await?;
+    if roles
+        .iter()
+        .any(|role| role.permissions.iter().any(|p| p == permission))
+    {
+        return Ok(true);
+    }
+
+    let Some(tenant) = tenant else {
+        return Ok(false);
+    };
+    let membership = DBOrganizationMember::find_by_user(tenant, user_id, &global.database).await?;
+    Ok(membership
+        .is_some_and(|membership| membership_permissions(membership.role).contains(&permission)))
+}
+
+/// What members may do in their organization by their membership role, as if they were assigned a role granting it
+/// there. Admins manage admins and members, owners manage owners too.
+pub const fn membership_permissions(role: MembershipRole) -> &'static [&'static str] {
+    match role {
+        MembershipRole::Owner => &[
+            organizations::ManageMembers::NAME,
+            organizations::ManageOwners::NAME,
+            invitations::ReadInvitations::NAME,
+            invitations::WriteInvitations::NAME,
+        ],
+        MembershipRole::Admin => &[
+            organizations::ManageMembers::NAME,
+            invitations::ReadInvitations::NAME,
+            invitations::WriteInvitations::NAME,
+        ],
+        MembershipRole::Member => &[],
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ApiError {
+    #[error("{0}")]
+    BadRequest(&'static str),
+    #[error("you have to be signed in")]
+    Unauthorized,
+    #[error("you are not allowed to do that")]
+    Forbidden,
+    #[error("not found")]
+    NotFound,
+    #[error("{0}")]
+    Conflict(&'static str),
+    #[error("the email could not be sent")]
+    Mail(#[from] MailError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct ApiErrorResponse {
+    pub error: String,
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let status = match &self {
+            Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden => StatusCode::FORBIDDEN,
+            Self::NotFound => StatusCode::NOT_FOUND,
+            Self::Conflict(_) => StatusCode::CONFLICT,
+            Self::Mail(e) => {
+                tracing::warn!("Sending an email failed: {e}");
+                StatusCode::BAD_GATEWAY
+            }
+            Self::Database(e) => return internal_error(e).into_response(),
+        };
+
+        (
+            status,
+            Json(ApiErrorResponse {
+                error: self.to_string(),
+            }),
+        )
+            .into_response()
+    }
+}
@@ -0,0 +1,268 @@
+use crate::database::models::organization::DBOrganization;
+use crate::database::models::organization_member::{DBOrganizationMember, MembershipRole};
+use crate::database::models::role_assignment::DBRoleAssignment;
+use crate::database::models::user::{DBUser, DBUserId};
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::v1::{
+    ApiError, ApiErrorResponse, ApiMember, ApiTenant, ApiUser, Authorized, Permission, require,
+};
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use chrono::{DateTime, Utc};
+use std::sync::Arc;
+use utoipa::ToSchema;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .routes(routes!(list_my_organizations))
+        .routes(routes!(get_organization))
+        .routes(routes!(list_members))
+        .routes(routes!(update_member, delete_member))
+}
+
+/// Changing the roles of members and removing them. Members with this but not [`ManageOwners`] leave owners alone
+pub struct ManageMembers;
+
+impl Permission for ManageMembers {
+    const NAME: &'static str = "organizations:manage";
+}
+
+/// Making members owners, and changing or removing owners
+pub struct ManageOwners;
+
+impl Permission for ManageOwners {
+    const NAME: &'static str = "organizations:owners";
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct OrganizationResponse {
+    pub id: String,
+    /// Names the organization in the path prefix and the organization header
+    pub slug: String,
+    pub name: String,
+    /// Host names requests for the organization come in at
+    pub domains: Vec<String>,
+}
+
+impl From<&DBOrganization> for OrganizationResponse {
+    fn from(organization: &DBOrganization) -> Self {
+        Self {
+            id: organization.id.to_string(),
+            slug: organization.slug.clone(),
+            name: organization.name.clone(),
+            domains: organization.domains.clone(),
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct MembershipResponse {
+    pub organization: OrganizationResponse,
+    pub role: MembershipRole,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct MemberResponse {
+    pub user_id: String,
+    pub username: String,
+    pub role: MembershipRole,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, serde::Deserialize, ToSchema)]
+pub struct UpdateMemberRequest {
+    pub role: MembershipRole,
+}
+
+/// Lists the organizations the signed in user is a member of.
+#[utoipa::path(
+    get,
+    path = "/me/organizations",
+    tag = "me",
+    responses(
+        (status = 200, body = Vec<MembershipResponse>),
+        (status = 401, body = ApiErrorResponse),
+    )
+)]
+pub async fn list_my_organizations(
+    State(global): State<Arc<GlobalState>>,
+    ApiUser(current): ApiUser,
+) -> Result<Json<Vec<MembershipResponse>>, ApiError> {
+    let organizations =
+        DBOrganization::find_many_by_member(current.user.id, &global.database).await?;
+
+    let mut response = Vec::with_capacity(organizations.len());
+    for organization in organizations {
+        let tenant = Tenant::new(organization);
+        let Some(membership) =
+            DBOrganizationMember::find_by_user(&tenant, current.user.id, &global.database).await?
+        else {
+            continue;
+        };
+        response.push(MembershipResponse {
+            organization: tenant.organization().into(),
+            role: membership.role,
+        });
+    }
+
+    Ok(Json(response))
+}
+
+/// The organization the request is for. Only its members see it.
+#[utoipa::path(
+    get,
+    path = "/organization",
+    tag = "organization",
+    responses(
+        (status = 200, body = OrganizationResponse),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+    )
+)]
+pub async fn get_organization(member: ApiMember) -> Json<OrganizationResponse> {
+    Json(member.tenant.organization().into())
+}
+
+/// Lists the members of the organization the request is for.
+#[utoipa::path(
+    get,
+    path = "/organization/members",
+    tag = "organization",
+    responses(
+        (status = 200, body = Vec<MemberResponse>),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+    )
+)]
+pub async fn list_members(
+    State(global): State<Arc<GlobalState>>,
+    member: ApiMember,
+) -> Result<Json<Vec<MemberResponse>>, ApiError> {
+    let members = DBOrganizationMember::find_many(&member.tenant, &global.database).await?;
+    let user_ids: Vec<DBUserId> = members.iter().map(|member| member.user_id).collect();
+    let users = DBUser::find_many_by_id(&user_ids, &global.database).await?;
+
+    let response = members
+        .into_iter()
+        .filter_map(|member| {
+            let user = users.iter().find(|user| user.id == member.user_id)?;
+            Some(MemberResponse {
+                user_id: member.user_id.to_string(),
+                username: user.username.clone(),
+                role: member.role,
+                created_at: member.created_at,
+            })
+        })
+        .collect();
+
+    Ok(Json(response))
+}
+
+/// Changes the role of a member. Needs the `organizations:manage` permission, and `organizations:owners` when owners are
+/// involved. Admins have the first, owners both.
+#[utoipa::path(
+    put,
+    path = "/organization/members/{user_id}",
+    tag = "organization",
+    params(("user_id" = String, Path, description = "The member")),
+    request_body = UpdateMemberRequest,
+    responses(
+        (status = 204, description = "The member has the role now"),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+        (status = 409, body = ApiErrorResponse),
+    )
+)]
+pub async fn update_member(
+    State(global): State<Arc<GlobalState>>,
+    authorized: Authorized<ManageMembers>,
+    Path(user_id): Path<String>,
+    Json(request): Json<UpdateMemberRequest>,
+) -> Result<StatusCode, ApiError> {
+    let tenant = authorized.tenant()?;
+    let mut target = find_member(&global, tenant, &user_id).await?;
+    if target.role == MembershipRole::Owner || request.role == MembershipRole::Owner {
+        require::<ManageOwners>(&global, authorized.current.user.id, Some(tenant)).await?;
+    }
+    if target.role == MembershipRole::Owner && request.role != MembershipRole::Owner {
+        ensure_other_owner(&global, tenant).await?;
+    }
+
+    target.role = request.role;
+    let mut transaction = global.database.begin().await?;
+    target.update_role(&mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Removes a member from the organization. Members can always leave on their own, removing anyone else needs the
+/// permissions changing their role would.
+#[utoipa::path(
+    delete,
+    path = "/organization/members/{user_id}",
+    tag = "organization",
+    params(("user_id" = String, Path, description = "The member")),
+    responses(
+        (status = 204, description = "The user is no longer a member"),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+        (status = 409, body = ApiErrorResponse),
+    )
+)]
+pub async fn delete_member(
+    State(global): State<Arc<GlobalState>>,
+    ApiUser(current): ApiUser,
+    ApiTenant(tenant): ApiTenant,
+    Path(user_id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let leaving = user_id
+        .parse::<DBUserId>()
+        .is_ok_and(|id| id == current.user.id);
+    if !leaving {
+        require::<ManageMembers>(&global, current.user.id, Some(&tenant)).await?;
+    }
+    let target = find_member(&global, &tenant, &user_id).await?;
+    if !leaving && target.role == MembershipRole::Owner {
+        require::<ManageOwners>(&global, current.user.id, Some(&tenant)).await?;
+    }
+    if target.role == MembershipRole::Owner {
+        ensure_other_owner(&global, &tenant).await?;
+    }
+
+    let mut transaction = global.database.begin().await?;
+    DBOrganizationMember::delete(&tenant, target.user_id, &mut transaction).await?;
+    // roles in the organization don't outlive the membership
+    DBRoleAssignment::delete_by_user_in_tenant(&tenant, target.user_id, &mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn find_member(
+    global: &GlobalState,
+    tenant: &Tenant,
+    user_id: &str,
+) -> Result<DBOrganizationMember, ApiError> {
+    let user_id: DBUserId = user_id.parse().map_err(|_| ApiError::NotFound)?;
+    DBOrganizationMember::find_by_user(tenant, user_id, &global.database)
+        .await?
+        .ok_or(ApiError::NotFound)
+}
+
+async fn ensure_other_owner(global: &GlobalState, tenant: &Tenant) -> Result<(), ApiError> {
+    if DBOrganizationMember::count_owners(tenant, &global.database).await? < 2 {
+        return Err(ApiError::Conflict(
+            "the organization would be left without an owner",
+        ));
+    }
+    Ok(())
+}
@@ -0,0 +1,305 @@
+use crate::database::models::organization_member::DBOrganizationMember;
+use crate::database::models::role::{DBRole, DBRoleId};
+use crate::database::models::role_assignment::DBRoleAssignment;
+use crate::database::models::user::{DBUser, DBUserId};
+use crate::database::tenant::Tenant;
+use crate::global::GlobalState;
+use crate::http::v1::{ApiError, ApiErrorResponse, ApiUser, Authorized, Permission};
+use axum::Json;
+use axum::extract::{Path, State};
+use axum::http::StatusCode;
+use std::sync::Arc;
+use utoipa::ToSchema;
+use utoipa_axum::router::OpenApiRouter;
+use utoipa_axum::routes;
+
+pub fn router() -> OpenApiRouter<Arc<GlobalState>> {
+    OpenApiRouter::new()
+        .routes(routes!(list_roles, create_role))
+        .routes(routes!(delete_role))
+        .routes(routes!(assign_role, unassign_role))
+        .routes(routes!(list_my_roles))
+}
+
+/// Seeing every role and the permissions it grants
+pub struct ReadRoles;
+
+impl Permission for ReadRoles {
+    const NAME: &'static str = "roles:read";
+}
+
+/// Creating and deleting roles. Roles hold in every organization, so only roles users have everywhere grant it.
+pub struct ManageRoles;
+
+impl Permission for ManageRoles {
+    const NAME: &'static str = "roles:write";
+    const EVERYWHERE: bool = true;
+}
+
+/// Giving users roles and taking them away, in the organization the request is for or everywhere without one
+pub struct AssignRoles;
+
+impl Permission for AssignRoles {
+    const NAME: &'static str = "roles:assign";
+}
+
+#[derive(Debug, serde::Deserialize, ToSchema)]
+pub struct CreateRoleRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// What users with the role may do, like `roles:read`
+    #[serde(default)]
+    pub permissions: Vec<String>,
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct RoleResponse {
+    pub id: String,
+    pub name: String,
+    pub description: String,
+    pub permissions: Vec<String>,
+}
+
+impl From<DBRole> for RoleResponse {
+    fn from(role: DBRole) -> Self {
+        Self {
+            id: role.id.to_string(),
+            name: role.name,
+            description: role.description,
+            permissions: role.permissions,
+        }
+    }
+}
+
+#[derive(Debug, serde::Serialize, ToSchema)]
+pub struct RoleAssignmentResponse {
+    pub role: RoleResponse,
+    /// The organization the role is limited to, none when the user has it everywhere
+    pub organization_id: Option<String>,
+}
+
+/// Lists every role. Needs the `roles:read` permission.
+#[utoipa::path(
+    get,
+    path = "/roles",
+    tag = "roles",
+    responses(
+        (status = 200, body = Vec<RoleResponse>),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+    )
+)]
+pub async fn list_roles(
+    State(global): State<Arc<GlobalState>>,
+    _: Authorized<ReadRoles>,
+) -> Result<Json<Vec<RoleResponse>>, ApiError> {
+    let roles = DBRole::find_all(&global.database).await?;
+    Ok(Json(roles.into_iter().map(RoleResponse::from).collect()))
+}
+
+/// Adds a role. Needs the `roles:write` permission everywhere.
+#[utoipa::path(
+    post,
+    path = "/roles",
+    tag = "roles",
+    request_body = CreateRoleRequest,
+    responses(
+        (status = 201, body = RoleResponse),
+        (status = 400, body = ApiErrorResponse),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 409, body = ApiErrorResponse),
+    )
+)]
+pub async fn create_role(
+    State(global): State<Arc<GlobalState>>,
+    _: Authorized<ManageRoles>,
+    Json(request): Json<CreateRoleRequest>,
+) -> Result<(StatusCode, Json<RoleResponse>), ApiError> {
+    if request.name.trim().is_empty() {
+        return Err(ApiError::BadRequest("the role needs a name"));
+    }
+    if DBRole::find_by_name(&request.name, &global.database)
+        .await?
+        .is_some()
+    {
+        return Err(ApiError::Conflict("there is a role with this name already"));
+    }
+
+    let role = DBRole::builder()
+        .name(request.name)
+        .description(request.description)
+        .permissions(request.permissions)
+        .build();
+    let mut transaction = global.database.begin().await?;
+    role.insert(&mut transaction).await?;
+    transaction.commit().await?;
+
+    Ok((StatusCode::CREATED, Json(role.into())))
+}
+
+/// Deletes a role, taking it away from everyone who has it. Needs the `roles:write` permission everywhere.
+#[utoipa::path(
+    delete,
+    path = "/roles/{id}",
+    tag = "roles",
+    params(("id" = String, Path, description = "The role")),
+    responses(
+        (status = 204, description = "The role is gone"),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+    )
+)]
+pub async fn delete_role(
+    State(global): State<Arc<GlobalState>>,
+    _: Authorized<ManageRoles>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    let id: DBRoleId = id.parse().map_err(|_| ApiError::NotFound)?;
+
+    let mut transaction = global.database.begin().await?;
+    if !DBRole::delete(id, &mut transaction).await? {
+        return Err(ApiError::NotFound);
+    }
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Gives a user a role in the organization the request is for, or everywhere when it is for none. Only members
+/// have roles in an organization. Needs the `roles:assign` permission there.
+#[utoipa::path(
+    put,
+    path = "/roles/{id}/assignments/{user_id}",
+    tag = "roles",
+    params(
+        ("id" = String, Path, description = "The role"),
+        ("user_id" = String, Path, description = "The user"),
+    ),
+    responses(
+        (status = 204, description = "The user has the role"),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+    )
+)]
+pub async fn assign_role(
+    State(global): State<Arc<GlobalState>>,
+    authorized: Authorized<AssignRoles>,
+    Path((id, user_id)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let (role, user_id) = find_role_and_user(&global, &authorized, &id, &user_id).await?;
+
+    let mut transaction = global.database.begin().await?;
+    DBRoleAssignment::builder()
+        .user_id(user_id)
+        .role_id(role.id)
+        .tenant_id(authorized.tenant.as_ref().map(Tenant::id))
+        .build()
+        .insert(&mut transaction)
+        .await?;
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Takes a role away from a user, in the organization the request is for or everywhere when it is for none.
+/// Needs the `roles:assign` permission there.
+#[utoipa::path(
+    delete,
+    path = "/roles/{id}/assignments/{user_id}",
+    tag = "roles",
+    params(
+        ("id" = String, Path, description = "The role"),
+        ("user_id" = String, Path, description = "The user"),
+    ),
+    responses(
+        (status = 204, description = "The user no longer has the role there"),
+        (status = 401, body = ApiErrorResponse),
+        (status = 403, body = ApiErrorResponse),
+        (status = 404, body = ApiErrorResponse),
+    )
+)]
+pub async fn unassign_role(
+    State(global): State<Arc<GlobalState>>,
+    authorized: Authorized<AssignRoles>,
+    Path((id, user_id)): Path<(String, String)>,
+) -> Result<StatusCode, ApiError> {
+    let (role, user_id) = find_role_and_user(&global, &authorized, &id, &user_id).await?;
+
+    let mut transaction = global.database.begin().await?;
+    if !DBRoleAssignment::delete_by_user_and_role(
+        user_id,
+        role.id,
+        authorized.tenant.as_ref(),
+        &mut transaction,
+    )
+    .await?
+    {
+        return Err(ApiError::NotFound);
+    }
+    transaction.commit().await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// The role and the user an assignment is about. In an organization the user has to be a member of it.
+async fn find_role_and_user(
+    global: &GlobalState,
+    authorized: &Authorized<AssignRoles>,
+    id: &str,
+    user_id: &str,
+) -> Result<(DBRole, DBUserId), ApiError> {
+    let id: DBRoleId = id.parse().map_err(|_| ApiError::NotFound)?;
+    let user_id: DBUserId = user_id.parse().map_err(|_| ApiError::NotFound)?;
+    let role = DBRole::find_by_id(id, &global.database)
+        .await?
+        .ok_or(ApiError::NotFound)?;
+
+    let exists = match &authorized.tenant {
+        Some(tenant) => DBOrganizationMember::find_by_user(tenant, user_id, &global.database)
+            .await?
+            .is_some(),
+        None => DBUser::find_by_id(user_id, &global.database)
+            .await?
+            .is_some(),
+    };
+    if !exists {
+        return Err(ApiError::NotFound);
+    }
+    Ok((role, user_id))
+}
+
+/// Lists the roles the signed in user has, and where.
+#[utoipa::path(
+    get,
+    path = "/me/roles",
+    tag = "me",
+    responses(
+        (status = 200, body = Vec<RoleAssignmentResponse>),
+        (status = 401, body = ApiErrorResponse),
+    )
+)]
+pub async fn list_my_roles(
+    State(global): State<Arc<GlobalState>>,
+    ApiUser(current): ApiUser,
+) -> Result<Json<Vec<RoleAssignmentResponse>>, ApiError> {
+    let assignments =
+        DBRoleAssignment::find_many_by_user(current.user.id, &global.database).await?;
+    let roles = DBRole::find_all(&global.database).await?;
+
+    let response = assignments
+        .into_iter()
+        .filter_map(|assignment| {
+            let role = roles.iter().find(|role| role.id == assignment.role_id)?;
+            Some(RoleAssignmentResponse {
+                role: role.clone().into(),
+                organization_id: assignment.tenant_id.map(|id| id.to_string()),
+            })
+        })
+        .collect();
+
+    Ok(Json(response))
+}
@@ -0,0 +1,92 @@
+use crate::database::models::signing_key::DBSigningKey;
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use jsonwebtoken::jwk::{
+    AlgorithmParameters, CommonParameters, EllipticCurve, EllipticCurveKeyParameters,
+    EllipticCurveKeyType, Jwk, KeyAlgorithm, PublicKeyUse,
+};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use ring::rand::SystemRandom;
+use ring::signature::{ECDSA_P256_SHA256_FIXED_SIGNING, EcdsaKeyPair, KeyPair};
+use sqlx::PgPool;
+use sqlx::types::Json;
+
+/// The key new JWTs are signed with.
+pub struct SigningKey {
+    pub kid: String,
+    encoding: EncodingKey,
+}
+
+impl SigningKey {
+    /// Loads the newest signing key, generating the first one when there is none yet.
+    pub async fn load_or_generate(pool: &PgPool) -> anyhow::Result<Self> {
+        let key = match DBSigningKey::find_latest(pool).await? {
+            Some(key) => key,
+            None => {
+                tracing::info!("No signing key found, generating one...");
+                let key = generate()?;
+                let mut transaction = pool.begin().await?;
+                key.insert(&mut transaction).await?;
+                transaction.commit().await?;
+                key
+            }
+        };
+
+        if key.algorithm != "ES256" {
+            anyhow::bail!("Unsupported signing key algorithm: {}", key.algorithm);
+        }
+
+        Ok(Self {
+            kid: key.id.to_string(),
+            encoding: EncodingKey::from_ec_der(&key.private_key),
+        })
+    }
+
+    pub fn sign<T: serde::Serialize>(
+        &self,
+        typ: &str,
+        claims: &T,
+    ) -> Result<String, jsonwebtoken::errors::Error> {
+        let mut header = Header::new(Algorithm::ES256);
+        header.typ = Some(typ.into());
+        header.kid = Some(self.kid.clone());
+
+        jsonwebtoken::encode(&header, claims, &self.encoding)
+    }
+}
+
+/// Generates a new ES256 signing key. It is used for new tokens once stored and the server restarted.
+pub fn generate() -> anyhow::Result<DBSigningKey> {
+    let rng = SystemRandom::new();
+    let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+        .map_err(|_| anyhow::anyhow!("Failed generating a signing key"))?;
+    let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+        .map_err(|_| anyhow::anyhow!("Failed reading the generated signing key"))?;
+
+    // uncompressed point, 0x04 followed by both coordinates
+    let point = pair.public_key().as_ref();
+    let (x, y) = point[1..].split_at(32);
+
+    let mut key = DBSigningKey::builder()
+        .algorithm("ES256".into())
+        .private_key(pkcs8.as_ref().to_vec())
+        .public_jwk(Json(Jwk {
+            common: CommonParameters::default(),
+            algorithm: AlgorithmParameters::EllipticCurve(EllipticCurveKeyParameters {
+                key_type: EllipticCurveKeyType::EC,
+                curve: EllipticCurve::P256,
+                x: BASE64_URL_SAFE_NO_PAD.encode(x),
+                y: BASE64_URL_SAFE_NO_PAD.encode(y),
+            }),
+        }))
+        .build();
+
+    key.public_jwk.common = CommonParameters {
+        public_key_use: Some(PublicKeyUse::Signature),
+        key_algorithm: Some(KeyAlgorithm::ES256),
+        key_id: Some(key.id.to_string()),
+        ..Default::default()
+    };
+
+    Ok(key)
+}
@@ -0,0 +1,185 @@
+//! Checking passwords against an LDAP directory, search-then-bind. Every login gets a connection of its own, closed
+//! right after, so neither the connection nor anything read over it outlives the login.
+
+use crate::settings::LdapDirectory;
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use ldap3::{Ldap, LdapConnAsync, LdapConnSettings, Scope, SearchEntry, ldap_escape};
+use serde_json::{Map, Value};
+use std::time::Duration;
+
+/// Result code of a bind with the wrong password, or for an entry that doesn't exist.
+const INVALID_CREDENTIALS: u32 = 49;
+
+#[derive(Debug, thiserror::Error)]
+pub enum LdapError {
+    #[error(transparent)]
+    Ldap(#[from] ldap3::LdapError),
+    /// The directory answered, but not with anything users can be made from
+    #[error("{0}")]
+    Invalid(String),
+}
+
+/// Someone the directory accepted the password of.
+#[derive(Debug)]
+pub struct DirectoryUser {
+    pub subject: String,
+    pub username: Option<String>,
+    pub email: Option<String>,
+    /// The mapped attributes, and `groups` when the directory has groups set up
+    pub attributes: Map<String, Value>,
+}
+
+/// Finds the entry of the user with the service account and checks their password by binding as it. `None` when
+/// there is no such user or the password is wrong.
+pub async fn authenticate(
+    directory: &LdapDirectory,
+    username: &str,
+    password: &str,
+) -> Result<Option<DirectoryUser>, LdapError> {
+    // binding with an empty password is an unauthenticated bind, which directories let through for any DN
+    if username.is_empty() || password.is_empty() {
+        return Ok(None);
+    }
+
+    let timeout = Duration::from_secs(directory.timeout);
+    let settings = LdapConnSettings::new()
+        .set_conn_timeout(timeout)
+        .set_starttls(directory.starttls && directory.url.starts_with("ldap://"));
+    let (connection, mut ldap) = LdapConnAsync::with_settings(settings, &directory.url).await?;
+    ldap3::drive!(connection);
+
+    let result = search_then_bind(&mut ldap, directory, timeout, username, password).await;
+    let _ = ldap.unbind().await;
+    result
+}
+
+async fn search_then_bind(
+    ldap: &mut Ldap,
+    directory: &LdapDirectory,
+    timeout: Duration,
+    username: &str,
+    password: &str,
+) -> Result<Option<DirectoryUser>, LdapError> {
+    ldap.with_timeout(timeout)
+        .simple_bind(&directory.bind_dn, &directory.bind_password)
+        .await?
+        .success()?;
+
+    let mapping = &directory.attributes;
+    let mut wanted = vec![
+        mapping.subject.as_str(),
+        mapping.username.as_str(),
+        mapping.email.as_str(),
+    ];
+    wanted.extend(mapping.attributes.keys().map(String::as_str));
+
+    let filter = directory
+        .user_filter
+        .replace("{username}", &ldap_escape(username));
+    let (entries, _) = ldap
+        .with_timeout(timeout)
+        .search(&directory.base_dn, Scope::Subtree, &filter, wanted)
+        .await?
+        .success()?;
+    let mut entries = entries.into_iter().map(SearchEntry::construct);
+    let Some(entry) = entries.next() else {
+        return Ok(None);
+    };
+    if entries.next().is_some() {
+        return Err(LdapError::Invalid(format!(
+            "more than one entry matches {filter}"
+        )));
+    }
+
+    let groups = match &directory.group_base_dn {
+        Some(base_dn) => Some(find_groups(ldap, directory, timeout, base_dn, &entry.dn).await?),
+        None => None,
+    };
+
+    let bind = ldap
+        .with_timeout(timeout)
+        .simple_bind(&entry.dn, password)
+        .await?;
+    if bind.rc == INVALID_CREDENTIALS {
+        return Ok(None);
+    }
+    bind.success()?;
+
+    let subject = first(&entry, &mapping.subject)
+        .ok_or_else(|| LdapError::Invalid(format!("{} has no {}", entry.dn, mapping.subject)))?;
+
+    let mut attributes = Map::new();
+    for (name, user_attribute) in &mapping.attributes {
+        let value = match values(&entry, name).as_slice() {
+            [] => continue,
+            [value] => Value::from(value.clone()),
+            values => Value::from(values.to_vec()),
+        };
+        attributes.insert(user_attribute.clone(), value);
+    }
+    if let Some(groups) = groups {
+        attributes.insert("groups".into(), Value::from(groups));
+    }
+
+    Ok(Some(DirectoryUser {
+        subject,
+        username: first(&entry, &mapping.username),
+        email: first(&entry, &mapping.email),
+        attributes,
+    }))
+}
+
+/// Names of the mapped groups the entry is a member of.
+async fn find_groups(
+    ldap: &mut Ldap,
+    directory: &LdapDirectory,
+    timeout: Duration,
+    base_dn: &str,
+    dn: &str,
+) -> Result<Vec<String>, LdapError> {
+    let filter = directory.group_filter.replace("{dn}", &ldap_escape(dn));
+    let (entries, _) = ldap
+        .with_timeout(timeout)
+        .search(base_dn, Scope::Subtree, &filter, vec!["1.1"])
+        .await?
+        .success()?;
+
+    // DNs aren't case sensitive
+    Ok(entries
+        .into_iter()
+        .map(SearchEntry::construct)
+        .filter_map(|group| {
+            directory
+                .groups
+                .iter()
+                .find(|(group_dn, _)| group_dn.eq_ignore_ascii_case(&group.dn))
+                .map(|(_, name)| name.clone())
+        })
+        .collect())
+}
+
+/// Values of an attribute, whatever case the directory spells its name in. Binary values are base64url encoded.
+fn values(entry: &SearchEntry, name: &str) -> Vec<String> {
+    let text = entry
+        .attrs
+        .iter()
+        .filter(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+        .flat_map(|(_, values)| values.iter().cloned());
+    let binary = entry
+        .bin_attrs
+        .iter()
+        .filter(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+        .flat_map(|(_, values)| {
+            values
+                .iter()
+                .map(|value| BASE64_URL_SAFE_NO_PAD.encode(value))
+        });
+    text.chain(binary).collect()
+}
+
+fn first(entry: &SearchEntry, name: &str) -> Option<String> {
+    values(entry, name)
+        .into_iter()
+        .find(|value| !value.is_empty())
+}
@@ -1,7 +1,13 @@
 pub mod cli;
+pub mod crypto;
 #[cfg(feature = "hack")] // This is for the belt cli! HACK GOD DAMMIT
 pub mod database;
 pub mod global;
 pub mod http;
+pub mod keys;
+pub mod ldap;
 pub mod logging;
+pub mod mail;
+pub mod saml;
+pub mod scim;
 pub mod settings;
@@ -0,0 +1,58 @@
+//! Sending emails to users over SMTP. Without an SMTP server they are only logged, which is enough for development.
+
+use crate::settings::Mail;
+use lettre::message::Mailbox;
+use lettre::{Address, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+
+#[derive(Debug, thiserror::Error)]
+pub enum MailError {
+    #[error(transparent)]
+    Address(#[from] lettre::address::AddressError),
+    #[error(transparent)]
+    Message(#[from] lettre::error::Error),
+    #[error(transparent)]
+    Smtp(#[from] lettre::transport::smtp::Error),
+}
+
+/// Whether emails can be sent to the address.
+pub fn is_valid_address(address: &str) -> bool {
+    address.parse::<Address>().is_ok()
+}
+
+pub struct Mailer {
+    from: Mailbox,
+    /// None when no SMTP server is configured
+    transport: Option<AsyncSmtpTransport<Tokio1Executor>>,
+}
+
+impl Mailer {
+    pub fn new(settings: &Mail) -> Result<Self, MailError> {
+        let transport = match &settings.smtp_url {
+            Some(url) => Some(AsyncSmtpTransport::<Tokio1Executor>::from_url(url)?.build()),
+            None => None,
+        };
+
+        Ok(Self {
+            from: settings.from.parse()?,
+            transport,
+        })
+    }
+
+    /// Sends a plain text email.
+    pub async fn send(&self, to: &str, subject: &str, body: String) -> Result<(), MailError> {
+        let Some(transport) = &self.transport else {
+            tracing::info!(
+                "No SMTP server to send an email to {to} with, it says: {subject}\n{body}"
+            );
+            return Ok(());
+        };
+
+        let message = Message::builder()
+            .from(self.from.clone())
+            .to(to.parse()?)
+            .subject(subject)
+            .body(body)?;
+        transport.send(message).await?;
+        Ok(())
+    }
+}
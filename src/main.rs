@@ -15,6 +15,8 @@ async fn main() {
             .expect("Failed trying to init global state"),
     );
 
+    tokio::spawn(http::oauth2::logout::run_backchannel_worker(global.clone()));
+
     let shutdown_channel = tokio::sync::oneshot::channel::<()>();
     let http_srv = tokio::spawn(http::run(global, shutdown_channel.1));
 
@@ -0,0 +1,205 @@
+//! Responses upstream identity providers send this server as a service provider. (SAML Profiles section 4.1.4.3)
+
+use crate::saml::signature::{is_signed, verify_enveloped};
+use crate::saml::{ASSERTION, PROTOCOL, STATUS_SUCCESS, SamlError, parse_root};
+use chrono::{DateTime, Duration, Utc};
+use roxmltree::{Document, Node};
+use std::collections::BTreeMap;
+
+const BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+pub const NAME_ID_TRANSIENT: &str = "urn:oasis:names:tc:SAML:2.0:nameid-format:transient";
+
+/// Clocks of identity providers are allowed to be off by this many seconds.
+const CLOCK_SKEW: i64 = 60;
+
+/// What a response has to be about, and who has to have signed it.
+pub struct ExpectedResponse<'a> {
+    /// Entity ID of the identity provider
+    pub issuer: &'a str,
+    /// DER certificates the identity provider signs with
+    pub certificates: &'a [Vec<u8>],
+    /// Entity ID of this server as a service provider
+    pub audience: &'a str,
+    /// Where the response was posted to
+    pub recipient: &'a str,
+    /// ID of the authentication request the response answers
+    pub in_response_to: &'a str,
+}
+
+/// The statements of a response that passed every check.
+#[derive(Debug)]
+pub struct VerifiedAssertion {
+    pub name_id: String,
+    pub name_id_format: Option<String>,
+    pub attributes: BTreeMap<String, Vec<String>>,
+}
+
+/// Checks a response to an authentication request and the one assertion in it. Either has to be signed, values are
+/// only ever read from signed elements.
+pub fn verify_response(
+    xml: &str,
+    expected: &ExpectedResponse,
+) -> Result<VerifiedAssertion, SamlError> {
+    let invalid = SamlError::Invalid;
+    let document = Document::parse(xml)?;
+    let response = parse_root(&document, "Response")?;
+
+    if response.attribute("InResponseTo") != Some(expected.in_response_to) {
+        return Err(invalid("response is not to the request"));
+    }
+    if response
+        .attribute("Destination")
+        .is_some_and(|destination| destination != expected.recipient)
+    {
+        return Err(invalid("response is for another destination"));
+    }
+    if issuer(response).is_some_and(|issuer| issuer != expected.issuer) {
+        return Err(invalid("response is from another issuer"));
+    }
+
+    let status = response
+        .children()
+        .find(|child| child.has_tag_name((PROTOCOL, "Status")))
+        .and_then(|status| child(status, PROTOCOL, "StatusCode"))
+        .and_then(|code| code.attribute("Value"))
+        .ok_or(invalid("response has no status"))?;
+    if status != STATUS_SUCCESS {
+        return Err(SamlError::Status(status.to_string()));
+    }
+
+    if response
+        .children()
+        .any(|child| child.has_tag_name((ASSERTION, "EncryptedAssertion")))
+    {
+        return Err(invalid("encrypted assertions are not supported"));
+    }
+    let mut assertions = response
+        .children()
+        .filter(|child| child.has_tag_name((ASSERTION, "Assertion")));
+    let assertion = assertions
+        .next()
+        .ok_or(invalid("response has no assertion"))?;
+    if assertions.next().is_some() {
+        return Err(invalid("response has more than one assertion"));
+    }
+
+    let response_signed = is_signed(response);
+    let assertion_signed = is_signed(assertion);
+    if !response_signed && !assertion_signed {
+        return Err(invalid("neither response nor assertion are signed"));
+    }
+    if response_signed {
+        verify_enveloped(response, expected.certificates)?;
+    }
+    if assertion_signed {
+        verify_enveloped(assertion, expected.certificates)?;
+    }
+
+    verify_assertion(assertion, expected)
+}
+
+fn verify_assertion(
+    assertion: Node,
+    expected: &ExpectedResponse,
+) -> Result<VerifiedAssertion, SamlError> {
+    let invalid = SamlError::Invalid;
+    let now = Utc::now();
+    let skew = Duration::seconds(CLOCK_SKEW);
+
+    if assertion.attribute("Version") != Some("2.0") {
+        return Err(invalid("unsupported SAML version"));
+    }
+    if issuer(assertion) != Some(expected.issuer) {
+        return Err(invalid("assertion is from another issuer"));
+    }
+
+    let subject =
+        child(assertion, ASSERTION, "Subject").ok_or(invalid("assertion has no subject"))?;
+    let name_id = child(subject, ASSERTION, "NameID").ok_or(invalid("subject has no NameID"))?;
+
+    // SAML Profiles section 4.1.4.2
+    let confirmed = subject
+        .children()
+        .filter(|child| child.has_tag_name((ASSERTION, "SubjectConfirmation")))
+        .filter(|confirmation| confirmation.attribute("Method") == Some(BEARER))
+        .filter_map(|confirmation| child(confirmation, ASSERTION, "SubjectConfirmationData"))
+        .any(|data| {
+            data.attribute("Recipient") == Some(expected.recipient)
+                && data.attribute("InResponseTo") == Some(expected.in_response_to)
+                && data.attribute("NotBefore").is_none()
+                && time(data, "NotOnOrAfter").is_some_and(|time| now < time + skew)
+        });
+    if !confirmed {
+        return Err(invalid("subject is not confirmed for this request"));
+    }
+
+    let conditions =
+        child(assertion, ASSERTION, "Conditions").ok_or(invalid("assertion has no conditions"))?;
+    if time(conditions, "NotBefore").is_some_and(|time| now + skew < time)
+        || time(conditions, "NotOnOrAfter").is_some_and(|time| time + skew <= now)
+    {
+        return Err(invalid("assertion is not valid now"));
+    }
+    // every restriction has to allow this server
+    let restrictions: Vec<Node> = conditions
+        .children()
+        .filter(|child| child.has_tag_name((ASSERTION, "AudienceRestriction")))
+        .collect();
+    if restrictions.is_empty()
+        || !restrictions.iter().all(|restriction| {
+            restriction
+                .children()
+                .filter(|child| child.has_tag_name((ASSERTION, "Audience")))
+                .any(|audience| text(audience) == expected.audience)
+        })
+    {
+        return Err(invalid("assertion is for another audience"));
+    }
+
+    let mut attributes: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for attribute in assertion
+        .children()
+        .filter(|child| child.has_tag_name((ASSERTION, "AttributeStatement")))
+        .flat_map(|statement| statement.children())
+        .filter(|child| child.has_tag_name((ASSERTION, "Attribute")))
+    {
+        let Some(name) = attribute.attribute("Name") else {
+            continue;
+        };
+        attributes.entry(name.to_string()).or_default().extend(
+            attribute
+                .children()
+                .filter(|child| child.has_tag_name((ASSERTION, "AttributeValue")))
+                .map(|value| text(value).trim().to_string()),
+        );
+    }
+
+    Ok(VerifiedAssertion {
+        name_id: text(name_id).trim().to_string(),
+        name_id_format: name_id.attribute("Format").map(String::from),
+        attributes,
+    })
+}
+
+fn child<'a>(node: Node<'a, 'a>, namespace: &str, name: &str) -> Option<Node<'a, 'a>> {
+    node.children()
+        .find(|child| child.has_tag_name((namespace, name)))
+}
+
+fn issuer<'a>(node: Node<'a, 'a>) -> Option<&'a str> {
+    child(node, ASSERTION, "Issuer").map(|issuer| issuer.text().unwrap_or_default().trim())
+}
+
+/// All text of an element, not just the first piece of it.
+fn text(node: Node) -> String {
+    node.descendants()
+        .filter(Node::is_text)
+        .filter_map(|node| node.text())
+        .collect()
+}
+
+fn time(node: Node, attribute: &str) -> Option<DateTime<Utc>> {
+    node.attribute(attribute)
+        .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+        .map(|time| time.with_timezone(&Utc))
+}
@@ -0,0 +1,154 @@
+//! Exclusive XML canonicalization of received documents, to check the signatures in them. Comments are never part of
+//! the output. (Exclusive XML Canonicalization 1.0)
+
+use roxmltree::{Node, NodeId};
+use std::collections::BTreeMap;
+
+const XML_NAMESPACE: &str = "http://www.w3.org/XML/1998/namespace";
+
+/// Canonicalizes an element and everything in it, leaving out the excluded element, the enveloped signature.
+/// Namespaces with a prefix in the inclusive list are rendered wherever they are in scope, `#default` stands for the
+/// default namespace.
+pub fn canonicalize(
+    element: Node,
+    excluded: Option<NodeId>,
+    inclusive_prefixes: &[&str],
+) -> String {
+    let mut out = String::new();
+    write_element(
+        element,
+        excluded,
+        inclusive_prefixes,
+        &BTreeMap::new(),
+        &mut out,
+    );
+    out
+}
+
+/// The qualified name as written in the document, roxmltree only keeps the namespace uri.
+fn element_qname<'a>(element: Node<'a, 'a>) -> &'a str {
+    let input = element.document().input_text();
+    let start = element.range().start + 1;
+    let end = input[start..]
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .map_or(input.len(), |end| start + end);
+    &input[start..end]
+}
+
+fn prefix_of(qname: &str) -> &str {
+    qname.split_once(':').map_or("", |(prefix, _)| prefix)
+}
+
+fn write_element(
+    element: Node,
+    excluded: Option<NodeId>,
+    inclusive_prefixes: &[&str],
+    rendered: &BTreeMap<String, String>,
+    out: &mut String,
+) {
+    let input = element.document().input_text();
+    let qname = element_qname(element);
+
+    // namespaces visibly used by the element and its attributes, plus the inclusive ones
+    let mut used: Vec<&str> = vec![prefix_of(qname)];
+    for attribute in element.attributes() {
+        if attribute
+            .namespace()
+            .is_some_and(|uri| uri != XML_NAMESPACE)
+        {
+            used.push(prefix_of(&input[attribute.range_qname()]));
+        }
+    }
+    for prefix in inclusive_prefixes {
+        used.push(if *prefix == "#default" { "" } else { prefix });
+    }
+
+    let in_scope = |prefix: &str| {
+        element
+            .namespaces()
+            .find(|namespace| namespace.name().unwrap_or_default() == prefix)
+            .map(|namespace| namespace.uri())
+    };
+
+    let mut declarations = BTreeMap::new();
+    for prefix in used {
+        let uri = match in_scope(prefix) {
+            Some(uri) => uri,
+            // an element without a default namespace undeclares one rendered further up
+            None if prefix.is_empty() => "",
+            None => continue,
+        };
+        let already = rendered.get(prefix).map_or("", String::as_str);
+        if already != uri {
+            declarations.insert(prefix.to_string(), uri.to_string());
+        }
+    }
+
+    out.push('<');
+    out.push_str(qname);
+    for (prefix, uri) in &declarations {
+        out.push_str(" xmlns");
+        if !prefix.is_empty() {
+            out.push(':');
+            out.push_str(prefix);
+        }
+        out.push_str("=\"");
+        escape_attribute(uri, out);
+        out.push('"');
+    }
+
+    let mut attributes: Vec<_> = element.attributes().collect();
+    attributes
+        .sort_by_key(|attribute| (attribute.namespace().unwrap_or_default(), attribute.name()));
+    for attribute in attributes {
+        out.push(' ');
+        out.push_str(&input[attribute.range_qname()]);
+        out.push_str("=\"");
+        escape_attribute(attribute.value(), out);
+        out.push('"');
+    }
+    out.push('>');
+
+    let mut rendered = rendered.clone();
+    rendered.extend(declarations);
+    for child in element.children() {
+        if Some(child.id()) == excluded {
+            continue;
+        }
+        if child.is_element() {
+            write_element(child, excluded, inclusive_prefixes, &rendered, out);
+        } else if child.is_text() {
+            escape_text(child.text().unwrap_or_default(), out);
+        }
+    }
+
+    out.push_str("</");
+    out.push_str(qname);
+    out.push('>');
+}
+
+fn escape_attribute(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn escape_text(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
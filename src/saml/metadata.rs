@@ -0,0 +1,285 @@
+use crate::saml::signature::SamlKey;
+use crate::saml::xml::Element;
+use crate::saml::{
+    DS, HTTP_POST, HTTP_REDIRECT, METADATA, NAME_ID_EMAIL, NAME_ID_PERSISTENT, NAME_ID_UNSPECIFIED,
+    SamlError,
+};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use roxmltree::{Document, Node};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// What an imported service provider's metadata says about it. (SAML Metadata section 2.4.4)
+#[derive(Debug)]
+pub struct ServiceProviderMetadata {
+    pub entity_id: String,
+    /// Where assertions are posted to
+    pub assertion_consumer_service_url: String,
+    /// Where logout messages go and with which binding
+    pub single_logout_service: Option<(String, String)>,
+    /// DER certificate the service provider signs its messages with
+    pub signing_certificate: Option<Vec<u8>>,
+    /// The first name ID format the service provider lists that this server supports
+    pub name_id_format: Option<String>,
+    pub authn_requests_signed: bool,
+}
+
+impl ServiceProviderMetadata {
+    pub fn parse(xml: &str) -> Result<Self, SamlError> {
+        let document = Document::parse(xml)?;
+        let root = document.root_element();
+        if !root.has_tag_name((METADATA, "EntityDescriptor")) {
+            return Err(SamlError::Invalid("not an EntityDescriptor"));
+        }
+
+        let entity_id = root
+            .attribute("entityID")
+            .ok_or(SamlError::Invalid("no entityID"))?;
+        let descriptor = root
+            .children()
+            .find(|node| node.has_tag_name((METADATA, "SPSSODescriptor")))
+            .ok_or(SamlError::Invalid("no SPSSODescriptor"))?;
+
+        // the default POST endpoint, else the one with the lowest index
+        let mut consumers: Vec<Node> = children(descriptor, "AssertionConsumerService")
+            .filter(|node| node.attribute("Binding") == Some(HTTP_POST))
+            .collect();
+        consumers.sort_by_key(|node| {
+            (
+                node.attribute("isDefault") != Some("true"),
+                node.attribute("index")
+                    .and_then(|index| index.parse::<u32>().ok())
+                    .unwrap_or(u32::MAX),
+            )
+        });
+        let assertion_consumer_service_url = consumers
+            .first()
+            .and_then(|node| node.attribute("Location"))
+            .ok_or(SamlError::Invalid(
+                "no AssertionConsumerService with the HTTP-POST binding",
+            ))?;
+
+        let logout_services: Vec<Node> = children(descriptor, "SingleLogoutService").collect();
+        let single_logout_service = [HTTP_REDIRECT, HTTP_POST].into_iter().find_map(|binding| {
+            logout_services
+                .iter()
+                .find(|node| node.attribute("Binding") == Some(binding))
+                .and_then(|node| node.attribute("Location"))
+                .map(|location| (location.to_string(), binding.to_string()))
+        });
+
+        let signing_certificate = signing_certificates(descriptor)?.into_iter().next();
+
+        let name_id_format = children(descriptor, "NameIDFormat")
+            .filter_map(|node| node.text())
+            .map(str::trim)
+            .find(|format| {
+                [NAME_ID_PERSISTENT, NAME_ID_EMAIL, NAME_ID_UNSPECIFIED].contains(format)
+            })
+            .map(String::from);
+
+        Ok(Self {
+            entity_id: entity_id.to_string(),
+            assertion_consumer_service_url: assertion_consumer_service_url.to_string(),
+            single_logout_service,
+            signing_certificate,
+            name_id_format,
+            authn_requests_signed: descriptor.attribute("AuthnRequestsSigned") == Some("true"),
+        })
+    }
+}
+
+/// What an upstream identity provider's metadata says about it. (SAML Metadata section 2.4.3)
+#[derive(Debug)]
+pub struct IdentityProviderMetadata {
+    pub entity_id: String,
+    /// Where authentication requests go and with which binding, HTTP-Redirect when the provider takes it
+    pub single_sign_on_service: (String, String),
+    /// DER certificates the identity provider signs with, more than one while it rolls its key over
+    pub signing_certificates: Vec<Vec<u8>>,
+}
+
+impl IdentityProviderMetadata {
+    pub fn parse(xml: &str) -> Result<Self, SamlError> {
+        let document = Document::parse(xml)?;
+        let root = document.root_element();
+        if !root.has_tag_name((METADATA, "EntityDescriptor")) {
+            return Err(SamlError::Invalid("not an EntityDescriptor"));
+        }
+
+        let entity_id = root
+            .attribute("entityID")
+            .ok_or(SamlError::Invalid("no entityID"))?;
+        let descriptor = root
+            .children()
+            .find(|node| node.has_tag_name((METADATA, "IDPSSODescriptor")))
+            .ok_or(SamlError::Invalid("no IDPSSODescriptor"))?;
+
+        let services: Vec<Node> = children(descriptor, "SingleSignOnService").collect();
+        let single_sign_on_service = [HTTP_REDIRECT, HTTP_POST]
+            .into_iter()
+            .find_map(|binding| {
+                services
+                    .iter()
+                    .find(|node| node.attribute("Binding") == Some(binding))
+                    .and_then(|node| node.attribute("Location"))
+                    .map(|location| (location.to_string(), binding.to_string()))
+            })
+            .ok_or(SamlError::Invalid(
+                "no SingleSignOnService with the HTTP-Redirect or HTTP-POST binding",
+            ))?;
+
+        let signing_certificates = signing_certificates(descriptor)?;
+        if signing_certificates.is_empty() {
+            return Err(SamlError::Invalid("no signing certificate"));
+        }
+
+        Ok(Self {
+            entity_id: entity_id.to_string(),
+            single_sign_on_service,
+            signing_certificates,
+        })
+    }
+}
+
+/// Metadata of upstream identity providers by provider id, so it isn't read for every login and response.
+#[derive(Default)]
+pub struct IdentityProviderCache {
+    entries: Mutex<HashMap<String, (Instant, Arc<IdentityProviderMetadata>)>>,
+}
+
+impl IdentityProviderCache {
+    /// The provider's metadata, unless it was read longer than `lifetime` ago.
+    pub fn get(
+        &self,
+        provider_id: &str,
+        lifetime: Duration,
+    ) -> Option<Arc<IdentityProviderMetadata>> {
+        let entries = self
+            .entries
+            .lock()
+            .expect("the cache lock is never poisoned");
+        entries
+            .get(provider_id)
+            .filter(|(read_at, _)| read_at.elapsed() < lifetime)
+            .map(|(_, metadata)| metadata.clone())
+    }
+
+    pub fn insert(&self, provider_id: String, metadata: Arc<IdentityProviderMetadata>) {
+        let mut entries = self
+            .entries
+            .lock()
+            .expect("the cache lock is never poisoned");
+        entries.insert(provider_id, (Instant::now(), metadata));
+    }
+}
+
+/// Certificates of the key descriptors for signing, or for any use.
+fn signing_certificates(descriptor: Node) -> Result<Vec<Vec<u8>>, SamlError> {
+    children(descriptor, "KeyDescriptor")
+        .filter(|node| node.attribute("use").is_none_or(|usage| usage == "signing"))
+        .filter_map(|node| {
+            node.descendants()
+                .find(|node| node.has_tag_name((DS, "X509Certificate")))
+        })
+        .map(|node| {
+            let text: String = node.text().unwrap_or_default().split_whitespace().collect();
+            BASE64_STANDARD
+                .decode(text)
+                .map_err(|_| SamlError::Invalid("invalid X509Certificate"))
+        })
+        .collect()
+}
+
+fn children<'a>(node: Node<'a, 'a>, name: &'a str) -> impl Iterator<Item = Node<'a, 'a>> {
+    node.children()
+        .filter(move |child| child.has_tag_name((METADATA, name)))
+}
+
+/// This server's metadata as an identity provider, for service providers to import.
+pub fn identity_provider(entity_id: &str, base_url: &str, key: &SamlKey) -> String {
+    let endpoint = |name: &'static str, binding: &'static str, path: &str| {
+        Element::new(name)
+            .attribute("Binding", binding)
+            .attribute("Location", format!("{base_url}{path}"))
+    };
+
+    let mut descriptor =
+        Element::new("md:IDPSSODescriptor")
+            .attribute("WantAuthnRequestsSigned", "false")
+            .attribute(
+                "protocolSupportEnumeration",
+                crate::saml::PROTOCOL.to_string(),
+            )
+            .child(
+                Element::new("md:KeyDescriptor")
+                    .attribute("use", "signing")
+                    .child(Element::new("ds:KeyInfo").namespace("ds", DS).child(
+                        Element::new("ds:X509Data").child(
+                            Element::new("ds:X509Certificate").text(key.certificate_base64()),
+                        ),
+                    )),
+            )
+            .child(endpoint(
+                "md:SingleLogoutService",
+                HTTP_REDIRECT,
+                "/saml/slo",
+            ))
+            .child(endpoint("md:SingleLogoutService", HTTP_POST, "/saml/slo"));
+    for format in [NAME_ID_PERSISTENT, NAME_ID_EMAIL, NAME_ID_UNSPECIFIED] {
+        descriptor = descriptor.child(Element::new("md:NameIDFormat").text(format));
+    }
+    descriptor = descriptor
+        .child(endpoint(
+            "md:SingleSignOnService",
+            HTTP_REDIRECT,
+            "/saml/sso",
+        ))
+        .child(endpoint("md:SingleSignOnService", HTTP_POST, "/saml/sso"));
+
+    Element::new("md:EntityDescriptor")
+        .namespace("md", METADATA)
+        .attribute("entityID", entity_id)
+        .child(descriptor)
+        .to_canonical_string()
+}
+
+/// This server's metadata as a service provider, for upstream identity providers to import.
+pub fn service_provider(entity_id: &str, acs_url: &str, key: Option<&SamlKey>) -> String {
+    let mut descriptor = Element::new("md:SPSSODescriptor")
+        .attribute("AuthnRequestsSigned", key.is_some().to_string())
+        .attribute("WantAssertionsSigned", "true")
+        .attribute(
+            "protocolSupportEnumeration",
+            crate::saml::PROTOCOL.to_string(),
+        );
+    if let Some(key) = key {
+        descriptor =
+            descriptor.child(
+                Element::new("md:KeyDescriptor")
+                    .attribute("use", "signing")
+                    .child(Element::new("ds:KeyInfo").namespace("ds", DS).child(
+                        Element::new("ds:X509Data").child(
+                            Element::new("ds:X509Certificate").text(key.certificate_base64()),
+                        ),
+                    )),
+            );
+    }
+    descriptor = descriptor
+        .child(Element::new("md:NameIDFormat").text(NAME_ID_PERSISTENT))
+        .child(
+            Element::new("md:AssertionConsumerService")
+                .attribute("Binding", HTTP_POST)
+                .attribute("Location", acs_url)
+                .attribute("index", "0")
+                .attribute("isDefault", "true"),
+        );
+
+    Element::new("md:EntityDescriptor")
+        .namespace("md", METADATA)
+        .attribute("entityID", entity_id)
+        .child(descriptor)
+        .to_canonical_string()
+}
@@ -0,0 +1,273 @@
+//! SAML 2.0 messages, bindings and metadata, shared by the identity provider endpoints and belt.
+
+use crate::saml::signature::SamlKey;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use chrono::{DateTime, Utc};
+use flate2::Compression;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use roxmltree::{Document, Node};
+use std::io::{Read, Write};
+
+pub mod assertion;
+pub mod c14n;
+pub mod metadata;
+pub mod response;
+pub mod signature;
+pub mod xml;
+
+pub const PROTOCOL: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+pub const ASSERTION: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+pub const METADATA: &str = "urn:oasis:names:tc:SAML:2.0:metadata";
+pub const DS: &str = "http://www.w3.org/2000/09/xmldsig#";
+
+pub const EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+pub const RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+pub const SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+
+pub const HTTP_REDIRECT: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-Redirect";
+pub const HTTP_POST: &str = "urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST";
+
+pub const STATUS_SUCCESS: &str = "urn:oasis:names:tc:SAML:2.0:status:Success";
+pub const STATUS_REQUESTER: &str = "urn:oasis:names:tc:SAML:2.0:status:Requester";
+pub const STATUS_NO_PASSIVE: &str = "urn:oasis:names:tc:SAML:2.0:status:NoPassive";
+
+pub const NAME_ID_PERSISTENT: &str = "urn:oasis:names:tc:SAML:2.0:nameid-format:persistent";
+pub const NAME_ID_EMAIL: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:emailAddress";
+pub const NAME_ID_UNSPECIFIED: &str = "urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified";
+
+/// Inflated messages larger than this are refused, deflate bombs stay small on the wire.
+const MAX_MESSAGE_SIZE: u64 = 256 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SamlError {
+    #[error("invalid encoding")]
+    Encoding,
+    #[error("invalid xml: {0}")]
+    Xml(#[from] roxmltree::Error),
+    #[error("{0}")]
+    Invalid(&'static str),
+    /// The other side answered, but not with success
+    #[error("status {0}")]
+    Status(String),
+}
+
+/// A fresh message ID. IDs have to be NCNames, so they can't start with a digit.
+pub fn generate_id() -> String {
+    format!("_{}", crate::crypto::generate_token())
+}
+
+/// Timestamps are always UTC without fractions.
+pub fn instant(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%dT%H:%M:%SZ").to_string()
+}
+
+/// Decodes a message sent with the HTTP-POST binding.
+pub fn decode_post(value: &str) -> Result<String, SamlError> {
+    let bytes = BASE64_STANDARD
+        .decode(value.split_whitespace().collect::<String>())
+        .map_err(|_| SamlError::Encoding)?;
+    String::from_utf8(bytes).map_err(|_| SamlError::Encoding)
+}
+
+/// Decodes a message sent with the HTTP-Redirect binding, deflated before being base64 encoded.
+pub fn decode_redirect(value: &str) -> Result<String, SamlError> {
+    let bytes = BASE64_STANDARD
+        .decode(value)
+        .map_err(|_| SamlError::Encoding)?;
+
+    let mut xml = String::new();
+    DeflateDecoder::new(bytes.as_slice())
+        .take(MAX_MESSAGE_SIZE)
+        .read_to_string(&mut xml)
+        .map_err(|_| SamlError::Encoding)?;
+    Ok(xml)
+}
+
+/// Encodes a message for the HTTP-Redirect binding.
+pub fn encode_redirect(xml: &str) -> String {
+    let mut deflated = DeflateEncoder::new(Vec::new(), Compression::default());
+    deflated
+        .write_all(xml.as_bytes())
+        .expect("writing to a vec can't fail");
+    BASE64_STANDARD.encode(deflated.finish().unwrap_or_default())
+}
+
+/// Where to send a message with the HTTP-Redirect binding, signed over the query string. (SAML Bindings section 3.4.4.1)
+pub fn redirect_url(
+    key: &SamlKey,
+    endpoint: &str,
+    parameter: &str,
+    xml: &str,
+    relay_state: Option<&str>,
+) -> String {
+    let message = encode_redirect(xml);
+
+    let encode =
+        |value: &str| url::form_urlencoded::byte_serialize(value.as_bytes()).collect::<String>();
+    let mut query = format!("{parameter}={}", encode(&message));
+    if let Some(relay_state) = relay_state {
+        query.push_str(&format!("&RelayState={}", encode(relay_state)));
+    }
+    query.push_str(&format!("&SigAlg={}", encode(RSA_SHA256)));
+    let signature = BASE64_STANDARD.encode(key.sign(query.as_bytes()));
+    query.push_str(&format!("&Signature={}", encode(&signature)));
+
+    let separator = if endpoint.contains('?') { '&' } else { '?' };
+    format!("{endpoint}{separator}{query}")
+}
+
+/// Checks the signature of a message received with the HTTP-Redirect binding. The signed string is rebuilt from the
+/// query as it was received, re-encoding the values could change it.
+pub fn verify_redirect(raw_query: &str, parameter: &str, certificate: &[u8]) -> bool {
+    let raw = |name: &str| {
+        raw_query
+            .split('&')
+            .filter_map(|pair| pair.split_once('='))
+            .find(|(key, _)| *key == name)
+            .map(|(_, value)| value)
+    };
+    let decoded = |name: &str| {
+        url::form_urlencoded::parse(raw_query.as_bytes())
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    };
+
+    let (Some(message), Some(sig_alg), Some(signature)) =
+        (raw(parameter), raw("SigAlg"), decoded("Signature"))
+    else {
+        return false;
+    };
+    if decoded("SigAlg").as_deref() != Some(RSA_SHA256) {
+        return false;
+    }
+    let Ok(signature) = BASE64_STANDARD.decode(signature) else {
+        return false;
+    };
+
+    let mut signed = format!("{parameter}={message}");
+    if let Some(relay_state) = raw("RelayState") {
+        signed.push_str(&format!("&RelayState={relay_state}"));
+    }
+    signed.push_str(&format!("&SigAlg={sig_alg}"));
+
+    signature::verify_rsa_sha256(certificate, signed.as_bytes(), &signature)
+}
+
+fn parse_root<'a>(document: &'a Document<'a>, name: &str) -> Result<Node<'a, 'a>, SamlError> {
+    let root = document.root_element();
+    if !root.has_tag_name((PROTOCOL, name)) {
+        return Err(SamlError::Invalid("unexpected message"));
+    }
+    if root.attribute("Version") != Some("2.0") {
+        return Err(SamlError::Invalid("unsupported SAML version"));
+    }
+
+    Ok(root)
+}
+
+fn child_text<'a>(node: Node<'a, 'a>, namespace: &str, name: &str) -> Option<&'a str> {
+    node.children()
+        .find(|child| child.has_tag_name((namespace, name)))
+        .and_then(|child| child.text())
+        .map(str::trim)
+}
+
+fn required_id(node: Node) -> Result<String, SamlError> {
+    node.attribute("ID")
+        .map(String::from)
+        .ok_or(SamlError::Invalid("message has no ID"))
+}
+
+fn required_issuer(node: Node) -> Result<String, SamlError> {
+    child_text(node, ASSERTION, "Issuer")
+        .map(String::from)
+        .ok_or(SamlError::Invalid("message has no Issuer"))
+}
+
+/// A service provider asking for a user to be authenticated. (SAML Core section 3.4.1)
+#[derive(Debug)]
+pub struct AuthnRequest {
+    pub id: String,
+    pub issuer: String,
+    pub destination: Option<String>,
+    pub assertion_consumer_service_url: Option<String>,
+    pub protocol_binding: Option<String>,
+    pub is_passive: bool,
+}
+
+impl AuthnRequest {
+    pub fn parse(xml: &str) -> Result<Self, SamlError> {
+        let document = Document::parse(xml)?;
+        let root = parse_root(&document, "AuthnRequest")?;
+
+        Ok(Self {
+            id: required_id(root)?,
+            issuer: required_issuer(root)?,
+            destination: root.attribute("Destination").map(String::from),
+            assertion_consumer_service_url: root
+                .attribute("AssertionConsumerServiceURL")
+                .map(String::from),
+            protocol_binding: root.attribute("ProtocolBinding").map(String::from),
+            is_passive: matches!(root.attribute("IsPassive"), Some("true" | "1")),
+        })
+    }
+}
+
+/// Either side asking the other to end a user's session. (SAML Core section 3.7.1)
+#[derive(Debug)]
+pub struct LogoutRequest {
+    pub id: String,
+    pub issuer: String,
+    pub name_id: String,
+    pub session_indexes: Vec<String>,
+}
+
+impl LogoutRequest {
+    pub fn parse(xml: &str) -> Result<Self, SamlError> {
+        let document = Document::parse(xml)?;
+        let root = parse_root(&document, "LogoutRequest")?;
+
+        Ok(Self {
+            id: required_id(root)?,
+            issuer: required_issuer(root)?,
+            name_id: child_text(root, ASSERTION, "NameID")
+                .map(String::from)
+                .ok_or(SamlError::Invalid("logout request has no NameID"))?,
+            session_indexes: root
+                .children()
+                .filter(|child| child.has_tag_name((PROTOCOL, "SessionIndex")))
+                .filter_map(|child| child.text())
+                .map(|index| index.trim().to_string())
+                .collect(),
+        })
+    }
+}
+
+/// The answer to a logout request.
+#[derive(Debug)]
+pub struct LogoutResponse {
+    pub issuer: String,
+    pub in_response_to: Option<String>,
+    pub status: Option<String>,
+}
+
+impl LogoutResponse {
+    pub fn parse(xml: &str) -> Result<Self, SamlError> {
+        let document = Document::parse(xml)?;
+        let root = parse_root(&document, "LogoutResponse")?;
+
+        let status = root
+            .descendants()
+            .find(|node| node.has_tag_name((PROTOCOL, "StatusCode")))
+            .and_then(|node| node.attribute("Value"))
+            .map(String::from);
+
+        Ok(Self {
+            issuer: required_issuer(root)?,
+            in_response_to: root.attribute("InResponseTo").map(String::from),
+            status,
+        })
+    }
+}
@@ -0,0 +1,206 @@
+//! Messages this server sends, mostly as an identity provider.
+
+use crate::saml::signature::SamlKey;
+use crate::saml::xml::Element;
+use crate::saml::{ASSERTION, HTTP_POST, PROTOCOL, STATUS_SUCCESS, generate_id, instant};
+use chrono::{Duration, Utc};
+
+const BEARER: &str = "urn:oasis:names:tc:SAML:2.0:cm:bearer";
+const PASSWORD_PROTECTED_TRANSPORT: &str =
+    "urn:oasis:names:tc:SAML:2.0:ac:classes:PasswordProtectedTransport";
+const ATTRIBUTE_BASIC: &str = "urn:oasis:names:tc:SAML:2.0:attrname-format:basic";
+
+/// A protocol message and its ID.
+pub struct Message {
+    pub id: String,
+    pub element: Element,
+}
+
+impl Message {
+    /// Signs the message right after its Issuer, for the HTTP-POST binding.
+    pub fn signed(mut self, key: &SamlKey) -> String {
+        key.sign_element(&mut self.element, &self.id, 1);
+        self.element.to_canonical_string()
+    }
+
+    /// Unsigned, the HTTP-Redirect binding signs the query string instead.
+    pub fn unsigned(&self) -> String {
+        self.element.to_canonical_string()
+    }
+}
+
+fn message(name: &'static str, issuer: &str, destination: &str) -> Message {
+    let id = generate_id();
+    let element = Element::new(name)
+        .namespace("samlp", PROTOCOL)
+        .attribute("ID", id.clone())
+        .attribute("Version", "2.0")
+        .attribute("IssueInstant", instant(Utc::now()))
+        .attribute("Destination", destination)
+        .child(
+            Element::new("saml:Issuer")
+                .namespace("saml", ASSERTION)
+                .text(issuer),
+        );
+
+    Message { id, element }
+}
+
+fn status(code: &str) -> Element {
+    Element::new("samlp:Status").child(Element::new("samlp:StatusCode").attribute("Value", code))
+}
+
+/// What gets asserted about a user to a service provider.
+pub struct Assertion<'a> {
+    pub issuer: &'a str,
+    /// The service provider's assertion consumer service url
+    pub recipient: &'a str,
+    pub in_response_to: Option<&'a str>,
+    pub audience: &'a str,
+    pub name_id: &'a str,
+    pub name_id_format: &'a str,
+    pub session_index: &'a str,
+    pub attributes: Vec<(String, Vec<String>)>,
+    /// Seconds the assertion may be used for
+    pub lifetime: i64,
+}
+
+/// A successful response carrying the assertion. Both are signed, so service providers checking either are happy.
+pub fn response(key: &SamlKey, assertion: &Assertion) -> String {
+    let now = Utc::now();
+    let not_on_or_after = instant(now + Duration::seconds(assertion.lifetime));
+
+    let mut confirmation_data = Element::new("saml:SubjectConfirmationData")
+        .attribute("NotOnOrAfter", not_on_or_after.clone())
+        .attribute("Recipient", assertion.recipient);
+    if let Some(in_response_to) = assertion.in_response_to {
+        confirmation_data = confirmation_data.attribute("InResponseTo", in_response_to);
+    }
+
+    let mut attribute_statement = Element::new("saml:AttributeStatement");
+    for (name, values) in &assertion.attributes {
+        let mut attribute = Element::new("saml:Attribute")
+            .attribute("Name", name.clone())
+            .attribute("NameFormat", ATTRIBUTE_BASIC);
+        for value in values {
+            attribute = attribute.child(Element::new("saml:AttributeValue").text(value.clone()));
+        }
+        attribute_statement = attribute_statement.child(attribute);
+    }
+
+    let assertion_id = generate_id();
+    let mut signed_assertion = Element::new("saml:Assertion")
+        .namespace("saml", ASSERTION)
+        .attribute("ID", assertion_id.clone())
+        .attribute("Version", "2.0")
+        .attribute("IssueInstant", instant(now))
+        .child(Element::new("saml:Issuer").text(assertion.issuer))
+        .child(
+            Element::new("saml:Subject")
+                .child(
+                    Element::new("saml:NameID")
+                        .attribute("Format", assertion.name_id_format)
+                        .attribute("SPNameQualifier", assertion.audience)
+                        .text(assertion.name_id),
+                )
+                .child(
+                    Element::new("saml:SubjectConfirmation")
+                        .attribute("Method", BEARER)
+                        .child(confirmation_data),
+                ),
+        )
+        .child(
+            Element::new("saml:Conditions")
+                .attribute("NotBefore", instant(now))
+                .attribute("NotOnOrAfter", not_on_or_after)
+                .child(
+                    Element::new("saml:AudienceRestriction")
+                        .child(Element::new("saml:Audience").text(assertion.audience)),
+                ),
+        )
+        .child(
+            Element::new("saml:AuthnStatement")
+                .attribute("AuthnInstant", instant(now))
+                .attribute("SessionIndex", assertion.session_index)
+                .child(Element::new("saml:AuthnContext").child(
+                    Element::new("saml:AuthnContextClassRef").text(PASSWORD_PROTECTED_TRANSPORT),
+                )),
+        );
+    if !assertion.attributes.is_empty() {
+        signed_assertion = signed_assertion.child(attribute_statement);
+    }
+    key.sign_element(&mut signed_assertion, &assertion_id, 1);
+
+    let mut response = message("samlp:Response", assertion.issuer, assertion.recipient);
+    if let Some(in_response_to) = assertion.in_response_to {
+        response.element = response.element.attribute("InResponseTo", in_response_to);
+    }
+    response.element = response
+        .element
+        .child(status(STATUS_SUCCESS))
+        .child(signed_assertion);
+    response.signed(key)
+}
+
+/// A response telling the service provider the user wasn't authenticated, and why.
+pub fn error_response(
+    key: &SamlKey,
+    issuer: &str,
+    recipient: &str,
+    in_response_to: &str,
+    status_code: &str,
+) -> String {
+    let mut response = message("samlp:Response", issuer, recipient);
+    response.element = response
+        .element
+        .attribute("InResponseTo", in_response_to)
+        .child(status(status_code));
+    response.signed(key)
+}
+
+/// Asks a service provider to end the user's session there.
+pub fn logout_request(
+    issuer: &str,
+    destination: &str,
+    name_id: &str,
+    name_id_format: &str,
+    session_index: &str,
+) -> Message {
+    let mut request = message("samlp:LogoutRequest", issuer, destination);
+    request.element = request
+        .element
+        .child(
+            Element::new("saml:NameID")
+                .namespace("saml", ASSERTION)
+                .attribute("Format", name_id_format)
+                .text(name_id),
+        )
+        .child(Element::new("samlp:SessionIndex").text(session_index));
+    request
+}
+
+/// Tells a service provider its logout request was handled.
+pub fn logout_response(
+    issuer: &str,
+    destination: &str,
+    in_response_to: &str,
+    status_code: &str,
+) -> Message {
+    let mut response = message("samlp:LogoutResponse", issuer, destination);
+    response.element = response
+        .element
+        .attribute("InResponseTo", in_response_to)
+        .child(status(status_code));
+    response
+}
+
+/// Asks an upstream identity provider to authenticate the user, as a service provider. (SAML Core section 3.4.1)
+pub fn authn_request(issuer: &str, destination: &str, acs_url: &str) -> Message {
+    let mut request = message("samlp:AuthnRequest", issuer, destination);
+    request.element = request
+        .element
+        .attribute("AssertionConsumerServiceURL", acs_url)
+        .attribute("ProtocolBinding", HTTP_POST)
+        .child(Element::new("samlp:NameIDPolicy").attribute("AllowCreate", "true"));
+    request
+}
@@ -0,0 +1,277 @@
+use crate::saml::xml::Element;
+use crate::saml::{DS, EXC_C14N, RSA_SHA256, SHA256, SamlError, c14n};
+use anyhow::Context;
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use ring::rand::SystemRandom;
+use ring::signature::{
+    RSA_PKCS1_2048_8192_SHA256, RSA_PKCS1_SHA256, RsaKeyPair, UnparsedPublicKey,
+};
+use roxmltree::Node;
+use sha2::{Digest, Sha256};
+use std::path::Path;
+
+const ENVELOPED_SIGNATURE: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+
+/// The RSA key SAML messages are signed with, and the certificate service providers know it by.
+pub struct SamlKey {
+    key_pair: RsaKeyPair,
+    certificate: Vec<u8>,
+}
+
+impl SamlKey {
+    /// Loads a PEM private key, PKCS#8 or PKCS#1, and a PEM certificate. Something like
+    /// `openssl req -x509 -newkey rsa:3072 -nodes -days 3650 -subj /CN=meow_auth -keyout saml.key -out saml.crt`
+    /// makes both.
+    pub fn load(private_key_path: &Path, certificate_path: &Path) -> anyhow::Result<Self> {
+        let private_key = std::fs::read_to_string(private_key_path)
+            .with_context(|| format!("Failed reading {private_key_path:?}"))?;
+        let key_pair = match pem_to_der(&private_key, "PRIVATE KEY") {
+            Ok(der) => RsaKeyPair::from_pkcs8(&der),
+            Err(_) => RsaKeyPair::from_der(&pem_to_der(&private_key, "RSA PRIVATE KEY")?),
+        }
+        .map_err(|e| anyhow::anyhow!("Invalid RSA private key: {e}"))?;
+
+        let certificate = std::fs::read_to_string(certificate_path)
+            .with_context(|| format!("Failed reading {certificate_path:?}"))?;
+
+        Ok(Self {
+            key_pair,
+            certificate: pem_to_der(&certificate, "CERTIFICATE")?,
+        })
+    }
+
+    /// The certificate as it goes into metadata and `X509Certificate` elements.
+    pub fn certificate_base64(&self) -> String {
+        BASE64_STANDARD.encode(&self.certificate)
+    }
+
+    /// RSA-SHA256 signature of the data.
+    pub fn sign(&self, data: &[u8]) -> Vec<u8> {
+        let mut signature = vec![0; self.key_pair.public().modulus_len()];
+        self.key_pair
+            .sign(
+                &RSA_PKCS1_SHA256,
+                &SystemRandom::new(),
+                data,
+                &mut signature,
+            )
+            .expect("signing with an RSA key can't fail for a correctly sized buffer");
+        signature
+    }
+
+    /// Adds an enveloped signature over the element, referenced by its ID, as the child at the given index.
+    /// (XML Signature Syntax and Processing section 6.6.4)
+    pub fn sign_element(&self, element: &mut Element, id: &str, index: usize) {
+        // the enveloped transform takes the signature out again, so the digest is over the element without it
+        let digest = BASE64_STANDARD.encode(Sha256::digest(element.to_canonical_string()));
+
+        let signed_info = || {
+            Element::new("ds:SignedInfo")
+                .child(Element::new("ds:CanonicalizationMethod").attribute("Algorithm", EXC_C14N))
+                .child(Element::new("ds:SignatureMethod").attribute("Algorithm", RSA_SHA256))
+                .child(
+                    Element::new("ds:Reference")
+                        .attribute("URI", format!("#{id}"))
+                        .child(
+                            Element::new("ds:Transforms")
+                                .child(
+                                    Element::new("ds:Transform")
+                                        .attribute("Algorithm", ENVELOPED_SIGNATURE),
+                                )
+                                .child(
+                                    Element::new("ds:Transform").attribute("Algorithm", EXC_C14N),
+                                ),
+                        )
+                        .child(Element::new("ds:DigestMethod").attribute("Algorithm", SHA256))
+                        .child(Element::new("ds:DigestValue").text(digest.clone())),
+                )
+        };
+
+        // canonicalized on its own, SignedInfo declares the namespace it inherits inside the signature
+        let canonical = signed_info().namespace("ds", DS).to_canonical_string();
+        let signature_value = BASE64_STANDARD.encode(self.sign(canonical.as_bytes()));
+
+        let signature = Element::new("ds:Signature")
+            .namespace("ds", DS)
+            .child(signed_info())
+            .child(Element::new("ds:SignatureValue").text(signature_value))
+            .child(
+                Element::new("ds:KeyInfo").child(
+                    Element::new("ds:X509Data")
+                        .child(Element::new("ds:X509Certificate").text(self.certificate_base64())),
+                ),
+            );
+        element.insert_child(index, signature);
+    }
+}
+
+/// Whether an element carries an enveloped signature of its own.
+pub fn is_signed(element: Node) -> bool {
+    element
+        .children()
+        .any(|child| child.has_tag_name((DS, "Signature")))
+}
+
+/// Checks the enveloped signature of an element against the certificates it may be signed with. Only the exact shape
+/// this server signs with is accepted: one reference to the element itself, the enveloped and exclusive
+/// canonicalization transforms, SHA-256 digests and RSA-SHA256 signatures. Comments and processing instructions in
+/// the element are refused outright, canonicalization drops them, so they could change what is read without breaking
+/// the signature.
+pub fn verify_enveloped(element: Node, certificates: &[Vec<u8>]) -> Result<(), SamlError> {
+    let invalid = SamlError::Invalid;
+
+    if element
+        .descendants()
+        .any(|node| node.is_comment() || node.is_pi())
+    {
+        return Err(invalid(
+            "signed element has comments or processing instructions",
+        ));
+    }
+
+    let mut signatures = element
+        .children()
+        .filter(|child| child.has_tag_name((DS, "Signature")));
+    let signature = signatures.next().ok_or(invalid("element is not signed"))?;
+    if signatures.next().is_some() {
+        return Err(invalid("element has more than one signature"));
+    }
+
+    // the referenced ID has to be the element's, and nothing else in the document may carry it
+    let id = element
+        .attribute("ID")
+        .ok_or(invalid("signed element has no ID"))?;
+    let with_id = element
+        .document()
+        .descendants()
+        .filter(|node| node.attribute("ID") == Some(id))
+        .count();
+    if with_id != 1 {
+        return Err(invalid("ID of the signed element is not unique"));
+    }
+
+    let [signed_info, signature_value, ..] = elements(signature)[..] else {
+        return Err(invalid("incomplete Signature"));
+    };
+    if !signed_info.has_tag_name((DS, "SignedInfo"))
+        || !signature_value.has_tag_name((DS, "SignatureValue"))
+    {
+        return Err(invalid("incomplete Signature"));
+    }
+
+    let [canonicalization, signature_method, reference] = elements(signed_info)[..] else {
+        return Err(invalid("SignedInfo has to hold exactly one Reference"));
+    };
+    if !canonicalization.has_tag_name((DS, "CanonicalizationMethod"))
+        || canonicalization.attribute("Algorithm") != Some(EXC_C14N)
+    {
+        return Err(invalid("unsupported CanonicalizationMethod"));
+    }
+    if !signature_method.has_tag_name((DS, "SignatureMethod"))
+        || signature_method.attribute("Algorithm") != Some(RSA_SHA256)
+    {
+        return Err(invalid("unsupported SignatureMethod"));
+    }
+    if !reference.has_tag_name((DS, "Reference"))
+        || reference
+            .attribute("URI")
+            .and_then(|uri| uri.strip_prefix('#'))
+            != Some(id)
+    {
+        return Err(invalid("Reference is not to the signed element"));
+    }
+
+    let [transforms, digest_method, digest_value] = elements(reference)[..] else {
+        return Err(invalid("incomplete Reference"));
+    };
+    let [enveloped, exclusive] = elements(transforms)[..] else {
+        return Err(invalid("unsupported Transforms"));
+    };
+    if !transforms.has_tag_name((DS, "Transforms"))
+        || enveloped.attribute("Algorithm") != Some(ENVELOPED_SIGNATURE)
+        || exclusive.attribute("Algorithm") != Some(EXC_C14N)
+    {
+        return Err(invalid("unsupported Transforms"));
+    }
+    if !digest_method.has_tag_name((DS, "DigestMethod"))
+        || digest_method.attribute("Algorithm") != Some(SHA256)
+        || !digest_value.has_tag_name((DS, "DigestValue"))
+    {
+        return Err(invalid("unsupported DigestMethod"));
+    }
+
+    let canonical = c14n::canonicalize(
+        element,
+        Some(signature.id()),
+        &inclusive_prefixes(exclusive),
+    );
+    let digest = base64_content(digest_value)?;
+    if Sha256::digest(canonical).as_slice() != digest.as_slice() {
+        return Err(invalid("digest does not match"));
+    }
+
+    let canonical = c14n::canonicalize(signed_info, None, &inclusive_prefixes(canonicalization));
+    let signature_value = base64_content(signature_value)?;
+    if !certificates
+        .iter()
+        .any(|certificate| verify_rsa_sha256(certificate, canonical.as_bytes(), &signature_value))
+    {
+        return Err(invalid("signature does not verify"));
+    }
+
+    Ok(())
+}
+
+/// Child elements, skipping the whitespace between them.
+fn elements<'a>(node: Node<'a, 'a>) -> Vec<Node<'a, 'a>> {
+    node.children().filter(Node::is_element).collect()
+}
+
+/// The prefix list of an exclusive canonicalization algorithm's InclusiveNamespaces.
+fn inclusive_prefixes<'a>(algorithm: Node<'a, 'a>) -> Vec<&'a str> {
+    algorithm
+        .children()
+        .find(|child| child.has_tag_name((EXC_C14N, "InclusiveNamespaces")))
+        .and_then(|child| child.attribute("PrefixList"))
+        .map(|list| list.split_whitespace().collect())
+        .unwrap_or_default()
+}
+
+fn base64_content(node: Node) -> Result<Vec<u8>, SamlError> {
+    let text: String = node
+        .text()
+        .unwrap_or_default()
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect();
+    BASE64_STANDARD
+        .decode(text)
+        .map_err(|_| SamlError::Encoding)
+}
+
+/// Checks an RSA-SHA256 signature made with the key of a DER certificate.
+pub fn verify_rsa_sha256(certificate: &[u8], data: &[u8], signature: &[u8]) -> bool {
+    let Ok((_, certificate)) = x509_parser::parse_x509_certificate(certificate) else {
+        return false;
+    };
+
+    let public_key = &certificate.public_key().subject_public_key.data;
+    UnparsedPublicKey::new(&RSA_PKCS1_2048_8192_SHA256, public_key)
+        .verify(data, signature)
+        .is_ok()
+}
+
+/// The DER contents of the first PEM block with the given label.
+pub fn pem_to_der(pem: &str, label: &str) -> anyhow::Result<Vec<u8>> {
+    let begin = format!("-----BEGIN {label}-----");
+    let end = format!("-----END {label}-----");
+    let body = pem
+        .split_once(&begin)
+        .and_then(|(_, rest)| rest.split_once(&end))
+        .map(|(body, _)| body)
+        .with_context(|| format!("No {label} in the PEM file"))?;
+
+    let base64: String = body.chars().filter(|c| !c.is_whitespace()).collect();
+    Ok(BASE64_STANDARD.decode(base64)?)
+}
@@ -0,0 +1,123 @@
+//! A small writer for the XML this server sends. It only writes exclusive canonical XML, so what gets signed is
+//! exactly what gets sent. (Exclusive XML Canonicalization 1.0)
+//!
+//! Namespaces have to be declared on the outermost element using them, and nowhere below it, for the output to be
+//! canonical. Elements never hold both text and children.
+
+pub enum Node {
+    Element(Element),
+    Text(String),
+}
+
+pub struct Element {
+    name: &'static str,
+    namespaces: Vec<(&'static str, &'static str)>,
+    attributes: Vec<(&'static str, String)>,
+    children: Vec<Node>,
+}
+
+impl Element {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            namespaces: Vec::new(),
+            attributes: Vec::new(),
+            children: Vec::new(),
+        }
+    }
+
+    pub fn namespace(mut self, prefix: &'static str, uri: &'static str) -> Self {
+        self.namespaces.push((prefix, uri));
+        self
+    }
+
+    pub fn attribute(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.attributes.push((name, value.into()));
+        self
+    }
+
+    pub fn child(mut self, child: Element) -> Self {
+        self.children.push(Node::Element(child));
+        self
+    }
+
+    pub fn text(mut self, text: impl Into<String>) -> Self {
+        self.children.push(Node::Text(text.into()));
+        self
+    }
+
+    pub fn insert_child(&mut self, index: usize, child: Element) {
+        self.children.insert(index, Node::Element(child));
+    }
+
+    pub fn to_canonical_string(&self) -> String {
+        let mut out = String::new();
+        self.write(&mut out);
+        out
+    }
+
+    fn write(&self, out: &mut String) {
+        out.push('<');
+        out.push_str(self.name);
+
+        // namespaces come first, by prefix, then the attributes by name. None of ours have a namespace of their own
+        let mut namespaces = self.namespaces.clone();
+        namespaces.sort_unstable();
+        for (prefix, uri) in namespaces {
+            out.push_str(" xmlns:");
+            out.push_str(prefix);
+            out.push_str("=\"");
+            escape_attribute(uri, out);
+            out.push('"');
+        }
+
+        let mut attributes: Vec<_> = self.attributes.iter().collect();
+        attributes.sort_unstable_by_key(|(name, _)| *name);
+        for (name, value) in attributes {
+            out.push(' ');
+            out.push_str(name);
+            out.push_str("=\"");
+            escape_attribute(value, out);
+            out.push('"');
+        }
+        out.push('>');
+
+        for child in &self.children {
+            match child {
+                Node::Element(element) => element.write(out),
+                Node::Text(text) => escape_text(text, out),
+            }
+        }
+
+        // canonical XML has no empty element tags
+        out.push_str("</");
+        out.push_str(self.name);
+        out.push('>');
+    }
+}
+
+fn escape_attribute(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '"' => out.push_str("&quot;"),
+            '\t' => out.push_str("&#x9;"),
+            '\n' => out.push_str("&#xA;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn escape_text(value: &str, out: &mut String) {
+    for c in value.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '\r' => out.push_str("&#xD;"),
+            c => out.push(c),
+        }
+    }
+}
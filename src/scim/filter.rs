@@ -0,0 +1,421 @@
+//! Filters of list requests and the paths of PATCH operations. (RFC 7644 sections 3.4.2.2 and 3.5.2)
+
+use crate::scim::{ScimError, key, unqualified};
+use serde_json::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Co,
+    Sw,
+    Ew,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+}
+
+impl Operator {
+    fn parse(word: &str) -> Option<Self> {
+        Some(match word.to_ascii_lowercase().as_str() {
+            "eq" => Self::Eq,
+            "ne" => Self::Ne,
+            "co" => Self::Co,
+            "sw" => Self::Sw,
+            "ew" => Self::Ew,
+            "gt" => Self::Gt,
+            "ge" => Self::Ge,
+            "lt" => Self::Lt,
+            "le" => Self::Le,
+            _ => return None,
+        })
+    }
+
+    /// Strings are compared without regard to case, no attribute this server has is case exact besides ids.
+    fn compare(self, actual: &Value, expected: &Value) -> bool {
+        if let (Value::String(actual), Value::String(expected)) = (actual, expected) {
+            let (actual, expected) = (actual.to_lowercase(), expected.to_lowercase());
+            return match self {
+                Self::Eq => actual == expected,
+                Self::Ne => actual != expected,
+                Self::Co => actual.contains(&expected),
+                Self::Sw => actual.starts_with(&expected),
+                Self::Ew => actual.ends_with(&expected),
+                // timestamps are all in the same format, so they order like their strings
+                Self::Gt => actual > expected,
+                Self::Ge => actual >= expected,
+                Self::Lt => actual < expected,
+                Self::Le => actual <= expected,
+            };
+        }
+
+        match self {
+            Self::Eq => actual == expected,
+            Self::Ne => actual != expected,
+            Self::Gt | Self::Ge | Self::Lt | Self::Le => {
+                let (Some(actual), Some(expected)) = (actual.as_f64(), expected.as_f64()) else {
+                    return false;
+                };
+                match self {
+                    Self::Gt => actual > expected,
+                    Self::Ge => actual >= expected,
+                    Self::Lt => actual < expected,
+                    _ => actual <= expected,
+                }
+            }
+            Self::Co | Self::Sw | Self::Ew => false,
+        }
+    }
+}
+
+/// An attribute, or a sub-attribute of a complex one like `name.givenName`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttributePath {
+    pub attribute: String,
+    pub sub_attribute: Option<String>,
+}
+
+impl AttributePath {
+    fn parse(word: &str) -> Self {
+        let word = unqualified(word);
+        match word.split_once('.') {
+            Some((attribute, sub_attribute)) => Self {
+                attribute: attribute.to_string(),
+                sub_attribute: Some(sub_attribute.to_string()),
+            },
+            None => Self {
+                attribute: word.to_string(),
+                sub_attribute: None,
+            },
+        }
+    }
+
+    /// The values the path points at. Multi-valued attributes stand for every one of their values, and complex
+    /// values without a sub-attribute for their `value`.
+    fn values<'a>(&self, resource: &'a Value) -> Vec<&'a Value> {
+        let Some(value) = get(resource, &self.attribute) else {
+            return Vec::new();
+        };
+        let values = match value {
+            Value::Array(values) => values.iter().collect(),
+            value => vec![value],
+        };
+
+        let sub_attribute = self.sub_attribute.as_deref();
+        values
+            .into_iter()
+            .filter_map(|value| match (value, sub_attribute) {
+                (Value::Object(_), Some(sub_attribute)) => get(value, sub_attribute),
+                (Value::Object(_), None) => get(value, "value"),
+                (_, Some(_)) => None,
+                (value, None) => Some(value),
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Present(AttributePath),
+    Compare(AttributePath, Operator, Value),
+    /// `emails[type eq "work"]`, matching when any value of the attribute does
+    ValuePath(String, Box<Filter>),
+}
+
+impl Filter {
+    pub fn parse(input: &str) -> Result<Self, ScimError> {
+        let mut parser = Parser::new(input)?;
+        let filter = parser.or()?;
+        match parser.next() {
+            None => Ok(filter),
+            Some(token) => Err(ScimError::InvalidFilter(format!(
+                "unexpected {token:?} in filter"
+            ))),
+        }
+    }
+
+    pub fn matches(&self, resource: &Value) -> bool {
+        match self {
+            Self::And(left, right) => left.matches(resource) && right.matches(resource),
+            Self::Or(left, right) => left.matches(resource) || right.matches(resource),
+            Self::Not(filter) => !filter.matches(resource),
+            Self::Present(path) => path.values(resource).into_iter().any(|value| match value {
+                Value::Null => false,
+                Value::String(value) => !value.is_empty(),
+                Value::Array(values) => !values.is_empty(),
+                _ => true,
+            }),
+            Self::Compare(path, operator, expected) => path
+                .values(resource)
+                .into_iter()
+                .any(|actual| operator.compare(actual, expected)),
+            Self::ValuePath(attribute, filter) => match get(resource, attribute) {
+                Some(Value::Array(values)) => values.iter().any(|value| filter.matches(value)),
+                Some(value @ Value::Object(_)) => filter.matches(value),
+                _ => false,
+            },
+        }
+    }
+
+    /// The string the filter compares an attribute with `eq` to, when that is all it does. Lists can look those up
+    /// in the database rather than reading every resource of the tenant to filter them.
+    pub fn equality(&self, attribute: &str) -> Option<&str> {
+        match self {
+            Self::Compare(path, Operator::Eq, Value::String(value))
+                if path.sub_attribute.is_none()
+                    && path.attribute.eq_ignore_ascii_case(attribute) =>
+            {
+                Some(value)
+            }
+            _ => None,
+        }
+    }
+
+    /// The attributes the filter requires to equal something, for creating the value a PATCH operation targets
+    /// when there is none yet.
+    pub fn equalities(&self) -> Vec<(&str, &Value)> {
+        match self {
+            Self::And(left, right) => [left.equalities(), right.equalities()].concat(),
+            Self::Compare(path, Operator::Eq, value) if path.sub_attribute.is_none() => {
+                vec![(path.attribute.as_str(), value)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// What a PATCH operation targets: `members`, `name.givenName` or `emails[type eq "work"].value`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PatchPath {
+    pub attribute: String,
+    pub filter: Option<Filter>,
+    pub sub_attribute: Option<String>,
+}
+
+impl PatchPath {
+    pub fn parse(input: &str) -> Result<Self, ScimError> {
+        let invalid = || ScimError::InvalidPath(format!("invalid path {input}"));
+        let mut parser = Parser::new(input).map_err(|_| invalid())?;
+
+        let Some(Token::Word(word)) = parser.next() else {
+            return Err(invalid());
+        };
+        let path = AttributePath::parse(&word);
+        if parser.peek() != Some(&Token::OpenBracket) {
+            return match parser.next() {
+                None => Ok(Self {
+                    attribute: path.attribute,
+                    filter: None,
+                    sub_attribute: path.sub_attribute,
+                }),
+                Some(_) => Err(invalid()),
+            };
+        }
+        if path.sub_attribute.is_some() {
+            return Err(invalid());
+        }
+
+        parser.next();
+        let filter = parser.or().map_err(|_| invalid())?;
+        if parser.next() != Some(Token::CloseBracket) {
+            return Err(invalid());
+        }
+        let sub_attribute = match parser.next() {
+            None => None,
+            Some(Token::Word(word)) if word.len() > 1 && word.starts_with('.') => {
+                Some(word[1..].to_string())
+            }
+            Some(_) => return Err(invalid()),
+        };
+        if parser.next().is_some() {
+            return Err(invalid());
+        }
+
+        Ok(Self {
+            attribute: path.attribute,
+            filter: Some(filter),
+            sub_attribute,
+        })
+    }
+}
+
+/// Looks up an attribute of an object, whatever the case of its name.
+fn get<'a>(resource: &'a Value, name: &str) -> Option<&'a Value> {
+    let object = resource.as_object()?;
+    object.get(key(object, name)?)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Open,
+    Close,
+    OpenBracket,
+    CloseBracket,
+    Word(String),
+    String(String),
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    position: usize,
+}
+
+impl Parser {
+    fn new(input: &str) -> Result<Self, ScimError> {
+        let mut tokens = Vec::new();
+        let mut chars = input.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            match c {
+                c if c.is_whitespace() => {}
+                '(' => tokens.push(Token::Open),
+                ')' => tokens.push(Token::Close),
+                '[' => tokens.push(Token::OpenBracket),
+                ']' => tokens.push(Token::CloseBracket),
+                '"' => {
+                    // JSON string rules, escapes and all
+                    let mut escaped = false;
+                    let mut end = None;
+                    for (index, c) in chars.by_ref() {
+                        match c {
+                            _ if escaped => escaped = false,
+                            '\\' => escaped = true,
+                            '"' => {
+                                end = Some(index);
+                                break;
+                            }
+                            _ => {}
+                        }
+                    }
+                    let end = end.ok_or_else(|| {
+                        ScimError::InvalidFilter("unterminated string in filter".into())
+                    })?;
+                    let value = serde_json::from_str(&input[start..=end])
+                        .map_err(|_| ScimError::InvalidFilter("invalid string in filter".into()))?;
+                    tokens.push(Token::String(value));
+                }
+                _ => {
+                    let mut end = input.len();
+                    while let Some(&(index, c)) = chars.peek() {
+                        if c.is_whitespace() || "()[]\"".contains(c) {
+                            end = index;
+                            break;
+                        }
+                        chars.next();
+                    }
+                    tokens.push(Token::Word(input[start..end].to_string()));
+                }
+            }
+        }
+
+        Ok(Self {
+            tokens,
+            position: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(word)) if word.eq_ignore_ascii_case(keyword) => {
+                self.position += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), ScimError> {
+        match self.next() {
+            Some(token) if token == expected => Ok(()),
+            token => Err(ScimError::InvalidFilter(format!(
+                "expected {expected:?} in filter, got {token:?}"
+            ))),
+        }
+    }
+
+    fn or(&mut self) -> Result<Filter, ScimError> {
+        let mut filter = self.and()?;
+        while self.keyword("or") {
+            filter = Filter::Or(Box::new(filter), Box::new(self.and()?));
+        }
+        Ok(filter)
+    }
+
+    fn and(&mut self) -> Result<Filter, ScimError> {
+        let mut filter = self.unary()?;
+        while self.keyword("and") {
+            filter = Filter::And(Box::new(filter), Box::new(self.unary()?));
+        }
+        Ok(filter)
+    }
+
+    fn unary(&mut self) -> Result<Filter, ScimError> {
+        if self.keyword("not") {
+            self.expect(Token::Open)?;
+            let filter = self.or()?;
+            self.expect(Token::Close)?;
+            return Ok(Filter::Not(Box::new(filter)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.next();
+            let filter = self.or()?;
+            self.expect(Token::Close)?;
+            return Ok(filter);
+        }
+        self.attribute_expression()
+    }
+
+    fn attribute_expression(&mut self) -> Result<Filter, ScimError> {
+        let Some(Token::Word(word)) = self.next() else {
+            return Err(ScimError::InvalidFilter(
+                "expected an attribute in filter".into(),
+            ));
+        };
+
+        if self.peek() == Some(&Token::OpenBracket) {
+            self.next();
+            let filter = self.or()?;
+            self.expect(Token::CloseBracket)?;
+            return Ok(Filter::ValuePath(
+                unqualified(&word).to_string(),
+                Box::new(filter),
+            ));
+        }
+
+        let path = AttributePath::parse(&word);
+        if self.keyword("pr") {
+            return Ok(Filter::Present(path));
+        }
+        let operator = match self.next() {
+            Some(Token::Word(word)) => Operator::parse(&word),
+            _ => None,
+        }
+        .ok_or_else(|| ScimError::InvalidFilter(format!("expected an operator after {word}")))?;
+
+        let value = match self.next() {
+            Some(Token::String(value)) => Value::String(value),
+            // true, false, null and numbers
+            Some(Token::Word(word)) => serde_json::from_str(&word)
+                .map_err(|_| ScimError::InvalidFilter(format!("invalid value {word}")))?,
+            _ => {
+                return Err(ScimError::InvalidFilter(format!(
+                    "expected a value after {word}"
+                )));
+            }
+        };
+        Ok(Filter::Compare(path, operator, value))
+    }
+}
@@ -0,0 +1,179 @@
+//! SCIM 2.0 filters and PATCH operations, applied to the JSON representation of resources. (RFC 7644)
+
+pub mod filter;
+pub mod patch;
+
+pub const USER_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:User";
+pub const GROUP_SCHEMA: &str = "urn:ietf:params:scim:schemas:core:2.0:Group";
+pub const LIST_RESPONSE: &str = "urn:ietf:params:scim:api:messages:2.0:ListResponse";
+pub const PATCH_OP: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+pub const ERROR: &str = "urn:ietf:params:scim:api:messages:2.0:Error";
+pub const SERVICE_PROVIDER_CONFIG: &str =
+    "urn:ietf:params:scim:schemas:core:2.0:ServiceProviderConfig";
+
+/// Prefix of attribute names qualified with their core schema.
+const CORE_SCHEMAS: &str = "urn:ietf:params:scim:schemas:core:2.0:";
+
+/// A request the service provider can't carry out, with the `scimType` it is answered with. (RFC 7644 section 3.12)
+#[derive(Debug, thiserror::Error)]
+pub enum ScimError {
+    #[error("{0}")]
+    InvalidFilter(String),
+    #[error("{0}")]
+    InvalidPath(String),
+    #[error("{0}")]
+    InvalidValue(String),
+    #[error("{0}")]
+    InvalidSyntax(String),
+    #[error("{0}")]
+    NoTarget(String),
+    #[error("{0}")]
+    Uniqueness(String),
+}
+
+impl ScimError {
+    pub fn scim_type(&self) -> &'static str {
+        match self {
+            Self::InvalidFilter(_) => "invalidFilter",
+            Self::InvalidPath(_) => "invalidPath",
+            Self::InvalidValue(_) => "invalidValue",
+            Self::InvalidSyntax(_) => "invalidSyntax",
+            Self::NoTarget(_) => "noTarget",
+            Self::Uniqueness(_) => "uniqueness",
+        }
+    }
+}
+
+/// An attribute name without the core schema it may be qualified with, `urn:...:2.0:User:userName` is `userName`.
+fn unqualified(name: &str) -> &str {
+    match name.get(..CORE_SCHEMAS.len()) {
+        Some(prefix) if prefix.eq_ignore_ascii_case(CORE_SCHEMAS) => name[CORE_SCHEMAS.len()..]
+            .split_once(':')
+            .map_or(name, |(_, attribute)| attribute),
+        _ => name,
+    }
+}
+
+/// Attribute names aren't case sensitive. (RFC 7643 section 2.1)
+fn key<'a>(object: &'a serde_json::Map<String, serde_json::Value>, name: &str) -> Option<&'a str> {
+    object
+        .keys()
+        .find(|key| key.eq_ignore_ascii_case(name))
+        .map(String::as_str)
+}
+
+/// Renames the attributes of a resource, and those of the values it holds, to how the schema spells them, so they
+/// can be deserialized whatever case the client sent them in.
+pub fn canonicalize(resource: &mut serde_json::Map<String, serde_json::Value>, names: &[&str]) {
+    let renames: Vec<(String, &str)> = resource
+        .keys()
+        .filter_map(|key| {
+            let unqualified = unqualified(key);
+            names
+                .iter()
+                .find(|name| name.eq_ignore_ascii_case(unqualified) && **name != key)
+                .map(|name| (key.clone(), *name))
+        })
+        .collect();
+    for (key, name) in renames {
+        if let Some(value) = resource.remove(&key) {
+            resource.insert(name.to_string(), value);
+        }
+    }
+
+    for value in resource.values_mut() {
+        match value {
+            serde_json::Value::Object(object) => canonicalize(object, names),
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    if let serde_json::Value::Object(object) = value {
+                        canonicalize(object, names);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Trims a representation down to the attributes a request asked for with `attributes` or `excludedAttributes`.
+/// `id` and `schemas` are always returned. (RFC 7644 section 3.9)
+pub fn project(
+    resource: &mut serde_json::Map<String, serde_json::Value>,
+    attributes: Option<&str>,
+    excluded_attributes: Option<&str>,
+) {
+    const ALWAYS: [&str; 2] = ["id", "schemas"];
+    let paths = |list: &str| -> Vec<(String, Option<String>)> {
+        list.split(',')
+            .map(|path| unqualified(path.trim()))
+            .filter(|path| !path.is_empty())
+            .map(|path| match path.split_once('.') {
+                Some((attribute, sub_attribute)) => {
+                    (attribute.to_string(), Some(sub_attribute.to_string()))
+                }
+                None => (path.to_string(), None),
+            })
+            .collect()
+    };
+
+    if let Some(attributes) = attributes.filter(|attributes| !attributes.trim().is_empty()) {
+        let wanted = paths(attributes);
+        resource.retain(|name, _| {
+            ALWAYS.contains(&name.as_str())
+                || wanted
+                    .iter()
+                    .any(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+        });
+        for (name, value) in resource.iter_mut() {
+            let sub_attributes: Vec<&str> = wanted
+                .iter()
+                .filter(|(attribute, _)| attribute.eq_ignore_ascii_case(name))
+                .map(|(_, sub_attribute)| sub_attribute.as_deref())
+                .collect::<Option<_>>()
+                .unwrap_or_default();
+            if !sub_attributes.is_empty() {
+                retain_sub_attributes(value, |sub_attribute| {
+                    sub_attributes
+                        .iter()
+                        .any(|wanted| wanted.eq_ignore_ascii_case(sub_attribute))
+                });
+            }
+        }
+    }
+
+    if let Some(excluded) = excluded_attributes {
+        for (attribute, sub_attribute) in paths(excluded) {
+            let Some(name) = key(resource, &attribute).map(str::to_string) else {
+                continue;
+            };
+            match sub_attribute {
+                _ if ALWAYS.contains(&name.as_str()) => {}
+                None => {
+                    resource.remove(&name);
+                }
+                Some(sub_attribute) => {
+                    if let Some(value) = resource.get_mut(&name) {
+                        retain_sub_attributes(value, |name| {
+                            !name.eq_ignore_ascii_case(&sub_attribute)
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn retain_sub_attributes(value: &mut serde_json::Value, keep: impl Fn(&str) -> bool) {
+    match value {
+        serde_json::Value::Object(object) => object.retain(|name, _| keep(name)),
+        serde_json::Value::Array(values) => {
+            for value in values {
+                if let serde_json::Value::Object(object) = value {
+                    object.retain(|name, _| keep(name));
+                }
+            }
+        }
+        _ => {}
+    }
+}
@@ -0,0 +1,242 @@
+//! PATCH operations, applied to the JSON representation of a resource. (RFC 7644 section 3.5.2)
+//!
+//! Identity providers don't all read the spec the same way, so this is lenient where it can be without guessing:
+//! operation names in any case, values without a path holding dotted attribute names, and filters that match
+//! nothing creating the value they describe.
+
+use crate::scim::filter::{Filter, PatchPath};
+use crate::scim::{PATCH_OP, ScimError, key};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+#[derive(Debug, Deserialize)]
+pub struct PatchRequest {
+    pub schemas: Vec<String>,
+    #[serde(rename = "Operations")]
+    pub operations: Vec<PatchOperation>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PatchOperation {
+    pub op: String,
+    pub path: Option<String>,
+    pub value: Option<Value>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op {
+    Add,
+    Replace,
+    Remove,
+}
+
+/// Applies every operation of the request in order. Nothing is applied unless all of them are.
+pub fn apply(resource: &mut Map<String, Value>, request: &PatchRequest) -> Result<(), ScimError> {
+    if !request.schemas.iter().any(|schema| schema == PATCH_OP) {
+        return Err(ScimError::InvalidSyntax(format!(
+            "schemas must contain {PATCH_OP}"
+        )));
+    }
+
+    let mut patched = resource.clone();
+    for operation in &request.operations {
+        let op = match operation.op.to_ascii_lowercase().as_str() {
+            "add" => Op::Add,
+            "replace" => Op::Replace,
+            "remove" => Op::Remove,
+            op => return Err(ScimError::InvalidSyntax(format!("unknown operation {op}"))),
+        };
+        apply_operation(
+            &mut patched,
+            op,
+            operation.path.as_deref(),
+            &operation.value,
+        )?;
+    }
+    *resource = patched;
+    Ok(())
+}
+
+fn apply_operation(
+    resource: &mut Map<String, Value>,
+    op: Op,
+    path: Option<&str>,
+    value: &Option<Value>,
+) -> Result<(), ScimError> {
+    let path = match path {
+        Some(path) => PatchPath::parse(path)?,
+        None if op == Op::Remove => {
+            return Err(ScimError::NoTarget("remove needs a path".into()));
+        }
+        // without a path the value holds the attributes to add or replace, keyed by their paths
+        None => {
+            let Some(Value::Object(values)) = value else {
+                return Err(ScimError::InvalidValue(
+                    "an operation without a path needs an object value".into(),
+                ));
+            };
+            for (path, value) in values {
+                apply_operation(resource, op, Some(path), &Some(value.clone()))?;
+            }
+            return Ok(());
+        }
+    };
+
+    if op == Op::Remove {
+        remove(resource, &path, value.as_ref());
+        return Ok(());
+    }
+    let Some(value) = value else {
+        return Err(ScimError::InvalidValue(format!(
+            "{} needs a value",
+            path.attribute
+        )));
+    };
+
+    match (&path.filter, &path.sub_attribute) {
+        (None, None) => {
+            let existing = entry(resource, &path.attribute);
+            match (op, &mut *existing, value) {
+                (Op::Add, Value::Array(values), Value::Array(added)) => {
+                    for value in added {
+                        if !values.contains(value) {
+                            values.push(value.clone());
+                        }
+                    }
+                }
+                (Op::Add, Value::Object(object), Value::Object(added)) => {
+                    for (name, value) in added {
+                        set(object, name, value.clone());
+                    }
+                }
+                _ => *existing = value.clone(),
+            }
+        }
+        (None, Some(sub_attribute)) => {
+            let existing = entry(resource, &path.attribute);
+            if !existing.is_object() {
+                *existing = Value::Object(Map::new());
+            }
+            if let Value::Object(object) = existing {
+                set(object, sub_attribute, value.clone());
+            }
+        }
+        (Some(filter), sub_attribute) => {
+            let existing = entry(resource, &path.attribute);
+            if !existing.is_array() {
+                *existing = Value::Array(Vec::new());
+            }
+            let Value::Array(values) = existing else {
+                unreachable!();
+            };
+
+            let mut matched = false;
+            for element in values.iter_mut().filter(|element| filter.matches(element)) {
+                matched = true;
+                set_element(element, op, sub_attribute.as_deref(), value);
+            }
+            if !matched {
+                values.push(described(filter, sub_attribute.as_deref(), value));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn remove(resource: &mut Map<String, Value>, path: &PatchPath, value: Option<&Value>) {
+    let Some(name) = key(resource, &path.attribute).map(str::to_string) else {
+        return;
+    };
+
+    match (&path.filter, &path.sub_attribute) {
+        (None, None) => match (resource.get_mut(&name), value) {
+            // removing some of the values, `members` with `[{"value": "<id>"}]` the way Azure does it
+            (Some(Value::Array(values)), Some(Value::Array(removed))) => {
+                values.retain(|value| !removed.iter().any(|removed| same_value(value, removed)));
+            }
+            _ => {
+                resource.remove(&name);
+            }
+        },
+        (None, Some(sub_attribute)) => {
+            if let Some(Value::Object(object)) = resource.get_mut(&name)
+                && let Some(sub_attribute) = key(object, sub_attribute).map(str::to_string)
+            {
+                object.remove(&sub_attribute);
+            }
+        }
+        (Some(filter), None) => {
+            if let Some(Value::Array(values)) = resource.get_mut(&name) {
+                values.retain(|value| !filter.matches(value));
+            }
+        }
+        (Some(filter), Some(sub_attribute)) => {
+            if let Some(Value::Array(values)) = resource.get_mut(&name) {
+                for value in values.iter_mut().filter(|value| filter.matches(value)) {
+                    if let Value::Object(object) = value
+                        && let Some(sub_attribute) = key(object, sub_attribute).map(str::to_string)
+                    {
+                        object.remove(&sub_attribute);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn set_element(element: &mut Value, op: Op, sub_attribute: Option<&str>, value: &Value) {
+    match (sub_attribute, &mut *element, value) {
+        (Some(sub_attribute), Value::Object(object), _) => {
+            set(object, sub_attribute, value.clone());
+        }
+        (None, Value::Object(object), Value::Object(added)) if op == Op::Add => {
+            for (name, value) in added {
+                set(object, name, value.clone());
+            }
+        }
+        _ => *element = value.clone(),
+    }
+}
+
+/// The value a filter that matched nothing describes, `emails[type eq "work"].value` is
+/// `{"type": "work", "value": ...}`.
+fn described(filter: &Filter, sub_attribute: Option<&str>, value: &Value) -> Value {
+    let mut element = Map::new();
+    for (name, value) in filter.equalities() {
+        element.insert(name.to_string(), value.clone());
+    }
+    match (sub_attribute, value) {
+        (Some(sub_attribute), value) => {
+            element.insert(sub_attribute.to_string(), value.clone());
+        }
+        (None, Value::Object(values)) => {
+            for (name, value) in values {
+                set(&mut element, name, value.clone());
+            }
+        }
+        (None, value) => return value.clone(),
+    }
+    Value::Object(element)
+}
+
+/// Values of multi-valued attributes are the same when their `value` is.
+fn same_value(value: &Value, other: &Value) -> bool {
+    let inner = |value: &Value| match value {
+        Value::Object(object) => key(object, "value").map(|key| object[key].clone()),
+        value => Some(value.clone()),
+    };
+    match (inner(value), inner(other)) {
+        (Some(value), Some(other)) => value == other,
+        _ => false,
+    }
+}
+
+/// The attribute of the resource, keeping the case it is spelled in. Null when it doesn't exist yet.
+fn entry<'a>(resource: &'a mut Map<String, Value>, name: &str) -> &'a mut Value {
+    let name = key(resource, name).unwrap_or(name).to_string();
+    resource.entry(name).or_insert(Value::Null)
+}
+
+fn set(object: &mut Map<String, Value>, name: &str, value: Value) {
+    *entry(object, name) = value;
+}
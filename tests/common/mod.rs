@@ -0,0 +1,90 @@
+//! The server and browser every integration test starts from.
+//!
+//! Each test file is a crate of its own and uses only some of this.
+#![allow(dead_code)]
+
+use axum::http::header::SET_COOKIE;
+use axum_extra::extract::CookieJar;
+use meow_auth::database::models::user::DBUser;
+use meow_auth::global::GlobalState;
+use meow_auth::settings::Settings;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::sync::oneshot;
+
+/// The server, running until this is dropped.
+pub struct App {
+    pub url: String,
+    pub global: Arc<GlobalState>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+pub async fn start_app() -> App {
+    start_app_with(|_| {}).await
+}
+
+/// Runs the server on a free port, which is also its issuer, with the settings changed by `configure`.
+pub async fn start_app_with(configure: impl FnOnce(&mut Settings)) -> App {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+
+    let mut settings = Settings::parse().unwrap();
+    settings.oauth2.issuer = url.clone();
+    configure(&mut settings);
+
+    let global = Arc::new(GlobalState::new(settings).await.unwrap());
+    let (shutdown, receiver) = oneshot::channel();
+    tokio::spawn(meow_auth::http::serve(listener, global.clone(), receiver));
+
+    App {
+        url,
+        global,
+        _shutdown: shutdown,
+    }
+}
+
+/// An HTTP client that doesn't follow redirects, so tests see where they lead.
+pub fn browser() -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+}
+
+/// The `name=value` part of the cookie the response sets with the given name.
+pub fn cookie(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok()?.split(';').next())
+        .find(|pair| pair.starts_with(&format!("{name}=")))
+        .map(String::from)
+}
+
+/// Something to tell apart what a test creates from what other tests created before, lowercase for slugs.
+pub fn suffix() -> String {
+    meow_auth::crypto::generate_token()[..8].to_lowercase()
+}
+
+/// A new user with an email, signed in. Returns them and their session cookie.
+pub async fn signed_in_user(app: &App) -> (DBUser, String) {
+    let suffix = suffix();
+    let user = DBUser::builder()
+        .username(format!("user-{suffix}"))
+        .email(Some(format!("user-{suffix}@example.com")))
+        .build();
+    let mut transaction = app.global.database.begin().await.unwrap();
+    user.insert(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    let jar = meow_auth::http::session::start_session(&app.global, CookieJar::new(), &user)
+        .await
+        .unwrap();
+    let cookie = jar
+        .get(&app.global.settings.session.cookie_name)
+        .unwrap()
+        .stripped()
+        .to_string();
+    (user, cookie)
+}
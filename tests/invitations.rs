@@ -0,0 +1,308 @@
+//! Invites people to an organization, with the emails going to an SMTP stand-in running next to the server.
+//!
+//! Needs the development database with migrations applied, like the server itself.
+
+mod common;
+
+use axum::http::StatusCode;
+use axum::http::header::COOKIE;
+use common::{App, browser, cookie, signed_in_user, suffix};
+use meow_auth::database::models::organization::DBOrganization;
+use meow_auth::database::models::organization_member::{DBOrganizationMember, MembershipRole};
+use meow_auth::database::models::user::DBUser;
+use serde_json::{Value, json};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+const PASSWORD: &str = "correct horse battery staple";
+
+/// Just enough of an SMTP server to take emails and keep them.
+struct MailStandIn {
+    url: String,
+    /// Bodies of every email it took, in order
+    emails: Mutex<Vec<String>>,
+}
+
+impl MailStandIn {
+    /// The link to accept the invitation with from the last email, undoing the quoted-printable encoding long
+    /// lines get.
+    fn last_link(&self) -> String {
+        let emails = self.emails.lock().unwrap();
+        let email = emails.last().expect("no email was sent");
+        let email = email.replace("=\r\n", "").replace("=3D", "=");
+        let start = email.find("http://").expect("no link in the email");
+        email[start..]
+            .split_whitespace()
+            .next()
+            .unwrap()
+            .to_string()
+    }
+}
+
+async fn serve_client(stand_in: Arc<MailStandIn>, socket: TcpStream) {
+    let (read, mut write) = socket.into_split();
+    let mut lines = BufReader::new(read).lines();
+    let _ = write.write_all(b"220 mail.test ESMTP\r\n").await;
+    while let Ok(Some(line)) = lines.next_line().await {
+        let command = line.to_ascii_uppercase();
+        let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO") {
+            b"250 mail.test\r\n"
+        } else if command.starts_with("DATA") {
+            let _ = write.write_all(b"354 go ahead\r\n").await;
+            let mut body = String::new();
+            while let Ok(Some(line)) = lines.next_line().await {
+                if line == "." {
+                    break;
+                }
+                body.push_str(&line);
+                body.push_str("\r\n");
+            }
+            stand_in.emails.lock().unwrap().push(body);
+            b"250 queued\r\n"
+        } else if command.starts_with("QUIT") {
+            let _ = write.write_all(b"221 bye\r\n").await;
+            return;
+        } else {
+            // MAIL, RCPT, RSET and NOOP
+            b"250 ok\r\n"
+        };
+        if write.write_all(reply).await.is_err() {
+            return;
+        }
+    }
+}
+
+async fn start_mail_stand_in() -> Arc<MailStandIn> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let stand_in = Arc::new(MailStandIn {
+        url: format!("smtp://{}", listener.local_addr().unwrap()),
+        emails: Mutex::default(),
+    });
+
+    let serving = stand_in.clone();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            tokio::spawn(serve_client(serving.clone(), socket));
+        }
+    });
+
+    stand_in
+}
+
+/// Runs the server sending mail to the stand-in.
+async fn start_app(stand_in: &MailStandIn) -> App {
+    common::start_app_with(|settings| settings.mail.smtp_url = Some(stand_in.url.clone())).await
+}
+
+fn session_cookie(response: &reqwest::Response) -> Option<String> {
+    cookie(response, "meow_session")
+}
+
+/// An organization owned by a new user. Returns it and the owner's session cookie.
+async fn organization_with_owner(app: &App) -> (DBOrganization, String) {
+    let organization = DBOrganization::builder()
+        .slug(format!("invitations-{}", suffix()))
+        .name("Invitations Inc".into())
+        .build();
+    let (owner, cookie) = signed_in_user(app).await;
+
+    let mut transaction = app.global.database.begin().await.unwrap();
+    organization.insert(&mut transaction).await.unwrap();
+    DBOrganizationMember::builder()
+        .tenant_id(organization.id)
+        .user_id(owner.id)
+        .role(MembershipRole::Owner)
+        .build()
+        .insert(&mut transaction)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+
+    (organization, cookie)
+}
+
+async fn invite(
+    app: &App,
+    organization: &DBOrganization,
+    cookie: &str,
+    body: Value,
+) -> reqwest::Response {
+    browser()
+        .post(format!(
+            "{}/o/{}/v1/organization/invitations",
+            app.url, organization.slug
+        ))
+        .header(COOKIE, cookie)
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The role the signed in user has in the organization, by the organizations they see themselves in.
+async fn role_in(app: &App, organization: &DBOrganization, cookie: &str) -> Option<String> {
+    let memberships: Value = browser()
+        .get(format!("{}/v1/me/organizations", app.url))
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    memberships
+        .as_array()?
+        .iter()
+        .find(|membership| membership["organization"]["id"] == organization.id.to_string())
+        .map(|membership| membership["role"].as_str().unwrap().to_string())
+}
+
+#[tokio::test]
+async fn invitees_register_through_the_emailed_link() {
+    let stand_in = start_mail_stand_in().await;
+    let app = start_app(&stand_in).await;
+    let (organization, owner) = organization_with_owner(&app).await;
+    let email = format!(
+        "new-{}@example.com",
+        &meow_auth::crypto::generate_token()[..8]
+    );
+
+    let created = invite(
+        &app,
+        &organization,
+        &owner,
+        json!({ "email": email, "role": "admin" }),
+    )
+    .await;
+    assert_eq!(created.status(), StatusCode::CREATED);
+    let created: Value = created.json().await.unwrap();
+    let first_link = stand_in.last_link();
+
+    let again = invite(&app, &organization, &owner, json!({ "email": email })).await;
+    assert_eq!(again.status(), StatusCode::CONFLICT);
+
+    // resending replaces the link
+    let resent = browser()
+        .post(format!(
+            "{}/o/{}/v1/organization/invitations/{}/resend",
+            app.url,
+            organization.slug,
+            created["id"].as_str().unwrap()
+        ))
+        .header(COOKIE, &owner)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(resent.status(), StatusCode::OK);
+    assert_eq!(stand_in.emails.lock().unwrap().len(), 2);
+    let link = stand_in.last_link();
+    assert_ne!(link, first_link);
+    let old = browser().get(&first_link).send().await.unwrap();
+    assert_eq!(old.status(), StatusCode::NOT_FOUND);
+
+    let page = browser().get(&link).send().await.unwrap();
+    assert_eq!(page.status(), StatusCode::OK);
+    assert!(page.text().await.unwrap().contains("Invitations Inc"));
+
+    let username = format!("invitee-{}", &meow_auth::crypto::generate_token()[..8]);
+    let accepted = browser()
+        .post(&link)
+        .form(&[("username", username.as_str()), ("password", PASSWORD)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(accepted.status(), StatusCode::OK);
+    let invitee = session_cookie(&accepted).expect("the invitee wasn't signed in");
+
+    let user = DBUser::find_by_username(&username, &app.global.database)
+        .await
+        .unwrap()
+        .expect("the account was not created");
+    assert_eq!(user.email.as_deref(), Some(email.as_str()));
+    assert_eq!(
+        role_in(&app, &organization, &invitee).await.as_deref(),
+        Some("admin")
+    );
+
+    let used = browser().get(&link).send().await.unwrap();
+    assert_eq!(used.status(), StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn signed_in_invitees_accept_and_revoked_invitations_stop_working() {
+    let stand_in = start_mail_stand_in().await;
+    let app = start_app(&stand_in).await;
+    let (organization, owner) = organization_with_owner(&app).await;
+    let (existing, cookie) = signed_in_user(&app).await;
+
+    let created = invite(
+        &app,
+        &organization,
+        &owner,
+        json!({ "email": existing.email.as_deref().unwrap() }),
+    )
+    .await;
+    assert_eq!(created.status(), StatusCode::CREATED);
+    let link = stand_in.last_link();
+
+    let accepted = browser()
+        .post(&link)
+        .header(COOKIE, &cookie)
+        .form::<[(&str, &str); 0]>(&[])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(accepted.status(), StatusCode::OK);
+    assert_eq!(
+        role_in(&app, &organization, &cookie).await.as_deref(),
+        Some("member")
+    );
+
+    // members can't invite anyone
+    let invitee = invite(
+        &app,
+        &organization,
+        &cookie,
+        json!({ "email": "x@example.com" }),
+    )
+    .await;
+    assert_eq!(invitee.status(), StatusCode::FORBIDDEN);
+
+    let created = invite(
+        &app,
+        &organization,
+        &owner,
+        json!({ "email": "revoked@example.com" }),
+    )
+    .await;
+    let created: Value = created.json().await.unwrap();
+    let link = stand_in.last_link();
+    let revoked = browser()
+        .delete(format!(
+            "{}/o/{}/v1/organization/invitations/{}",
+            app.url,
+            organization.slug,
+            created["id"].as_str().unwrap()
+        ))
+        .header(COOKIE, &owner)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(revoked.status(), StatusCode::NO_CONTENT);
+    let page = browser().get(&link).send().await.unwrap();
+    assert_eq!(page.status(), StatusCode::NOT_FOUND);
+    let invitations: Value = browser()
+        .get(format!(
+            "{}/o/{}/v1/organization/invitations",
+            app.url, organization.slug
+        ))
+        .header(COOKIE, &owner)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(invitations, json!([]));
+}
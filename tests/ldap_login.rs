@@ -0,0 +1,402 @@
+//! Signs in with passwords checked by an LDAP stand-in running next to the server.
+//!
+//! Needs the development database with migrations applied, like the server itself.
+
+mod common;
+
+use axum::http::StatusCode;
+use axum::http::header::{COOKIE, HOST, SET_COOKIE};
+use common::{App, browser, cookie, suffix};
+use futures_util::{SinkExt, StreamExt};
+use ldap3_proto::LdapCodec;
+use ldap3_proto::simple::{
+    DisconnectionNotice, LdapFilter, LdapMsg, LdapPartialAttribute, LdapResultCode,
+    LdapSearchResultEntry, SearchRequest, ServerOps,
+};
+use meow_auth::database::models::identity::DBIdentity;
+use meow_auth::database::models::organization::DBOrganization;
+use meow_auth::database::models::user::DBUser;
+use meow_auth::global::GlobalState;
+use meow_auth::settings::LdapDirectory;
+use serde_json::json;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+const SERVICE_DN: &str = "cn=service,dc=example,dc=com";
+const SERVICE_PASSWORD: &str = "service-secret";
+const PASSWORD: &str = "correct horse battery staple";
+
+struct Entry {
+    dn: String,
+    password: Option<String>,
+    attributes: Vec<(String, Vec<String>)>,
+}
+
+impl Entry {
+    fn values(&self, name: &str) -> impl Iterator<Item = &String> {
+        self.attributes
+            .iter()
+            .filter(move |(attribute, _)| attribute.eq_ignore_ascii_case(name))
+            .flat_map(|(_, values)| values)
+    }
+
+    fn matches(&self, filter: &LdapFilter) -> bool {
+        match filter {
+            LdapFilter::And(filters) => filters.iter().all(|filter| self.matches(filter)),
+            LdapFilter::Or(filters) => filters.iter().any(|filter| self.matches(filter)),
+            LdapFilter::Not(filter) => !self.matches(filter),
+            LdapFilter::Equality(name, value) => self
+                .values(name)
+                .any(|candidate| candidate.eq_ignore_ascii_case(value)),
+            LdapFilter::Present(name) => self.values(name).next().is_some(),
+            _ => false,
+        }
+    }
+}
+
+/// Just enough of a directory for search-then-bind: binds, equality searches and subtrees.
+struct StandIn {
+    url: String,
+    username: String,
+    entry_uuid: String,
+    user_dn: String,
+    entries: Mutex<Vec<Entry>>,
+    /// DNs of every bind that was attempted, in order
+    binds: Mutex<Vec<String>>,
+}
+
+impl StandIn {
+    fn bind(&self, dn: &str, password: &str) -> bool {
+        self.binds.lock().unwrap().push(dn.to_string());
+        self.entries.lock().unwrap().iter().any(|entry| {
+            entry.dn.eq_ignore_ascii_case(dn) && entry.password.as_deref() == Some(password)
+        })
+    }
+
+    fn search(&self, request: &SearchRequest) -> Vec<LdapMsg> {
+        let entries = self.entries.lock().unwrap();
+        let base = format!(",{}", request.base.to_lowercase());
+        let mut messages: Vec<LdapMsg> = entries
+            .iter()
+            .filter(|entry| entry.dn.to_lowercase().ends_with(&base))
+            .filter(|entry| entry.matches(&request.filter))
+            .map(|entry| {
+                let attributes = entry
+                    .attributes
+                    .iter()
+                    .filter(|(name, _)| {
+                        request
+                            .attrs
+                            .iter()
+                            .any(|wanted| wanted.eq_ignore_ascii_case(name))
+                    })
+                    .map(|(name, values)| LdapPartialAttribute {
+                        atype: name.clone(),
+                        vals: values
+                            .iter()
+                            .map(|value| value.as_bytes().to_vec())
+                            .collect(),
+                    })
+                    .collect();
+                request.gen_result_entry(LdapSearchResultEntry {
+                    dn: entry.dn.clone(),
+                    attributes,
+                })
+            })
+            .collect();
+        messages.push(request.gen_success());
+        messages
+    }
+
+    fn set_department(&self, department: &str) {
+        let mut entries = self.entries.lock().unwrap();
+        let user = entries
+            .iter_mut()
+            .find(|entry| entry.dn == self.user_dn)
+            .unwrap();
+        for (name, values) in &mut user.attributes {
+            if name == "departmentNumber" {
+                *values = vec![department.to_string()];
+            }
+        }
+    }
+}
+
+async fn serve_client(stand_in: Arc<StandIn>, socket: TcpStream) {
+    let (read, write) = tokio::io::split(socket);
+    let mut requests = FramedRead::new(read, LdapCodec::default());
+    let mut responses = FramedWrite::new(write, LdapCodec::default());
+    let mut bound: Option<String> = None;
+
+    while let Some(message) = requests.next().await {
+        let responded = match message.map_err(|_| ()).and_then(ServerOps::try_from) {
+            Ok(ServerOps::SimpleBind(request)) => {
+                if stand_in.bind(&request.dn, &request.pw) {
+                    bound = Some(request.dn.clone());
+                    vec![request.gen_success()]
+                } else {
+                    bound = None;
+                    vec![request.gen_invalid_cred()]
+                }
+            }
+            // only the service account may look around
+            Ok(ServerOps::Search(request)) if bound.as_deref() == Some(SERVICE_DN) => {
+                stand_in.search(&request)
+            }
+            Ok(ServerOps::Search(request)) => vec![
+                request.gen_error(LdapResultCode::InsufficentAccessRights, "bind first".into()),
+            ],
+            Ok(ServerOps::Unbind(_)) => return,
+            // StartTLS among them, the stand-in only speaks plain LDAP
+            _ => vec![DisconnectionNotice::gen_response(
+                LdapResultCode::UnwillingToPerform,
+                "not supported",
+            )],
+        };
+
+        for message in responded {
+            if responses.send(message).await.is_err() {
+                return;
+            }
+        }
+    }
+}
+
+async fn start_stand_in() -> Arc<StandIn> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let suffix = &meow_auth::crypto::generate_token()[..8];
+    let username = format!("ldap-user-{suffix}");
+    let user_dn = format!("uid={username},ou=people,dc=example,dc=com");
+
+    let entry_uuid = ulid::Ulid::new().to_string();
+    let attribute = |name: &str, value: &str| (name.to_string(), vec![value.to_string()]);
+    let group = |name: &str, member: &str| Entry {
+        dn: format!("cn={name},ou=groups,dc=example,dc=com"),
+        password: None,
+        attributes: vec![attribute("cn", name), attribute("member", member)],
+    };
+    let entries = vec![
+        Entry {
+            dn: SERVICE_DN.into(),
+            password: Some(SERVICE_PASSWORD.into()),
+            attributes: vec![attribute("cn", "service")],
+        },
+        Entry {
+            dn: user_dn.clone(),
+            password: Some(PASSWORD.into()),
+            attributes: vec![
+                attribute("uid", &username),
+                attribute("entryUUID", &entry_uuid),
+                attribute("mail", &format!("{username}@example.com")),
+                attribute("departmentNumber", "engineering"),
+            ],
+        },
+        group("admins", &user_dn.to_uppercase()),
+        group("unmapped", &user_dn),
+    ];
+
+    let stand_in = Arc::new(StandIn {
+        url: format!("ldap://{}", listener.local_addr().unwrap()),
+        username,
+        entry_uuid,
+        user_dn,
+        entries: Mutex::new(entries),
+        binds: Mutex::default(),
+    });
+
+    let serving = stand_in.clone();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            tokio::spawn(serve_client(serving.clone(), socket));
+        }
+    });
+
+    stand_in
+}
+
+/// Runs the server checking passwords of a new organization with the stand-in, and local passwords at its own host.
+/// Returns it and the domain the organization's users sign in at.
+async fn start_app(stand_in: &StandIn, starttls: bool) -> (App, String) {
+    let slug = format!("ldap-{}", suffix());
+    let app = common::start_app_with(|settings| {
+        settings.authentication.ldap = vec![LdapDirectory {
+            id: "corp".into(),
+            name: "Corp".into(),
+            url: stand_in.url.clone(),
+            starttls,
+            bind_dn: SERVICE_DN.into(),
+            bind_password: SERVICE_PASSWORD.into(),
+            base_dn: "ou=people,dc=example,dc=com".into(),
+            group_base_dn: Some("ou=groups,dc=example,dc=com".into()),
+            groups: BTreeMap::from([(
+                "CN=Admins,OU=Groups,DC=example,DC=com".into(),
+                "admins".into(),
+            )]),
+            ..Default::default()
+        }];
+        settings.authentication.ldap[0]
+            .attributes
+            .attributes
+            .insert("departmentNumber".into(), "department".into());
+        settings.authentication.tenants = BTreeMap::from([(slug.clone(), "corp".into())]);
+    })
+    .await;
+
+    let host = organization(&app.global, slug).await;
+    (app, host)
+}
+
+/// A new organization, its users sign in at the domain it returns.
+async fn organization(global: &GlobalState, slug: String) -> String {
+    let host = format!("{slug}.test");
+    let organization = DBOrganization::builder()
+        .slug(slug)
+        .name("LDAP Inc".into())
+        .domains(vec![host.clone()])
+        .build();
+    let mut transaction = global.database.begin().await.unwrap();
+    organization.insert(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+    host
+}
+
+/// Opens the login page first, for the token the form has to send back.
+async fn sign_in(app: &App, host: &str, username: &str, password: &str) -> reqwest::Response {
+    let http = browser();
+    let page = http
+        .get(format!("{}/login", app.url))
+        .header(HOST, host)
+        .send()
+        .await
+        .unwrap();
+    let csrf = cookie(&page, "meow_session_csrf").unwrap();
+    let token = csrf.split_once('=').unwrap().1;
+
+    http.post(format!("{}/login", app.url))
+        .header(HOST, host)
+        .header(COOKIE, &csrf)
+        .form(&[
+            ("username", username),
+            ("password", password),
+            ("return_to", "/welcome"),
+            ("csrf_token", token),
+        ])
+        .send()
+        .await
+        .unwrap()
+}
+
+fn signed_in(response: &reqwest::Response) -> bool {
+    response.status() == StatusCode::SEE_OTHER
+        && response
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .any(|cookie| cookie.to_str().unwrap().starts_with("meow_session="))
+}
+
+async fn directory_user(app: &App, stand_in: &StandIn) -> Option<DBUser> {
+    let identity = DBIdentity::find_by_subject("corp", &stand_in.entry_uuid, &app.global.database)
+        .await
+        .unwrap()?;
+    DBUser::find_by_id(identity.user_id, &app.global.database)
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn signs_in_and_syncs_attributes_on_every_login() {
+    let stand_in = start_stand_in().await;
+    let (app, host) = start_app(&stand_in, false).await;
+
+    let first = sign_in(&app, &host, &stand_in.username, PASSWORD).await;
+    assert!(signed_in(&first));
+    // the service account looks the user up before their password is tried
+    assert_eq!(
+        *stand_in.binds.lock().unwrap(),
+        [SERVICE_DN, stand_in.user_dn.as_str()]
+    );
+
+    let user = directory_user(&app, &stand_in)
+        .await
+        .expect("the user was not created");
+    assert_eq!(user.username, stand_in.username);
+    assert_eq!(
+        user.email.as_deref(),
+        Some(format!("{}@example.com", stand_in.username).as_str())
+    );
+    assert!(user.password_hash.is_none());
+    assert_eq!(
+        user.attributes,
+        json!({ "department": "engineering", "groups": ["admins"] })
+    );
+
+    stand_in.set_department("research");
+    let second = sign_in(&app, &host, &stand_in.username, PASSWORD).await;
+    assert!(signed_in(&second));
+    let again = directory_user(&app, &stand_in).await.unwrap();
+    assert_eq!(again.id, user.id);
+    assert_eq!(again.attributes["department"], "research");
+}
+
+#[tokio::test]
+async fn rejects_wrong_passwords_and_filter_injection() {
+    let stand_in = start_stand_in().await;
+    let (app, host) = start_app(&stand_in, false).await;
+
+    let wrong = sign_in(&app, &host, &stand_in.username, "wrong").await;
+    assert_eq!(wrong.status(), StatusCode::UNAUTHORIZED);
+
+    // unescaped, this would find the only user and bind as them
+    let wildcard = sign_in(&app, &host, "*", PASSWORD).await;
+    assert_eq!(wildcard.status(), StatusCode::UNAUTHORIZED);
+
+    // an empty password would be an unauthenticated bind, which directories accept
+    let empty = sign_in(&app, &host, &stand_in.username, "").await;
+    assert_eq!(empty.status(), StatusCode::UNAUTHORIZED);
+
+    assert!(directory_user(&app, &stand_in).await.is_none());
+    let binds = stand_in.binds.lock().unwrap();
+    assert_eq!(
+        binds.iter().filter(|dn| **dn == stand_in.user_dn).count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn checks_passwords_with_the_backend_of_the_tenant() {
+    let stand_in = start_stand_in().await;
+    let (app, host) = start_app(&stand_in, false).await;
+
+    let issuer_host = app.url.strip_prefix("http://").unwrap();
+    let elsewhere = sign_in(&app, issuer_host, &stand_in.username, PASSWORD).await;
+    assert_eq!(elsewhere.status(), StatusCode::UNAUTHORIZED);
+    assert!(stand_in.binds.lock().unwrap().is_empty());
+
+    // made up host names pick no backend at all
+    let unknown = sign_in(&app, "attacker.test", &stand_in.username, PASSWORD).await;
+    assert_eq!(unknown.status(), StatusCode::MISDIRECTED_REQUEST);
+    assert!(!signed_in(&unknown));
+    assert!(stand_in.binds.lock().unwrap().is_empty());
+
+    // organizations without a backend of their own get the default one
+    let other = organization(&app.global, format!("ldap-{}", suffix())).await;
+    let default = sign_in(&app, &other, &stand_in.username, PASSWORD).await;
+    assert_eq!(default.status(), StatusCode::UNAUTHORIZED);
+    assert!(stand_in.binds.lock().unwrap().is_empty());
+
+    let tenant = sign_in(&app, &host, &stand_in.username, PASSWORD).await;
+    assert!(signed_in(&tenant));
+}
+
+#[tokio::test]
+async fn sends_no_password_when_starttls_fails() {
+    let stand_in = start_stand_in().await;
+    let (app, host) = start_app(&stand_in, true).await;
+
+    let response = sign_in(&app, &host, &stand_in.username, PASSWORD).await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(stand_in.binds.lock().unwrap().is_empty());
+}
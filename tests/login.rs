@@ -0,0 +1,123 @@
+//! Signs in with local passwords through the login form.
+//!
+//! Needs the development database with migrations applied, like the server itself.
+
+mod common;
+
+use axum::http::StatusCode;
+use axum::http::header::COOKIE;
+use common::{App, browser, cookie, start_app};
+use meow_auth::database::models::user::DBUser;
+
+const PASSWORD: &str = "correct horse battery staple";
+
+async fn user_with_password(app: &App) -> DBUser {
+    let suffix = &meow_auth::crypto::generate_token()[..8];
+    let user = DBUser::builder()
+        .username(format!("login-{suffix}"))
+        .password_hash(Some(meow_auth::crypto::hash_password(PASSWORD).unwrap()))
+        .build();
+    let mut transaction = app.global.database.begin().await.unwrap();
+    user.insert(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+    user
+}
+
+/// The cookie of the login page and the token its form sends back.
+async fn login_page(app: &App) -> (String, String) {
+    let page = browser()
+        .get(format!("{}/login", app.url))
+        .send()
+        .await
+        .unwrap();
+    let csrf = cookie(&page, "meow_session_csrf").expect("the page set no token");
+    let html = page.text().await.unwrap();
+    let token = csrf.split_once('=').unwrap().1.to_string();
+    assert!(html.contains(&format!(r#"name="csrf_token" value="{token}""#)));
+    (csrf, token)
+}
+
+async fn sign_in(app: &App, csrf: Option<&str>, form: &[(&str, &str)]) -> reqwest::Response {
+    let mut request = browser().post(format!("{}/login", app.url)).form(form);
+    if let Some(csrf) = csrf {
+        request = request.header(COOKIE, csrf);
+    }
+    request.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn signs_in_with_the_token_of_the_login_page() {
+    let app = start_app().await;
+    let user = user_with_password(&app).await;
+    let (csrf, token) = login_page(&app).await;
+
+    let signed_in = sign_in(
+        &app,
+        Some(&csrf),
+        &[
+            ("username", &user.username),
+            ("password", PASSWORD),
+            ("return_to", "/welcome"),
+            ("csrf_token", &token),
+        ],
+    )
+    .await;
+    assert_eq!(signed_in.status(), StatusCode::SEE_OTHER);
+    assert!(cookie(&signed_in, "meow_session").is_some());
+
+    let wrong_password = sign_in(
+        &app,
+        Some(&csrf),
+        &[
+            ("username", &user.username),
+            ("password", "wrong"),
+            ("csrf_token", &token),
+        ],
+    )
+    .await;
+    assert_eq!(wrong_password.status(), StatusCode::UNAUTHORIZED);
+
+    let nobody = sign_in(
+        &app,
+        Some(&csrf),
+        &[
+            ("username", "nobody-by-this-name"),
+            ("password", PASSWORD),
+            ("csrf_token", &token),
+        ],
+    )
+    .await;
+    assert_eq!(nobody.status(), StatusCode::UNAUTHORIZED);
+}
+
+#[tokio::test]
+async fn rejects_logins_posted_from_other_sites() {
+    let app = start_app().await;
+    let user = user_with_password(&app).await;
+    let (csrf, token) = login_page(&app).await;
+    let credentials = [("username", user.username.as_str()), ("password", PASSWORD)];
+
+    // another site can make the browser post the form, but can't know the token
+    let without_token = sign_in(&app, Some(&csrf), &credentials).await;
+    assert_eq!(without_token.status(), StatusCode::BAD_REQUEST);
+    assert!(cookie(&without_token, "meow_session").is_none());
+
+    let (_, other_token) = login_page(&app).await;
+    let wrong_token = sign_in(
+        &app,
+        Some(&csrf),
+        &[credentials[0], credentials[1], ("csrf_token", &other_token)],
+    )
+    .await;
+    assert_eq!(wrong_token.status(), StatusCode::BAD_REQUEST);
+    assert!(cookie(&wrong_token, "meow_session").is_none());
+
+    let without_cookie = sign_in(
+        &app,
+        None,
+        &[credentials[0], credentials[1], ("csrf_token", &token)],
+    )
+    .await;
+    assert_eq!(without_cookie.status(), StatusCode::BAD_REQUEST);
+    assert!(cookie(&without_cookie, "meow_session").is_none());
+}
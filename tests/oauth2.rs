@@ -0,0 +1,1661 @@
+//! Gets tokens from the oauth2 endpoints the way clients would.
+//!
+//! Needs the development database with migrations applied, like the server itself.
+
+mod common;
+
+use axum::http::StatusCode;
+use axum::http::header::{AUTHORIZATION, COOKIE, LOCATION, WWW_AUTHENTICATE};
+use axum_extra::extract::CookieJar;
+use base64::Engine;
+use base64::prelude::BASE64_URL_SAFE_NO_PAD;
+use clap::Parser;
+use common::{App, browser, signed_in_user, start_app, start_app_with, suffix};
+use jsonwebtoken::jwk::Jwk;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use meow_auth::cli::Run;
+use meow_auth::database::models::client::{
+    AccessTokenFormat, ApplicationType, ClientAuthMethod, DBClient, SubjectType,
+};
+use meow_auth::database::models::organization::DBOrganization;
+use meow_auth::database::models::role::DBRole;
+use meow_auth::database::models::role_assignment::DBRoleAssignment;
+use meow_auth::database::models::scope::DBScope;
+use serde_json::{Value, json};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+const REDIRECT_URI: &str = "https://client.example.com/callback";
+const SECRET: &str = "client-secret";
+
+/// A registered client and the secret it authenticates with.
+struct Client {
+    id: String,
+    secret: String,
+}
+
+async fn register(app: &App, client: DBClient) -> Client {
+    let mut transaction = app.global.database.begin().await.unwrap();
+    client.insert(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    Client {
+        id: client.id.to_string(),
+        secret: SECRET.into(),
+    }
+}
+
+/// The start of a confidential, first party client that sends users back to [`REDIRECT_URI`].
+fn confidential_client() -> DBClient {
+    DBClient::builder()
+        .name("oauth2 test client".into())
+        .secret_hash(Some(meow_auth::crypto::hash_token(SECRET)))
+        .token_endpoint_auth_method(ClientAuthMethod::ClientSecretBasic)
+        .grant_types(vec!["authorization_code".into(), "refresh_token".into()])
+        .scopes(vec!["openid".into(), "profile".into()])
+        .redirect_uris(vec![REDIRECT_URI.into()])
+        .first_party(true)
+        .build()
+}
+
+fn query(location: &str) -> HashMap<String, String> {
+    url::Url::parse(location)
+        .unwrap()
+        .query_pairs()
+        .into_owned()
+        .collect()
+}
+
+async fn authorization_response(
+    app: &App,
+    cookie: &str,
+    params: &[(&str, &str)],
+) -> reqwest::Response {
+    browser()
+        .get(format!("{}/oauth2/authorize", app.url))
+        .query(params)
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// Goes through the authorization endpoint as the signed in user, returning where they were sent back to.
+async fn authorize(app: &App, cookie: &str, params: &[(&str, &str)]) -> HashMap<String, String> {
+    let response = authorization_response(app, cookie, params).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let location = response.headers()[LOCATION].to_str().unwrap();
+    assert!(location.starts_with(REDIRECT_URI), "sent to {location}");
+    query(location)
+}
+
+/// A code for the signed in user, issued to the client for [`REDIRECT_URI`].
+async fn code(app: &App, cookie: &str, client: &Client) -> String {
+    let callback = authorize(
+        app,
+        cookie,
+        &[
+            ("response_type", "code"),
+            ("client_id", client.id.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+        ],
+    )
+    .await;
+    callback["code"].clone()
+}
+
+async fn token(app: &App, client: &Client, params: &[(&str, &str)]) -> (StatusCode, Value) {
+    token_with_proof(app, client, params, None).await
+}
+
+async fn token_with_proof(
+    app: &App,
+    client: &Client,
+    params: &[(&str, &str)],
+    proof: Option<String>,
+) -> (StatusCode, Value) {
+    let mut request = browser()
+        .post(format!("{}/oauth2/token", app.url))
+        .basic_auth(&client.id, Some(&client.secret))
+        .form(params);
+    if let Some(proof) = proof {
+        request = request.header("DPoP", proof);
+    }
+    let response = request.send().await.unwrap();
+    (response.status(), response.json().await.unwrap())
+}
+
+/// A key the client proves possession of with DPoP proofs.
+struct DPoPKey {
+    encoding: EncodingKey,
+    jwk: Jwk,
+}
+
+impl DPoPKey {
+    fn generate() -> Self {
+        let key = meow_auth::keys::generate().unwrap();
+        Self {
+            encoding: EncodingKey::from_ec_der(&key.private_key),
+            jwk: key.public_jwk.0,
+        }
+    }
+
+    fn thumbprint(&self) -> String {
+        meow_auth::crypto::jwk_thumbprint(&self.jwk).unwrap()
+    }
+
+    /// A proof for one request, bound to the access token sent with it. (RFC 9449 section 4.2)
+    fn proof(
+        &self,
+        method: &str,
+        url: &str,
+        access_token: Option<&str>,
+        nonce: Option<&str>,
+    ) -> String {
+        let mut header = Header::new(Algorithm::ES256);
+        header.typ = Some("dpop+jwt".into());
+        header.jwk = Some(self.jwk.clone());
+        let mut claims = json!({
+            "jti": meow_auth::crypto::generate_token(),
+            "htm": method,
+            "htu": url,
+            "iat": chrono::Utc::now().timestamp(),
+        });
+        if let Some(token) = access_token {
+            claims["ath"] = meow_auth::crypto::sha256_base64url(token).into();
+        }
+        if let Some(nonce) = nonce {
+            claims["nonce"] = nonce.into();
+        }
+        jsonwebtoken::encode(&header, &claims, &self.encoding).unwrap()
+    }
+}
+
+async fn userinfo(app: &App, authorization: &str, proof: Option<&str>) -> reqwest::Response {
+    let mut request = browser()
+        .get(format!("{}/oauth2/userinfo", app.url))
+        .header(AUTHORIZATION, authorization);
+    if let Some(proof) = proof {
+        request = request.header("DPoP", proof);
+    }
+    request.send().await.unwrap()
+}
+
+fn challenge(response: &reqwest::Response) -> &str {
+    response.headers()[WWW_AUTHENTICATE].to_str().unwrap()
+}
+
+#[tokio::test]
+async fn issues_client_credentials_tokens_to_authenticated_clients() {
+    let app = start_app().await;
+    let client = register(
+        &app,
+        DBClient::builder()
+            .name("service".into())
+            .secret_hash(Some(meow_auth::crypto::hash_token(SECRET)))
+            .token_endpoint_auth_method(ClientAuthMethod::ClientSecretBasic)
+            .grant_types(vec!["client_credentials".into()])
+            .scopes(vec!["profile".into()])
+            .build(),
+    )
+    .await;
+
+    let (status, body) = token(&app, &client, &[("grant_type", "client_credentials")]).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["token_type"], "Bearer");
+    assert_eq!(body["scope"], "profile");
+    assert!(body.get("refresh_token").is_none());
+
+    let wrong = Client {
+        id: client.id.clone(),
+        secret: "not-the-secret".into(),
+    };
+    let (status, body) = token(&app, &wrong, &[("grant_type", "client_credentials")]).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    assert_eq!(body["error"], "invalid_client");
+
+    let (status, body) = token(&app, &client, &[("grant_type", "authorization_code")]).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "unauthorized_client");
+}
+
+#[tokio::test]
+async fn codes_need_the_redirect_uri_they_were_issued_for() {
+    let app = start_app().await;
+    let client = register(&app, confidential_client()).await;
+    let (_, cookie) = signed_in_user(&app).await;
+    let params = [
+        ("response_type", "code"),
+        ("client_id", client.id.as_str()),
+        ("redirect_uri", REDIRECT_URI),
+        ("state", "xyz"),
+    ];
+
+    let callback = authorize(&app, &cookie, &params).await;
+    assert_eq!(callback["state"], "xyz");
+    let (status, body) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &callback["code"]),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_request");
+
+    let callback = authorize(&app, &cookie, &params).await;
+    let (status, body) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &callback["code"]),
+            ("redirect_uri", "https://client.example.com/elsewhere"),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_grant");
+
+    let callback = authorize(&app, &cookie, &params).await;
+    let code = callback["code"].as_str();
+    let redeem = [
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", REDIRECT_URI),
+    ];
+    let (status, body) = token(&app, &client, &redeem).await;
+    assert_eq!(status, StatusCode::OK);
+    assert!(body["access_token"].is_string());
+    assert!(body["refresh_token"].is_string());
+
+    // codes are single use
+    let (status, body) = token(&app, &client, &redeem).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_grant");
+
+    // left out of the authorization request, the token request doesn't have to repeat it
+    let callback = authorize(&app, &cookie, &params[..2]).await;
+    let (status, _) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &callback["code"]),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+/// A confidential client on a device without a browser, one that can only show users a code.
+fn device_client() -> DBClient {
+    DBClient::builder()
+        .name("oauth2 test device".into())
+        .secret_hash(Some(meow_auth::crypto::hash_token(SECRET)))
+        .token_endpoint_auth_method(ClientAuthMethod::ClientSecretBasic)
+        .grant_types(vec!["urn:ietf:params:oauth:grant-type:device_code".into()])
+        .scopes(vec!["profile".into()])
+        .build()
+}
+
+/// Starts a device authorization, returning the device code and the code the user types in.
+async fn device_authorization(app: &App, client: &Client) -> (String, String) {
+    let response = browser()
+        .post(format!("{}/oauth2/device_authorization", app.url))
+        .basic_auth(&client.id, Some(&client.secret))
+        .form(&[("scope", "profile")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body: Value = response.json().await.unwrap();
+    (
+        body["device_code"].as_str().unwrap().to_string(),
+        body["user_code"].as_str().unwrap().to_string(),
+    )
+}
+
+async fn poll(app: &App, client: &Client, device_code: &str) -> (StatusCode, Value) {
+    token(
+        app,
+        client,
+        &[
+            ("grant_type", "urn:ietf:params:oauth:grant-type:device_code"),
+            ("device_code", device_code),
+        ],
+    )
+    .await
+}
+
+async fn approve_device(app: &App, cookie: &str, user_code: &str) -> StatusCode {
+    browser()
+        .post(format!("{}/device", app.url))
+        .header(COOKIE, cookie)
+        .form(&[("user_code", user_code), ("decision", "approve")])
+        .send()
+        .await
+        .unwrap()
+        .status()
+}
+
+#[tokio::test]
+async fn devices_polling_too_fast_slow_down_and_get_tokens_once_approved() {
+    let app = start_app().await;
+    let client = register(&app, device_client()).await;
+    let (_, cookie) = signed_in_user(&app).await;
+    let (device_code, user_code) = device_authorization(&app, &client).await;
+
+    let (status, body) = poll(&app, &client, &device_code).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "authorization_pending");
+    let (status, body) = poll(&app, &client, &device_code).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "slow_down");
+
+    // users type the code in whatever case, with or without the dash
+    let typed = user_code.replace('-', " ").to_lowercase();
+    assert_eq!(approve_device(&app, &cookie, &typed).await, StatusCode::OK);
+    let (status, body) = poll(&app, &client, &device_code).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["scope"], "profile");
+    assert!(body["access_token"].is_string());
+
+    let (status, body) = poll(&app, &client, &device_code).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_grant");
+}
+
+#[tokio::test]
+async fn expired_device_codes_are_neither_approved_nor_traded_for_tokens() {
+    let app = start_app_with(|settings| settings.oauth2.device_code_lifetime = 0).await;
+    let client = register(&app, device_client()).await;
+    let (_, cookie) = signed_in_user(&app).await;
+    let (device_code, user_code) = device_authorization(&app, &client).await;
+
+    assert_eq!(
+        approve_device(&app, &cookie, &user_code).await,
+        StatusCode::BAD_REQUEST
+    );
+    let (status, body) = poll(&app, &client, &device_code).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "expired_token");
+    let (_, body) = poll(&app, &client, &device_code).await;
+    assert_eq!(body["error"], "invalid_grant");
+}
+
+/// Tokens of the signed in user, for the device the client runs on.
+async fn device_tokens(app: &App, cookie: &str, client: &Client) -> Value {
+    let (device_code, user_code) = device_authorization(app, client).await;
+    assert_eq!(
+        approve_device(app, cookie, &user_code).await,
+        StatusCode::OK
+    );
+    let (status, body) = poll(app, client, &device_code).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    body
+}
+
+async fn introspect(app: &App, client: &Client, token: &str) -> Value {
+    browser()
+        .post(format!("{}/oauth2/introspect", app.url))
+        .basic_auth(&client.id, Some(&client.secret))
+        .form(&[("token", token)])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn introspection_shows_tokens_only_to_clients_allowed_to_see_them() {
+    let app = start_app().await;
+    let api = format!(
+        "https://api-{}.example.com",
+        &meow_auth::crypto::generate_token()[..8]
+    );
+    let mut client = device_client();
+    client.audiences = vec![api.clone()];
+    let client = register(&app, client).await;
+    let (user, cookie) = signed_in_user(&app).await;
+    let tokens = device_tokens(&app, &cookie, &client).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+
+    let access = introspect(&app, &client, access_token).await;
+    assert_eq!(access["active"], true);
+    assert_eq!(access["scope"], "profile");
+    assert_eq!(access["client_id"], client.id.as_str());
+    assert_eq!(access["sub"], user.id.to_string());
+    assert_eq!(access["token_type"], "Bearer");
+    assert_eq!(access["aud"], json!([api]));
+    assert_eq!(access["iss"], app.url.as_str());
+
+    // resources the token is for see it, other clients learn nothing, not even that it exists
+    let mut resource = device_client();
+    resource.introspect_audiences = vec![api];
+    let resource = register(&app, resource).await;
+    assert_eq!(
+        introspect(&app, &resource, access_token).await["active"],
+        true
+    );
+    let other = register(&app, device_client()).await;
+    assert_eq!(
+        introspect(&app, &other, access_token).await,
+        json!({ "active": false })
+    );
+    assert_eq!(
+        introspect(&app, &client, "not-a-token").await,
+        json!({ "active": false })
+    );
+
+    let wrong = Client {
+        id: client.id.clone(),
+        secret: "not-the-secret".into(),
+    };
+    let response = browser()
+        .post(format!("{}/oauth2/introspect", app.url))
+        .basic_auth(&wrong.id, Some(&wrong.secret))
+        .form(&[("token", access_token)])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"], "invalid_client");
+}
+
+async fn revoke(app: &App, client: &Client, token: &str) -> (StatusCode, Value) {
+    let response = browser()
+        .post(format!("{}/oauth2/revoke", app.url))
+        .basic_auth(&client.id, Some(&client.secret))
+        .form(&[("token", token)])
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn revoking_a_refresh_token_ends_everything_issued_with_it() {
+    let app = start_app().await;
+    let mut client = device_client();
+    client.grant_types.push("refresh_token".into());
+    let client = register(&app, client).await;
+    let (_, cookie) = signed_in_user(&app).await;
+    let tokens = device_tokens(&app, &cookie, &client).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let refresh_token = tokens["refresh_token"].as_str().unwrap();
+
+    // only the client the token was issued to can revoke it
+    let other = register(&app, device_client()).await;
+    let (status, body) = revoke(&app, &other, refresh_token).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "unauthorized_client");
+    assert_eq!(
+        introspect(&app, &client, access_token).await["active"],
+        true
+    );
+
+    let (status, _) = revoke(&app, &client, refresh_token).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(
+        introspect(&app, &client, access_token).await,
+        json!({ "active": false })
+    );
+    assert_eq!(
+        introspect(&app, &client, refresh_token).await,
+        json!({ "active": false })
+    );
+    let (status, body) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_grant");
+
+    // revoking what is already gone is fine
+    let (status, _) = revoke(&app, &client, refresh_token).await;
+    assert_eq!(status, StatusCode::OK);
+}
+
+/// Sends the consent form back with the user's decision, returning where they were sent back to.
+async fn decide(
+    app: &App,
+    cookie: &str,
+    params: &[(&str, &str)],
+    decision: &str,
+) -> HashMap<String, String> {
+    let mut form = params.to_vec();
+    form.push(("decision", decision));
+    let response = browser()
+        .post(format!("{}/oauth2/authorize", app.url))
+        .header(COOKIE, cookie)
+        .form(&form)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let location = response.headers()[LOCATION].to_str().unwrap();
+    assert!(location.starts_with(REDIRECT_URI), "sent to {location}");
+    query(location)
+}
+
+#[tokio::test]
+async fn third_party_clients_need_consent_for_every_scope_they_ask_for() {
+    let app = start_app().await;
+    let mut third_party = confidential_client();
+    third_party.first_party = false;
+    third_party.scopes.push("email".into());
+    let client = register(&app, third_party).await;
+    let (_, cookie) = signed_in_user(&app).await;
+    let request = |scope: &'static str| {
+        [
+            ("response_type", "code"),
+            ("client_id", client.id.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+            ("scope", scope),
+            ("state", "xyz"),
+        ]
+    };
+
+    let page = browser()
+        .get(format!("{}/oauth2/authorize", app.url))
+        .query(&request("openid profile"))
+        .header(COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(page.status(), StatusCode::OK);
+    assert_eq!(page.headers()["x-frame-options"], "DENY");
+    let html = page.text().await.unwrap();
+    assert!(html.contains("See your username"));
+    assert!(!html.contains("See your email address"));
+
+    let denied = decide(&app, &cookie, &request("openid profile"), "deny").await;
+    assert_eq!(denied["error"], "access_denied");
+    assert_eq!(denied["state"], "xyz");
+    assert!(!denied.contains_key("code"));
+
+    let approved = decide(&app, &cookie, &request("openid profile"), "approve").await;
+    assert!(approved.contains_key("code"));
+    assert_eq!(approved["state"], "xyz");
+
+    // what the user allowed once isn't asked for again, anything more is
+    let again = authorize(&app, &cookie, &request("profile")).await;
+    assert!(again.contains_key("code"));
+    let more = browser()
+        .get(format!("{}/oauth2/authorize", app.url))
+        .query(&request("openid email"))
+        .header(COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(more.status(), StatusCode::OK);
+    assert!(
+        more.text()
+            .await
+            .unwrap()
+            .contains("See your email address")
+    );
+}
+
+#[tokio::test]
+async fn registered_scopes_release_the_attributes_they_name_as_claims() {
+    let app = start_app().await;
+    let scope = DBScope::builder()
+        .name(format!(
+            "department-{}",
+            &meow_auth::crypto::generate_token()[..8]
+        ))
+        .description("See your department".into())
+        .claims(vec!["department".into()])
+        .build();
+    let mut transaction = app.global.database.begin().await.unwrap();
+    scope.insert(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    let mut client = confidential_client();
+    client.scopes.push(scope.name.clone());
+    let client = register(&app, client).await;
+    let (mut user, cookie) = signed_in_user(&app).await;
+    user.attributes = json!({ "department": "engineering", "shoe_size": 42 });
+    let mut transaction = app.global.database.begin().await.unwrap();
+    user.update_attributes(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    let requested = format!("openid {}", scope.name);
+    let callback = authorize(
+        &app,
+        &cookie,
+        &[
+            ("response_type", "code"),
+            ("client_id", client.id.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+            ("scope", &requested),
+        ],
+    )
+    .await;
+    let (status, tokens) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &callback["code"]),
+            ("redirect_uri", REDIRECT_URI),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{tokens}");
+    assert_eq!(tokens["scope"], requested.as_str());
+
+    // only what the granted scopes name, not the rest of the attributes or what profile would release
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let claims: Value = userinfo(&app, &format!("Bearer {access_token}"), None)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        claims,
+        json!({ "sub": user.id.to_string(), "department": "engineering" })
+    );
+
+    let callback = authorize(
+        &app,
+        &cookie,
+        &[
+            ("response_type", "code"),
+            ("client_id", client.id.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+            ("scope", "openid shoe_size"),
+        ],
+    )
+    .await;
+    assert_eq!(callback["error"], "invalid_scope");
+    assert!(!callback.contains_key("code"));
+
+    // every test run would leave one behind otherwise
+    let mut transaction = app.global.database.begin().await.unwrap();
+    DBScope::delete_by_name(&scope.name, &mut transaction)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+}
+
+async fn push(app: &App, client: &Client, params: &[(&str, &str)]) -> (StatusCode, Value) {
+    let response = browser()
+        .post(format!("{}/oauth2/par", app.url))
+        .basic_auth(&client.id, Some(&client.secret))
+        .form(params)
+        .send()
+        .await
+        .unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap())
+}
+
+#[tokio::test]
+async fn pushed_requests_are_checked_up_front_and_used_only_once() {
+    let app = start_app().await;
+    let client = register(&app, confidential_client()).await;
+    let (_, cookie) = signed_in_user(&app).await;
+
+    let (status, error) = push(
+        &app,
+        &client,
+        &[
+            ("response_type", "code"),
+            ("redirect_uri", "https://attacker.example.com/callback"),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["error"], "invalid_request");
+
+    let (status, pushed) = push(
+        &app,
+        &client,
+        &[
+            ("response_type", "code"),
+            ("redirect_uri", REDIRECT_URI),
+            ("state", "pushed"),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{pushed}");
+    let request_uri = pushed["request_uri"].as_str().unwrap();
+    let params = [
+        ("client_id", client.id.as_str()),
+        ("request_uri", request_uri),
+    ];
+
+    let callback = authorize(&app, &cookie, &params).await;
+    assert_eq!(callback["state"], "pushed");
+    let (status, tokens) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &callback["code"]),
+            ("redirect_uri", REDIRECT_URI),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{tokens}");
+
+    let response = browser()
+        .get(format!("{}/oauth2/authorize", app.url))
+        .query(&params)
+        .header(COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+/// Tokens of the signed in user, from a code issued to the client.
+async fn user_tokens(app: &App, cookie: &str, client: &Client) -> Value {
+    let code = code(app, cookie, client).await;
+    let (status, body) = token(
+        app,
+        client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &code),
+            ("redirect_uri", REDIRECT_URI),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    body
+}
+
+#[tokio::test]
+async fn jwt_access_tokens_verify_with_the_published_keys_and_cannot_be_altered() {
+    let app = start_app().await;
+    let mut client = confidential_client();
+    client.access_token_format = AccessTokenFormat::Jwt;
+    let client = register(&app, client).await;
+    let (user, cookie) = signed_in_user(&app).await;
+
+    let tokens = user_tokens(&app, &cookie, &client).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let header = jsonwebtoken::decode_header(access_token).unwrap();
+    assert_eq!(header.typ.as_deref(), Some("at+jwt"));
+
+    let keys: jsonwebtoken::jwk::JwkSet = browser()
+        .get(format!("{}/oauth2/jwks", app.url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let key = keys.find(header.kid.as_deref().unwrap()).unwrap();
+    let issuer = &app.global.settings.oauth2.issuer;
+    let mut validation = jsonwebtoken::Validation::new(Algorithm::ES256);
+    validation.set_issuer(&[issuer]);
+    validation.set_audience(&[issuer]);
+    let claims = jsonwebtoken::decode::<Value>(
+        access_token,
+        &jsonwebtoken::DecodingKey::from_jwk(key).unwrap(),
+        &validation,
+    )
+    .unwrap()
+    .claims;
+    assert_eq!(claims["sub"], user.id.to_string());
+    assert_eq!(claims["client_id"], client.id.as_str());
+    assert!(claims["jti"].is_string());
+    let response = userinfo(&app, &format!("Bearer {access_token}"), None).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // someone else's subject under the original signature
+    let (other, _) = signed_in_user(&app).await;
+    let mut parts: Vec<String> = access_token.split('.').map(str::to_string).collect();
+    let mut altered = claims.clone();
+    altered["sub"] = json!(other.id.to_string());
+    parts[1] = BASE64_URL_SAFE_NO_PAD.encode(altered.to_string());
+    let response = userinfo(&app, &format!("Bearer {}", parts.join(".")), None).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(
+        jsonwebtoken::decode::<Value>(
+            &parts.join("."),
+            &jsonwebtoken::DecodingKey::from_jwk(key).unwrap(),
+            &validation,
+        )
+        .is_err()
+    );
+}
+
+#[tokio::test]
+async fn request_objects_have_to_come_from_the_client_and_be_meant_for_this_server() {
+    let app = start_app().await;
+    let key = meow_auth::keys::generate().unwrap();
+    let mut client = confidential_client();
+    client.jwks = Some(sqlx::types::Json(jsonwebtoken::jwk::JwkSet {
+        keys: vec![key.public_jwk.0.clone()],
+    }));
+    let client = register(&app, client).await;
+    let (_, cookie) = signed_in_user(&app).await;
+
+    let issuer = app.global.settings.oauth2.issuer.clone();
+    let signed = |iss: &str, aud: &str| {
+        let mut header = Header::new(Algorithm::ES256);
+        header.typ = Some("oauth-authz-req+jwt".into());
+        header.kid = key.public_jwk.0.common.key_id.clone();
+        let claims = json!({
+            "iss": iss,
+            "aud": aud,
+            "exp": chrono::Utc::now().timestamp() + 60,
+            "client_id": client.id,
+            "response_type": "code",
+            "redirect_uri": REDIRECT_URI,
+            "state": "signed",
+        });
+        jsonwebtoken::encode(
+            &header,
+            &claims,
+            &EncodingKey::from_ec_der(&key.private_key),
+        )
+        .unwrap()
+    };
+
+    let object = signed(&client.id, &issuer);
+    let callback = authorize(
+        &app,
+        &cookie,
+        &[("client_id", client.id.as_str()), ("request", &object)],
+    )
+    .await;
+    assert_eq!(callback["state"], "signed");
+    assert!(callback.contains_key("code"));
+
+    for object in [
+        signed("someone-else", &issuer),
+        signed(&client.id, "https://other-server.example.com"),
+    ] {
+        let response = browser()
+            .get(format!("{}/oauth2/authorize", app.url))
+            .query(&[("client_id", client.id.as_str()), ("request", &object)])
+            .header(COOKIE, &cookie)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
+
+#[tokio::test]
+async fn tokens_are_only_for_the_resources_granted_and_asked_for() {
+    let app = start_app().await;
+    let calendar = "https://calendar.example.com/";
+    let mail = "https://mail.example.com/";
+    let mut client = confidential_client();
+    client.audiences = vec![calendar.into(), mail.into()];
+    let client = register(&app, client).await;
+    let (_, cookie) = signed_in_user(&app).await;
+
+    let authorization = [
+        ("response_type", "code"),
+        ("client_id", client.id.as_str()),
+        ("redirect_uri", REDIRECT_URI),
+        ("resource", calendar),
+        ("resource", mail),
+    ];
+    let callback = authorize(&app, &cookie, &authorization).await;
+    let (status, tokens) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &callback["code"]),
+            ("redirect_uri", REDIRECT_URI),
+            ("resource", calendar),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{tokens}");
+    let access_token = tokens["access_token"].as_str().unwrap();
+    assert_eq!(
+        introspect(&app, &client, access_token).await["aud"],
+        json!([calendar])
+    );
+
+    // the refresh token is still good for everything granted, but nothing more
+    let refresh_token = tokens["refresh_token"].as_str().unwrap();
+    let (status, refreshed) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("resource", mail),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{refreshed}");
+    let access_token = refreshed["access_token"].as_str().unwrap();
+    assert_eq!(
+        introspect(&app, &client, access_token).await["aud"],
+        json!([mail])
+    );
+    let refresh_token = refreshed["refresh_token"].as_str().unwrap_or(refresh_token);
+    let (status, error) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token),
+            ("resource", "https://files.example.com/"),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(error["error"], "invalid_target");
+
+    for resource in [
+        "https://files.example.com/",
+        "https://calendar.example.com/#week",
+    ] {
+        let callback = authorize(
+            &app,
+            &cookie,
+            &[
+                ("response_type", "code"),
+                ("client_id", client.id.as_str()),
+                ("redirect_uri", REDIRECT_URI),
+                ("resource", resource),
+            ],
+        )
+        .await;
+        assert_eq!(callback["error"], "invalid_target", "{resource}");
+        assert!(!callback.contains_key("code"));
+    }
+}
+
+#[tokio::test]
+async fn binds_tokens_to_dpop_keys_at_protected_resources() {
+    let app = start_app().await;
+    let client = register(&app, confidential_client()).await;
+    let (_, cookie) = signed_in_user(&app).await;
+    let key = DPoPKey::generate();
+    let token_url = format!("{}/oauth2/token", app.url);
+    let userinfo_url = format!("{}/oauth2/userinfo", app.url);
+
+    let code = code(&app, &cookie, &client).await;
+    let redeem = [
+        ("grant_type", "authorization_code"),
+        ("code", code.as_str()),
+        ("redirect_uri", REDIRECT_URI),
+    ];
+    // the proof is checked before the code is looked at, so the code survives this
+    let wrong_target = key.proof("POST", &userinfo_url, None, None);
+    let (status, body) = token_with_proof(&app, &client, &redeem, Some(wrong_target)).await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    assert_eq!(body["error"], "invalid_dpop_proof");
+
+    let proof = key.proof("POST", &token_url, None, None);
+    let (status, body) = token_with_proof(&app, &client, &redeem, Some(proof)).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["token_type"], "DPoP");
+    let access_token = body["access_token"].as_str().unwrap();
+    let authorization = format!("DPoP {access_token}");
+
+    let proof = key.proof("GET", &userinfo_url, Some(access_token), None);
+    let response = userinfo(&app, &authorization, Some(&proof)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // every proof is good for one request
+    let replayed = userinfo(&app, &authorization, Some(&proof)).await;
+    assert_eq!(replayed.status(), StatusCode::UNAUTHORIZED);
+    assert!(challenge(&replayed).starts_with(r#"DPoP error="invalid_dpop_proof", algs=""#));
+    assert!(challenge(&replayed).contains("ES256"));
+
+    let without_ath = key.proof("GET", &userinfo_url, None, None);
+    let response = userinfo(&app, &authorization, Some(&without_ath)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(challenge(&response).starts_with(r#"DPoP error="invalid_dpop_proof""#));
+
+    let other_token = key.proof("GET", &userinfo_url, Some("another-token"), None);
+    let response = userinfo(&app, &authorization, Some(&other_token)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(challenge(&response).starts_with(r#"DPoP error="invalid_dpop_proof""#));
+
+    let missing = userinfo(&app, &authorization, None).await;
+    assert_eq!(missing.status(), StatusCode::UNAUTHORIZED);
+    assert!(challenge(&missing).starts_with(r#"DPoP error="invalid_dpop_proof""#));
+
+    // a valid proof of another key doesn't match the token's cnf.jkt
+    let stolen = DPoPKey::generate().proof("GET", &userinfo_url, Some(access_token), None);
+    let response = userinfo(&app, &authorization, Some(&stolen)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(challenge(&response).starts_with(r#"DPoP error="invalid_token""#));
+
+    let as_bearer = userinfo(&app, &format!("Bearer {access_token}"), None).await;
+    assert_eq!(as_bearer.status(), StatusCode::UNAUTHORIZED);
+    assert!(challenge(&as_bearer).starts_with(r#"DPoP error="invalid_token""#));
+
+    let introspected: Value = browser()
+        .post(format!("{}/oauth2/introspect", app.url))
+        .basic_auth(&client.id, Some(&client.secret))
+        .form(&[("token", access_token)])
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(introspected["cnf"]["jkt"], key.thumbprint());
+}
+
+#[tokio::test]
+async fn resources_can_require_dpop_nonces() {
+    let app = start_app_with(|settings| settings.oauth2.dpop_resource_require_nonce = true).await;
+    let client = register(&app, confidential_client()).await;
+    let (_, cookie) = signed_in_user(&app).await;
+    let key = DPoPKey::generate();
+    let userinfo_url = format!("{}/oauth2/userinfo", app.url);
+
+    let code = code(&app, &cookie, &client).await;
+    let proof = key.proof("POST", &format!("{}/oauth2/token", app.url), None, None);
+    let (status, body) = token_with_proof(
+        &app,
+        &client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+        ],
+        Some(proof),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK);
+    let access_token = body["access_token"].as_str().unwrap();
+    let authorization = format!("DPoP {access_token}");
+
+    let proof = key.proof("GET", &userinfo_url, Some(access_token), None);
+    let response = userinfo(&app, &authorization, Some(&proof)).await;
+    assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    assert!(challenge(&response).starts_with(r#"DPoP error="use_dpop_nonce""#));
+    let nonce = response.headers()["DPoP-Nonce"]
+        .to_str()
+        .unwrap()
+        .to_string();
+
+    let proof = key.proof("GET", &userinfo_url, Some(access_token), Some(&nonce));
+    let response = userinfo(&app, &authorization, Some(&proof)).await;
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+/// Whether the browser with the cookie is still asked before being signed out, rather than signed out already.
+async fn still_signed_in(app: &App, cookie: &str) -> bool {
+    let page = browser()
+        .get(format!("{}/oauth2/logout", app.url))
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(page.status(), StatusCode::OK);
+    page.text().await.unwrap().contains(r#"name="decision""#)
+}
+
+async fn logout_with_hint(app: &App, cookie: &str, hint: &str) -> reqwest::Response {
+    browser()
+        .get(format!("{}/oauth2/logout", app.url))
+        .query(&[("id_token_hint", hint)])
+        .header(COOKIE, cookie)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// The claims of a JWT, unverified.
+fn jwt_claims(token: &str) -> Value {
+    serde_json::from_slice(
+        &BASE64_URL_SAFE_NO_PAD
+            .decode(token.split('.').nth(1).unwrap())
+            .unwrap(),
+    )
+    .unwrap()
+}
+
+#[tokio::test]
+async fn id_tokens_name_the_session_and_end_it_as_logout_hints() {
+    let app = start_app().await;
+    let mut client = confidential_client();
+    client.access_token_format = AccessTokenFormat::Jwt;
+    let client = register(&app, client).await;
+    let (user, cookie) = signed_in_user(&app).await;
+
+    let callback = authorize(
+        &app,
+        &cookie,
+        &[
+            ("response_type", "code"),
+            ("client_id", client.id.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+            ("nonce", "n-0S6_WzA2Mj"),
+        ],
+    )
+    .await;
+    let (status, body) = token(
+        &app,
+        &client,
+        &[
+            ("grant_type", "authorization_code"),
+            ("code", &callback["code"]),
+            ("redirect_uri", REDIRECT_URI),
+        ],
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    let id_token = body["id_token"].as_str().expect("no id_token issued");
+    let claims = jwt_claims(id_token);
+    assert_eq!(claims["iss"], app.global.settings.oauth2.issuer);
+    assert_eq!(claims["aud"], client.id);
+    assert_eq!(claims["sub"], user.id.to_string());
+    assert_eq!(claims["nonce"], "n-0S6_WzA2Mj");
+    assert!(claims["sid"].is_string());
+
+    // signed with the same key and naming the same user, but for resources
+    let access_token = body["access_token"].as_str().unwrap();
+    let rejected = logout_with_hint(&app, &cookie, access_token).await;
+    assert_eq!(rejected.status(), StatusCode::BAD_REQUEST);
+    assert!(still_signed_in(&app, &cookie).await);
+
+    // the same user in another browser is asked first, the hint is for a different session
+    let jar = meow_auth::http::session::start_session(&app.global, CookieJar::new(), &user)
+        .await
+        .unwrap();
+    let other_browser = jar
+        .get(&app.global.settings.session.cookie_name)
+        .unwrap()
+        .stripped()
+        .to_string();
+    let asked = logout_with_hint(&app, &other_browser, id_token).await;
+    assert_eq!(asked.status(), StatusCode::OK);
+    assert!(still_signed_in(&app, &other_browser).await);
+
+    let logged_out = logout_with_hint(&app, &cookie, id_token).await;
+    assert_eq!(logged_out.status(), StatusCode::OK);
+    assert!(!still_signed_in(&app, &cookie).await);
+}
+
+#[tokio::test]
+async fn discovery_names_the_logout_endpoint_and_channels() {
+    let app = start_app().await;
+    let metadata: Value = browser()
+        .get(format!("{}/.well-known/openid-configuration", app.url))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let issuer = &app.global.settings.oauth2.issuer;
+    assert_eq!(metadata["issuer"], *issuer);
+    assert_eq!(
+        metadata["end_session_endpoint"],
+        format!("{issuer}/oauth2/logout")
+    );
+    assert_eq!(metadata["jwks_uri"], format!("{issuer}/oauth2/jwks"));
+    assert_eq!(metadata["frontchannel_logout_supported"], true);
+    assert_eq!(metadata["backchannel_logout_supported"], true);
+}
+
+/// A client's back-channel logout uri, answering with the given status and keeping the logout tokens it got.
+async fn backchannel_stand_in(status: StatusCode) -> (String, Arc<Mutex<Vec<String>>>) {
+    let tokens = Arc::new(Mutex::new(Vec::new()));
+    let received = tokens.clone();
+    let router = axum::Router::new().route(
+        "/logout",
+        axum::routing::post(
+            move |axum::Form(form): axum::Form<HashMap<String, String>>| async move {
+                received.lock().unwrap().push(form["logout_token"].clone());
+                status
+            },
+        ),
+    );
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let uri = format!("http://{}/logout", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (uri, tokens)
+}
+
+#[tokio::test]
+async fn delivers_backchannel_logouts_and_retries_failed_ones_later() {
+    let app = start_app().await;
+    let (working_uri, delivered) = backchannel_stand_in(StatusCode::OK).await;
+    let (broken_uri, attempted) = backchannel_stand_in(StatusCode::INTERNAL_SERVER_ERROR).await;
+
+    let mut working = confidential_client();
+    working.backchannel_logout_uri = Some(working_uri);
+    let working = register(&app, working).await;
+    let mut broken = confidential_client();
+    broken.backchannel_logout_uri = Some(broken_uri);
+    let broken = register(&app, broken).await;
+
+    let (_, cookie) = signed_in_user(&app).await;
+    code(&app, &cookie, &working).await;
+    code(&app, &cookie, &broken).await;
+    let signed_out = browser()
+        .post(format!("{}/oauth2/logout", app.url))
+        .header(COOKIE, &cookie)
+        .form(&[("decision", "logout")])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(signed_out.status(), StatusCode::OK);
+
+    meow_auth::http::oauth2::logout::deliver_backchannel_logouts(&app.global)
+        .await
+        .unwrap();
+    let token = delivered
+        .lock()
+        .unwrap()
+        .pop()
+        .expect("nothing was delivered");
+    let claims = jwt_claims(&token);
+    assert_eq!(claims["aud"], working.id);
+    assert_eq!(attempted.lock().unwrap().len(), 1);
+
+    // the failed one waits for its retry, the delivered one is gone
+    meow_auth::http::oauth2::logout::deliver_backchannel_logouts(&app.global)
+        .await
+        .unwrap();
+    assert!(delivered.lock().unwrap().is_empty());
+    assert_eq!(attempted.lock().unwrap().len(), 1);
+}
+
+#[tokio::test]
+async fn exchanged_tokens_keep_to_the_scopes_of_the_subject_and_the_client() {
+    let app = start_app().await;
+    let api = format!(
+        "https://api-{}.example.com",
+        &meow_auth::crypto::generate_token()[..8]
+    );
+    let mut frontend = confidential_client();
+    frontend.audiences = vec![api.clone()];
+    let frontend = register(&app, frontend).await;
+    let backend = register(
+        &app,
+        DBClient::builder()
+            .name("backend".into())
+            .secret_hash(Some(meow_auth::crypto::hash_token(SECRET)))
+            .token_endpoint_auth_method(ClientAuthMethod::ClientSecretBasic)
+            .grant_types(vec![
+                "urn:ietf:params:oauth:grant-type:token-exchange".into(),
+            ])
+            .scopes(vec!["profile".into(), "email".into()])
+            .introspect_audiences(vec![api])
+            .build(),
+    )
+    .await;
+    let (_, cookie) = signed_in_user(&app).await;
+    let tokens = user_tokens(&app, &cookie, &frontend).await;
+    assert_eq!(tokens["scope"], "openid profile");
+    let subject_token = tokens["access_token"].as_str().unwrap();
+
+    let exchange = |scope: Option<&'static str>| {
+        let mut params = vec![
+            (
+                "grant_type",
+                "urn:ietf:params:oauth:grant-type:token-exchange",
+            ),
+            ("subject_token", subject_token),
+            (
+                "subject_token_type",
+                "urn:ietf:params:oauth:token-type:access_token",
+            ),
+        ];
+        if let Some(scope) = scope {
+            params.push(("scope", scope));
+        }
+        let app = &app;
+        let backend = &backend;
+        async move { token(app, backend, &params).await }
+    };
+
+    // openid is the subject's but not the backend's, email the backend's but not the subject's
+    let (status, body) = exchange(None).await;
+    assert_eq!(status, StatusCode::OK, "{body}");
+    assert_eq!(body["scope"], "profile");
+    assert_eq!(
+        body["issued_token_type"],
+        "urn:ietf:params:oauth:token-type:access_token"
+    );
+
+    for scope in ["openid", "email", "profile email"] {
+        let (status, body) = exchange(Some(scope)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{scope}");
+        assert_eq!(body["error"], "invalid_scope");
+    }
+}
+
+#[tokio::test]
+async fn introspection_shows_pairwise_subjects_for_every_token() {
+    let app = start_app().await;
+    let mut pairwise = confidential_client();
+    pairwise.subject_type = SubjectType::Pairwise;
+    let pairwise = register(&app, pairwise).await;
+    let (user, cookie) = signed_in_user(&app).await;
+    let tokens = user_tokens(&app, &cookie, &pairwise).await;
+
+    let access = introspect(&app, &pairwise, tokens["access_token"].as_str().unwrap()).await;
+    let refresh = introspect(&app, &pairwise, tokens["refresh_token"].as_str().unwrap()).await;
+    assert_eq!(access["active"], true);
+    assert_eq!(refresh["active"], true);
+    assert_eq!(refresh["token_type"], "refresh_token");
+    assert_ne!(access["sub"], user.id.to_string());
+    assert_eq!(refresh["sub"], access["sub"]);
+}
+
+/// A desktop app listening on a loopback port or claiming its own scheme, with no secret to keep.
+fn native_client() -> DBClient {
+    DBClient::builder()
+        .name("oauth2 test native app".into())
+        .token_endpoint_auth_method(ClientAuthMethod::None)
+        .grant_types(vec!["authorization_code".into()])
+        .scopes(vec!["openid".into()])
+        .redirect_uris(vec![
+            "http://127.0.0.1/callback".into(),
+            "com.example.app:/callback".into(),
+        ])
+        .application_type(ApplicationType::Native)
+        .first_party(true)
+        .build()
+}
+
+/// Where the authorization endpoint sent the browser, `None` when it showed an error page instead.
+async fn native_redirect(
+    app: &App,
+    cookie: &str,
+    client: &Client,
+    redirect_uri: &str,
+    pkce: bool,
+) -> Option<HashMap<String, String>> {
+    let challenge = meow_auth::crypto::sha256_base64url("native-app-code-verifier");
+    let mut params = vec![
+        ("response_type", "code"),
+        ("client_id", client.id.as_str()),
+        ("redirect_uri", redirect_uri),
+    ];
+    if pkce {
+        params.extend([
+            ("code_challenge", challenge.as_str()),
+            ("code_challenge_method", "S256"),
+        ]);
+    }
+    let response = authorization_response(app, cookie, &params).await;
+    if response.status() == StatusCode::BAD_REQUEST {
+        return None;
+    }
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    let location = response.headers()[LOCATION].to_str().unwrap();
+    assert!(location.starts_with(redirect_uri), "sent to {location}");
+    Some(query(location))
+}
+
+#[tokio::test]
+async fn native_apps_are_sent_back_to_any_loopback_port_and_their_own_scheme() {
+    let app = start_app().await;
+    let native = register(&app, native_client()).await;
+    let (_, cookie) = signed_in_user(&app).await;
+
+    // the OS hands the app whatever port is free (RFC 8252 section 7.3)
+    for redirect_uri in [
+        "http://127.0.0.1/callback",
+        "http://127.0.0.1:51004/callback",
+        "http://127.0.0.1:8080/callback",
+        "com.example.app:/callback",
+    ] {
+        let callback = native_redirect(&app, &cookie, &native, redirect_uri, true).await;
+        assert!(
+            callback.is_some_and(|callback| callback.contains_key("code")),
+            "{redirect_uri}"
+        );
+    }
+
+    for redirect_uri in [
+        // localhost can be made to resolve somewhere else (RFC 8252 section 8.3)
+        "http://localhost/callback",
+        "http://localhost:51004/callback",
+        "https://127.0.0.1:51004/callback",
+        "http://127.0.0.1:51004/other",
+        "http://127.0.0.2:51004/callback",
+        "com.example.app:/other",
+        "com.example.evil:/callback",
+    ] {
+        let callback = native_redirect(&app, &cookie, &native, redirect_uri, true).await;
+        assert!(callback.is_none(), "{redirect_uri}");
+    }
+
+    // only native apps get the port left open, web clients register the one they use
+    let mut web = confidential_client();
+    web.redirect_uris = vec!["http://127.0.0.1/callback".into()];
+    let web = register(&app, web).await;
+    let callback = native_redirect(&app, &cookie, &web, "http://127.0.0.1/callback", true).await;
+    assert!(callback.is_some());
+    let callback =
+        native_redirect(&app, &cookie, &web, "http://127.0.0.1:51004/callback", true).await;
+    assert!(callback.is_none());
+}
+
+#[tokio::test]
+async fn native_apps_are_public_and_use_pkce() {
+    let app = start_app().await;
+    let (_, cookie) = signed_in_user(&app).await;
+
+    let native = register(&app, native_client()).await;
+    let callback = native_redirect(&app, &cookie, &native, "com.example.app:/callback", false)
+        .await
+        .unwrap();
+    assert_eq!(callback["error"], "invalid_request");
+
+    // even one that got a secret somehow, the app's redirect can be intercepted on the device
+    let mut with_secret = native_client();
+    with_secret.secret_hash = Some(meow_auth::crypto::hash_token(SECRET));
+    with_secret.token_endpoint_auth_method = ClientAuthMethod::ClientSecretBasic;
+    let with_secret = register(&app, with_secret).await;
+    let callback = native_redirect(
+        &app,
+        &cookie,
+        &with_secret,
+        "com.example.app:/callback",
+        false,
+    )
+    .await
+    .unwrap();
+    assert_eq!(callback["error"], "invalid_request");
+    assert!(callback["error_description"].contains("native"));
+
+    let create = |args: &[&str]| {
+        let mut argv = vec![
+            "belt",
+            "clients",
+            "create",
+            "--name",
+            "native",
+            "--application-type",
+            "native",
+        ];
+        argv.extend_from_slice(args);
+        meow_auth::cli::Commands::try_parse_from(argv).unwrap()
+    };
+    let error = create(&[
+        "--auth-method",
+        "client-secret-basic",
+        "--redirect-uri",
+        "http://127.0.0.1/callback",
+    ])
+    .run()
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("public"), "{error}");
+
+    let error = create(&[
+        "--auth-method",
+        "none",
+        "--redirect-uri",
+        "http://localhost/callback",
+    ])
+    .run()
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("127.0.0.1"), "{error}");
+}
+
+#[tokio::test]
+async fn pairwise_clients_need_a_sector_of_their_own() {
+    let register = |args: &[&str]| {
+        let mut argv = vec![
+            "belt",
+            "clients",
+            "create",
+            "--name",
+            "pairwise",
+            "--subject-type",
+            "pairwise",
+        ];
+        argv.extend_from_slice(args);
+        meow_auth::cli::Commands::try_parse_from(argv).unwrap()
+    };
+
+    // every app on every device redirects to these, they would all share a sector
+    for redirect_uri in [
+        "http://127.0.0.1/callback",
+        "http://[::1]/callback",
+        "com.example.app:/callback",
+    ] {
+        let error = register(&["--redirect-uri", redirect_uri])
+            .run()
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("--sector-identifier-uri"),
+            "{redirect_uri}: {error}"
+        );
+    }
+
+    let error = register(&[
+        "--redirect-uri",
+        "https://one.example.com/callback",
+        "--redirect-uri",
+        "https://two.example.com/callback",
+    ])
+    .run()
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("different hosts"), "{error}");
+
+    let error = register(&[
+        "--redirect-uri",
+        "http://127.0.0.1/callback",
+        "--sector-identifier-uri",
+        "http://sector.example.com/uris.json",
+    ])
+    .run()
+    .await
+    .unwrap_err();
+    assert!(error.to_string().contains("https"), "{error}");
+}
+
+/// Tokens of the signed in user, from a code traded in at the token endpoint of the organization, if any.
+async fn organization_tokens(
+    app: &App,
+    cookie: &str,
+    client: &Client,
+    organization: Option<&DBOrganization>,
+) -> Value {
+    let code = code(app, cookie, client).await;
+    let mut request = browser()
+        .post(format!("{}/oauth2/token", app.url))
+        .basic_auth(&client.id, Some(&client.secret))
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code.as_str()),
+            ("redirect_uri", REDIRECT_URI),
+        ]);
+    if let Some(organization) = organization {
+        request = request.header("X-Organization", &organization.slug);
+    }
+    let response = request.send().await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn tokens_carry_the_roles_of_the_organization_they_are_requested_for() {
+    let app = start_app_with(|settings| settings.rbac.roles_in_tokens = true).await;
+    let (user, cookie) = signed_in_user(&app).await;
+    let suffix = suffix();
+
+    let mut transaction = app.global.database.begin().await.unwrap();
+    let mut organizations = Vec::new();
+    for name in ["acme", "globex"] {
+        let organization = DBOrganization::builder()
+            .slug(format!("{name}-{suffix}"))
+            .name(name.into())
+            .build();
+        organization.insert(&mut transaction).await.unwrap();
+        organizations.push(organization);
+    }
+    let mut roles = Vec::new();
+    for (name, organization) in [
+        ("everywhere", None),
+        ("acme", Some(organizations[0].id)),
+        ("globex", Some(organizations[1].id)),
+    ] {
+        let role = DBRole::builder().name(format!("{name}-{suffix}")).build();
+        role.insert(&mut transaction).await.unwrap();
+        DBRoleAssignment::builder()
+            .user_id(user.id)
+            .role_id(role.id)
+            .tenant_id(organization)
+            .build()
+            .insert(&mut transaction)
+            .await
+            .unwrap();
+        roles.push(role.name);
+    }
+    transaction.commit().await.unwrap();
+    let acme = &organizations[0];
+
+    let mut client = confidential_client();
+    client.access_token_format = AccessTokenFormat::Jwt;
+    let client = register(&app, client).await;
+
+    let tokens = organization_tokens(&app, &cookie, &client, Some(acme)).await;
+    let access_token = tokens["access_token"].as_str().unwrap();
+    let claims = jwt_claims(access_token);
+    assert_eq!(claims["roles"], json!([roles[1], roles[0]]));
+    assert_eq!(claims["org_id"], acme.id.to_string());
+    let introspection = introspect(&app, &client, access_token).await;
+    assert_eq!(introspection["roles"], claims["roles"]);
+    assert_eq!(introspection["org_id"], claims["org_id"]);
+
+    // outside of any organization only the roles held everywhere count
+    let tokens = organization_tokens(&app, &cookie, &client, None).await;
+    let claims = jwt_claims(tokens["access_token"].as_str().unwrap());
+    assert_eq!(claims["roles"], json!([roles[0]]));
+    assert!(claims.get("org_id").is_none());
+
+    // opaque tokens carry the same roles, resource servers see them through introspection
+    let client = register(&app, confidential_client()).await;
+    let tokens = organization_tokens(&app, &cookie, &client, Some(acme)).await;
+    let introspection = introspect(&app, &client, tokens["access_token"].as_str().unwrap()).await;
+    assert_eq!(introspection["active"], true);
+    assert_eq!(introspection["roles"], json!([roles[1], roles[0]]));
+    assert_eq!(introspection["org_id"], acme.id.to_string());
+}
@@ -0,0 +1,320 @@
+//! Manages roles and who has them, which takes permissions roles grant.
+//!
+//! Needs the development database with migrations applied, like the server itself.
+
+mod common;
+
+use axum::http::StatusCode;
+use axum::http::header::COOKIE;
+use common::{App, signed_in_user, start_app, suffix};
+use meow_auth::database::ids::UlidId;
+use meow_auth::database::models::organization::DBOrganization;
+use meow_auth::database::models::organization_member::{DBOrganizationMember, MembershipRole};
+use meow_auth::database::models::role::DBRole;
+use meow_auth::database::models::role_assignment::DBRoleAssignment;
+use meow_auth::database::models::user::DBUser;
+use reqwest::Method;
+use serde_json::{Value, json};
+
+async fn role_granting(app: &App, permissions: &[&str]) -> DBRole {
+    let role = DBRole::builder()
+        .name(format!("role-{}", suffix()))
+        .permissions(permissions.iter().map(ToString::to_string).collect())
+        .build();
+    let mut transaction = app.global.database.begin().await.unwrap();
+    role.insert(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+    role
+}
+
+async fn assign(app: &App, user: &DBUser, role: &DBRole, organization_id: Option<UlidId>) {
+    let mut transaction = app.global.database.begin().await.unwrap();
+    DBRoleAssignment::builder()
+        .user_id(user.id)
+        .role_id(role.id)
+        .tenant_id(organization_id)
+        .build()
+        .insert(&mut transaction)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+}
+
+/// An organization the users are members of.
+async fn organization_of(app: &App, members: &[&DBUser]) -> DBOrganization {
+    let organization = DBOrganization::builder()
+        .slug(format!("roles-{}", suffix()))
+        .name("Roles Inc".into())
+        .build();
+    let mut transaction = app.global.database.begin().await.unwrap();
+    organization.insert(&mut transaction).await.unwrap();
+    for member in members {
+        DBOrganizationMember::builder()
+            .tenant_id(organization.id)
+            .user_id(member.id)
+            .role(MembershipRole::Member)
+            .build()
+            .insert(&mut transaction)
+            .await
+            .unwrap();
+    }
+    transaction.commit().await.unwrap();
+    organization
+}
+
+async fn send(
+    app: &App,
+    method: Method,
+    path: &str,
+    cookie: Option<&str>,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = reqwest::Client::new().request(method, format!("{}{path}", app.url));
+    if let Some(cookie) = cookie {
+        request = request.header(COOKIE, cookie);
+    }
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    (status, response.json().await.unwrap_or(Value::Null))
+}
+
+#[tokio::test]
+async fn only_roles_held_everywhere_let_users_create_and_delete_roles() {
+    let app = start_app().await;
+    let body = || Some(json!({ "name": format!("created-{}", suffix()), "permissions": ["a:b"] }));
+
+    let (status, _) = send(&app, Method::POST, "/v1/roles", None, body()).await;
+    assert_eq!(status, StatusCode::UNAUTHORIZED);
+    let (_, nobody) = signed_in_user(&app).await;
+    let (status, _) = send(&app, Method::POST, "/v1/roles", Some(&nobody), body()).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // a role limited to an organization doesn't reach roles every organization has
+    let manager = role_granting(&app, &["roles:write"]).await;
+    let (limited, limited_cookie) = signed_in_user(&app).await;
+    let organization = organization_of(&app, &[&limited]).await;
+    assign(&app, &limited, &manager, Some(organization.id)).await;
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        &format!("/o/{}/v1/roles", organization.slug),
+        Some(&limited_cookie),
+        body(),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    let (admin, cookie) = signed_in_user(&app).await;
+    assign(&app, &admin, &manager, None).await;
+    let name = format!("created-{}", suffix());
+    let request =
+        json!({ "name": name, "description": "Reads roles", "permissions": ["roles:read"] });
+    let (status, created) = send(
+        &app,
+        Method::POST,
+        "/v1/roles",
+        Some(&cookie),
+        Some(request.clone()),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CREATED, "{created}");
+    assert_eq!(created["name"], name.as_str());
+    assert_eq!(created["permissions"], json!(["roles:read"]));
+    let (status, _) = send(
+        &app,
+        Method::POST,
+        "/v1/roles",
+        Some(&cookie),
+        Some(request),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT);
+
+    let role = format!("/v1/roles/{}", created["id"].as_str().unwrap());
+    let (status, _) = send(&app, Method::DELETE, &role, Some(&nobody), None).await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, Method::DELETE, &role, Some(&cookie), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, Method::DELETE, &role, Some(&cookie), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
+
+#[tokio::test]
+async fn assigns_roles_within_the_organization_the_request_is_for() {
+    let app = start_app().await;
+    let assigner = role_granting(&app, &["roles:assign"]).await;
+    let role = role_granting(&app, &["things:read"]).await;
+    let (admin, cookie) = signed_in_user(&app).await;
+    let (member, member_cookie) = signed_in_user(&app).await;
+    let (outsider, _) = signed_in_user(&app).await;
+    let organization = organization_of(&app, &[&admin, &member]).await;
+    let other = organization_of(&app, &[&admin, &member]).await;
+    assign(&app, &admin, &assigner, Some(organization.id)).await;
+    let assignment = |slug: Option<&str>, user: &DBUser| {
+        let prefix = slug.map(|slug| format!("/o/{slug}")).unwrap_or_default();
+        format!("{prefix}/v1/roles/{}/assignments/{}", role.id, user.id)
+    };
+
+    let in_organization = assignment(Some(&organization.slug), &member);
+    let (status, _) = send(
+        &app,
+        Method::PUT,
+        &in_organization,
+        Some(&member_cookie),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, Method::PUT, &in_organization, Some(&cookie), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (_, roles) = send(
+        &app,
+        Method::GET,
+        "/v1/me/roles",
+        Some(&member_cookie),
+        None,
+    )
+    .await;
+    assert_eq!(roles.as_array().unwrap().len(), 1);
+    assert_eq!(roles[0]["role"]["name"], role.name.as_str());
+    assert_eq!(roles[0]["organization_id"], organization.id.to_string());
+
+    // nowhere else, and only for members
+    for path in [
+        assignment(None, &member),
+        assignment(Some(&other.slug), &member),
+    ] {
+        let (status, _) = send(&app, Method::PUT, &path, Some(&cookie), None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN, "{path}");
+    }
+    let not_a_member = assignment(Some(&organization.slug), &outsider);
+    let (status, _) = send(&app, Method::PUT, &not_a_member, Some(&cookie), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _) = send(&app, Method::DELETE, &in_organization, Some(&cookie), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(&app, Method::DELETE, &in_organization, Some(&cookie), None).await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    let (_, roles) = send(
+        &app,
+        Method::GET,
+        "/v1/me/roles",
+        Some(&member_cookie),
+        None,
+    )
+    .await;
+    assert_eq!(roles, json!([]));
+}
+
+/// Changes what the user is in the organization.
+async fn make(app: &App, organization: &DBOrganization, user: &DBUser, role: MembershipRole) {
+    let mut transaction = app.global.database.begin().await.unwrap();
+    DBOrganizationMember::builder()
+        .tenant_id(organization.id)
+        .user_id(user.id)
+        .role(role)
+        .build()
+        .update_role(&mut transaction)
+        .await
+        .unwrap();
+    transaction.commit().await.unwrap();
+}
+
+#[tokio::test]
+async fn membership_roles_and_assigned_roles_grant_managing_members_alike() {
+    let app = start_app().await;
+    let (owner, _) = signed_in_user(&app).await;
+    let (admin, admin_cookie) = signed_in_user(&app).await;
+    let (manager, manager_cookie) = signed_in_user(&app).await;
+    let (member, member_cookie) = signed_in_user(&app).await;
+    let organization = organization_of(&app, &[&owner, &admin, &manager, &member]).await;
+    make(&app, &organization, &owner, MembershipRole::Owner).await;
+    make(&app, &organization, &admin, MembershipRole::Admin).await;
+    let managing = role_granting(&app, &["organizations:manage"]).await;
+    assign(&app, &manager, &managing, Some(organization.id)).await;
+    let member_path = |user: &DBUser| {
+        format!(
+            "/o/{}/v1/organization/members/{}",
+            organization.slug, user.id
+        )
+    };
+    let to = |role: &str| Some(json!({ "role": role }));
+
+    // plain members manage nobody
+    let (status, _) = send(
+        &app,
+        Method::PUT,
+        &member_path(&manager),
+        Some(&member_cookie),
+        to("admin"),
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+
+    // the assigned role lets a plain member do what admins do, and no more
+    for cookie in [&admin_cookie, &manager_cookie] {
+        let (status, _) = send(
+            &app,
+            Method::PUT,
+            &member_path(&member),
+            Some(cookie),
+            to("admin"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(
+            &app,
+            Method::PUT,
+            &member_path(&member),
+            Some(cookie),
+            to("member"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = send(
+            &app,
+            Method::PUT,
+            &member_path(&member),
+            Some(cookie),
+            to("owner"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _) = send(
+            &app,
+            Method::DELETE,
+            &member_path(&owner),
+            Some(cookie),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    // members leave on their own, and only the organization the role was assigned in counts
+    let other = organization_of(&app, &[&manager, &member]).await;
+    let elsewhere = format!("/o/{}/v1/organization/members/{}", other.slug, member.id);
+    let (status, _) = send(
+        &app,
+        Method::DELETE,
+        &elsewhere,
+        Some(&manager_cookie),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let (status, _) = send(&app, Method::DELETE, &elsewhere, Some(&member_cookie), None).await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+    let (status, _) = send(
+        &app,
+        Method::DELETE,
+        &member_path(&member),
+        Some(&manager_cookie),
+        None,
+    )
+    .await;
+    assert_eq!(status, StatusCode::NO_CONTENT);
+}
@@ -0,0 +1,673 @@
+//! Signs and checks SAML messages, and signs into a service provider through the identity provider endpoints.
+//!
+//! Needs the development database with migrations applied, like the server itself. The keys are in
+//! `tests/fixtures/saml`, made with `openssl req -x509 -newkey rsa:2048 -nodes -days 36500`.
+
+mod common;
+
+use axum::http::StatusCode;
+use axum::http::header::{COOKIE, LOCATION, SET_COOKIE};
+use base64::Engine;
+use base64::prelude::BASE64_STANDARD;
+use chrono::{DateTime, Duration, Utc};
+use common::{App, browser, signed_in_user};
+use meow_auth::database::models::saml_service_provider::DBSamlServiceProvider;
+use meow_auth::saml::assertion::{ExpectedResponse, VerifiedAssertion, verify_response};
+use meow_auth::saml::response::Assertion;
+use meow_auth::saml::signature::{SamlKey, pem_to_der, verify_enveloped};
+use meow_auth::saml::xml::Element;
+use meow_auth::saml::{ASSERTION, NAME_ID_PERSISTENT, PROTOCOL, SamlError, c14n, instant};
+use meow_auth::settings::{SamlProvider, Settings};
+use roxmltree::Document;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::net::TcpListener;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/saml")
+        .join(name)
+}
+
+fn key(name: &str) -> SamlKey {
+    SamlKey::load(
+        &fixture(&format!("{name}.key")),
+        &fixture(&format!("{name}.crt")),
+    )
+    .unwrap()
+}
+
+/// The DER certificate of a fixture key.
+fn certificate(name: &str) -> Vec<u8> {
+    let pem = std::fs::read_to_string(fixture(&format!("{name}.crt"))).unwrap();
+    pem_to_der(&pem, "CERTIFICATE").unwrap()
+}
+
+fn assertion(id: &str, name_id: &str) -> Element {
+    Element::new("saml:Assertion")
+        .namespace("saml", ASSERTION)
+        .attribute("ID", id)
+        .attribute("Version", "2.0")
+        .child(Element::new("saml:Issuer").text("https://idp.example.com"))
+        .child(Element::new("saml:Subject").child(Element::new("saml:NameID").text(name_id)))
+}
+
+fn signed_assertion(id: &str, name_id: &str) -> String {
+    let mut assertion = assertion(id, name_id);
+    key("signing").sign_element(&mut assertion, id, 1);
+    assertion.to_canonical_string()
+}
+
+/// A response around the given assertions, written out by hand so anything can go into it.
+fn response(assertions: &str) -> String {
+    format!(
+        r#"<samlp:Response xmlns:samlp="{PROTOCOL}" ID="_response" Version="2.0">{assertions}</samlp:Response>"#
+    )
+}
+
+/// Checks the signature of the first element with the ID against the signing key's certificate.
+fn verify(xml: &str, id: &str) -> Result<(), SamlError> {
+    let document = Document::parse(xml).unwrap();
+    let element = document
+        .descendants()
+        .find(|node| node.attribute("ID") == Some(id))
+        .unwrap();
+    verify_enveloped(element, &[certificate("signing")])
+}
+
+fn between<'a>(xml: &'a str, start: &str, end: &str) -> &'a str {
+    let from = xml.find(start).unwrap() + start.len();
+    let to = from + xml[from..].find(end).unwrap();
+    &xml[from..to]
+}
+
+#[test]
+fn canonicalizes_received_xml_like_the_writer_writes_it() {
+    let written = signed_assertion("_canonical", "alice");
+    let document = Document::parse(&written).unwrap();
+    assert_eq!(
+        c14n::canonicalize(document.root_element(), None, &[]),
+        written
+    );
+
+    // attribute order, empty element tags, unused namespaces and comments are all gone, the namespace an element
+    // inherits is declared on it
+    let received = format!(
+        r#"<samlp:Response xmlns:samlp="{PROTOCOL}" xmlns:saml="{ASSERTION}" xmlns:unused="urn:unused" Version="2.0" ID="_r"><!-- note --><saml:Issuer Format="x" /></samlp:Response>"#
+    );
+    let document = Document::parse(&received).unwrap();
+    assert_eq!(
+        c14n::canonicalize(document.root_element(), None, &[]),
+        format!(
+            r#"<samlp:Response xmlns:samlp="{PROTOCOL}" ID="_r" Version="2.0"><saml:Issuer xmlns:saml="{ASSERTION}" Format="x"></saml:Issuer></samlp:Response>"#
+        )
+    );
+    let issuer = document.root_element().first_element_child().unwrap();
+    assert_eq!(
+        c14n::canonicalize(issuer, None, &[]),
+        format!(r#"<saml:Issuer xmlns:saml="{ASSERTION}" Format="x"></saml:Issuer>"#)
+    );
+}
+
+#[test]
+fn signed_elements_verify_until_anything_in_them_changes() {
+    let signed = signed_assertion("_signed", "alice");
+    verify(&signed, "_signed").unwrap();
+    verify(&response(&signed), "_signed").unwrap();
+
+    let document = Document::parse(&signed).unwrap();
+    assert!(verify_enveloped(document.root_element(), &[certificate("other")]).is_err());
+    let mut other_key = assertion("_signed", "alice");
+    key("other").sign_element(&mut other_key, "_signed", 1);
+    assert!(verify(&other_key.to_canonical_string(), "_signed").is_err());
+
+    let changed = signed.replace(">alice<", ">mallory<");
+    assert!(verify(&changed, "_signed").is_err());
+
+    let digest = between(&signed, "<ds:DigestValue>", "</ds:DigestValue>");
+    let changed = signed.replace(digest, &BASE64_STANDARD.encode([0; 32]));
+    assert!(verify(&changed, "_signed").is_err());
+
+    let signature = between(&signed, "<ds:SignatureValue>", "</ds:SignatureValue>");
+    let other_signature = BASE64_STANDARD.encode(key("signing").sign(b"something else"));
+    let changed = signed.replace(signature, &other_signature);
+    assert!(verify(&changed, "_signed").is_err());
+
+    let unsigned = assertion("_unsigned", "alice").to_canonical_string();
+    assert!(verify(&unsigned, "_unsigned").is_err());
+}
+
+#[test]
+fn refuses_comments_and_processing_instructions_in_signed_content() {
+    // canonicalization drops both, the digest would still match while the NameID reads differently
+    let signed = signed_assertion("_comments", "alice@example.com.evil.test");
+    verify(&signed, "_comments").unwrap();
+
+    for inserted in ["<!---->", "<?pi?>"] {
+        let changed = signed.replace(".evil.test<", &format!("{inserted}.evil.test<"));
+        assert!(verify(&changed, "_comments").is_err(), "{inserted}");
+    }
+}
+
+#[test]
+fn refuses_duplicate_ids_and_references_to_other_elements() {
+    let signed = signed_assertion("_original", "alice");
+
+    let duplicated = response(&format!("{signed}{signed}"));
+    assert!(verify(&duplicated, "_original").is_err());
+
+    let impostor = assertion("_original", "mallory").to_canonical_string();
+    assert!(verify(&response(&format!("{impostor}{signed}")), "_original").is_err());
+    assert!(verify(&response(&format!("{signed}{impostor}")), "_original").is_err());
+
+    // a signature over another element's ID, and one whose reference is to the element next to it
+    let mut elsewhere = assertion("_here", "alice");
+    key("signing").sign_element(&mut elsewhere, "_elsewhere", 1);
+    assert!(verify(&elsewhere.to_canonical_string(), "_here").is_err());
+
+    let mut next_to = assertion("_next", "mallory");
+    key("signing").sign_element(&mut next_to, "_original", 1);
+    let both = response(&format!("{signed}{}", next_to.to_canonical_string()));
+    verify(&both, "_original").unwrap();
+    assert!(verify(&both, "_next").is_err());
+}
+
+#[test]
+fn refuses_elements_added_to_signed_content() {
+    let mut signed_response = Element::new("samlp:Response")
+        .namespace("samlp", PROTOCOL)
+        .attribute("ID", "_response")
+        .attribute("Version", "2.0")
+        .child(
+            Element::new("saml:Issuer")
+                .namespace("saml", ASSERTION)
+                .text("https://idp.example.com"),
+        )
+        .child(assertion("_inside", "alice"));
+    key("signing").sign_element(&mut signed_response, "_response", 1);
+    let signed = signed_response.to_canonical_string();
+    verify(&signed, "_response").unwrap();
+
+    let unsigned = assertion("_added", "mallory").to_canonical_string();
+    let added = signed.replace("</samlp:Response>", &format!("{unsigned}</samlp:Response>"));
+    assert!(verify(&added, "_response").is_err());
+}
+
+#[test]
+fn refuses_other_algorithms_and_transforms() {
+    let signed = signed_assertion("_algorithms", "alice");
+    let enveloped = r#"<ds:Transform Algorithm="http://www.w3.org/2000/09/xmldsig#enveloped-signature"></ds:Transform>"#;
+    let exclusive =
+        r#"<ds:Transform Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#"></ds:Transform>"#;
+    assert!(signed.contains(enveloped) && signed.contains(exclusive));
+
+    for (from, to) in [
+        (
+            "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256",
+            "http://www.w3.org/2000/09/xmldsig#rsa-sha1",
+        ),
+        (
+            "http://www.w3.org/2001/04/xmlenc#sha256",
+            "http://www.w3.org/2000/09/xmldsig#sha1",
+        ),
+        (
+            r#"<ds:CanonicalizationMethod Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#">"#,
+            r#"<ds:CanonicalizationMethod Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#WithComments">"#,
+        ),
+        (enveloped, ""),
+        (exclusive, ""),
+        (
+            exclusive,
+            r#"<ds:Transform Algorithm="http://www.w3.org/2001/10/xml-exc-c14n#WithComments"></ds:Transform>"#,
+        ),
+        (
+            enveloped,
+            r#"<ds:Transform Algorithm="http://www.w3.org/TR/1999/REC-xpath-19991116"></ds:Transform>"#,
+        ),
+    ] {
+        let changed = signed.replace(from, to);
+        assert_ne!(changed, signed);
+        assert!(verify(&changed, "_algorithms").is_err(), "{from} -> {to}");
+    }
+}
+
+async fn start_app() -> App {
+    start_app_with(|_| {}).await
+}
+
+/// Runs the server signing with the fixture key.
+async fn start_app_with(configure: impl FnOnce(&mut Settings)) -> App {
+    common::start_app_with(|settings| {
+        settings.saml.private_key_path = Some(fixture("signing.key"));
+        settings.saml.certificate_path = Some(fixture("signing.crt"));
+        configure(settings);
+    })
+    .await
+}
+
+async fn service_provider(app: &App) -> DBSamlServiceProvider {
+    let suffix = &meow_auth::crypto::generate_token()[..8];
+    let sp = DBSamlServiceProvider::builder()
+        .entity_id(format!("https://sp-{suffix}.example.com"))
+        .name("Service provider".into())
+        .acs_url(format!("https://sp-{suffix}.example.com/acs"))
+        .name_id_format(NAME_ID_PERSISTENT.into())
+        .build();
+    let mut transaction = app.global.database.begin().await.unwrap();
+    sp.insert(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+    sp
+}
+
+#[tokio::test]
+async fn posted_authentication_requests_come_back_as_a_get_with_the_session() {
+    let app = start_app().await;
+    let sp = service_provider(&app).await;
+    let (_, cookie) = signed_in_user(&app).await;
+    let request = meow_auth::saml::response::authn_request(
+        &sp.entity_id,
+        &format!("{}/saml/sso", app.url),
+        &sp.acs_url,
+    );
+
+    // the post comes from the service provider's site, the session isn't looked at even when it's there
+    let posted = browser()
+        .post(format!("{}/saml/sso", app.url))
+        .header(COOKIE, &cookie)
+        .form(&[
+            ("SAMLRequest", BASE64_STANDARD.encode(request.unsigned())),
+            ("RelayState", "where-i-was".into()),
+        ])
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(posted.status(), StatusCode::SEE_OTHER);
+    let location = posted.headers()[LOCATION].to_str().unwrap().to_string();
+    assert!(location.starts_with("/saml/sso?SAMLRequest="), "{location}");
+    assert!(location.contains("&RelayState=where-i-was"), "{location}");
+
+    let signed_out = browser()
+        .get(format!("{}{location}", app.url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(signed_out.status(), StatusCode::SEE_OTHER);
+    assert!(
+        signed_out.headers()[LOCATION]
+            .to_str()
+            .unwrap()
+            .starts_with("/login?")
+    );
+
+    let page = browser()
+        .get(format!("{}{location}", app.url))
+        .header(COOKIE, &cookie)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(page.status(), StatusCode::OK);
+    let html = page.text().await.unwrap();
+    assert!(html.contains(&format!(r#"action="{}""#, sp.acs_url)));
+    assert!(html.contains(r#"name="RelayState" value="where-i-was""#));
+
+    let message = between(&html, r#"name="SAMLResponse" value=""#, r#"""#);
+    let xml = meow_auth::saml::decode_post(message).unwrap();
+    let verified = verify_response(
+        &xml,
+        &ExpectedResponse {
+            issuer: &format!("{}/saml/metadata", app.url),
+            certificates: &[certificate("signing")],
+            audience: &sp.entity_id,
+            recipient: &sp.acs_url,
+            in_response_to: &request.id,
+        },
+    )
+    .unwrap();
+    assert!(!verified.name_id.is_empty());
+}
+
+const IDP: &str = "https://idp.example.com";
+const SP: &str = "https://sp.example.com/login/saml/metadata";
+const ACS: &str = "https://sp.example.com/login/saml/acs";
+const REQUEST_ID: &str = "_request";
+
+/// What an upstream identity provider puts into its response, and which parts of it it signs.
+struct Upstream {
+    sign_response: bool,
+    sign_assertion: bool,
+    audience: &'static str,
+    recipient: &'static str,
+    in_response_to: &'static str,
+    not_on_or_after: DateTime<Utc>,
+}
+
+impl Default for Upstream {
+    fn default() -> Self {
+        Self {
+            sign_response: false,
+            sign_assertion: true,
+            audience: SP,
+            recipient: ACS,
+            in_response_to: REQUEST_ID,
+            not_on_or_after: Utc::now() + Duration::minutes(5),
+        }
+    }
+}
+
+fn upstream_assertion(upstream: &Upstream, id: &str, name_id: &str) -> Element {
+    let not_on_or_after = instant(upstream.not_on_or_after);
+    let mut assertion = Element::new("saml:Assertion")
+        .namespace("saml", ASSERTION)
+        .attribute("ID", id)
+        .attribute("Version", "2.0")
+        .attribute("IssueInstant", instant(Utc::now()))
+        .child(Element::new("saml:Issuer").text(IDP))
+        .child(
+            Element::new("saml:Subject")
+                .child(Element::new("saml:NameID").text(name_id))
+                .child(
+                    Element::new("saml:SubjectConfirmation")
+                        .attribute("Method", "urn:oasis:names:tc:SAML:2.0:cm:bearer")
+                        .child(
+                            Element::new("saml:SubjectConfirmationData")
+                                .attribute("InResponseTo", upstream.in_response_to)
+                                .attribute("NotOnOrAfter", not_on_or_after.clone())
+                                .attribute("Recipient", upstream.recipient),
+                        ),
+                ),
+        )
+        .child(
+            Element::new("saml:Conditions")
+                .attribute("NotOnOrAfter", not_on_or_after)
+                .child(
+                    Element::new("saml:AudienceRestriction")
+                        .child(Element::new("saml:Audience").text(upstream.audience)),
+                ),
+        );
+    if upstream.sign_assertion {
+        key("signing").sign_element(&mut assertion, id, 1);
+    }
+    assertion
+}
+
+fn upstream_response(upstream: &Upstream) -> String {
+    let mut response = Element::new("samlp:Response")
+        .namespace("samlp", PROTOCOL)
+        .attribute("ID", "_upstream")
+        .attribute("Version", "2.0")
+        .attribute("Destination", upstream.recipient)
+        .attribute("InResponseTo", upstream.in_response_to)
+        .child(
+            Element::new("saml:Issuer")
+                .namespace("saml", ASSERTION)
+                .text(IDP),
+        )
+        .child(Element::new("samlp:Status").child(
+            Element::new("samlp:StatusCode").attribute("Value", meow_auth::saml::STATUS_SUCCESS),
+        ))
+        .child(upstream_assertion(upstream, "_assertion", "alice"));
+    if upstream.sign_response {
+        key("signing").sign_element(&mut response, "_upstream", 1);
+    }
+    response.to_canonical_string()
+}
+
+/// An unsigned response around whatever an attacker put into it.
+fn forged_response(inside: &str) -> String {
+    format!(
+        r#"<samlp:Response xmlns:samlp="{PROTOCOL}" Destination="{ACS}" ID="_forged" InResponseTo="{REQUEST_ID}" Version="2.0"><saml:Issuer xmlns:saml="{ASSERTION}">{IDP}</saml:Issuer><samlp:Status><samlp:StatusCode Value="{}"></samlp:StatusCode></samlp:Status>{inside}</samlp:Response>"#,
+        meow_auth::saml::STATUS_SUCCESS
+    )
+}
+
+fn verify_upstream(xml: &str) -> Result<VerifiedAssertion, SamlError> {
+    verify_response(
+        xml,
+        &ExpectedResponse {
+            issuer: IDP,
+            certificates: &[certificate("signing")],
+            audience: SP,
+            recipient: ACS,
+            in_response_to: REQUEST_ID,
+        },
+    )
+}
+
+#[test]
+fn accepts_responses_with_either_the_response_or_the_assertion_signed() {
+    for (sign_response, sign_assertion) in [(false, true), (true, false), (true, true)] {
+        let xml = upstream_response(&Upstream {
+            sign_response,
+            sign_assertion,
+            ..Upstream::default()
+        });
+        let verified = verify_upstream(&xml).unwrap();
+        assert_eq!(verified.name_id, "alice");
+    }
+
+    let unsigned = upstream_response(&Upstream {
+        sign_assertion: false,
+        ..Upstream::default()
+    });
+    assert!(verify_upstream(&unsigned).is_err());
+
+    // signed, by someone else
+    let signed = upstream_response(&Upstream::default());
+    let document = Document::parse(&signed).unwrap();
+    let assertion = document
+        .descendants()
+        .find(|node| node.has_tag_name((ASSERTION, "Assertion")))
+        .unwrap();
+    assert!(verify_enveloped(assertion, &[certificate("other")]).is_err());
+}
+
+#[test]
+fn refuses_wrapped_assertions() {
+    let upstream = Upstream::default();
+    let signed = upstream_assertion(&upstream, "_assertion", "alice").to_canonical_string();
+    let forged = upstream_assertion(
+        &Upstream {
+            sign_assertion: false,
+            ..Upstream::default()
+        },
+        "_forged_assertion",
+        "mallory",
+    )
+    .to_canonical_string();
+    let same_id = forged.replace("_forged_assertion", "_assertion");
+
+    for inside in [
+        // the signed assertion tucked away where nobody reads it
+        format!("<samlp:Extensions>{signed}</samlp:Extensions>{forged}"),
+        format!("<samlp:Extensions>{signed}</samlp:Extensions>{same_id}"),
+        format!("{forged}{signed}"),
+        format!("{signed}{forged}"),
+        format!("{same_id}{signed}"),
+    ] {
+        assert!(
+            verify_upstream(&forged_response(&inside)).is_err(),
+            "{inside}"
+        );
+    }
+
+    // a response signed as a whole, with an unsigned assertion added afterwards
+    let signed_response = upstream_response(&Upstream {
+        sign_response: true,
+        sign_assertion: false,
+        ..Upstream::default()
+    });
+    verify_upstream(&signed_response).unwrap();
+    let added = signed_response.replace("</samlp:Response>", &format!("{forged}</samlp:Response>"));
+    assert!(verify_upstream(&added).is_err());
+    let nested = forged_response(&format!(
+        "<samlp:Extensions>{signed_response}</samlp:Extensions>{forged}"
+    ));
+    assert!(verify_upstream(&nested).is_err());
+}
+
+#[test]
+fn refuses_assertions_meant_for_others_or_for_later() {
+    let refused = [
+        Upstream {
+            audience: "https://other-sp.example.com",
+            ..Upstream::default()
+        },
+        Upstream {
+            recipient: "https://other-sp.example.com/acs",
+            ..Upstream::default()
+        },
+        Upstream {
+            in_response_to: "_another_request",
+            ..Upstream::default()
+        },
+        Upstream {
+            not_on_or_after: Utc::now() - Duration::minutes(5),
+            ..Upstream::default()
+        },
+    ];
+    for upstream in refused {
+        for sign_response in [false, true] {
+            let xml = upstream_response(&Upstream {
+                sign_response,
+                ..upstream
+            });
+            assert!(verify_upstream(&xml).is_err(), "{xml}");
+        }
+    }
+}
+
+/// An upstream identity provider's metadata url, counting how often it is read.
+async fn metadata_stand_in(entity_id: &'static str) -> (String, Arc<AtomicUsize>) {
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counter = reads.clone();
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let xml = meow_auth::saml::metadata::identity_provider(entity_id, &base_url, &key("signing"));
+    let router = axum::Router::new().route(
+        "/metadata",
+        axum::routing::get(move || async move {
+            counter.fetch_add(1, Ordering::SeqCst);
+            xml
+        }),
+    );
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (format!("{base_url}/metadata"), reads)
+}
+
+/// Starts signing in at the provider. Returns the state cookie, the RelayState and the ID of the request.
+async fn start_upstream_login(app: &App, provider: &str) -> (String, String, String) {
+    let started = browser()
+        .get(format!("{}/login/saml/{provider}", app.url))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(started.status(), StatusCode::SEE_OTHER);
+    let state_cookie = started
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok()?.split(';').next())
+        .find(|pair| pair.starts_with("meow_session_saml="))
+        .unwrap()
+        .to_string();
+    let location = url::Url::parse(started.headers()[LOCATION].to_str().unwrap()).unwrap();
+    let param = |name: &str| {
+        location
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+            .unwrap()
+    };
+    let xml = meow_auth::saml::decode_redirect(&param("SAMLRequest")).unwrap();
+    let request = meow_auth::saml::AuthnRequest::parse(&xml).unwrap();
+    (state_cookie, param("RelayState"), request.id)
+}
+
+async fn post_to_acs(
+    app: &App,
+    state_cookie: &str,
+    relay_state: &str,
+    xml: &str,
+) -> reqwest::Response {
+    browser()
+        .post(format!("{}/login/saml/acs", app.url))
+        .header(COOKIE, state_cookie)
+        .form(&[
+            ("SAMLResponse", BASE64_STANDARD.encode(xml)),
+            ("RelayState", relay_state.into()),
+        ])
+        .send()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn signs_in_at_upstream_providers_reading_their_metadata_once() {
+    const UPSTREAM: &str = "https://upstream-idp.example.com";
+    let (metadata_url, reads) = metadata_stand_in(UPSTREAM).await;
+    let app = start_app_with(|settings| {
+        settings.upstream.saml_providers = vec![SamlProvider {
+            id: "upstream".into(),
+            name: "Upstream".into(),
+            metadata: metadata_url,
+            ..SamlProvider::default()
+        }];
+    })
+    .await;
+
+    let upstream_response = |in_response_to: &str, name_id: &str| {
+        meow_auth::saml::response::response(
+            &key("signing"),
+            &Assertion {
+                issuer: UPSTREAM,
+                recipient: &format!("{}/login/saml/acs", app.url),
+                in_response_to: Some(in_response_to),
+                audience: &format!("{}/login/saml/metadata", app.url),
+                name_id,
+                name_id_format: NAME_ID_PERSISTENT,
+                session_index: "_upstream_session",
+                attributes: Vec::new(),
+                lifetime: 300,
+            },
+        )
+    };
+    let name_id = format!("upstream-{}", &meow_auth::crypto::generate_token()[..8]);
+
+    let (state_cookie, relay_state, request_id) = start_upstream_login(&app, "upstream").await;
+    let signed_in = post_to_acs(
+        &app,
+        &state_cookie,
+        &relay_state,
+        &upstream_response(&request_id, &name_id),
+    )
+    .await;
+    assert_eq!(signed_in.status(), StatusCode::SEE_OTHER);
+    assert!(
+        signed_in
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .any(|value| value.to_str().unwrap().starts_with("meow_session="))
+    );
+
+    // answering another request doesn't sign anyone in
+    let (state_cookie, relay_state, _) = start_upstream_login(&app, "upstream").await;
+    let refused = post_to_acs(
+        &app,
+        &state_cookie,
+        &relay_state,
+        &upstream_response("_another_request", &name_id),
+    )
+    .await;
+    assert_ne!(refused.status(), StatusCode::SEE_OTHER);
+    assert!(
+        !refused
+            .headers()
+            .get_all(SET_COOKIE)
+            .iter()
+            .any(|value| value.to_str().unwrap().starts_with("meow_session="))
+    );
+
+    assert_eq!(reads.load(Ordering::SeqCst), 1);
+}
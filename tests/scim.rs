@@ -0,0 +1,556 @@
+//! Provisions users and groups over SCIM, the way an organization's identity provider does.
+//!
+//! Needs the development database with migrations applied, like the server itself.
+
+mod common;
+
+use axum::http::StatusCode;
+use common::{App, start_app, suffix};
+use meow_auth::database::models::organization::DBOrganization;
+use meow_auth::database::models::scim_token::DBScimToken;
+use reqwest::Method;
+use serde_json::{Value, json};
+
+const PATCH_OP: &str = "urn:ietf:params:scim:api:messages:2.0:PatchOp";
+
+/// A bearer token of a new organization, which has no users or groups yet.
+async fn scim_token(app: &App) -> String {
+    let organization = DBOrganization::builder()
+        .slug(format!("scim-{}", suffix()))
+        .name("SCIM Inc".into())
+        .build();
+    let secret = meow_auth::crypto::generate_token();
+    let token = DBScimToken::builder()
+        .token_hash(meow_auth::crypto::hash_token(&secret))
+        .tenant_id(organization.id)
+        .name("Test IdP".into())
+        .build();
+    let mut transaction = app.global.database.begin().await.unwrap();
+    organization.insert(&mut transaction).await.unwrap();
+    token.insert(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+    secret
+}
+
+async fn send(
+    app: &App,
+    token: &str,
+    method: Method,
+    path: &str,
+    body: Option<Value>,
+) -> (StatusCode, Value) {
+    let mut request = reqwest::Client::new()
+        .request(method, format!("{}/scim/v2{path}", app.url))
+        .bearer_auth(token);
+    if let Some(body) = body {
+        request = request.json(&body);
+    }
+    let response = request.send().await.unwrap();
+    let status = response.status();
+    let body = response.json().await.unwrap_or(Value::Null);
+    (status, body)
+}
+
+async fn create(app: &App, token: &str, path: &str, body: Value) -> Value {
+    let (status, created) = send(app, token, Method::POST, path, Some(body)).await;
+    assert_eq!(status, StatusCode::CREATED, "{created}");
+    created
+}
+
+/// A username no other test uses.
+fn username(name: &str) -> String {
+    format!("{name}-{}", &meow_auth::crypto::generate_token()[..8])
+}
+
+/// Lists users with the given filter, URL-encoded here.
+async fn list_users(app: &App, token: &str, filter: &str) -> (StatusCode, Value) {
+    let filter: String = url::form_urlencoded::byte_serialize(filter.as_bytes()).collect();
+    send(
+        app,
+        token,
+        Method::GET,
+        &format!("/Users?filter={filter}"),
+        None,
+    )
+    .await
+}
+
+/// The display names of the users a filter matches, sorted.
+async fn matching(app: &App, token: &str, filter: &str) -> Vec<String> {
+    let (status, list) = list_users(app, token, filter).await;
+    assert_eq!(status, StatusCode::OK, "{filter}: {list}");
+    let mut names: Vec<String> = list["Resources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|user| user["displayName"].as_str().unwrap().to_string())
+        .collect();
+    names.sort();
+    assert_eq!(list["totalResults"], names.len(), "{filter}");
+    names
+}
+
+async fn patch(app: &App, token: &str, path: &str, operations: Value) -> (StatusCode, Value) {
+    send(
+        app,
+        token,
+        Method::PATCH,
+        path,
+        Some(json!({ "schemas": [PATCH_OP], "Operations": operations })),
+    )
+    .await
+}
+
+/// The ids of the members of a group, sorted.
+fn member_ids(group: &Value) -> Vec<&str> {
+    let mut ids: Vec<&str> = group["members"]
+        .as_array()
+        .map(|members| {
+            members
+                .iter()
+                .map(|member| member["value"].as_str().unwrap())
+                .collect()
+        })
+        .unwrap_or_default();
+    ids.sort_unstable();
+    ids
+}
+
+#[tokio::test]
+async fn lists_page_by_page_and_looks_resources_up_by_name_and_external_id() {
+    let app = start_app().await;
+    let token = scim_token(&app).await;
+    let mut usernames = Vec::new();
+    for name in ["first", "second", "third"] {
+        let username = username(name);
+        create(
+            &app,
+            &token,
+            "/Users",
+            json!({ "userName": username, "externalId": format!("ext-{username}"), "displayName": name }),
+        )
+        .await;
+        usernames.push(username);
+    }
+
+    let (_, first_page) = send(&app, &token, Method::GET, "/Users?count=2", None).await;
+    assert_eq!(first_page["totalResults"], 3);
+    assert_eq!(first_page["startIndex"], 1);
+    assert_eq!(first_page["itemsPerPage"], 2);
+    assert_eq!(first_page["Resources"][0]["displayName"], "first");
+    assert_eq!(first_page["Resources"][1]["displayName"], "second");
+    let (_, last_page) = send(
+        &app,
+        &token,
+        Method::GET,
+        "/Users?startIndex=3&count=2",
+        None,
+    )
+    .await;
+    assert_eq!(last_page["totalResults"], 3);
+    assert_eq!(last_page["itemsPerPage"], 1);
+    assert_eq!(last_page["Resources"][0]["displayName"], "third");
+    let (_, past_the_end) = send(&app, &token, Method::GET, "/Users?startIndex=10", None).await;
+    assert_eq!(past_the_end["totalResults"], 3);
+    assert_eq!(past_the_end["itemsPerPage"], 0);
+
+    // userName isn't case exact
+    let second = &usernames[1];
+    let by_name = matching(
+        &app,
+        &token,
+        &format!(r#"userName eq "{}""#, second.to_uppercase()),
+    )
+    .await;
+    assert_eq!(by_name, ["second"]);
+    let by_external_id = matching(
+        &app,
+        &token,
+        &format!(r#"urn:ietf:params:scim:schemas:core:2.0:User:externalId eq "ext-{second}""#),
+    )
+    .await;
+    assert_eq!(by_external_id, ["second"]);
+    assert!(
+        matching(&app, &token, r#"userName eq "nobody""#)
+            .await
+            .is_empty()
+    );
+
+    // other organizations see none of them
+    let other = scim_token(&app).await;
+    let other = matching(&app, &other, &format!(r#"userName eq "{second}""#)).await;
+    assert!(other.is_empty());
+
+    create(&app, &token, "/Groups", json!({ "displayName": "Readers" })).await;
+    create(
+        &app,
+        &token,
+        "/Groups",
+        json!({ "displayName": "Writers", "externalId": "writers" }),
+    )
+    .await;
+    let (_, groups) = send(
+        &app,
+        &token,
+        Method::GET,
+        "/Groups?filter=displayName%20eq%20%22readers%22",
+        None,
+    )
+    .await;
+    assert_eq!(groups["totalResults"], 1);
+    assert_eq!(groups["Resources"][0]["displayName"], "Readers");
+    let (_, groups) = send(
+        &app,
+        &token,
+        Method::GET,
+        "/Groups?filter=externalId%20eq%20%22writers%22",
+        None,
+    )
+    .await;
+    assert_eq!(groups["totalResults"], 1);
+    assert_eq!(groups["Resources"][0]["displayName"], "Writers");
+    let (_, groups) = send(&app, &token, Method::GET, "/Groups?count=1", None).await;
+    assert_eq!(groups["totalResults"], 2);
+    assert_eq!(groups["itemsPerPage"], 1);
+}
+
+#[tokio::test]
+async fn filters_with_precedence_presence_and_substrings() {
+    let app = start_app().await;
+    let token = scim_token(&app).await;
+    let alice = username("alice");
+    let bob = username("bob");
+    create(
+        &app,
+        &token,
+        "/Users",
+        json!({
+            "userName": alice,
+            "externalId": "alice",
+            "displayName": "Alice Liddell",
+            "name": { "givenName": "Alice", "familyName": "Liddell" },
+        }),
+    )
+    .await;
+    create(
+        &app,
+        &token,
+        "/Users",
+        json!({ "userName": bob, "displayName": "Bob Builder", "active": false }),
+    )
+    .await;
+    create(
+        &app,
+        &token,
+        "/Users",
+        json!({
+            "userName": username("carol"),
+            "externalId": "carol",
+            "displayName": "Carol Danvers",
+            "name": { "givenName": "Carol", "familyName": "Danvers" },
+        }),
+    )
+    .await;
+
+    // and binds tighter than or, and parentheses and not say otherwise
+    assert_eq!(
+        matching(
+            &app,
+            &token,
+            &format!(r#"userName eq "{alice}" or userName eq "{bob}" and active eq false"#)
+        )
+        .await,
+        ["Alice Liddell", "Bob Builder"]
+    );
+    assert_eq!(
+        matching(
+            &app,
+            &token,
+            &format!(r#"(userName eq "{alice}" or userName eq "{bob}") and active eq false"#)
+        )
+        .await,
+        ["Bob Builder"]
+    );
+    assert_eq!(
+        matching(&app, &token, "not (active eq true)").await,
+        ["Bob Builder"]
+    );
+    assert_eq!(
+        matching(
+            &app,
+            &token,
+            r#"active eq true AND not (externalId eq "ALICE")"#
+        )
+        .await,
+        ["Carol Danvers"]
+    );
+
+    assert_eq!(
+        matching(&app, &token, "externalId pr").await,
+        ["Alice Liddell", "Carol Danvers"]
+    );
+    assert_eq!(
+        matching(&app, &token, "name.familyName pr").await,
+        ["Alice Liddell", "Carol Danvers"]
+    );
+    assert_eq!(
+        matching(&app, &token, r#"displayName co "LI""#).await,
+        ["Alice Liddell"]
+    );
+    assert_eq!(
+        matching(&app, &token, r#"name.familyName sw "dan""#).await,
+        ["Carol Danvers"]
+    );
+    assert_eq!(
+        matching(&app, &token, r#"displayName ew "er""#).await,
+        ["Bob Builder"]
+    );
+    assert_eq!(
+        matching(&app, &token, r#"emails[type eq "work"]"#).await,
+        Vec::<String>::new()
+    );
+}
+
+#[tokio::test]
+async fn rejects_invalid_filters_and_paths() {
+    let app = start_app().await;
+    let token = scim_token(&app).await;
+
+    for filter in [
+        "userName eq",
+        r#"userName is "alice""#,
+        r#"(userName eq "alice""#,
+        r#"userName eq "alice" or"#,
+        r#"userName eq "alice"#,
+        "not active eq true",
+    ] {
+        let (status, error) = list_users(&app, &token, filter).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{filter}");
+        assert_eq!(error["scimType"], "invalidFilter", "{filter}");
+    }
+
+    let user = create(
+        &app,
+        &token,
+        "/Users",
+        json!({ "userName": username("paths") }),
+    )
+    .await;
+    let location = format!("/Users/{}", user["id"].as_str().unwrap());
+    for path in [
+        r#"emails[type eq "work""#,
+        r#"emails[type eq "work"]value"#,
+        r#"name.givenName[value eq "x"]"#,
+        "name givenName",
+        "",
+    ] {
+        let (status, error) = patch(
+            &app,
+            &token,
+            &location,
+            json!([{ "op": "replace", "path": path, "value": "x" }]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST, "{path}");
+        assert_eq!(error["scimType"], "invalidPath", "{path}");
+    }
+
+    // nothing of a rejected request is applied
+    let (status, _) = patch(
+        &app,
+        &token,
+        &location,
+        json!([
+            { "op": "replace", "path": "displayName", "value": "Changed" },
+            { "op": "replace", "path": "emails[", "value": "x" },
+        ]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::BAD_REQUEST);
+    let (_, unchanged) = send(&app, &token, Method::GET, &location, None).await;
+    assert!(unchanged.get("displayName").is_none());
+}
+
+#[tokio::test]
+async fn patches_add_replace_and_remove_values_of_multi_valued_attributes() {
+    let app = start_app().await;
+    let token = scim_token(&app).await;
+    let mut ids = Vec::new();
+    for name in ["one", "two", "three"] {
+        let user = create(
+            &app,
+            &token,
+            "/Users",
+            json!({ "userName": username(name) }),
+        )
+        .await;
+        ids.push(user["id"].as_str().unwrap().to_string());
+    }
+    let mut sorted = ids.clone();
+    sorted.sort();
+    let group = create(
+        &app,
+        &token,
+        "/Groups",
+        json!({ "displayName": "Members", "members": [{ "value": ids[0] }] }),
+    )
+    .await;
+    let location = format!("/Groups/{}", group["id"].as_str().unwrap());
+
+    // adding keeps what is there and doesn't add anything twice
+    let (status, group) = patch(
+        &app,
+        &token,
+        &location,
+        json!([{
+            "op": "add",
+            "path": "members",
+            "value": [{ "value": ids[0] }, { "value": ids[1] }, { "value": ids[2] }],
+        }]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{group}");
+    assert_eq!(member_ids(&group), sorted);
+
+    // by a filter, and by the values themselves the way Azure does it
+    let (_, group) = patch(
+        &app,
+        &token,
+        &location,
+        json!([{ "op": "remove", "path": format!(r#"members[value eq "{}"]"#, ids[0]) }]),
+    )
+    .await;
+    let mut rest = vec![ids[1].as_str(), ids[2].as_str()];
+    rest.sort_unstable();
+    assert_eq!(member_ids(&group), rest);
+    let (_, group) = patch(
+        &app,
+        &token,
+        &location,
+        json!([{ "op": "Remove", "path": "members", "value": [{ "value": ids[1] }] }]),
+    )
+    .await;
+    assert_eq!(member_ids(&group), [ids[2].as_str()]);
+
+    let (_, group) = patch(
+        &app,
+        &token,
+        &location,
+        json!([{ "op": "replace", "path": "members", "value": [{ "value": ids[0] }] }]),
+    )
+    .await;
+    assert_eq!(member_ids(&group), [ids[0].as_str()]);
+    let (_, group) = patch(
+        &app,
+        &token,
+        &location,
+        json!([{ "op": "remove", "path": "members" }]),
+    )
+    .await;
+    assert!(member_ids(&group).is_empty());
+
+    // a user keeps one email, the primary one
+    let user = format!("/Users/{}", ids[0]);
+    let (_, patched) = patch(
+        &app,
+        &token,
+        &user,
+        json!([{
+            "op": "add",
+            "path": "emails",
+            "value": [{ "value": "first@example.com", "type": "work", "primary": true }],
+        }]),
+    )
+    .await;
+    assert_eq!(patched["emails"][0]["value"], "first@example.com");
+    let (_, patched) = patch(
+        &app,
+        &token,
+        &user,
+        json!([{ "op": "add", "path": "emails", "value": [{ "value": "home@example.com" }] }]),
+    )
+    .await;
+    assert_eq!(patched["emails"].as_array().unwrap().len(), 1);
+    assert_eq!(patched["emails"][0]["value"], "first@example.com");
+    let (_, patched) = patch(
+        &app,
+        &token,
+        &user,
+        json!([{
+            "op": "replace",
+            "path": r#"emails[type eq "work"].value"#,
+            "value": "second@example.com",
+        }]),
+    )
+    .await;
+    assert_eq!(patched["emails"][0]["value"], "second@example.com");
+    let (_, patched) = patch(
+        &app,
+        &token,
+        &user,
+        json!([{ "op": "remove", "path": r#"emails[type eq "work"]"# }]),
+    )
+    .await;
+    assert!(patched.get("emails").is_none(), "{patched}");
+}
+
+#[tokio::test]
+async fn user_names_and_emails_are_unique_across_tenants() {
+    let app = start_app().await;
+    let first = scim_token(&app).await;
+    let second = scim_token(&app).await;
+    let name = username("unique");
+    let email = format!("{name}@example.com");
+    create(
+        &app,
+        &first,
+        "/Users",
+        json!({ "userName": name, "emails": [{ "value": email, "primary": true }] }),
+    )
+    .await;
+
+    let taken = [
+        json!({ "userName": name }),
+        json!({ "userName": username("other"), "emails": [{ "value": email.to_uppercase() }] }),
+    ];
+    for body in taken {
+        let (status, error) = send(&app, &second, Method::POST, "/Users", Some(body)).await;
+        assert_eq!(status, StatusCode::CONFLICT, "{error}");
+        assert_eq!(error["scimType"], "uniqueness");
+    }
+
+    let own = username("own");
+    let user = create(
+        &app,
+        &second,
+        "/Users",
+        json!({ "userName": own, "emails": [{ "value": format!("{own}@example.com") }] }),
+    )
+    .await;
+    let location = format!("/Users/{}", user["id"].as_str().unwrap());
+
+    let (status, error) = patch(
+        &app,
+        &second,
+        &location,
+        json!([{ "op": "replace", "path": "emails", "value": [{ "value": email, "primary": true }] }]),
+    )
+    .await;
+    assert_eq!(status, StatusCode::CONFLICT, "{error}");
+    assert_eq!(error["scimType"], "uniqueness");
+
+    // keeping their own email is no clash
+    let (status, user) = send(
+        &app,
+        &second,
+        Method::PUT,
+        &location,
+        Some(json!({
+            "userName": own,
+            "displayName": "Own",
+            "emails": [{ "value": format!("{own}@example.com") }],
+        })),
+    )
+    .await;
+    assert_eq!(status, StatusCode::OK, "{user}");
+}
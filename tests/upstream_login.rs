@@ -0,0 +1,513 @@
+//! Signs in through a mock OpenID Connect provider running next to the server.
+//!
+//! Needs the development database with migrations applied, like the server itself.
+
+mod common;
+
+use axum::extract::{Query, State};
+use axum::http::StatusCode;
+use axum::http::header::{COOKIE, LOCATION, SET_COOKIE};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::routing::{get, post};
+use axum::{Form, Json, Router};
+use axum_extra::TypedHeader;
+use axum_extra::headers::Authorization;
+use axum_extra::headers::authorization::{Basic, Bearer};
+use common::{App, browser};
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use meow_auth::database::models::identity::DBIdentity;
+use meow_auth::database::models::organization::DBOrganization;
+use meow_auth::database::models::role::DBRole;
+use meow_auth::database::models::role_assignment::DBRoleAssignment;
+use meow_auth::database::models::user::DBUser;
+use meow_auth::settings::{ProvisioningRule, Settings, UpstreamProvider};
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tokio::net::TcpListener;
+
+const CLIENT_ID: &str = "meow";
+const CLIENT_SECRET: &str = "mock-secret";
+const ACCESS_TOKEN: &str = "mock-access-token";
+
+/// A code the mock handed out, with what it has to be redeemed with.
+struct PendingCode {
+    nonce: String,
+    code_challenge: String,
+    redirect_uri: String,
+}
+
+struct MockIdp {
+    issuer: String,
+    key: EncodingKey,
+    kid: String,
+    jwks: serde_json::Value,
+    subject: String,
+    username: String,
+    /// Sent as the `department` claim, tests change it in between sign ins
+    department: Mutex<String>,
+    codes: Mutex<HashMap<String, PendingCode>>,
+}
+
+async fn discovery(State(idp): State<Arc<MockIdp>>) -> Json<serde_json::Value> {
+    let issuer = &idp.issuer;
+    Json(json!({
+        "issuer": issuer,
+        "authorization_endpoint": format!("{issuer}/authorize"),
+        "token_endpoint": format!("{issuer}/token"),
+        "userinfo_endpoint": format!("{issuer}/userinfo"),
+        "jwks_uri": format!("{issuer}/jwks"),
+    }))
+}
+
+async fn jwks(State(idp): State<Arc<MockIdp>>) -> Json<serde_json::Value> {
+    Json(idp.jwks.clone())
+}
+
+/// Signs the user in right away and sends them back with a code.
+async fn authorize(
+    State(idp): State<Arc<MockIdp>>,
+    Query(params): Query<HashMap<String, String>>,
+) -> Response {
+    if params.get("client_id").map(String::as_str) != Some(CLIENT_ID)
+        || params.get("code_challenge_method").map(String::as_str) != Some("S256")
+    {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let code = meow_auth::crypto::generate_token();
+    let redirect_uri = params["redirect_uri"].clone();
+    idp.codes.lock().unwrap().insert(
+        code.clone(),
+        PendingCode {
+            nonce: params["nonce"].clone(),
+            code_challenge: params["code_challenge"].clone(),
+            redirect_uri: redirect_uri.clone(),
+        },
+    );
+
+    let mut url = url::Url::parse(&redirect_uri).unwrap();
+    url.query_pairs_mut()
+        .append_pair("code", &code)
+        .append_pair("state", &params["state"]);
+    Redirect::to(url.as_str()).into_response()
+}
+
+async fn token(
+    State(idp): State<Arc<MockIdp>>,
+    TypedHeader(Authorization(credentials)): TypedHeader<Authorization<Basic>>,
+    Form(params): Form<HashMap<String, String>>,
+) -> Response {
+    if credentials.username() != CLIENT_ID || credentials.password() != CLIENT_SECRET {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    let Some(pending) = idp.codes.lock().unwrap().remove(&params["code"]) else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+    if pending.redirect_uri != params["redirect_uri"]
+        || !meow_auth::crypto::verify_pkce_s256(&params["code_verifier"], &pending.code_challenge)
+    {
+        return StatusCode::BAD_REQUEST.into_response();
+    }
+
+    let now = chrono::Utc::now().timestamp();
+    let mut header = Header::new(Algorithm::ES256);
+    header.kid = Some(idp.kid.clone());
+    let claims = json!({
+        "iss": idp.issuer,
+        "aud": CLIENT_ID,
+        "sub": idp.subject,
+        "iat": now,
+        "exp": now + 300,
+        "nonce": pending.nonce,
+    });
+    let id_token = jsonwebtoken::encode(&header, &claims, &idp.key).unwrap();
+
+    Json(json!({
+        "access_token": ACCESS_TOKEN,
+        "token_type": "Bearer",
+        "id_token": id_token,
+    }))
+    .into_response()
+}
+
+async fn userinfo(
+    State(idp): State<Arc<MockIdp>>,
+    TypedHeader(Authorization(bearer)): TypedHeader<Authorization<Bearer>>,
+) -> Response {
+    if bearer.token() != ACCESS_TOKEN {
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+
+    Json(json!({
+        "sub": idp.subject,
+        "preferred_username": idp.username,
+        "email": format!("{}@example.com", idp.username),
+        "email_verified": true,
+        "department": *idp.department.lock().unwrap(),
+    }))
+    .into_response()
+}
+
+async fn start_mock_idp() -> Arc<MockIdp> {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let key = meow_auth::keys::generate().unwrap();
+    let suffix = &meow_auth::crypto::generate_token()[..8];
+
+    let idp = Arc::new(MockIdp {
+        issuer: format!("http://{}", listener.local_addr().unwrap()),
+        key: EncodingKey::from_ec_der(&key.private_key),
+        kid: key.id.to_string(),
+        jwks: json!({ "keys": [key.public_jwk.0] }),
+        subject: format!("mock-subject-{suffix}"),
+        username: format!("mock-user-{suffix}"),
+        department: Mutex::new("engineering".into()),
+        codes: Mutex::default(),
+    });
+
+    let router = Router::new()
+        .route("/.well-known/openid-configuration", get(discovery))
+        .route("/jwks", get(jwks))
+        .route("/authorize", get(authorize))
+        .route("/token", post(token))
+        .route("/userinfo", get(userinfo))
+        .with_state(idp.clone());
+    tokio::spawn(async move { axum::serve(listener, router).await });
+
+    idp
+}
+
+/// Runs the server with a provider for each of the mock providers, named after their ids.
+async fn start_app(idps: &[(&str, &MockIdp)]) -> App {
+    start_app_with(idps, |_| {}).await
+}
+
+async fn start_app_with(idps: &[(&str, &MockIdp)], configure: impl FnOnce(&mut Settings)) -> App {
+    common::start_app_with(|settings| {
+        settings.upstream.providers = idps
+            .iter()
+            .map(|(id, idp)| UpstreamProvider {
+                id: id.to_string(),
+                name: id.to_string(),
+                issuer: Some(idp.issuer.clone()),
+                client_id: CLIENT_ID.into(),
+                client_secret: CLIENT_SECRET.into(),
+                ..Default::default()
+            })
+            .collect();
+        configure(settings);
+    })
+    .await
+}
+
+fn location(response: &reqwest::Response) -> String {
+    response.headers()[LOCATION].to_str().unwrap().to_string()
+}
+
+/// The `name=value` part of the cookie the response sets with the given name, unless it removes the cookie.
+fn cookie(response: &reqwest::Response, name: &str) -> Option<String> {
+    response
+        .headers()
+        .get_all(SET_COOKIE)
+        .iter()
+        .filter_map(|value| value.to_str().ok()?.split(';').next())
+        .find(|pair| pair.starts_with(&format!("{name}=")) && pair.len() > name.len() + 1)
+        .map(String::from)
+}
+
+/// Follows the redirect to the mock provider and back like a browser would, up to the response of the callback.
+async fn finish_at_provider(
+    http: &reqwest::Client,
+    start: reqwest::Response,
+    session_cookie: Option<&str>,
+    send_state_cookie: bool,
+) -> reqwest::Response {
+    assert_eq!(start.status(), StatusCode::SEE_OTHER);
+    let state_cookie = cookie(&start, "meow_session_upstream").unwrap();
+
+    let authorize = http.get(location(&start)).send().await.unwrap();
+    assert_eq!(authorize.status(), StatusCode::SEE_OTHER);
+
+    let cookies: Vec<&str> = session_cookie
+        .into_iter()
+        .chain(send_state_cookie.then_some(state_cookie.as_str()))
+        .collect();
+    http.get(location(&authorize))
+        .header(COOKIE, cookies.join("; "))
+        .send()
+        .await
+        .unwrap()
+}
+
+async fn sign_in(app: &App, provider: &str, send_state_cookie: bool) -> reqwest::Response {
+    let http = browser();
+    let start = http
+        .get(format!(
+            "{}/login/upstream/{provider}?return_to=/welcome",
+            app.url
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    finish_at_provider(&http, start, None, send_state_cookie).await
+}
+
+#[tokio::test]
+async fn signs_in_and_creates_the_user_once() {
+    let idp = start_mock_idp().await;
+    let app = start_app(&[("mock", &idp)]).await;
+
+    let first = sign_in(&app, "mock", true).await;
+    assert_eq!(first.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&first), "/welcome");
+    assert!(cookie(&first, "meow_session").is_some());
+
+    let identity = DBIdentity::find_by_subject("mock", &idp.subject, &app.global.database)
+        .await
+        .unwrap()
+        .expect("the identity was not created");
+    let user = DBUser::find_by_id(identity.user_id, &app.global.database)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.username, idp.username);
+    assert_eq!(
+        user.email.as_deref(),
+        Some(format!("{}@example.com", idp.username).as_str())
+    );
+    assert!(user.password_hash.is_none());
+
+    let second = sign_in(&app, "mock", true).await;
+    assert_eq!(second.status(), StatusCode::SEE_OTHER);
+    let again = DBIdentity::find_by_subject("mock", &idp.subject, &app.global.database)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(again.user_id, user.id);
+}
+
+#[tokio::test]
+async fn leaves_out_an_email_another_account_has() {
+    let idp = start_mock_idp().await;
+    let app = start_app(&[("mock", &idp)]).await;
+    let email = format!("{}@example.com", idp.username);
+    let existing = DBUser::builder()
+        .username(format!("{}-local", idp.username))
+        .email(Some(email.to_uppercase()))
+        .build();
+    let mut transaction = app.global.database.begin().await.unwrap();
+    existing.insert(&mut transaction).await.unwrap();
+    transaction.commit().await.unwrap();
+
+    let response = sign_in(&app, "mock", true).await;
+    assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    assert!(cookie(&response, "meow_session").is_some());
+
+    let identity = DBIdentity::find_by_subject("mock", &idp.subject, &app.global.database)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_ne!(identity.user_id, existing.id);
+    let user = DBUser::find_by_id(identity.user_id, &app.global.database)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.username, idp.username);
+    assert!(user.email.is_none());
+}
+
+#[tokio::test]
+async fn rejects_callbacks_from_another_browser() {
+    let idp = start_mock_idp().await;
+    let app = start_app(&[("mock", &idp)]).await;
+
+    let response = sign_in(&app, "mock", false).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    assert!(cookie(&response, "meow_session").is_none());
+
+    let identity = DBIdentity::find_by_subject("mock", &idp.subject, &app.global.database)
+        .await
+        .unwrap();
+    assert!(identity.is_none());
+}
+
+#[tokio::test]
+async fn links_and_unlinks_identities_but_keeps_the_last() {
+    let first_idp = start_mock_idp().await;
+    let second_idp = start_mock_idp().await;
+    let app = start_app(&[("first", &first_idp), ("second", &second_idp)]).await;
+    let http = browser();
+
+    let signed_in = sign_in(&app, "first", true).await;
+    let session = cookie(&signed_in, "meow_session").unwrap();
+
+    let start = http
+        .post(format!("{}/login/upstream/second/link", app.url))
+        .header(COOKIE, &session)
+        .form(&[("return_to", "/linked")])
+        .send()
+        .await
+        .unwrap();
+    let linked = finish_at_provider(&http, start, Some(&session), true).await;
+    assert_eq!(linked.status(), StatusCode::SEE_OTHER);
+    assert_eq!(location(&linked), "/linked");
+
+    let identities: Vec<serde_json::Value> = http
+        .get(format!("{}/v1/me/identities", app.url))
+        .header(COOKIE, &session)
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let providers: Vec<&str> = identities
+        .iter()
+        .map(|identity| identity["provider"].as_str().unwrap())
+        .collect();
+    assert_eq!(providers, ["first", "second"]);
+
+    let unlink = |identity: &serde_json::Value| {
+        http.delete(format!(
+            "{}/v1/me/identities/{}",
+            app.url,
+            identity["id"].as_str().unwrap()
+        ))
+        .header(COOKIE, &session)
+        .send()
+    };
+    assert_eq!(
+        unlink(&identities[0]).await.unwrap().status(),
+        StatusCode::NO_CONTENT
+    );
+    assert_eq!(
+        unlink(&identities[1]).await.unwrap().status(),
+        StatusCode::CONFLICT
+    );
+
+    // the second provider signs in to the same account now
+    let again = sign_in(&app, "second", true).await;
+    assert_eq!(again.status(), StatusCode::SEE_OTHER);
+    let unlinked = identities[0]["id"].as_str().unwrap();
+    let first = DBIdentity::find_by_id(unlinked.parse().unwrap(), &app.global.database)
+        .await
+        .unwrap();
+    assert!(first.is_none());
+    let second = DBIdentity::find_by_subject("second", &second_idp.subject, &app.global.database)
+        .await
+        .unwrap()
+        .unwrap();
+    let user = DBUser::find_by_username(&first_idp.username, &app.global.database)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(second.user_id, user.id);
+}
+
+#[tokio::test]
+async fn provisions_users_by_the_rules() {
+    let contractor = start_mock_idp().await;
+    *contractor.department.lock().unwrap() = "contractors".into();
+    let employee = start_mock_idp().await;
+    let app = start_app_with(&[("mock", &contractor), ("other", &employee)], |settings| {
+        for provider in &mut settings.upstream.providers {
+            provider
+                .claims
+                .attributes
+                .insert("department".into(), "department".into());
+        }
+        settings.upstream.provisioning = vec![
+            ProvisioningRule {
+                name: "no contractors".into(),
+                claims: [("department".into(), "contractors".into())].into(),
+                create: false,
+                ..Default::default()
+            },
+            ProvisioningRule {
+                name: "employees".into(),
+                email_domains: vec!["EXAMPLE.com".into()],
+                organization: Some("acme".into()),
+                roles: vec!["developer".into()],
+                sync: vec!["department".into()],
+                ..Default::default()
+            },
+        ];
+    })
+    .await;
+    let developer = match DBRole::find_by_name("developer", &app.global.database)
+        .await
+        .unwrap()
+    {
+        Some(role) => role,
+        None => {
+            let role = DBRole::builder().name("developer".into()).build();
+            let mut transaction = app.global.database.begin().await.unwrap();
+            role.insert(&mut transaction).await.unwrap();
+            transaction.commit().await.unwrap();
+            role
+        }
+    };
+    let acme = match DBOrganization::find_by_slug("acme", &app.global.database)
+        .await
+        .unwrap()
+    {
+        Some(organization) => organization,
+        None => {
+            let organization = DBOrganization::builder()
+                .slug("acme".into())
+                .name("Acme".into())
+                .build();
+            let mut transaction = app.global.database.begin().await.unwrap();
+            organization.insert(&mut transaction).await.unwrap();
+            transaction.commit().await.unwrap();
+            organization
+        }
+    };
+
+    let turned_away = sign_in(&app, "mock", true).await;
+    assert_eq!(turned_away.status(), StatusCode::BAD_REQUEST);
+    assert!(cookie(&turned_away, "meow_session").is_none());
+    let identity = DBIdentity::find_by_subject("mock", &contractor.subject, &app.global.database)
+        .await
+        .unwrap();
+    assert!(identity.is_none());
+
+    let first = sign_in(&app, "other", true).await;
+    assert_eq!(first.status(), StatusCode::SEE_OTHER);
+    let identity = DBIdentity::find_by_subject("other", &employee.subject, &app.global.database)
+        .await
+        .unwrap()
+        .expect("the identity was not created");
+    let user = DBUser::find_by_id(identity.user_id, &app.global.database)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(
+        user.attributes,
+        json!({ "department": "engineering", "organization": "acme" })
+    );
+    let roles = DBRoleAssignment::find_roles(user.id, None, &app.global.database)
+        .await
+        .unwrap();
+    assert_eq!(roles.len(), 1);
+    assert_eq!(roles[0].id, developer.id);
+    let organizations = DBOrganization::find_many_by_member(user.id, &app.global.database)
+        .await
+        .unwrap();
+    assert!(
+        organizations
+            .iter()
+            .any(|organization| organization.id == acme.id)
+    );
+
+    *employee.department.lock().unwrap() = "platform".into();
+    let second = sign_in(&app, "other", true).await;
+    assert_eq!(second.status(), StatusCode::SEE_OTHER);
+    let user = DBUser::find_by_id(identity.user_id, &app.global.database)
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(user.attributes["department"], "platform");
+}